@@ -1,45 +1,275 @@
-use crate::app::resp::{Resp, Value};
-use std::fs::File;
-use std::io:: {ErrorKind, Read, Result, Seek, SeekFrom, Write};
-use std::sync::{Arc, RwLock};
+use crate::app::keyspace::Entry;
+use crate::app::resp::{format_double, Frame, Resp};
+use crate::app::util::sync_parent_dir;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
 use std::thread;
-use std::time::Duration; // Import necessary types
+use std::time::Duration;
 
+/// When appended commands are forced to disk, as Redis's `appendfsync`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// After every write, before the command's reply is sent.
+    Always,
+    /// Once a second from a background thread, batching every write made
+    /// since the last one. A crash loses at most about a second of writes.
+    #[default]
+    EverySec,
+    /// Never; the operating system flushes when it sees fit.
+    No,
+}
+
+impl FsyncPolicy {
+    const NAMES: [(FsyncPolicy, &'static str); 3] = [
+        (FsyncPolicy::Always, "always"),
+        (FsyncPolicy::EverySec, "everysec"),
+        (FsyncPolicy::No, "no"),
+    ];
+
+    /// The name used by `--appendfsync`.
+    pub fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(policy, _)| *policy == self)
+            .map(|(_, name)| *name)
+            .expect("every policy is named")
+    }
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        Self::NAMES
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(s))
+            .map(|(policy, _)| *policy)
+            .ok_or(())
+    }
+}
+
+/// Elements per command when a rewrite rebuilds a collection, as Redis's
+/// `AOF_REWRITE_ITEMS_PER_CMD`.
+pub const REWRITE_ITEMS_PER_CMD: usize = 64;
+
+/// When the AOF is rewritten without being asked, as Redis's
+/// `auto-aof-rewrite-percentage` and `auto-aof-rewrite-min-size`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AutoRewrite {
+    /// How much the file must have grown since the last rewrite, as a
+    /// percentage of its size then. 0 turns automatic rewrites off.
+    pub percentage: u64,
+    /// Files smaller than this are never rewritten automatically.
+    pub min_size: u64,
+}
+
+impl AutoRewrite {
+    const DEFAULT: AutoRewrite = AutoRewrite {
+        percentage: 100,
+        min_size: 64 * 1024 * 1024,
+    };
+}
+
+impl Default for AutoRewrite {
+    fn default() -> Self {
+        AutoRewrite::DEFAULT
+    }
+}
+
+static AUTO_REWRITE: RwLock<AutoRewrite> = RwLock::new(AutoRewrite::DEFAULT);
+
+pub fn auto_rewrite() -> AutoRewrite {
+    *AUTO_REWRITE.read().unwrap()
+}
+
+/// Changes the automatic rewrite thresholds, as `CONFIG SET` does.
+pub fn set_auto_rewrite(config: AutoRewrite) {
+    *AUTO_REWRITE.write().unwrap() = config;
+}
+
+// The file and what has been appended but not written to it yet.
+#[derive(Debug)]
+struct Log {
+    file: Arc<File>,
+    buf: Vec<u8>,
+    // While a rewrite runs, everything appended since it started, to go at
+    // the end of the new file.
+    rewrite_buf: Option<Vec<u8>>,
+}
+
+/// Forces a file to disk; see `Aof::with_sync_hook`.
+pub type SyncHook = Box<dyn Fn(&File) -> Result<()> + Send + Sync>;
+
+// Every sync of the AOF goes through here.
+struct Syncer(SyncHook);
+
+impl std::fmt::Debug for Syncer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Syncer")
+    }
+}
+
+// State shared with the background threads, which stop once the `Aof` it
+// belongs to is dropped.
+#[derive(Debug)]
+struct Shared {
+    path: PathBuf,
+    log: Mutex<Log>,
+    fsync: FsyncPolicy,
+    sync: Syncer,
+    // Byte offsets in the current file: how far it has been written, and
+    // how far it is known to be on disk.
+    written: AtomicU64,
+    synced: AtomicU64,
+    // Size of the file after the last rewrite, or when it was opened.
+    base_size: AtomicU64,
+}
+
+impl Shared {
+    fn sync_file(&self, file: &File) -> Result<()> {
+        (self.sync.0)(file)
+    }
+
+    // Writes out the buffer; under `always`, also forces it to disk.
+    fn flush(&self) -> Result<()> {
+        let mut log = self.log.lock().expect("AOF lock poisoned");
+        if log.buf.is_empty() {
+            return Ok(());
+        }
+        let Log { file, buf, .. } = &mut *log;
+        let mut file: &File = file;
+        let end = file.seek(SeekFrom::End(0))? + buf.len() as u64;
+        file.write_all(buf)?;
+        buf.clear();
+        self.written.store(end, Ordering::Release);
+        if self.fsync == FsyncPolicy::Always {
+            self.sync_file(file)?;
+            self.synced.fetch_max(end, Ordering::AcqRel);
+        }
+        Ok(())
+    }
+
+    // Writes the new file next to the current one, then swaps it in.
+    fn rewrite(&self, snapshot: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+        let temp = rewrite_path(&self.path);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp)?;
+        let mut out = BufWriter::new(&file);
+        snapshot(&mut out)?;
+        out.flush()?;
+        drop(out);
+        self.sync_file(&file)?;
+
+        // Writers wait from here on, for as long as it takes to add what
+        // they appended meanwhile; it is all on disk before anyone sees
+        // the new file.
+        let mut log = self.log.lock().expect("AOF lock poisoned");
+        let appended = log.rewrite_buf.take().unwrap_or_default();
+        (&file).write_all(&appended)?;
+        self.sync_file(&file)?;
+        std::fs::rename(&temp, &self.path)?;
+        // What was waiting for the old file went into the new one too.
+        log.buf.clear();
+        log.file = Arc::new(file);
+        let len = log.file.metadata()?.len();
+        self.written.store(len, Ordering::Release);
+        self.synced.store(len, Ordering::Release);
+        self.base_size.store(len, Ordering::Release);
+        // Still holding the log: nothing written to the new file may be
+        // acknowledged while a crash could swap the old one back in.
+        sync_parent_dir(&self.path)
+    }
+}
+
+// Where a rewrite writes the new file before it replaces `path`.
+fn rewrite_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".rewrite");
+    PathBuf::from(name)
+}
+
+/// The append-only file. One instance is shared by every connection:
+/// commands are appended to an in-memory buffer in the order they were
+/// applied, and the buffer is written out with `flush` before their
+/// replies are sent, so one pipeline costs one write.
+#[derive(Debug)]
 pub struct Aof {
-    file: Arc<RwLock<File>>,
+    shared: Arc<Shared>,
 }
 
 impl Aof {
+    /// Opens (or creates) the file, syncing it every second.
     pub fn new(file_path: &str) -> Self {
+        Aof::with_fsync(file_path, FsyncPolicy::default())
+    }
+
+    pub fn with_fsync(file_path: &str, fsync: FsyncPolicy) -> Self {
+        Aof::with_sync_hook(file_path, fsync, Box::new(File::sync_data))
+    }
+
+    /// Like `with_fsync`, with every sync of the file made through `sync`
+    /// instead of `File::sync_data`, so tests can see when the disk is
+    /// really asked to catch up.
+    pub fn with_sync_hook(file_path: &str, fsync: FsyncPolicy, sync: SyncHook) -> Self {
         // Open file with read+write permissions instead of just read
-        let file = std::fs::OpenOptions::new()
+        let file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(file_path)
             .expect("Unable to open AOF file");
 
-        let file = Arc::new(RwLock::new(file));
-
-        // Spawn a thread that syncs the file to disk every 1 second.
-        let file_clone = Arc::clone(&file);
-        thread::spawn(move || loop {
-            {
-                let file_guard = file_clone.write().expect("Failed to acquire write lock");
-                file_guard.sync_all().expect("Error syncing file");
-            }
-            thread::sleep(Duration::from_secs(1));
+        // Whatever was there before is taken to be on disk already.
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        let shared = Arc::new(Shared {
+            path: PathBuf::from(file_path),
+            log: Mutex::new(Log {
+                file: Arc::new(file),
+                buf: Vec::new(),
+                rewrite_buf: None,
+            }),
+            fsync,
+            sync: Syncer(sync),
+            written: AtomicU64::new(len),
+            synced: AtomicU64::new(len),
+            base_size: AtomicU64::new(len),
         });
+        if fsync == FsyncPolicy::EverySec {
+            let shared = Arc::downgrade(&shared);
+            thread::spawn(move || sync_every_second(shared));
+        }
+
+        Aof { shared }
+    }
 
-        Aof { file }
+    pub fn fsync(&self) -> FsyncPolicy {
+        self.shared.fsync
+    }
+
+    /// How many bytes of the file are known to have reached the disk.
+    /// Under `always` this is everything `flush` has returned for.
+    pub fn synced_len(&self) -> u64 {
+        self.shared.synced.load(Ordering::Acquire)
     }
 
     // New method that reads and processes RESP values with a callback function
     pub fn read<F>(&self, mut callback: F) -> Result<()>
     where
-        F: FnMut(Value),
+        F: FnMut(Frame),
     {
-        let mut file_guard = self.file.write().expect("Failed to acquire write lock");
+        // Anything still buffered is read too.
+        self.flush()?;
+        let log = self.shared.log.lock().expect("AOF lock poisoned");
+        let mut file_guard: &File = &log.file;
 
         // Seek to the beginning of the file
         file_guard.seek(SeekFrom::Start(0))?;
@@ -74,18 +304,200 @@ impl Aof {
         Ok(())
     }
 
-    pub fn write(&self, data: &[u8]) -> Result<usize> {
-        let mut file_guard = self.file.write().expect("Failed to acquire write lock");
+    /// Buffers `data` to be written by the next `flush`.
+    pub fn append(&self, data: &[u8]) {
+        let mut log = self.shared.log.lock().expect("AOF lock poisoned");
+        log.buf.extend_from_slice(data);
+        if let Some(rewrite_buf) = &mut log.rewrite_buf {
+            rewrite_buf.extend_from_slice(data);
+        }
+    }
 
-        // Seek to end to append data
-        file_guard.seek(SeekFrom::End(0))?;
+    /// Writes out everything appended so far. Under `always` it is on disk
+    /// by the time this returns.
+    pub fn flush(&self) -> Result<()> {
+        self.shared.flush()
+    }
 
-        // Write the data and return bytes written
-        file_guard.write(data)
+    /// Appends `data` and flushes it at once.
+    pub fn write(&self, data: &[u8]) -> Result<usize> {
+        self.append(data);
+        self.flush()?;
+        Ok(data.len())
     }
 
+    /// Forces everything appended so far to disk, whatever the policy.
     pub fn sync(&self) -> Result<()> {
-        let file_guard = self.file.write().expect("Failed to acquire write lock");
-        file_guard.sync_all()
+        self.flush()?;
+        let log = self.shared.log.lock().expect("AOF lock poisoned");
+        let end = self.shared.written.load(Ordering::Acquire);
+        self.shared.sync_file(&log.file)?;
+        self.shared.synced.fetch_max(end, Ordering::AcqRel);
+        Ok(())
+    }
+
+    pub fn rewrite_in_progress(&self) -> bool {
+        let log = self.shared.log.lock().expect("AOF lock poisoned");
+        log.rewrite_buf.is_some()
+    }
+
+    /// Whether the file has grown enough since the last rewrite for
+    /// `config` to call for another.
+    pub fn rewrite_due(&self, config: AutoRewrite) -> bool {
+        let size = self.shared.written.load(Ordering::Acquire);
+        let base = self.shared.base_size.load(Ordering::Acquire).max(1);
+        config.percentage > 0
+            && size >= config.min_size
+            && (size - base.min(size)) * 100 / base >= config.percentage
+            && !self.rewrite_in_progress()
+    }
+
+    /// Replaces the file, in the background, with one holding `snapshot`
+    /// followed by everything appended from now until it is done.
+    /// `snapshot` writes the commands that rebuild the dataset as of now,
+    /// so the caller must keep commands from being applied between taking
+    /// it and this call. Fails if a rewrite is already running.
+    pub fn start_rewrite(
+        &self,
+        snapshot: impl FnOnce(&mut dyn Write) -> Result<()> + Send + 'static,
+    ) -> std::result::Result<(), &'static str> {
+        {
+            let mut log = self.shared.log.lock().expect("AOF lock poisoned");
+            if log.rewrite_buf.is_some() {
+                return Err("Background append only file rewriting already in progress");
+            }
+            log.rewrite_buf = Some(Vec::new());
+        }
+        let shared = Arc::clone(&self.shared);
+        thread::spawn(move || {
+            if let Err(e) = shared.rewrite(snapshot) {
+                eprintln!("Error rewriting AOF file: {}", e);
+                let _ = std::fs::remove_file(rewrite_path(&shared.path));
+                let mut log = shared.log.lock().expect("AOF lock poisoned");
+                log.rewrite_buf = None;
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Drop for Aof {
+    // A clean shutdown leaves nothing behind in the buffer or the page
+    // cache.
+    fn drop(&mut self) {
+        if let Err(e) = self.sync() {
+            eprintln!("Error syncing AOF file: {}", e);
+        }
+    }
+}
+
+// The `everysec` group commit: once a second, writes out the buffer and
+// issues one `sync_data` covering everything written since the last one,
+// skipped when nothing was.
+fn sync_every_second(shared: Weak<Shared>) {
+    loop {
+        thread::sleep(Duration::from_secs(1));
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        if let Err(e) = shared.flush() {
+            eprintln!("Error writing AOF file: {}", e);
+        }
+        // Anything written before `written` was read is covered. The file
+        // is synced without the lock, so writers never wait on the disk.
+        let (file, written) = {
+            let log = shared.log.lock().expect("AOF lock poisoned");
+            (
+                Arc::clone(&log.file),
+                shared.written.load(Ordering::Acquire),
+            )
+        };
+        if written > shared.synced.load(Ordering::Acquire) {
+            if let Err(e) = shared.sync_file(&file) {
+                eprintln!("Error syncing AOF file: {}", e);
+                continue;
+            }
+            // A rewrite may have swapped the file meanwhile, and with it
+            // the offsets.
+            let log = shared.log.lock().expect("AOF lock poisoned");
+            if Arc::ptr_eq(&log.file, &file) {
+                shared.synced.fetch_max(written, Ordering::AcqRel);
+            }
+        }
+    }
+}
+
+fn command(name: &str, key: &[u8]) -> Vec<Frame> {
+    vec![Frame::bulk(name), Frame::bulk(key.to_vec())]
+}
+
+/// Writes the commands that rebuild `key`, holding `entry` and expiring at
+/// `expire_at`, as a rewrite does: one command per value, with collections
+/// split every `REWRITE_ITEMS_PER_CMD` elements, then the expiries as
+/// absolute times.
+pub fn write_entry(
+    out: &mut dyn Write,
+    key: &[u8],
+    entry: &Entry,
+    expire_at: Option<u64>,
+) -> Result<()> {
+    let mut emit = |argv: Vec<Frame>| out.write_all(&Frame::array(argv).marshal());
+    // Each chunk of `items` becomes `name key item...`.
+    let mut batched = |name: &str, items: Vec<Vec<Frame>>| -> Result<()> {
+        for chunk in items.chunks(REWRITE_ITEMS_PER_CMD) {
+            let mut argv = command(name, key);
+            argv.extend(chunk.iter().flatten().cloned());
+            emit(argv)?;
+        }
+        Ok(())
+    };
+    match entry {
+        Entry::String(value) => {
+            let mut argv = command("SET", key);
+            argv.push(Frame::bulk(value.clone()));
+            emit(argv)?;
+        }
+        Entry::List(list) => batched(
+            "RPUSH",
+            list.iter().map(|v| vec![Frame::bulk(v.clone())]).collect(),
+        )?,
+        Entry::Set(set) => batched(
+            "SADD",
+            set.iter()
+                .map(|(m, _)| vec![Frame::bulk(m.clone())])
+                .collect(),
+        )?,
+        Entry::ZSet(zset) => batched(
+            "ZADD",
+            zset.iter()
+                .map(|(m, score)| vec![Frame::bulk(format_double(score)), Frame::bulk(m.to_vec())])
+                .collect(),
+        )?,
+        Entry::Hash(hash) => {
+            batched(
+                "HSET",
+                hash.iter()
+                    .map(|(f, v)| vec![Frame::bulk(f.clone()), Frame::bulk(v.clone())])
+                    .collect(),
+            )?;
+            for (field, _) in hash.iter() {
+                if let Some(Some(at)) = hash.expire_at(field) {
+                    let mut argv = command("HPEXPIREAT", key);
+                    argv.push(Frame::bulk(at.to_string()));
+                    argv.extend([
+                        Frame::bulk("FIELDS"),
+                        Frame::bulk("1"),
+                        Frame::bulk(field.clone()),
+                    ]);
+                    emit(argv)?;
+                }
+            }
+        }
+    }
+    if let Some(at) = expire_at {
+        let mut argv = command("PEXPIREAT", key);
+        argv.push(Frame::bulk(at.to_string()));
+        emit(argv)?;
     }
+    Ok(())
 }
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+/// A hash map from byte-string keys that also supports positional access.
+///
+/// Entries live in a dense vector with a side index, so picking a uniformly
+/// random entry is O(1), which the expiry sweeper and the eviction sampler
+/// rely on. Removal swaps the last entry into the hole.
+#[derive(Clone, Debug)]
+pub struct Dict<V> {
+    index: HashMap<Vec<u8>, usize>,
+    slots: Vec<(Vec<u8>, V)>,
+}
+
+impl<V> Default for Dict<V> {
+    fn default() -> Self {
+        Dict {
+            index: HashMap::new(),
+            slots: Vec::new(),
+        }
+    }
+}
+
+// Equal when they hold the same entries, whatever their order.
+impl<V: PartialEq> PartialEq for Dict<V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<V> Dict<V> {
+    pub fn new() -> Self {
+        Dict::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.index.contains_key(key)
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        self.index.get(key).map(|&i| &self.slots[i].1)
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        match self.index.get(key) {
+            Some(&i) => Some(&mut self.slots[i].1),
+            None => None,
+        }
+    }
+
+    /// Inserts or replaces the value for `key`, returning the previous value.
+    pub fn insert(&mut self, key: Vec<u8>, value: V) -> Option<V> {
+        if let Some(&i) = self.index.get(&key) {
+            return Some(std::mem::replace(&mut self.slots[i].1, value));
+        }
+        self.index.insert(key.clone(), self.slots.len());
+        self.slots.push((key, value));
+        None
+    }
+
+    /// The value for `key`, inserting `make()` first if it is missing.
+    pub fn get_or_insert_with(&mut self, key: &[u8], make: impl FnOnce() -> V) -> &mut V {
+        let i = match self.index.get(key) {
+            Some(&i) => i,
+            None => {
+                self.index.insert(key.to_vec(), self.slots.len());
+                self.slots.push((key.to_vec(), make()));
+                self.slots.len() - 1
+            }
+        };
+        &mut self.slots[i].1
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let i = self.index.remove(key)?;
+        let (_, value) = self.slots.swap_remove(i);
+        if let Some((moved, _)) = self.slots.get(i) {
+            self.index.insert(moved.clone(), i);
+        }
+        Some(value)
+    }
+
+    /// The entry stored at position `i`; positions are dense, `0..len()`.
+    pub fn get_index(&self, i: usize) -> Option<(&Vec<u8>, &V)> {
+        self.slots.get(i).map(|(k, v)| (k, v))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &V)> {
+        self.slots.iter().map(|(k, v)| (k, v))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.slots.iter().map(|(k, _)| k)
+    }
+
+    /// One step of a cursor-based scan: visits up to `count` entries and
+    /// returns the cursor to continue from, which is 0 once the scan is
+    /// done. A scan starts from cursor 0.
+    ///
+    /// Positions are walked from the end down, so an entry present for the
+    /// whole scan is always visited: a removal only ever moves an entry
+    /// that was already visited into the hole. Entries may be visited twice.
+    pub fn scan<'a>(
+        &'a self,
+        cursor: usize,
+        count: usize,
+        mut visit: impl FnMut(&'a Vec<u8>, &'a V),
+    ) -> usize {
+        let mut i = if cursor == 0 {
+            self.slots.len()
+        } else {
+            cursor.min(self.slots.len())
+        };
+        for _ in 0..count.max(1) {
+            if i == 0 {
+                break;
+            }
+            i -= 1;
+            let (key, value) = &self.slots[i];
+            visit(key, value);
+        }
+        i
+    }
+
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.slots.clear();
+    }
+}
@@ -0,0 +1,207 @@
+use crate::app::util::random_u64;
+use std::str::FromStr;
+
+/// Candidates remembered between eviction runs, as in Redis.
+pub const EVICTION_POOL_SIZE: usize = 16;
+
+/// Initial LFU counter of a new key, so it is not evicted before it has had
+/// a chance to be accessed again.
+pub const LFU_INIT_VAL: u8 = 5;
+
+/// What to do when a write would take the keyspace past `maxmemory`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Refuse commands that may grow the dataset.
+    #[default]
+    NoEviction,
+    AllKeysLru,
+    AllKeysLfu,
+    AllKeysRandom,
+    VolatileLru,
+    VolatileLfu,
+    VolatileRandom,
+    /// Evict the keys with an expiry that are closest to expiring.
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    const NAMES: [(EvictionPolicy, &'static str); 8] = [
+        (EvictionPolicy::NoEviction, "noeviction"),
+        (EvictionPolicy::AllKeysLru, "allkeys-lru"),
+        (EvictionPolicy::AllKeysLfu, "allkeys-lfu"),
+        (EvictionPolicy::AllKeysRandom, "allkeys-random"),
+        (EvictionPolicy::VolatileLru, "volatile-lru"),
+        (EvictionPolicy::VolatileLfu, "volatile-lfu"),
+        (EvictionPolicy::VolatileRandom, "volatile-random"),
+        (EvictionPolicy::VolatileTtl, "volatile-ttl"),
+    ];
+
+    /// The name used by `CONFIG GET maxmemory-policy`.
+    pub fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(policy, _)| *policy == self)
+            .map(|(_, name)| *name)
+            .expect("every policy is named")
+    }
+
+    /// Whether only keys with an expiry may be evicted.
+    pub fn is_volatile(self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::VolatileLru
+                | EvictionPolicy::VolatileLfu
+                | EvictionPolicy::VolatileRandom
+                | EvictionPolicy::VolatileTtl
+        )
+    }
+
+    pub fn is_lfu(self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu
+        )
+    }
+
+    pub fn is_random(self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::AllKeysRandom | EvictionPolicy::VolatileRandom
+        )
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        Self::NAMES
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(s))
+            .map(|(policy, _)| *policy)
+            .ok_or(())
+    }
+}
+
+/// The memory limit and how it is enforced, set through `CONFIG SET`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvictionConfig {
+    /// Limit on the estimated dataset size in bytes; 0 means no limit.
+    pub maxmemory: usize,
+    pub policy: EvictionPolicy,
+    /// Keys sampled per eviction; more is more accurate but slower.
+    pub samples: usize,
+    /// How many hits it takes to saturate the LFU counter.
+    pub lfu_log_factor: u32,
+    /// Minutes an idle key takes to lose one step of its LFU counter.
+    pub lfu_decay_time: u64,
+}
+
+impl Default for EvictionConfig {
+    fn default() -> Self {
+        EvictionConfig {
+            maxmemory: 0,
+            policy: EvictionPolicy::NoEviction,
+            samples: 5,
+            lfu_log_factor: 10,
+            lfu_decay_time: 1,
+        }
+    }
+}
+
+/// Parses a memory size such as `100mb`, `1gb` or `4096`. As in Redis, `k`,
+/// `m` and `g` are powers of 1000 and `kb`, `mb` and `gb` powers of 1024.
+pub fn parse_memory(s: &str) -> Option<usize> {
+    let s = s.to_ascii_lowercase();
+    let digits = s.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit: usize = match &s[digits.len()..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    digits.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+// The LFU state packs the last decrement time, in minutes modulo 2^16, above
+// an 8-bit logarithmic access counter, like the 24 bits Redis keeps per key.
+
+/// LFU state for a key created at `now_ms`.
+pub fn lfu_new(now_ms: u64) -> u32 {
+    (lfu_minutes(now_ms) << 8) | LFU_INIT_VAL as u32
+}
+
+fn lfu_minutes(now_ms: u64) -> u32 {
+    ((now_ms / 60_000) & 0xffff) as u32
+}
+
+/// The counter after decaying it for the time since it was last touched.
+pub fn lfu_counter(state: u32, now_ms: u64, decay_time: u64) -> u8 {
+    let counter = (state & 0xff) as u8;
+    if decay_time == 0 {
+        return counter;
+    }
+    let last = state >> 8;
+    let now = lfu_minutes(now_ms);
+    let elapsed = if now >= last {
+        now - last
+    } else {
+        0x10000 - last + now
+    };
+    let periods = (elapsed as u64 / decay_time).min(255) as u8;
+    counter.saturating_sub(periods)
+}
+
+/// LFU state after one more access: decay, then increment with a
+/// probability that falls as the counter grows.
+pub fn lfu_touch(state: u32, now_ms: u64, config: &EvictionConfig) -> u32 {
+    let mut counter = lfu_counter(state, now_ms, config.lfu_decay_time);
+    if counter < 255 {
+        let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+        let p = 1.0 / (base * config.lfu_log_factor as f64 + 1.0);
+        let r = (random_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        if r < p {
+            counter += 1;
+        }
+    }
+    (lfu_minutes(now_ms) << 8) | counter as u32
+}
+
+/// The best eviction candidates seen so far, sorted by ascending score so
+/// the most evictable key is last. Scores go stale as keys are accessed;
+/// that is part of the approximation, as in Redis.
+#[derive(Debug, Default)]
+pub struct EvictionPool {
+    candidates: Vec<(u64, Vec<u8>)>,
+}
+
+impl EvictionPool {
+    /// Offers a sampled key; it is kept if it beats the worst candidate.
+    pub fn offer(&mut self, score: u64, key: &[u8]) {
+        if let Some(existing) = self.candidates.iter().position(|(_, k)| k == key) {
+            self.candidates.remove(existing);
+        }
+        let full = self.candidates.len() >= EVICTION_POOL_SIZE;
+        if full && self.candidates[0].0 >= score {
+            return;
+        }
+        if full {
+            self.candidates.remove(0);
+        }
+        let at = self.candidates.partition_point(|(s, _)| *s < score);
+        self.candidates.insert(at, (score, key.to_vec()));
+    }
+
+    /// Takes the candidate with the highest score.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.candidates.pop().map(|(_, key)| key)
+    }
+
+    pub fn clear(&mut self) {
+        self.candidates.clear();
+    }
+}
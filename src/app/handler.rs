@@ -1,220 +1,1227 @@
-use crate::app::resp::Value;
+use crate::app::aof::{self, Aof, AutoRewrite};
+use crate::app::evict::{self, EvictionConfig};
+use crate::app::keyspace::Keyspace;
+use crate::app::resp::{Frame, Protocol};
+use crate::app::snapshot::{self, SaveConfig, SharedDataset};
+use crate::app::util::{glob_match, now_ms, parse_integer};
 use std::collections::HashMap;
-use std::sync::RwLock;
+use std::net::TcpStream;
+use std::ops::BitOr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
 
 use lazy_static::lazy_static;
 
-type HandlerFunc = fn(Vec<Value>) -> Value;
+mod blocking;
+mod hash;
+mod keys;
+mod list;
+mod save;
+mod set;
+mod string;
+mod zset;
+
+pub use save::{load_dataset, load_snapshot, spawn_save_points};
+
+pub type HandlerFunc = fn(&mut Client, Vec<Frame>) -> Frame;
 
 lazy_static! {
-    static ref SETS: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
-    static ref HSETS: RwLock<HashMap<String, HashMap<String, String>>> =
-        RwLock::new(HashMap::new());
+    static ref KEYSPACE: RwLock<Keyspace> = RwLock::new(Keyspace::new());
+    static ref COMMANDS: CommandTable = CommandTable::builtin();
+}
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+// Writes made since startup, counted for the `save` points.
+static DIRTY: AtomicU64 = AtomicU64::new(0);
+
+// Taken by commands that log to the AOF; see `Client::log_order`.
+static LOG_ORDER: Mutex<()> = Mutex::new(());
+
+/// Per-connection state handed to every command.
+#[derive(Debug)]
+pub struct Client {
+    pub id: u64,
+    pub protocol: Protocol,
+    pub name: Option<Vec<u8>>,
+    /// Makes blocking commands time out at once instead of waiting, as
+    /// while replaying the AOF, where nobody else could ever serve them.
+    pub deny_blocking: bool,
+    /// The connection, if any, so a blocked command can notice it closing.
+    pub connection: Option<TcpStream>,
+    /// Where the connection's write commands are logged, if anywhere.
+    pub aof: Option<Arc<Aof>>,
+    /// Held by a write command from before it runs until it is appended to
+    /// the AOF, so commands are logged in the order they were applied. Other
+    /// commands take it with `order_log` only once they have something to
+    /// log, such as evictions.
+    pub log_order: Option<MutexGuard<'static, ()>>,
+    /// Set by `SHUTDOWN`: the server flushes the AOF and exits once the
+    /// command returns.
+    pub shutdown: bool,
+    // Commands to log to the AOF instead of the one being run, if set.
+    propagate: Option<Vec<Vec<Frame>>>,
+    // Commands to log before the one being run, whatever it logs itself.
+    also_propagate: Vec<Vec<Frame>>,
 }
 
-fn ping_handler(_args: Vec<Value>) -> Value {
-    if _args.len() == 0 {
-        Value {
-            typ: "string".to_string(),
-            str: "PONG".to_string(),
-            num: 0,
-            bulk: String::new(),
-            array: vec![],
+impl Client {
+    pub fn new() -> Self {
+        Client {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            protocol: Protocol::Resp2,
+            name: None,
+            deny_blocking: false,
+            connection: None,
+            aof: None,
+            log_order: None,
+            shutdown: false,
+            propagate: None,
+            also_propagate: Vec::new(),
         }
-    } else {
-        Value {
-            typ: "string".to_string(),
-            str: _args[0].bulk.to_string(),
-            num: 0,
-            bulk: String::new(),
-            array: vec![],
+    }
+
+    /// Logs `argv` to the AOF in place of the running command. Handlers use
+    /// this to make replay deterministic, e.g. turning a relative TTL into an
+    /// absolute one. May be called several times to log several commands.
+    pub fn propagate(&mut self, argv: Vec<Frame>) {
+        self.propagate.get_or_insert_with(Vec::new).push(argv);
+    }
+
+    /// Logs nothing for the running command, e.g. because it had no effect.
+    pub fn propagate_nothing(&mut self) {
+        self.propagate = Some(Vec::new());
+    }
+
+    /// What the last command asked to have logged instead of itself.
+    pub fn take_propagation(&mut self) -> Option<Vec<Vec<Frame>>> {
+        self.propagate.take()
+    }
+
+    /// Logs `argv` ahead of the running command, for side effects such as
+    /// evictions that must be logged even if the command itself is not.
+    pub fn also_propagate(&mut self, argv: Vec<Frame>) {
+        self.also_propagate.push(argv);
+    }
+
+    /// What the last command logged through `also_propagate`.
+    pub fn take_also_propagated(&mut self) -> Vec<Vec<Frame>> {
+        std::mem::take(&mut self.also_propagate)
+    }
+
+    /// Takes the log order for the running command, unless it already holds
+    /// it or there is no AOF to log to. Must come before the keyspace lock.
+    pub fn order_log(&mut self) {
+        if self.aof.is_some() && self.log_order.is_none() {
+            self.log_order = Some(LOG_ORDER.lock().unwrap_or_else(|e| e.into_inner()));
         }
     }
 }
 
-fn fn_get_handler(_args: Vec<Value>) -> Value {
-    let map = SETS.read().unwrap();
-    if let Some(val) = map.get(_args[0].bulk.as_str()) {
-        // If found, return the associated value.
-        Value {
-            typ: "bulk".to_string(),
-            str: "".to_string(),
-            num: 0,
-            bulk: val.clone(),
-            array: vec![],
+impl Default for Client {
+    fn default() -> Self {
+        Client::new()
+    }
+}
+
+/// Command properties, combined with `|`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Flags(u32);
+
+impl Flags {
+    pub const NONE: Flags = Flags(0);
+    /// May modify the keyspace; such commands are logged to the AOF.
+    pub const WRITE: Flags = Flags(1);
+    /// Only reads data.
+    pub const READONLY: Flags = Flags(1 << 1);
+    /// Server administration, not data access.
+    pub const ADMIN: Flags = Flags(1 << 2);
+    /// Runs in constant or logarithmic time.
+    pub const FAST: Flags = Flags(1 << 3);
+    /// May park the connection until data is available.
+    pub const BLOCKING: Flags = Flags(1 << 4);
+    /// May grow the dataset, so it is refused when over `maxmemory`.
+    pub const DENYOOM: Flags = Flags(1 << 5);
+
+    const NAMES: [(Flags, &'static str); 6] = [
+        (Flags::WRITE, "write"),
+        (Flags::READONLY, "readonly"),
+        (Flags::ADMIN, "admin"),
+        (Flags::FAST, "fast"),
+        (Flags::BLOCKING, "blocking"),
+        (Flags::DENYOOM, "denyoom"),
+    ];
+
+    pub fn contains(self, other: Flags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The lowercase names Redis uses for these flags, e.g. in `COMMAND` replies.
+    pub fn names(self) -> Vec<&'static str> {
+        Flags::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+impl BitOr for Flags {
+    type Output = Flags;
+
+    fn bitor(self, rhs: Flags) -> Flags {
+        Flags(self.0 | rhs.0)
+    }
+}
+
+/// Metadata and implementation of a single command.
+///
+/// `arity` and the key positions follow Redis conventions: they count the
+/// command name as argument 0, a negative arity means "at least that many",
+/// and a negative `last_key` counts back from the end of the arguments.
+#[derive(Clone, Debug)]
+pub struct Command {
+    pub name: &'static str,
+    pub arity: i32,
+    pub flags: Flags,
+    pub first_key: i32,
+    pub last_key: i32,
+    pub key_step: i32,
+    pub handler: HandlerFunc,
+    /// Documentation group, e.g. `string` or `connection`.
+    pub group: &'static str,
+    /// Redis version that introduced the command.
+    pub since: &'static str,
+    pub summary: &'static str,
+}
+
+impl Command {
+    pub fn new(name: &'static str, arity: i32, flags: Flags, handler: HandlerFunc) -> Self {
+        Command {
+            name,
+            arity,
+            flags,
+            first_key: 0,
+            last_key: 0,
+            key_step: 0,
+            handler,
+            group: "generic",
+            since: "1.0.0",
+            summary: "",
+        }
+    }
+
+    /// Attaches the documentation reported by `COMMAND DOCS`.
+    pub fn docs(mut self, group: &'static str, since: &'static str, summary: &'static str) -> Self {
+        self.group = group;
+        self.since = since;
+        self.summary = summary;
+        self
+    }
+
+    /// Declares where the command's keys sit in its arguments.
+    pub fn keys(mut self, first: i32, last: i32, step: i32) -> Self {
+        self.first_key = first;
+        self.last_key = last;
+        self.key_step = step;
+        self
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.flags.contains(Flags::WRITE)
+    }
+
+    /// Checks an argument count, where `argc` includes the command name.
+    pub fn arity_ok(&self, argc: usize) -> bool {
+        let argc = argc as i64;
+        let arity = self.arity as i64;
+        if arity >= 0 {
+            argc == arity
+        } else {
+            argc >= -arity
         }
+    }
+
+    /// Positions of the keys in `argv`, which includes the command name.
+    pub fn key_positions(&self, argv: &[Frame]) -> Vec<usize> {
+        if self.first_key <= 0 {
+            return vec![];
+        }
+        let argc = argv.len() as i32;
+        let last = if self.last_key < 0 {
+            argc + self.last_key
+        } else {
+            self.last_key.min(argc - 1)
+        };
+        let step = self.key_step.max(1) as usize;
+        (self.first_key..=last)
+            .step_by(step)
+            .map(|i| i as usize)
+            .collect()
+    }
+}
+
+/// Registry of every command the server understands, keyed by name.
+pub struct CommandTable {
+    commands: HashMap<String, Command>,
+}
+
+impl CommandTable {
+    pub fn new() -> Self {
+        CommandTable {
+            commands: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, command: Command) {
+        self.commands
+            .insert(command.name.to_ascii_uppercase(), command);
+    }
+
+    /// Looks a command up by name, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        self.commands.get(&name.to_ascii_uppercase())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Command> {
+        self.commands.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    fn builtin() -> Self {
+        use Flags as F;
+        let mut table = CommandTable::new();
+        table.register(Command::new("ping", -1, F::FAST, ping_handler).docs(
+            "connection",
+            "1.0.0",
+            "Returns the server's liveliness response.",
+        ));
+        table.register(Command::new("hello", -1, F::FAST, hello_handler).docs(
+            "connection",
+            "6.0.0",
+            "Handshakes with the server and selects the protocol version.",
+        ));
+        table.register(Command::new("config", -2, F::ADMIN, config_handler).docs(
+            "server",
+            "2.0.0",
+            "Reads or changes the server's configuration parameters.",
+        ));
+        table.register(Command::new("command", -1, F::NONE, command_handler).docs(
+            "server",
+            "2.8.13",
+            "Returns detailed information about all commands.",
+        ));
+        table.register(
+            Command::new("bgrewriteaof", 1, F::ADMIN, bgrewriteaof_handler).docs(
+                "server",
+                "1.0.0",
+                "Asynchronously rewrites the append-only file to disk.",
+            ),
+        );
+        table.register(
+            Command::new("shutdown", -1, F::ADMIN, shutdown_handler).docs(
+                "server",
+                "1.0.0",
+                "Synchronously saves the database(s) to disk and shuts down the Redis server.",
+            ),
+        );
+        string::register(&mut table);
+        hash::register(&mut table);
+        keys::register(&mut table);
+        save::register(&mut table);
+        list::register(&mut table);
+        blocking::register(&mut table);
+        set::register(&mut table);
+        zset::register(&mut table);
+        table.register(
+            Command::new("type", 2, F::READONLY | F::FAST, type_handler)
+                .keys(1, 1, 1)
+                .docs(
+                    "generic",
+                    "1.0.0",
+                    "Determines the type of value stored at a key.",
+                ),
+        );
+        table.register(
+            Command::new("expire", -3, F::WRITE | F::FAST, expire_handler)
+                .keys(1, 1, 1)
+                .docs(
+                    "generic",
+                    "1.0.0",
+                    "Sets the expiration time of a key in seconds.",
+                ),
+        );
+        table.register(
+            Command::new("pexpire", -3, F::WRITE | F::FAST, pexpire_handler)
+                .keys(1, 1, 1)
+                .docs(
+                    "generic",
+                    "2.6.0",
+                    "Sets the expiration time of a key in milliseconds.",
+                ),
+        );
+        table.register(
+            Command::new("expireat", -3, F::WRITE | F::FAST, expireat_handler)
+                .keys(1, 1, 1)
+                .docs(
+                    "generic",
+                    "1.2.0",
+                    "Sets the expiration time of a key to a Unix timestamp.",
+                ),
+        );
+        table.register(
+            Command::new("pexpireat", -3, F::WRITE | F::FAST, pexpireat_handler)
+                .keys(1, 1, 1)
+                .docs(
+                    "generic",
+                    "2.6.0",
+                    "Sets the expiration time of a key to a Unix milliseconds timestamp.",
+                ),
+        );
+        table.register(
+            Command::new("ttl", 2, F::READONLY | F::FAST, ttl_handler)
+                .keys(1, 1, 1)
+                .docs(
+                    "generic",
+                    "1.0.0",
+                    "Returns the expiration time in seconds of a key.",
+                ),
+        );
+        table.register(
+            Command::new("pttl", 2, F::READONLY | F::FAST, pttl_handler)
+                .keys(1, 1, 1)
+                .docs(
+                    "generic",
+                    "2.6.0",
+                    "Returns the expiration time in milliseconds of a key.",
+                ),
+        );
+        table.register(
+            Command::new("expiretime", 2, F::READONLY | F::FAST, expiretime_handler)
+                .keys(1, 1, 1)
+                .docs(
+                    "generic",
+                    "7.0.0",
+                    "Returns the expiration time of a key as a Unix timestamp.",
+                ),
+        );
+        table.register(
+            Command::new("pexpiretime", 2, F::READONLY | F::FAST, pexpiretime_handler)
+                .keys(1, 1, 1)
+                .docs(
+                    "generic",
+                    "7.0.0",
+                    "Returns the expiration time of a key as a Unix milliseconds timestamp.",
+                ),
+        );
+        table.register(
+            Command::new("persist", 2, F::WRITE | F::FAST, persist_handler)
+                .keys(1, 1, 1)
+                .docs("generic", "2.2.0", "Removes the expiration time of a key."),
+        );
+        table
+    }
+}
+
+impl Default for CommandTable {
+    fn default() -> Self {
+        CommandTable::new()
+    }
+}
+
+/// The table of built-in commands.
+pub fn commands() -> &'static CommandTable {
+    &COMMANDS
+}
+
+pub fn get_handler(command: &str) -> Option<&'static Command> {
+    COMMANDS.get(command)
+}
+
+/// Runs a command given its full argument vector (name first), checking
+/// that the command exists and that it was given an acceptable number of
+/// arguments before calling its handler.
+pub fn dispatch(client: &mut Client, mut argv: Vec<Frame>) -> Frame {
+    let Some(name) = argv.first() else {
+        return Frame::error("ERR empty command");
+    };
+    let name = String::from_utf8_lossy(name.as_bulk().unwrap_or_default()).into_owned();
+
+    let Some(command) = get_handler(&name) else {
+        return Frame::error(format!("ERR unknown command '{}'", name));
+    };
+    if !command.arity_ok(argv.len()) {
+        return wrong_arity(command.name);
+    }
+
+    client.propagate = None;
+    client.also_propagate.clear();
+    if let Err(e) = evict_if_needed(client, command) {
+        return e;
+    }
+
+    let keys: Vec<Vec<u8>> = if command.is_write() {
+        command
+            .key_positions(&argv)
+            .into_iter()
+            .map(|i| argv[i].as_bulk().unwrap_or_default().to_vec())
+            .collect()
     } else {
-        // If not found, return a null Value.
-        Value {
-            typ: "null".to_string(),
-            str: "".to_string(),
-            num: 0,
-            bulk: String::new(),
-            array: vec![],
+        vec![]
+    };
+    // Kept to log ahead of whatever blocked clients this command serves.
+    // A client may block on one of the keys while the handler runs, so with
+    // an AOF to log to it is kept whether or not anyone is waiting yet.
+    let original = (!keys.is_empty() && (client.aof.is_some() || blocking::has_waiters()))
+        .then(|| argv.clone());
+    argv.remove(0);
+    let reply = (command.handler)(client, argv);
+    // A write that logs nothing changed nothing.
+    if command.is_write()
+        && !reply.is_error()
+        && client.propagate.as_ref().map_or(true, |p| !p.is_empty())
+    {
+        DIRTY.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Writes may have grown values in place; charge the new sizes.
+    if !keys.is_empty() {
+        let mut keyspace = KEYSPACE.write().unwrap();
+        for key in &keys {
+            keyspace.refresh_size(key);
+        }
+        // Clients block under this lock, so checking here rather than before
+        // the handler cannot miss one that blocked while it ran.
+        let served = blocking::serve_blocked(&mut keyspace, &keys);
+        if !served.is_empty() {
+            let logged = client
+                .propagate
+                .take()
+                .or_else(|| original.map(|o| vec![o]));
+            if let Some(mut logged) = logged {
+                logged.extend(served);
+                client.propagate = Some(logged);
+            }
         }
     }
+    reply
 }
 
-fn set_handler(_args: Vec<Value>) -> Value {
-    let mut map = SETS.write().unwrap();
-    map.insert(_args[0].bulk.clone(), _args[1].bulk.clone());
-    Value {
-        typ: "string".to_string(),
-        str: "OK".to_string(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![],
+pub const OOM_ERR: &str = "OOM command not allowed when used memory > 'maxmemory'.";
+
+// Makes room before running a command when the dataset is over
+// `maxmemory`, as Redis does. Evicted keys are logged as deleted. Commands
+// that may grow the dataset are refused if not enough could be evicted.
+fn evict_if_needed(client: &mut Client, command: &Command) -> Result<(), Frame> {
+    if !KEYSPACE.read().unwrap().over_maxmemory() {
+        return Ok(());
     }
+    // Evictions are logged, and must land between the writes around them.
+    client.order_log();
+    let mut keyspace = KEYSPACE.write().unwrap();
+    for key in keyspace.evict() {
+        client.also_propagate(vec![Frame::bulk("DEL"), Frame::bulk(key)]);
+    }
+    if keyspace.over_maxmemory() && command.flags.contains(Flags::DENYOOM) {
+        return Err(Frame::error(OOM_ERR));
+    }
+    Ok(())
+}
+
+fn wrong_arity(name: &str) -> Frame {
+    Frame::error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        name
+    ))
+}
+
+// Commands always arrive as arrays of bulk strings; anything else reads as empty.
+fn arg(args: &[Frame], i: usize) -> Vec<u8> {
+    args[i].as_bulk().unwrap_or_default().to_vec()
+}
+
+// The argument upper-cased, for matching option names.
+fn option(args: &[Frame], i: usize) -> String {
+    String::from_utf8_lossy(args[i].as_bulk().unwrap_or_default()).to_ascii_uppercase()
 }
 
-fn hset_handler(_args: Vec<Value>) -> Value {
-    if _args.len() != 3 {
-        return Value {
-            typ: "error".to_string(),
-            str: "ERR wrong number of arguments for 'hset' command".to_string(),
-            num: 0,
-            bulk: String::new(),
-            array: vec![],
+fn parse_i64(frame: &Frame) -> Result<i64, Frame> {
+    parse_integer(frame.as_bulk().unwrap_or_default())
+        .ok_or_else(|| Frame::error("ERR value is not an integer or out of range"))
+}
+
+fn syntax_error() -> Frame {
+    Frame::error("ERR syntax error")
+}
+
+// The arguments of SCAN and HSCAN: `cursor [MATCH pattern] [COUNT count]`,
+// plus `TYPE type` for SCAN and `NOVALUES` for HSCAN.
+struct ScanArgs {
+    cursor: usize,
+    pattern: Option<Vec<u8>>,
+    count: usize,
+    type_name: Option<String>,
+    novalues: bool,
+}
+
+impl ScanArgs {
+    // Parses the arguments from the cursor at `args[first]` on.
+    fn parse(args: &[Frame], first: usize, command: &str) -> Result<ScanArgs, Frame> {
+        let cursor = std::str::from_utf8(args[first].as_bulk().unwrap_or_default())
+            .ok()
+            .and_then(|s| s.parse::<usize>().ok())
+            .ok_or_else(|| Frame::error("ERR invalid cursor"))?;
+        let mut scan = ScanArgs {
+            cursor,
+            pattern: None,
+            count: 10,
+            type_name: None,
+            novalues: false,
         };
+        let mut i = first + 1;
+        while i < args.len() {
+            let has_value = i + 1 < args.len();
+            match option(args, i).as_str() {
+                "MATCH" if has_value => {
+                    scan.pattern = Some(arg(args, i + 1));
+                    i += 1;
+                }
+                "COUNT" if has_value => {
+                    scan.count = match parse_i64(&args[i + 1])? {
+                        n if n >= 1 => n as usize,
+                        _ => return Err(syntax_error()),
+                    };
+                    i += 1;
+                }
+                "TYPE" if has_value && command == "scan" => {
+                    scan.type_name = Some(option(args, i + 1).to_ascii_lowercase());
+                    i += 1;
+                }
+                "NOVALUES" if command == "hscan" => scan.novalues = true,
+                _ => return Err(syntax_error()),
+            }
+            i += 1;
+        }
+        Ok(scan)
+    }
+
+    fn matches(&self, item: &[u8]) -> bool {
+        self.pattern.as_ref().map_or(true, |p| glob_match(p, item))
     }
+}
+
+fn invalid_expire(command: &str) -> Frame {
+    Frame::error(format!("ERR invalid expire time in '{}' command", command))
+}
 
-    let hash = _args[0].bulk.clone();
-    let key = _args[1].bulk.clone();
-    let value = _args[2].bulk.clone();
+// Turns a TTL argument into an absolute Unix time in milliseconds. `unit_ms`
+// is 1000 for seconds and 1 for milliseconds; relative times are added to
+// the current time. Overflow is reported as an invalid expire time.
+fn expire_deadline(
+    value: &Frame,
+    unit_ms: i64,
+    absolute: bool,
+    command: &str,
+) -> Result<i64, Frame> {
+    let n = parse_i64(value)?;
+    let base = if absolute { 0 } else { now_ms() as i64 };
+    n.checked_mul(unit_ms)
+        .and_then(|ms| ms.checked_add(base))
+        .ok_or_else(|| invalid_expire(command))
+}
+
+/// A copy of every live key, for a snapshot or a rewrite to work from
+/// without holding up clients; Redis forks for it instead. The values are
+/// shared rather than copied, and the keyspace copies one only if it
+/// changes while the snapshot is still around.
+fn dataset() -> SharedDataset {
+    let keyspace = KEYSPACE.read().unwrap();
+    keyspace
+        .iter_shared()
+        .map(|(key, entry)| (key.clone(), Arc::clone(entry), keyspace.expire_at(key)))
+        .collect()
+}
+
+/// Starts rewriting `aof` from the current dataset, which is shared with
+/// the rewrite rather than copied, as for `BGSAVE`. Commands must not be
+/// applied concurrently, which holding the log order guarantees.
+pub fn rewrite_aof(aof: &Aof) -> Result<(), &'static str> {
+    if aof.rewrite_in_progress() {
+        return Err("Background append only file rewriting already in progress");
+    }
+    let snapshot = dataset();
+    aof.start_rewrite(move |out| {
+        for (key, entry, expire_at) in &snapshot {
+            aof::write_entry(out, key, entry, *expire_at)?;
+        }
+        Ok(())
+    })
+}
 
-    let mut hsets = HSETS.write().unwrap();
-    if !hsets.contains_key(&hash) {
-        hsets.insert(hash.clone(), HashMap::new());
+/// `BGREWRITEAOF`
+fn bgrewriteaof_handler(client: &mut Client, _args: Vec<Frame>) -> Frame {
+    let Some(aof) = client.aof.clone() else {
+        return Frame::error(
+            "ERR Background append only file rewriting needs the AOF to be enabled",
+        );
+    };
+    // No write may land between the copy and the start of the rewrite.
+    client.order_log();
+    match rewrite_aof(&aof) {
+        Ok(()) => Frame::simple("Background append only file rewriting started"),
+        Err(e) => Frame::error(format!("ERR {}", e)),
     }
+}
 
-    if let Some(hash_map) = hsets.get_mut(&hash) {
-        hash_map.insert(key, value);
+/// `SHUTDOWN [NOSAVE | SAVE]`
+///
+/// Saves a snapshot first if asked to, or by default when there are
+/// `save` points; a failed save cancels the shutdown. Either way the AOF is
+/// flushed to disk before the server exits.
+fn shutdown_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let save = match args.len() {
+        0 => !snapshot::save_config().save_points.is_empty(),
+        1 if option(&args, 0) == "NOSAVE" => false,
+        1 if option(&args, 0) == "SAVE" => true,
+        _ => return syntax_error(),
+    };
+    if save {
+        // A background save in progress would be out of date.
+        while save::save_in_progress() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        if let Err(e) = save::save_now() {
+            eprintln!("Error saving snapshot before shutdown: {:?}", e);
+            return Frame::error("ERR Errors trying to SHUTDOWN. Check logs.");
+        }
     }
+    client.shutdown = true;
+    Frame::ok()
+}
 
-    Value {
-        typ: "string".to_string(),
-        str: "OK".to_string(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![],
+fn ping_handler(_client: &mut Client, _args: Vec<Frame>) -> Frame {
+    match _args.len() {
+        0 => Frame::simple("PONG"),
+        1 => Frame::bulk(arg(&_args, 0)),
+        _ => wrong_arity("ping"),
     }
 }
 
-fn hget_handler(_args: Vec<Value>) -> Value {
-    if _args.len() != 2 {
-        return Value {
-            typ: "error".to_string(),
-            str: "ERR wrong number of arguments for 'hget' command".to_string(),
-            num: 0,
-            bulk: String::new(),
-            array: vec![],
+/// `HELLO [protover [AUTH username password] [SETNAME clientname]]`
+///
+/// Switches the connection's protocol and replies with a map describing the
+/// server. The reply is encoded in the newly selected protocol.
+fn hello_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let mut protocol = client.protocol;
+    if let Some(version) = args.first() {
+        let version = version.as_bulk().unwrap_or_default();
+        protocol = match std::str::from_utf8(version)
+            .ok()
+            .and_then(|v| v.parse::<i64>().ok())
+        {
+            Some(2) => Protocol::Resp2,
+            Some(3) => Protocol::Resp3,
+            Some(_) => return Frame::error("NOPROTO unsupported protocol version"),
+            None => return Frame::error("ERR Protocol version is not an integer or out of range"),
         };
     }
 
-    let hash = _args[0].bulk.clone();
-    let key = _args[1].bulk.clone();
+    // Options are validated in full before anything is applied.
+    let mut name = None;
+    let mut i = 1;
+    while i < args.len() {
+        let option = args[i].as_bulk().unwrap_or_default().to_ascii_uppercase();
+        match option.as_slice() {
+            // No users are configured, so any credentials are accepted.
+            b"AUTH" if i + 2 < args.len() => i += 3,
+            b"SETNAME" if i + 1 < args.len() => {
+                let value = args[i + 1].as_bulk().unwrap_or_default();
+                if value.iter().any(|&c| c <= b' ' || c > b'~') {
+                    return Frame::error(
+                        "ERR Client names cannot contain spaces, newlines or special characters.",
+                    );
+                }
+                name = Some(value.to_vec());
+                i += 2;
+            }
+            _ => {
+                return Frame::error(format!(
+                    "ERR Syntax error in HELLO option '{}'",
+                    String::from_utf8_lossy(&option)
+                ))
+            }
+        }
+    }
 
-    let hsets = HSETS.read().unwrap();
+    client.protocol = protocol;
+    if name.is_some() {
+        client.name = name;
+    }
 
-    if let Some(hash_map) = hsets.get(&hash) {
-        if let Some(value) = hash_map.get(&key) {
-            return Value {
-                typ: "bulk".to_string(),
-                str: String::new(),
-                num: 0,
-                bulk: value.clone(),
-                array: vec![],
-            };
+    Frame::map(vec![
+        (Frame::bulk("server"), Frame::bulk("crache")),
+        (
+            Frame::bulk("version"),
+            Frame::bulk(env!("CARGO_PKG_VERSION")),
+        ),
+        (Frame::bulk("proto"), Frame::integer(protocol.version())),
+        (Frame::bulk("id"), Frame::integer(client.id as i64)),
+        (Frame::bulk("mode"), Frame::bulk("standalone")),
+        (Frame::bulk("role"), Frame::bulk("master")),
+        (Frame::bulk("modules"), Frame::array(vec![])),
+    ])
+}
+
+// Shared by EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT:
+// `<command> key time [NX | XX | GT | LT]`.
+fn expire_generic(
+    client: &mut Client,
+    args: Vec<Frame>,
+    unit_ms: i64,
+    absolute: bool,
+    command: &str,
+) -> Frame {
+    let key = arg(&args, 0);
+    let (mut nx, mut xx, mut gt, mut lt) = (false, false, false, false);
+    for i in 2..args.len() {
+        match option(&args, i).as_str() {
+            "NX" => nx = true,
+            "XX" => xx = true,
+            "GT" => gt = true,
+            "LT" => lt = true,
+            other => {
+                return Frame::error(format!("ERR Unsupported option {}", other));
+            }
         }
     }
+    if nx && (xx || gt || lt) {
+        return Frame::error("ERR NX and XX, GT or LT options at the same time are not compatible");
+    }
+    if gt && lt {
+        return Frame::error("ERR GT and LT options at the same time are not compatible");
+    }
+    let at = match expire_deadline(&args[1], unit_ms, absolute, command) {
+        Ok(at) => at,
+        Err(e) => return e,
+    };
 
-    // If key not found, return null
-    Value {
-        typ: "null".to_string(),
-        str: String::new(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![],
+    let mut keyspace = KEYSPACE.write().unwrap();
+    if !keyspace.contains_key(&key) {
+        client.propagate_nothing();
+        return Frame::integer(0);
+    }
+    // A key without a TTL counts as expiring never, i.e. infinitely late.
+    let current = keyspace.expire_at(&key);
+    let allowed = (!nx || current.is_none())
+        && (!xx || current.is_some())
+        && (!gt || current.is_some_and(|c| at > c as i64))
+        && (!lt || current.map_or(true, |c| at < c as i64));
+    if !allowed {
+        client.propagate_nothing();
+        return Frame::integer(0);
     }
+
+    // A deadline in the past deletes the key; replaying it does the same.
+    let at = at.max(1);
+    keyspace.set_expire_at(&key, at as u64);
+    client.propagate(vec![
+        Frame::bulk("PEXPIREAT"),
+        Frame::bulk(key),
+        Frame::bulk(at.to_string()),
+    ]);
+    Frame::integer(1)
 }
 
-fn command_handler(_args: Vec<Value>) -> Value {
-    Value {
-        typ: "string".to_string(),
-        str: "OK".to_string(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![],
+/// `EXPIRE key seconds [NX | XX | GT | LT]`
+fn expire_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    expire_generic(client, args, 1000, false, "expire")
+}
+
+/// `PEXPIRE key milliseconds [NX | XX | GT | LT]`
+fn pexpire_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    expire_generic(client, args, 1, false, "pexpire")
+}
+
+/// `EXPIREAT key unix-time-seconds [NX | XX | GT | LT]`
+fn expireat_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    expire_generic(client, args, 1000, true, "expireat")
+}
+
+/// `PEXPIREAT key unix-time-milliseconds [NX | XX | GT | LT]`
+fn pexpireat_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    expire_generic(client, args, 1, true, "pexpireat")
+}
+
+// Shared by TTL, PTTL, EXPIRETIME and PEXPIRETIME: -2 if the key does not
+// exist, -1 if it has no expiry, otherwise `report(deadline_ms, now_ms)`.
+fn ttl_generic(args: &[Frame], report: fn(u64, u64) -> i64) -> Frame {
+    let key = arg(args, 0);
+    let keyspace = KEYSPACE.read().unwrap();
+    if !keyspace.contains_key(&key) {
+        return Frame::integer(-2);
+    }
+    match keyspace.expire_at(&key) {
+        Some(at) => Frame::integer(report(at, now_ms())),
+        None => Frame::integer(-1),
     }
 }
 
+/// `TTL key`
+fn ttl_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    ttl_generic(&args, |at, now| {
+        (at.saturating_sub(now) as i64 + 500) / 1000
+    })
+}
 
-fn hgetall_handler(_args: Vec<Value>) -> Value {
-    if _args.len() != 1 {
-        return Value {
-            typ: "error".to_string(),
-            str: "ERR wrong number of arguments for 'hgetall' command".to_string(),
-            num: 0,
-            bulk: String::new(),
-            array: vec![],
-        };
+/// `PTTL key`
+fn pttl_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    ttl_generic(&args, |at, now| at.saturating_sub(now) as i64)
+}
+
+/// `EXPIRETIME key`
+fn expiretime_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    ttl_generic(&args, |at, _| at as i64 / 1000)
+}
+
+/// `PEXPIRETIME key`
+fn pexpiretime_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    ttl_generic(&args, |at, _| at as i64)
+}
+
+/// `PERSIST key`
+fn persist_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let mut keyspace = KEYSPACE.write().unwrap();
+    if keyspace.persist(&arg(&args, 0)) {
+        Frame::integer(1)
+    } else {
+        client.propagate_nothing();
+        Frame::integer(0)
     }
+}
 
-    let hash = _args[0].bulk.clone();
-    let hsets = HSETS.read().unwrap();
-
-    if let Some(hash_map) = hsets.get(&hash) {
-        let mut result = Vec::new();
-
-        // For each key-value pair in the hash, add both key and value to the result array
-        for (field, value) in hash_map {
-            // Add field
-            result.push(Value {
-                typ: "bulk".to_string(),
-                str: String::new(),
-                num: 0,
-                bulk: field.clone(),
-                array: vec![],
-            });
-
-            // Add value
-            result.push(Value {
-                typ: "bulk".to_string(),
-                str: String::new(),
-                num: 0,
-                bulk: value.clone(),
-                array: vec![],
-            });
-        }
-
-        return Value {
-            typ: "array".to_string(),
-            str: String::new(),
-            num: 0,
-            bulk: String::new(),
-            array: result,
-        };
+/// Spawns the background thread that reclaims expired keys and hash fields
+/// nobody reads, running the active expiry cycles ten times a second.
+pub fn spawn_active_expire() -> std::thread::JoinHandle<()> {
+    std::thread::spawn(|| loop {
+        {
+            let mut keyspace = KEYSPACE.write().unwrap();
+            keyspace.active_expire_cycle(now_ms(), 16);
+            keyspace.active_expire_fields(now_ms(), 16);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    })
+}
+
+/// `TYPE key`
+fn type_handler(_client: &mut Client, _args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    let name = keyspace
+        .get(&arg(&_args, 0))
+        .map_or("none", |entry| entry.type_name());
+    Frame::simple(name)
+}
+
+// Everything `CONFIG` can change, gathered so `CONFIG SET` can apply
+// several parameters all or nothing.
+struct Config {
+    eviction: EvictionConfig,
+    auto_rewrite: AutoRewrite,
+    save: SaveConfig,
+}
+
+impl Config {
+    fn current() -> Config {
+        Config {
+            eviction: KEYSPACE.read().unwrap().eviction_config().clone(),
+            auto_rewrite: aof::auto_rewrite(),
+            save: snapshot::save_config(),
+        }
     }
+}
 
-    // If the hash does not exist, return an empty array
-    Value {
-        typ: "array".to_string(),
-        str: String::new(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![],
+// The parameters `CONFIG` knows, with their current values.
+fn config_params(config: &Config) -> Vec<(&'static str, String)> {
+    let Config {
+        eviction,
+        auto_rewrite,
+        save,
+    } = config;
+    vec![
+        ("maxmemory", eviction.maxmemory.to_string()),
+        ("maxmemory-policy", eviction.policy.name().to_string()),
+        ("maxmemory-samples", eviction.samples.to_string()),
+        ("lfu-log-factor", eviction.lfu_log_factor.to_string()),
+        ("lfu-decay-time", eviction.lfu_decay_time.to_string()),
+        (
+            "auto-aof-rewrite-percentage",
+            auto_rewrite.percentage.to_string(),
+        ),
+        (
+            "auto-aof-rewrite-min-size",
+            auto_rewrite.min_size.to_string(),
+        ),
+        ("save", save.save_points_string()),
+        ("dbfilename", save.dbfilename.clone()),
+    ]
+}
+
+// Sets one parameter; `Err` holds the reason the value was rejected, or
+// `None` if the parameter is unknown.
+fn config_set(config: &mut Config, name: &str, value: &str) -> Result<(), Option<&'static str>> {
+    let invalid_integer = Some("argument couldn't be parsed into an integer");
+    let Config {
+        eviction: config,
+        auto_rewrite,
+        save,
+    } = config;
+    match name {
+        "maxmemory" => {
+            config.maxmemory =
+                evict::parse_memory(value).ok_or(Some("argument must be a memory value"))?
+        }
+        "maxmemory-policy" => {
+            config.policy = value.parse().map_err(|_| Some("argument(s) must be one of the following: noeviction, allkeys-lru, allkeys-lfu, allkeys-random, volatile-lru, volatile-lfu, volatile-random, volatile-ttl"))?
+        }
+        "maxmemory-samples" => {
+            config.samples = match value.parse() {
+                Ok(n @ 1..=64) => n,
+                Ok(_) => return Err(Some("argument must be between 1 and 64 inclusive")),
+                Err(_) => return Err(invalid_integer),
+            }
+        }
+        "lfu-log-factor" => config.lfu_log_factor = value.parse().map_err(|_| invalid_integer)?,
+        "lfu-decay-time" => config.lfu_decay_time = value.parse().map_err(|_| invalid_integer)?,
+        "auto-aof-rewrite-percentage" => {
+            auto_rewrite.percentage = value.parse().map_err(|_| invalid_integer)?
+        }
+        "auto-aof-rewrite-min-size" => {
+            auto_rewrite.min_size =
+                evict::parse_memory(value).ok_or(Some("argument must be a memory value"))? as u64
+        }
+        "save" => {
+            save.save_points =
+                SaveConfig::parse_save_points(value).ok_or(Some("Invalid save parameters"))?
+        }
+        "dbfilename" => {
+            if value.is_empty() || value.contains('/') {
+                return Err(Some("dbfilename can't be a path, just a filename"));
+            }
+            save.dbfilename = value.to_string()
+        }
+        _ => return Err(None),
     }
+    Ok(())
 }
 
-pub fn get_handler(command: &str) -> Option<HandlerFunc> {
-    match command {
-        "PING" => Some(ping_handler),
-        "SET" => Some(set_handler),
-        "GET" => Some(fn_get_handler),
-        "HSET" => Some(hset_handler),
-        "HGET" => Some(hget_handler),
-        "HGETALL" => Some(hgetall_handler),
-        "COMMAND" => Some(command_handler),
-        _ => None,
+/// `CONFIG GET parameter [parameter ...]` or
+/// `CONFIG SET parameter value [parameter value ...]`
+fn config_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let sub = option(&args, 0);
+    match sub.as_str() {
+        "GET" if args.len() > 1 => {
+            let params = config_params(&Config::current());
+            let patterns: Vec<Vec<u8>> = (1..args.len())
+                .map(|i| arg(&args, i).to_ascii_lowercase())
+                .collect();
+            Frame::map(
+                params
+                    .into_iter()
+                    .filter(|(name, _)| patterns.iter().any(|p| glob_match(p, name.as_bytes())))
+                    .map(|(name, value)| (Frame::bulk(name), Frame::bulk(value)))
+                    .collect(),
+            )
+        }
+        "SET" if args.len() > 1 && args.len() % 2 == 1 => {
+            let mut keyspace = KEYSPACE.write().unwrap();
+            // All or nothing: apply to a copy and only keep it if every
+            // parameter was accepted.
+            let mut config = Config {
+                eviction: keyspace.eviction_config().clone(),
+                auto_rewrite: aof::auto_rewrite(),
+                save: snapshot::save_config(),
+            };
+            for pair in args[1..].chunks(2) {
+                let name = String::from_utf8_lossy(pair[0].as_bulk().unwrap_or_default())
+                    .to_ascii_lowercase();
+                let value = String::from_utf8_lossy(pair[1].as_bulk().unwrap_or_default());
+                match config_set(&mut config, &name, &value) {
+                    Ok(()) => {}
+                    Err(None) => {
+                        return Frame::error(format!(
+                            "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                            name
+                        ))
+                    }
+                    Err(Some(reason)) => {
+                        return Frame::error(format!(
+                            "ERR CONFIG SET failed (possibly related to argument '{}') - {}",
+                            name, reason
+                        ))
+                    }
+                }
+            }
+            keyspace.set_eviction_config(config.eviction);
+            aof::set_auto_rewrite(config.auto_rewrite);
+            snapshot::set_save_config(config.save);
+            Frame::ok()
+        }
+        _ => Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try CONFIG HELP.",
+            sub
+        )),
     }
 }
+
+/// `COMMAND [COUNT | LIST | INFO [name ...] | DOCS [name ...] | GETKEYS cmd [arg ...]]`
+fn command_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let mut all: Vec<&Command> = COMMANDS.iter().collect();
+    all.sort_by_key(|c| c.name);
+
+    let Some(sub) = args.first() else {
+        return Frame::array(all.iter().map(|c| command_info(c)).collect());
+    };
+    let sub = String::from_utf8_lossy(sub.as_bulk().unwrap_or_default()).to_ascii_uppercase();
+    let names: Vec<String> = args[1..]
+        .iter()
+        .map(|a| String::from_utf8_lossy(a.as_bulk().unwrap_or_default()).into_owned())
+        .collect();
+
+    match sub.as_str() {
+        "COUNT" if names.is_empty() => Frame::integer(COMMANDS.len() as i64),
+        "LIST" if names.is_empty() => {
+            Frame::array(all.iter().map(|c| Frame::bulk(c.name)).collect())
+        }
+        "INFO" if names.is_empty() => Frame::array(all.iter().map(|c| command_info(c)).collect()),
+        "INFO" => Frame::array(
+            names
+                .iter()
+                .map(|n| COMMANDS.get(n).map_or(Frame::Null, command_info))
+                .collect(),
+        ),
+        "DOCS" => {
+            let selected: Vec<&Command> = if names.is_empty() {
+                all
+            } else {
+                names.iter().filter_map(|n| COMMANDS.get(n)).collect()
+            };
+            Frame::map(
+                selected
+                    .into_iter()
+                    .map(|c| (Frame::bulk(c.name), command_docs(c)))
+                    .collect(),
+            )
+        }
+        "GETKEYS" if !names.is_empty() => {
+            let argv = &args[1..];
+            let Some(command) = COMMANDS.get(&names[0]) else {
+                return Frame::error("ERR Invalid command specified");
+            };
+            if !command.arity_ok(argv.len()) {
+                return Frame::error("ERR Invalid number of arguments specified for command");
+            }
+            let keys = command.key_positions(argv);
+            if keys.is_empty() {
+                return Frame::error("ERR The command has no key arguments");
+            }
+            Frame::array(keys.into_iter().map(|i| argv[i].clone()).collect())
+        }
+        _ => Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try COMMAND HELP.",
+            sub
+        )),
+    }
+}
+
+// One entry of the `COMMAND` / `COMMAND INFO` reply, in the Redis 7 layout:
+// name, arity, flags, first key, last key, step, ACL categories, tips,
+// key specs and subcommands.
+fn command_info(command: &Command) -> Frame {
+    let flags = command.flags.names();
+    let mut categories = vec![format!("@{}", command.group)];
+    for flag in &flags {
+        categories.push(match *flag {
+            "readonly" => "@read".to_string(),
+            other => format!("@{}", other),
+        });
+    }
+    if !command.flags.contains(Flags::FAST) {
+        categories.push("@slow".to_string());
+    }
+
+    let mut key_specs = vec![];
+    if command.first_key > 0 {
+        // The range's last key is relative to the first one unless it counts
+        // back from the end of the arguments.
+        let last = if command.last_key < 0 {
+            command.last_key
+        } else {
+            command.last_key - command.first_key
+        };
+        let access = if command.is_write() { "RW" } else { "RO" };
+        key_specs.push(Frame::map(vec![
+            (
+                Frame::bulk("flags"),
+                Frame::Set(vec![Frame::simple(access)]),
+            ),
+            (
+                Frame::bulk("begin_search"),
+                Frame::map(vec![
+                    (Frame::bulk("type"), Frame::bulk("index")),
+                    (
+                        Frame::bulk("spec"),
+                        Frame::map(vec![(
+                            Frame::bulk("index"),
+                            Frame::integer(command.first_key as i64),
+                        )]),
+                    ),
+                ]),
+            ),
+            (
+                Frame::bulk("find_keys"),
+                Frame::map(vec![
+                    (Frame::bulk("type"), Frame::bulk("range")),
+                    (
+                        Frame::bulk("spec"),
+                        Frame::map(vec![
+                            (Frame::bulk("lastkey"), Frame::integer(last as i64)),
+                            (
+                                Frame::bulk("keystep"),
+                                Frame::integer(command.key_step as i64),
+                            ),
+                            (Frame::bulk("limit"), Frame::integer(0)),
+                        ]),
+                    ),
+                ]),
+            ),
+        ]));
+    }
+
+    Frame::array(vec![
+        Frame::bulk(command.name),
+        Frame::integer(command.arity as i64),
+        Frame::Set(flags.into_iter().map(Frame::simple).collect()),
+        Frame::integer(command.first_key as i64),
+        Frame::integer(command.last_key as i64),
+        Frame::integer(command.key_step as i64),
+        Frame::Set(categories.into_iter().map(Frame::simple).collect()),
+        Frame::Set(vec![]),
+        Frame::array(key_specs),
+        Frame::array(vec![]),
+    ])
+}
+
+// The documentation map for one command in `COMMAND DOCS`.
+fn command_docs(command: &Command) -> Frame {
+    Frame::map(vec![
+        (Frame::bulk("summary"), Frame::bulk(command.summary)),
+        (Frame::bulk("since"), Frame::bulk(command.since)),
+        (Frame::bulk("group"), Frame::bulk(command.group)),
+    ])
+}
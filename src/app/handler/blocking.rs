@@ -0,0 +1,416 @@
+//! Blocking list and sorted set commands.
+//!
+//! A client that finds every key empty registers itself as a waiter on
+//! each key and parks on a condition variable. Commands that write to a key
+//! with waiters serve them before returning, in the order they blocked, the
+//! way Redis hands pushed elements straight to blocked clients. The served
+//! pops are logged by the pushing client, after its own command, so replay
+//! never needs to block.
+
+use super::list::{list_move, list_pop, End};
+use super::zset::{zset_pop, Extreme};
+use super::{arg, Client, Command, CommandTable, Flags, KEYSPACE};
+use crate::app::keyspace::{Keyspace, WrongType};
+use crate::app::resp::Frame;
+use std::collections::{HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::net::TcpStream;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+/// How often a blocked client checks whether its connection has closed.
+const DISCONNECT_POLL: Duration = Duration::from_millis(100);
+
+lazy_static! {
+    static ref BLOCKED: Mutex<Blocked> = Mutex::new(Blocked::default());
+    static ref SERVED: Condvar = Condvar::new();
+}
+
+pub(super) fn register(table: &mut CommandTable) {
+    use Flags as F;
+    table.register(
+        Command::new("blpop", -3, F::WRITE | F::BLOCKING, blpop_handler)
+            .keys(1, -2, 1)
+            .docs(
+                "list",
+                "2.0.0",
+                "Removes and returns the first element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+            ),
+    );
+    table.register(
+        Command::new("brpop", -3, F::WRITE | F::BLOCKING, brpop_handler)
+            .keys(1, -2, 1)
+            .docs(
+                "list",
+                "2.0.0",
+                "Removes and returns the last element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+            ),
+    );
+    table.register(
+        Command::new(
+            "blmove",
+            6,
+            F::WRITE | F::DENYOOM | F::BLOCKING,
+            blmove_handler,
+        )
+        .keys(1, 2, 1)
+        .docs(
+            "list",
+            "6.2.0",
+            "Pops an element from a list, pushes it to another list and returns it. Blocks until an element is available otherwise. Deletes the list if the last element was moved.",
+        ),
+    );
+    table.register(
+        Command::new(
+            "brpoplpush",
+            4,
+            F::WRITE | F::DENYOOM | F::BLOCKING,
+            brpoplpush_handler,
+        )
+        .keys(1, 2, 1)
+        .docs(
+            "list",
+            "2.2.0",
+            "Pops an element from a list, pushes it to another list and returns it. Block until an element is available otherwise. Deletes the list if the last element was popped.",
+        ),
+    );
+    table.register(
+        Command::new("bzpopmin", -3, F::WRITE | F::FAST | F::BLOCKING, bzpopmin_handler)
+            .keys(1, -2, 1)
+            .docs(
+                "sorted-set",
+                "5.0.0",
+                "Removes and returns the member with the lowest score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.",
+            ),
+    );
+    table.register(
+        Command::new("bzpopmax", -3, F::WRITE | F::FAST | F::BLOCKING, bzpopmax_handler)
+            .keys(1, -2, 1)
+            .docs(
+                "sorted-set",
+                "5.0.0",
+                "Removes and returns the member with the highest score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.",
+            ),
+    );
+}
+
+// What a blocked client wants to do once one of its keys has data.
+#[derive(Clone, Debug)]
+enum Op {
+    Pop(End),
+    Move {
+        destination: Vec<u8>,
+        from: End,
+        to: End,
+    },
+    ZPop(Extreme),
+}
+
+impl Op {
+    // The non-blocking command with the same effect, as logged to the AOF.
+    fn propagated(&self, key: &[u8]) -> Vec<Frame> {
+        match self {
+            Op::Pop(End::Left) => vec![Frame::bulk("LPOP"), Frame::bulk(key)],
+            Op::Pop(End::Right) => vec![Frame::bulk("RPOP"), Frame::bulk(key)],
+            Op::Move {
+                destination,
+                from,
+                to,
+            } => vec![
+                Frame::bulk("LMOVE"),
+                Frame::bulk(key),
+                Frame::bulk(destination.clone()),
+                Frame::bulk(from.name()),
+                Frame::bulk(to.name()),
+            ],
+            Op::ZPop(extreme) => vec![Frame::bulk(extreme.pop_command()), Frame::bulk(key)],
+        }
+    }
+
+    // Whether `key` holds the type of value this operation takes from.
+    fn ready(&self, keyspace: &Keyspace, key: &[u8]) -> bool {
+        match self {
+            Op::Pop(_) | Op::Move { .. } => matches!(keyspace.get_list(key), Ok(Some(_))),
+            Op::ZPop(_) => matches!(keyspace.get_zset(key), Ok(Some(_))),
+        }
+    }
+
+    // Runs the operation against `key` and returns what it took, as reply
+    // frames; `None` if the key is missing.
+    fn run(&self, keyspace: &mut Keyspace, key: &[u8]) -> Result<Option<Vec<Frame>>, WrongType> {
+        Ok(match self {
+            Op::Pop(end) => list_pop(keyspace, key, *end)?.map(|v| vec![Frame::bulk(v)]),
+            Op::Move {
+                destination,
+                from,
+                to,
+            } => list_move(keyspace, key, destination, *from, *to)?.map(|v| vec![Frame::bulk(v)]),
+            Op::ZPop(extreme) => zset_pop(keyspace, key, *extreme)?
+                .map(|(member, score)| vec![Frame::bulk(member), Frame::double(score)]),
+        })
+    }
+}
+
+// A key and what was taken from it.
+type Popped = (Vec<u8>, Vec<Frame>);
+
+#[derive(Debug)]
+struct Waiter {
+    keys: Vec<Vec<u8>>,
+    op: Op,
+    // Set by the client that served this waiter.
+    served: Option<Result<Popped, WrongType>>,
+}
+
+// Waiters by client id, and per key the ids blocked on it in FIFO order.
+#[derive(Debug, Default)]
+struct Blocked {
+    waiters: HashMap<u64, Waiter>,
+    queues: HashMap<Vec<u8>, VecDeque<u64>>,
+}
+
+impl Blocked {
+    fn block(&mut self, id: u64, waiter: Waiter) {
+        for key in &waiter.keys {
+            self.queues.entry(key.clone()).or_default().push_back(id);
+        }
+        self.waiters.insert(id, waiter);
+    }
+
+    // Takes `id` off every key's queue; its entry in `waiters` stays until
+    // the client itself collects the result.
+    fn dequeue(&mut self, id: u64) {
+        let Some(waiter) = self.waiters.get(&id) else {
+            return;
+        };
+        for key in &waiter.keys {
+            if let Some(queue) = self.queues.get_mut(key) {
+                queue.retain(|&other| other != id);
+                if queue.is_empty() {
+                    self.queues.remove(key);
+                }
+            }
+        }
+    }
+}
+
+/// Whether any client is blocked, so callers can skip `serve_blocked`.
+pub(super) fn has_waiters() -> bool {
+    !BLOCKED.lock().unwrap().queues.is_empty()
+}
+
+/// Hands elements of the given keys, which were just written, to clients
+/// blocked on them, first come first served. A served move may in turn
+/// feed clients blocked on its destination. Returns the commands to log
+/// for what the blocked clients did.
+pub(super) fn serve_blocked(keyspace: &mut Keyspace, keys: &[Vec<u8>]) -> Vec<Vec<Frame>> {
+    let mut blocked = BLOCKED.lock().unwrap();
+    let mut ready: VecDeque<Vec<u8>> = keys.iter().cloned().collect();
+    let mut logged = Vec::new();
+    let mut woken = false;
+
+    while let Some(key) = ready.pop_front() {
+        // The first client in line that can take from the key's type.
+        while let Some(id) = blocked.queues.get(&key).and_then(|queue| {
+            queue
+                .iter()
+                .copied()
+                .find(|id| blocked.waiters[id].op.ready(keyspace, &key))
+        }) {
+            blocked.dequeue(id);
+            let waiter = blocked.waiters.get_mut(&id).expect("queued waiters exist");
+            let served = match waiter.op.run(keyspace, &key) {
+                Ok(Some(taken)) => {
+                    logged.push(waiter.op.propagated(&key));
+                    if let Op::Move { destination, .. } = &waiter.op {
+                        keyspace.refresh_size(destination);
+                        ready.push_back(destination.clone());
+                    }
+                    Ok((key.clone(), taken))
+                }
+                Ok(None) => unreachable!("checked that the key is ready"),
+                Err(e) => Err(e),
+            };
+            waiter.served = Some(served);
+            woken = true;
+        }
+    }
+    if woken {
+        SERVED.notify_all();
+    }
+    logged
+}
+
+// Whether the peer has closed the connection, judged without consuming any
+// pipelined commands it may have sent.
+fn disconnected(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let mut byte = [0u8; 1];
+    let gone = match stream.peek(&mut byte) {
+        Ok(0) => true,
+        Ok(_) => false,
+        Err(e) => e.kind() != ErrorKind::WouldBlock,
+    };
+    let _ = stream.set_nonblocking(false);
+    gone
+}
+
+// Parses a timeout in seconds, which may be fractional; `None` means wait
+// forever.
+fn parse_timeout(frame: &Frame) -> Result<Option<Instant>, Frame> {
+    let seconds = std::str::from_utf8(frame.as_bulk().unwrap_or_default())
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|s| s.is_finite())
+        .ok_or_else(|| Frame::error("ERR timeout is not a float or out of range"))?;
+    if seconds < 0.0 {
+        return Err(Frame::error("ERR timeout is negative"));
+    }
+    if seconds == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .and_then(|d| Instant::now().checked_add(d))
+        .map(Some)
+        .ok_or_else(|| Frame::error("ERR timeout is out of range"))
+}
+
+// Runs `op` on the first key holding a value, or blocks until another client
+// serves one, the deadline passes or the connection closes. Returns the key
+// and what was taken from it, or `None` on timeout.
+fn block_generic(
+    client: &mut Client,
+    keys: Vec<Vec<u8>>,
+    op: Op,
+    deadline: Option<Instant>,
+) -> Result<Option<Popped>, WrongType> {
+    let mut keyspace = KEYSPACE.write().unwrap();
+    for key in &keys {
+        if let Some(taken) = op.run(&mut keyspace, key)? {
+            client.propagate(op.propagated(key));
+            return Ok(Some((key.clone(), taken)));
+        }
+    }
+    // Nothing changed, and a served wait is logged by whoever served it.
+    client.propagate_nothing();
+    if client.deny_blocking || deadline.is_some_and(|d| Instant::now() >= d) {
+        return Ok(None);
+    }
+
+    // Register before letting go of the keyspace so no push can slip in
+    // between the check above and the wait.
+    let mut blocked = BLOCKED.lock().unwrap();
+    blocked.block(
+        client.id,
+        Waiter {
+            keys,
+            op,
+            served: None,
+        },
+    );
+    drop(keyspace);
+    // Whatever serves this wait logs it, so other writes may be logged
+    // while it lasts.
+    client.log_order = None;
+
+    loop {
+        if let Some(served) = blocked
+            .waiters
+            .get_mut(&client.id)
+            .and_then(|w| w.served.take())
+        {
+            blocked.waiters.remove(&client.id);
+            return served.map(Some);
+        }
+        let now = Instant::now();
+        let timed_out = deadline.is_some_and(|d| now >= d);
+        if timed_out || client.connection.as_ref().is_some_and(disconnected) {
+            blocked.dequeue(client.id);
+            blocked.waiters.remove(&client.id);
+            return Ok(None);
+        }
+        let wait = deadline.map_or(DISCONNECT_POLL, |d| (d - now).min(DISCONNECT_POLL));
+        blocked = SERVED.wait_timeout(blocked, wait).unwrap().0;
+    }
+}
+
+// Shared by BLPOP, BRPOP, BZPOPMIN and BZPOPMAX:
+// `<command> key [key ...] timeout`.
+fn bpop_generic(client: &mut Client, args: Vec<Frame>, op: Op) -> Frame {
+    let deadline = match parse_timeout(&args[args.len() - 1]) {
+        Ok(deadline) => deadline,
+        Err(e) => return e,
+    };
+    let keys = (0..args.len() - 1).map(|i| arg(&args, i)).collect();
+    match block_generic(client, keys, op, deadline) {
+        Ok(Some((key, taken))) => {
+            let mut reply = vec![Frame::bulk(key)];
+            reply.extend(taken);
+            Frame::array(reply)
+        }
+        Ok(None) => Frame::NullArray,
+        Err(e) => e.into(),
+    }
+}
+
+/// `BLPOP key [key ...] timeout`
+fn blpop_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    bpop_generic(client, args, Op::Pop(End::Left))
+}
+
+/// `BRPOP key [key ...] timeout`
+fn brpop_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    bpop_generic(client, args, Op::Pop(End::Right))
+}
+
+// Shared by BLMOVE and BRPOPLPUSH.
+fn bmove_generic(
+    client: &mut Client,
+    args: &[Frame],
+    from: End,
+    to: End,
+    timeout: &Frame,
+) -> Frame {
+    let deadline = match parse_timeout(timeout) {
+        Ok(deadline) => deadline,
+        Err(e) => return e,
+    };
+    let op = Op::Move {
+        destination: arg(args, 1),
+        from,
+        to,
+    };
+    match block_generic(client, vec![arg(args, 0)], op, deadline) {
+        Ok(Some((_, taken))) => taken.into_iter().next().expect("a move takes one element"),
+        Ok(None) => Frame::Null,
+        Err(e) => e.into(),
+    }
+}
+
+/// `BLMOVE source destination LEFT | RIGHT LEFT | RIGHT timeout`
+fn blmove_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    match (End::parse(&args, 2), End::parse(&args, 3)) {
+        (Ok(from), Ok(to)) => bmove_generic(client, &args, from, to, &args[4]),
+        (Err(e), _) | (_, Err(e)) => e,
+    }
+}
+
+/// `BRPOPLPUSH source destination timeout`
+fn brpoplpush_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    bmove_generic(client, &args, End::Right, End::Left, &args[2])
+}
+
+/// `BZPOPMIN key [key ...] timeout`
+fn bzpopmin_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    bpop_generic(client, args, Op::ZPop(Extreme::Min))
+}
+
+/// `BZPOPMAX key [key ...] timeout`
+fn bzpopmax_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    bpop_generic(client, args, Op::ZPop(Extreme::Max))
+}
@@ -0,0 +1,793 @@
+//! Hash commands.
+
+use super::{
+    arg, expire_deadline, option, parse_i64, syntax_error, wrong_arity, Client, Command,
+    CommandTable, Flags, ScanArgs, KEYSPACE,
+};
+use crate::app::keyspace::Hash;
+use crate::app::resp::{format_double, Frame, Protocol};
+use crate::app::util::{now_ms, parse_float, parse_integer};
+
+pub(super) fn register(table: &mut CommandTable) {
+    use Flags as F;
+    table.register(
+        Command::new("hset", -4, F::WRITE | F::DENYOOM | F::FAST, hset_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "2.0.0",
+                "Creates or modifies the value of a field in a hash.",
+            ),
+    );
+    table.register(
+        Command::new("hmset", -4, F::WRITE | F::DENYOOM | F::FAST, hmset_handler)
+            .keys(1, 1, 1)
+            .docs("hash", "2.0.0", "Sets the values of multiple fields."),
+    );
+    table.register(
+        Command::new("hsetnx", 4, F::WRITE | F::DENYOOM | F::FAST, hsetnx_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "2.0.0",
+                "Sets the value of a field in a hash only when the field doesn't exist.",
+            ),
+    );
+    table.register(
+        Command::new("hget", 3, F::READONLY | F::FAST, hget_handler)
+            .keys(1, 1, 1)
+            .docs("hash", "2.0.0", "Returns the value of a field in a hash."),
+    );
+    table.register(
+        Command::new("hmget", -3, F::READONLY | F::FAST, hmget_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "2.0.0",
+                "Returns the values of all fields in a hash.",
+            ),
+    );
+    table.register(
+        Command::new("hgetall", 2, F::READONLY, hgetall_handler)
+            .keys(1, 1, 1)
+            .docs("hash", "2.0.0", "Returns all fields and values in a hash."),
+    );
+    table.register(
+        Command::new("hdel", -3, F::WRITE | F::FAST, hdel_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "2.0.0",
+                "Deletes one or more fields and their values from a hash. Deletes the hash if no fields remain.",
+            ),
+    );
+    table.register(
+        Command::new("hexists", 3, F::READONLY | F::FAST, hexists_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "2.0.0",
+                "Determines whether a field exists in a hash.",
+            ),
+    );
+    table.register(
+        Command::new("hlen", 2, F::READONLY | F::FAST, hlen_handler)
+            .keys(1, 1, 1)
+            .docs("hash", "2.0.0", "Returns the number of fields in a hash."),
+    );
+    table.register(
+        Command::new("hkeys", 2, F::READONLY, hkeys_handler)
+            .keys(1, 1, 1)
+            .docs("hash", "2.0.0", "Returns all fields in a hash."),
+    );
+    table.register(
+        Command::new("hvals", 2, F::READONLY, hvals_handler)
+            .keys(1, 1, 1)
+            .docs("hash", "2.0.0", "Returns all values in a hash."),
+    );
+    table.register(
+        Command::new("hstrlen", 3, F::READONLY | F::FAST, hstrlen_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "3.2.0",
+                "Returns the length of the value of a field.",
+            ),
+    );
+    table.register(
+        Command::new("hincrby", 4, F::WRITE | F::DENYOOM | F::FAST, hincrby_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "2.0.0",
+                "Increments the integer value of a field in a hash by a number. Uses 0 as initial value if the field doesn't exist.",
+            ),
+    );
+    table.register(
+        Command::new(
+            "hincrbyfloat",
+            4,
+            F::WRITE | F::DENYOOM | F::FAST,
+            hincrbyfloat_handler,
+        )
+        .keys(1, 1, 1)
+        .docs(
+            "hash",
+            "2.6.0",
+            "Increments the floating point value of a field by a number. Uses 0 as initial value if the field doesn't exist.",
+        ),
+    );
+    table.register(
+        Command::new("hexpire", -6, F::WRITE | F::FAST, hexpire_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "7.4.0",
+                "Sets the expiration time of hash fields in seconds.",
+            ),
+    );
+    table.register(
+        Command::new("hpexpire", -6, F::WRITE | F::FAST, hpexpire_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "7.4.0",
+                "Sets the expiration time of hash fields in milliseconds.",
+            ),
+    );
+    table.register(
+        Command::new("hexpireat", -6, F::WRITE | F::FAST, hexpireat_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "7.4.0",
+                "Sets the expiration time of hash fields to a Unix timestamp in seconds.",
+            ),
+    );
+    table.register(
+        Command::new("hpexpireat", -6, F::WRITE | F::FAST, hpexpireat_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "7.4.0",
+                "Sets the expiration time of hash fields to a Unix timestamp in milliseconds.",
+            ),
+    );
+    table.register(
+        Command::new("httl", -5, F::READONLY | F::FAST, httl_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "7.4.0",
+                "Returns the remaining time to live of hash fields in seconds.",
+            ),
+    );
+    table.register(
+        Command::new("hpttl", -5, F::READONLY | F::FAST, hpttl_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "7.4.0",
+                "Returns the remaining time to live of hash fields in milliseconds.",
+            ),
+    );
+    table.register(
+        Command::new("hpersist", -5, F::WRITE | F::FAST, hpersist_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "7.4.0",
+                "Removes the expiration time of hash fields.",
+            ),
+    );
+    table.register(
+        Command::new("hrandfield", -2, F::READONLY, hrandfield_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "6.2.0",
+                "Returns one or more random fields from a hash.",
+            ),
+    );
+    table.register(
+        Command::new("hscan", -3, F::READONLY, hscan_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "hash",
+                "2.8.0",
+                "Iterates over fields and values of a hash.",
+            ),
+    );
+}
+
+// Parses `FIELDS numfields field [field ...]`, which must be the last
+// arguments of the HEXPIRE family, starting at `args[i]`.
+fn parse_fields(args: &[Frame], i: usize) -> Result<Vec<Vec<u8>>, Frame> {
+    if i + 1 >= args.len() || option(args, i) != "FIELDS" {
+        return Err(Frame::error(
+            "ERR Mandatory argument FIELDS is missing or not at the right position",
+        ));
+    }
+    let n = match parse_i64(&args[i + 1]) {
+        Ok(n) if n > 0 => n as usize,
+        _ => {
+            return Err(Frame::error(
+                "ERR Parameter `numFields` should be greater than 0",
+            ))
+        }
+    };
+    if n != args.len() - i - 2 {
+        return Err(Frame::error(
+            "ERR The `numfields` parameter must match the number of arguments",
+        ));
+    }
+    Ok((i + 2..args.len()).map(|j| arg(args, j)).collect())
+}
+
+// `HPEXPIREAT key at FIELDS n field...`, as the HEXPIRE family is logged.
+fn hpexpireat_command(key: Vec<u8>, at: u64, fields: Vec<Vec<u8>>) -> Vec<Frame> {
+    let mut argv = vec![
+        Frame::bulk("HPEXPIREAT"),
+        Frame::bulk(key),
+        Frame::bulk(at.to_string()),
+        Frame::bulk("FIELDS"),
+        Frame::bulk(fields.len().to_string()),
+    ];
+    argv.extend(fields.into_iter().map(Frame::bulk));
+    argv
+}
+
+// Field/value pairs as RESP3 nests them, or flattened for RESP2.
+fn pairs_reply<'a>(
+    client: &Client,
+    pairs: impl Iterator<Item = (&'a Vec<u8>, &'a Vec<u8>)>,
+) -> Frame {
+    if client.protocol == Protocol::Resp3 {
+        Frame::array(
+            pairs
+                .map(|(f, v)| Frame::array(vec![Frame::bulk(f.clone()), Frame::bulk(v.clone())]))
+                .collect(),
+        )
+    } else {
+        Frame::array(
+            pairs
+                .flat_map(|(f, v)| [Frame::bulk(f.clone()), Frame::bulk(v.clone())])
+                .collect(),
+        )
+    }
+}
+
+// Shared by HSET and HMSET: stores every field/value pair after the key and
+// returns how many fields are new.
+fn hset_generic(args: &[Frame]) -> Result<usize, Frame> {
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let hash = keyspace.hash_or_insert(&arg(args, 0))?;
+    Ok((1..args.len())
+        .step_by(2)
+        .filter(|&i| hash.insert(arg(args, i), arg(args, i + 1)).is_none())
+        .count())
+}
+
+/// `HSET key field value [field value ...]`
+fn hset_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    if args.len() % 2 == 0 {
+        return wrong_arity("hset");
+    }
+    match hset_generic(&args) {
+        Ok(added) => Frame::integer(added as i64),
+        Err(e) => e,
+    }
+}
+
+/// `HMSET key field value [field value ...]`
+fn hmset_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    if args.len() % 2 == 0 {
+        return wrong_arity("hmset");
+    }
+    match hset_generic(&args) {
+        Ok(_) => Frame::ok(),
+        Err(e) => e,
+    }
+}
+
+/// `HSETNX key field value`
+fn hsetnx_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let field = arg(&args, 1);
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let hash = match keyspace.hash_or_insert(&arg(&args, 0)) {
+        Ok(hash) => hash,
+        Err(e) => return e.into(),
+    };
+    if hash.contains_key(&field) {
+        client.propagate_nothing();
+        return Frame::integer(0);
+    }
+    hash.insert(field, arg(&args, 2));
+    Frame::integer(1)
+}
+
+/// `HGET key field`
+fn hget_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_hash(&arg(&args, 0)) {
+        Ok(hash) => match hash.and_then(|h| h.get(&arg(&args, 1))) {
+            Some(value) => Frame::bulk(value.clone()),
+            None => Frame::Null,
+        },
+        Err(e) => e.into(),
+    }
+}
+
+/// `HMGET key field [field ...]`
+fn hmget_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    let hash = match keyspace.get_hash(&arg(&args, 0)) {
+        Ok(hash) => hash,
+        Err(e) => return e.into(),
+    };
+    Frame::array(
+        (1..args.len())
+            .map(|i| match hash.and_then(|h| h.get(&arg(&args, i))) {
+                Some(value) => Frame::bulk(value.clone()),
+                None => Frame::Null,
+            })
+            .collect(),
+    )
+}
+
+/// `HGETALL key`
+///
+/// Replies with a map; RESP2 clients see it as a flat field/value array.
+/// If the hash does not exist, the map is empty.
+fn hgetall_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_hash(&arg(&args, 0)) {
+        Ok(hash) => Frame::map(
+            hash.into_iter()
+                .flat_map(|h| h.iter())
+                .map(|(field, value)| (Frame::bulk(field.clone()), Frame::bulk(value.clone())))
+                .collect(),
+        ),
+        Err(e) => e.into(),
+    }
+}
+
+/// `HDEL key field [field ...]`
+fn hdel_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let hash = match keyspace.get_hash_mut(&key) {
+        Ok(Some(hash)) => hash,
+        Ok(None) => {
+            client.propagate_nothing();
+            return Frame::integer(0);
+        }
+        Err(e) => return e.into(),
+    };
+    let removed = (1..args.len())
+        .filter(|&i| hash.remove(&arg(&args, i)).is_some())
+        .count();
+    keyspace.remove_if_empty(&key);
+    if removed == 0 {
+        client.propagate_nothing();
+    }
+    Frame::integer(removed as i64)
+}
+
+/// `HEXISTS key field`
+fn hexists_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_hash(&arg(&args, 0)) {
+        Ok(hash) => Frame::integer(hash.is_some_and(|h| h.contains_key(&arg(&args, 1))) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+/// `HLEN key`
+fn hlen_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_hash(&arg(&args, 0)) {
+        Ok(hash) => Frame::integer(hash.map_or(0, |h| h.len()) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+/// `HKEYS key`
+fn hkeys_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_hash(&arg(&args, 0)) {
+        Ok(hash) => Frame::array(
+            hash.into_iter()
+                .flat_map(|h| h.keys())
+                .map(|field| Frame::bulk(field.clone()))
+                .collect(),
+        ),
+        Err(e) => e.into(),
+    }
+}
+
+/// `HVALS key`
+fn hvals_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_hash(&arg(&args, 0)) {
+        Ok(hash) => Frame::array(
+            hash.into_iter()
+                .flat_map(|h| h.iter())
+                .map(|(_, value)| Frame::bulk(value.clone()))
+                .collect(),
+        ),
+        Err(e) => e.into(),
+    }
+}
+
+/// `HSTRLEN key field`
+fn hstrlen_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_hash(&arg(&args, 0)) {
+        Ok(hash) => Frame::integer(
+            hash.and_then(|h| h.get(&arg(&args, 1)))
+                .map_or(0, |v| v.len()) as i64,
+        ),
+        Err(e) => e.into(),
+    }
+}
+
+/// `HINCRBY key field increment`
+fn hincrby_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let by = match parse_i64(&args[2]) {
+        Ok(by) => by,
+        Err(e) => return e,
+    };
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let hash = match keyspace.hash_or_insert(&arg(&args, 0)) {
+        Ok(hash) => hash,
+        Err(e) => return e.into(),
+    };
+    let field = arg(&args, 1);
+    let current = match hash.get(&field) {
+        None => 0,
+        Some(value) => match parse_integer(value) {
+            Some(current) => current,
+            None => return Frame::error("ERR hash value is not an integer"),
+        },
+    };
+    let Some(next) = current.checked_add(by) else {
+        return Frame::error("ERR increment or decrement would overflow");
+    };
+    set_keep_ttl(hash, field, next.to_string().into_bytes());
+    Frame::integer(next)
+}
+
+/// `HINCRBYFLOAT key field increment`
+fn hincrbyfloat_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let (key, field) = (arg(&args, 0), arg(&args, 1));
+    let Some(by) = parse_float(&arg(&args, 2)) else {
+        return Frame::error("ERR value is not a valid float");
+    };
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let hash = match keyspace.hash_or_insert(&key) {
+        Ok(hash) => hash,
+        Err(e) => return e.into(),
+    };
+    let current = match hash.get(&field) {
+        None => 0.0,
+        Some(value) => match parse_float(value) {
+            Some(current) => current,
+            None => return Frame::error("ERR hash value is not a float"),
+        },
+    };
+    let next = current + by;
+    if !next.is_finite() {
+        return Frame::error("ERR increment would produce NaN or Infinity");
+    }
+    let next = format_double(next);
+    set_keep_ttl(hash, field.clone(), next.clone().into_bytes());
+
+    // Replay must not depend on float rounding, so log the result. HSET
+    // clears the field's expiry, so that is logged again after it.
+    let expire_at = hash.expire_at(&field).flatten();
+    client.propagate(vec![
+        Frame::bulk("HSET"),
+        Frame::bulk(key.clone()),
+        Frame::bulk(field.clone()),
+        Frame::bulk(next.clone()),
+    ]);
+    if let Some(at) = expire_at {
+        client.propagate(hpexpireat_command(key, at, vec![field]));
+    }
+    Frame::bulk(next)
+}
+
+// Updates a field in place, unlike `Hash::insert` keeping its expiry.
+fn set_keep_ttl(hash: &mut Hash, field: Vec<u8>, value: Vec<u8>) {
+    match hash.get_mut(&field) {
+        Some(current) => *current = value,
+        None => {
+            hash.insert(field, value);
+        }
+    }
+}
+
+/// `HRANDFIELD key [count [WITHVALUES]]`
+///
+/// A positive count returns that many distinct fields at most; a negative
+/// one returns exactly that many, possibly repeating fields.
+fn hrandfield_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let count = match args.get(1).map(parse_i64) {
+        None => None,
+        // Redis's guard against replies too large to build.
+        Some(Ok(n)) if n < -(i64::MAX / 2) => return Frame::error("ERR value is out of range"),
+        Some(Ok(n)) => Some(n),
+        Some(Err(e)) => return e,
+    };
+    let withvalues = match args.len() {
+        1 | 2 => false,
+        3 if option(&args, 2) == "WITHVALUES" => true,
+        _ => return syntax_error(),
+    };
+
+    let keyspace = KEYSPACE.read().unwrap();
+    let hash = match keyspace.get_hash(&arg(&args, 0)) {
+        Ok(Some(hash)) => hash,
+        Ok(None) if count.is_some() => return Frame::array(vec![]),
+        Ok(None) => return Frame::Null,
+        Err(e) => return e.into(),
+    };
+    let Some(count) = count else {
+        let (field, _) = random_field(hash);
+        return Frame::bulk(field.clone());
+    };
+    let picked: Vec<(&Vec<u8>, &Vec<u8>)> = if count < 0 {
+        (0..count.unsigned_abs())
+            .map(|_| random_field(hash))
+            .collect()
+    } else {
+        hash.random_fields(count as usize)
+    };
+    if withvalues {
+        pairs_reply(client, picked.into_iter())
+    } else {
+        Frame::array(
+            picked
+                .into_iter()
+                .map(|(field, _)| Frame::bulk(field.clone()))
+                .collect(),
+        )
+    }
+}
+
+// Hashes in the keyspace are never empty.
+fn random_field(hash: &Hash) -> (&Vec<u8>, &Vec<u8>) {
+    hash.random_field().expect("hashes are never empty")
+}
+
+/// `HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]`
+///
+/// A field present for the whole iteration is returned at least once; one
+/// added or removed meanwhile may or may not be.
+fn hscan_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let scan = match ScanArgs::parse(&args, 1, "hscan") {
+        Ok(scan) => scan,
+        Err(e) => return e,
+    };
+
+    let keyspace = KEYSPACE.read().unwrap();
+    let hash = match keyspace.get_hash(&arg(&args, 0)) {
+        Ok(hash) => hash,
+        Err(e) => return e.into(),
+    };
+    let mut found = Vec::new();
+    let next = hash.map_or(0, |h| {
+        h.scan(scan.cursor, scan.count, |field, value| {
+            if scan.matches(field) {
+                found.push((field, value));
+            }
+        })
+    });
+    let items = if scan.novalues {
+        Frame::array(
+            found
+                .into_iter()
+                .map(|(field, _)| Frame::bulk(field.clone()))
+                .collect(),
+        )
+    } else {
+        // Always flat, even in RESP3, as in Redis.
+        Frame::array(
+            found
+                .into_iter()
+                .flat_map(|(f, v)| [Frame::bulk(f.clone()), Frame::bulk(v.clone())])
+                .collect(),
+        )
+    };
+    Frame::array(vec![Frame::bulk(next.to_string()), items])
+}
+
+// Shared by HEXPIRE, HPEXPIRE, HEXPIREAT and HPEXPIREAT:
+// `<command> key time [NX | XX | GT | LT] FIELDS numfields field [field ...]`.
+//
+// Replies per field: -2 if there is no such field, 0 if the condition was
+// not met, 1 if the expiry was set and 2 if the deadline had already passed
+// and the field was deleted.
+fn hexpire_generic(
+    client: &mut Client,
+    args: Vec<Frame>,
+    unit_ms: i64,
+    absolute: bool,
+    command: &str,
+) -> Frame {
+    let key = arg(&args, 0);
+    // At most one condition, right before FIELDS.
+    let condition = if args.len() > 2 {
+        option(&args, 2)
+    } else {
+        String::new()
+    };
+    let (nx, xx, gt, lt) = (
+        condition == "NX",
+        condition == "XX",
+        condition == "GT",
+        condition == "LT",
+    );
+    let fields = match parse_fields(&args, if nx || xx || gt || lt { 3 } else { 2 }) {
+        Ok(fields) => fields,
+        Err(e) => return e,
+    };
+    match parse_i64(&args[1]) {
+        Ok(n) if n < 0 => return Frame::error("ERR invalid expire time, must be >= 0"),
+        Ok(_) => {}
+        Err(e) => return e,
+    }
+    let at = match expire_deadline(&args[1], unit_ms, absolute, command) {
+        Ok(at) => at as u64,
+        Err(e) => return e,
+    };
+
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let hash = match keyspace.get_hash_mut(&key) {
+        Ok(Some(hash)) => hash,
+        Ok(None) => {
+            client.propagate_nothing();
+            return Frame::array(fields.iter().map(|_| Frame::integer(-2)).collect());
+        }
+        Err(e) => return e.into(),
+    };
+    let now = now_ms();
+    let (mut updated, mut deleted) = (Vec::new(), Vec::new());
+    let replies = fields
+        .into_iter()
+        .map(|field| {
+            let Some(current) = hash.expire_at(&field) else {
+                return Frame::integer(-2);
+            };
+            // A field without a TTL counts as expiring never.
+            let allowed = (!nx || current.is_none())
+                && (!xx || current.is_some())
+                && (!gt || current.is_some_and(|c| at > c))
+                && (!lt || current.map_or(true, |c| at < c));
+            if !allowed {
+                Frame::integer(0)
+            } else if at <= now {
+                hash.remove(&field);
+                deleted.push(field);
+                Frame::integer(2)
+            } else {
+                hash.set_expire_at(&field, at);
+                updated.push(field);
+                Frame::integer(1)
+            }
+        })
+        .collect();
+    if !updated.is_empty() {
+        keyspace.track_field_expiry(&key);
+    }
+    keyspace.remove_if_empty(&key);
+
+    // Logged with the absolute deadline, so replay expires fields on time.
+    client.propagate_nothing();
+    if !updated.is_empty() {
+        client.propagate(hpexpireat_command(key.clone(), at, updated));
+    }
+    if !deleted.is_empty() {
+        let mut argv = vec![Frame::bulk("HDEL"), Frame::bulk(key)];
+        argv.extend(deleted.into_iter().map(Frame::bulk));
+        client.propagate(argv);
+    }
+    Frame::array(replies)
+}
+
+/// `HEXPIRE key seconds [NX | XX | GT | LT] FIELDS numfields field [field ...]`
+fn hexpire_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    hexpire_generic(client, args, 1000, false, "hexpire")
+}
+
+/// `HPEXPIRE key milliseconds [NX | XX | GT | LT] FIELDS numfields field [field ...]`
+fn hpexpire_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    hexpire_generic(client, args, 1, false, "hpexpire")
+}
+
+/// `HEXPIREAT key unix-time-seconds [NX | XX | GT | LT] FIELDS numfields field [field ...]`
+fn hexpireat_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    hexpire_generic(client, args, 1000, true, "hexpireat")
+}
+
+/// `HPEXPIREAT key unix-time-milliseconds [NX | XX | GT | LT] FIELDS numfields field [field ...]`
+fn hpexpireat_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    hexpire_generic(client, args, 1, true, "hpexpireat")
+}
+
+// Shared by HTTL and HPTTL: `<command> key FIELDS numfields field [field ...]`.
+// Replies per field with -2 if there is no such field, -1 if it has no
+// expiry, and otherwise what `report` makes of its deadline and the time.
+fn httl_generic(args: &[Frame], report: fn(u64, u64) -> i64) -> Frame {
+    let fields = match parse_fields(args, 1) {
+        Ok(fields) => fields,
+        Err(e) => return e,
+    };
+    let keyspace = KEYSPACE.read().unwrap();
+    let hash = match keyspace.get_hash(&arg(args, 0)) {
+        Ok(hash) => hash,
+        Err(e) => return e.into(),
+    };
+    let now = now_ms();
+    Frame::array(
+        fields
+            .iter()
+            .map(|field| match hash.and_then(|h| h.expire_at(field)) {
+                None => Frame::integer(-2),
+                Some(None) => Frame::integer(-1),
+                Some(Some(at)) => Frame::integer(report(at, now)),
+            })
+            .collect(),
+    )
+}
+
+/// `HTTL key FIELDS numfields field [field ...]`
+fn httl_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    httl_generic(&args, |at, now| {
+        (at.saturating_sub(now) as i64 + 500) / 1000
+    })
+}
+
+/// `HPTTL key FIELDS numfields field [field ...]`
+fn hpttl_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    httl_generic(&args, |at, now| at.saturating_sub(now) as i64)
+}
+
+/// `HPERSIST key FIELDS numfields field [field ...]`
+///
+/// Replies per field with -2 if there is no such field, -1 if it had no
+/// expiry and 1 if its expiry was removed.
+fn hpersist_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let fields = match parse_fields(&args, 1) {
+        Ok(fields) => fields,
+        Err(e) => return e,
+    };
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let hash = match keyspace.get_hash_mut(&arg(&args, 0)) {
+        Ok(Some(hash)) => hash,
+        Ok(None) => {
+            client.propagate_nothing();
+            return Frame::array(fields.iter().map(|_| Frame::integer(-2)).collect());
+        }
+        Err(e) => return e.into(),
+    };
+    let mut persisted = false;
+    let replies = fields
+        .iter()
+        .map(|field| {
+            if hash.persist(field) {
+                persisted = true;
+                Frame::integer(1)
+            } else if hash.contains_key(field) {
+                Frame::integer(-1)
+            } else {
+                Frame::integer(-2)
+            }
+        })
+        .collect();
+    if !persisted {
+        client.propagate_nothing();
+    }
+    Frame::array(replies)
+}
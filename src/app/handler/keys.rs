@@ -0,0 +1,351 @@
+//! Generic keyspace commands, which work on keys of any type.
+
+use super::{
+    arg, option, parse_i64, syntax_error, Client, Command, CommandTable, Flags, ScanArgs, KEYSPACE,
+};
+use crate::app::keyspace::{Entry, Keyspace};
+use crate::app::resp::Frame;
+use crate::app::util::glob_match;
+use std::sync::mpsc::{self, Sender};
+
+use lazy_static::lazy_static;
+
+/// Values with more elements than this are freed by the background thread
+/// by `UNLINK` and `FLUSHALL ASYNC`, as in Redis.
+pub const LAZYFREE_THRESHOLD: usize = 64;
+
+lazy_static! {
+    // Feeds the thread that drops whatever is sent to it.
+    static ref LAZYFREE: Sender<Box<dyn Send>> = {
+        let (sender, receiver) = mpsc::channel::<Box<dyn Send>>();
+        std::thread::spawn(move || receiver.into_iter().for_each(drop));
+        sender
+    };
+}
+
+pub(super) fn register(table: &mut CommandTable) {
+    use Flags as F;
+    table.register(
+        Command::new("del", -2, F::WRITE, del_handler)
+            .keys(1, -1, 1)
+            .docs("generic", "1.0.0", "Deletes one or more keys."),
+    );
+    table.register(
+        Command::new("unlink", -2, F::WRITE | F::FAST, unlink_handler)
+            .keys(1, -1, 1)
+            .docs(
+                "generic",
+                "4.0.0",
+                "Asynchronously deletes one or more keys.",
+            ),
+    );
+    table.register(
+        Command::new("exists", -2, F::READONLY | F::FAST, exists_handler)
+            .keys(1, -1, 1)
+            .docs(
+                "generic",
+                "1.0.0",
+                "Determines whether one or more keys exist.",
+            ),
+    );
+    table.register(
+        Command::new("touch", -2, F::READONLY | F::FAST, touch_handler)
+            .keys(1, -1, 1)
+            .docs(
+                "generic",
+                "3.2.1",
+                "Returns the number of existing keys out of those specified after updating the time they were last accessed.",
+            ),
+    );
+    table.register(Command::new("keys", 2, F::READONLY, keys_handler).docs(
+        "generic",
+        "1.0.0",
+        "Returns all key names that match a pattern.",
+    ));
+    table.register(Command::new("scan", -2, F::READONLY, scan_handler).docs(
+        "generic",
+        "2.8.0",
+        "Iterates over the key names in the database.",
+    ));
+    table.register(
+        Command::new("randomkey", 1, F::READONLY, randomkey_handler).docs(
+            "generic",
+            "1.0.0",
+            "Returns a random key name from the database.",
+        ),
+    );
+    table.register(
+        Command::new("rename", 3, F::WRITE, rename_handler)
+            .keys(1, 2, 1)
+            .docs(
+                "generic",
+                "1.0.0",
+                "Renames a key and overwrites the destination.",
+            ),
+    );
+    table.register(
+        Command::new("renamenx", 3, F::WRITE | F::FAST, renamenx_handler)
+            .keys(1, 2, 1)
+            .docs(
+                "generic",
+                "1.0.0",
+                "Renames a key only when the target key name doesn't exist.",
+            ),
+    );
+    table.register(
+        Command::new("copy", -3, F::WRITE | F::DENYOOM, copy_handler)
+            .keys(1, 2, 1)
+            .docs(
+                "generic",
+                "6.2.0",
+                "Copies the value of a key to a new key.",
+            ),
+    );
+    table.register(
+        Command::new("dbsize", 1, F::READONLY | F::FAST, dbsize_handler).docs(
+            "server",
+            "1.0.0",
+            "Returns the number of keys in the database.",
+        ),
+    );
+    table.register(
+        Command::new("flushdb", -1, F::WRITE, flushall_handler).docs(
+            "server",
+            "1.0.0",
+            "Removes all keys from the current database.",
+        ),
+    );
+    table.register(
+        Command::new("flushall", -1, F::WRITE, flushall_handler).docs(
+            "server",
+            "1.0.0",
+            "Removes all keys from all databases.",
+        ),
+    );
+}
+
+// Roughly how many allocations freeing a value takes, like Redis's
+// `lazyfreeGetFreeEffort`.
+fn free_effort(entry: &Entry) -> usize {
+    match entry {
+        Entry::String(_) => 1,
+        Entry::Hash(h) => h.len(),
+        Entry::List(l) => l.len(),
+        Entry::Set(s) => s.len(),
+        Entry::ZSet(z) => z.len(),
+    }
+}
+
+// Drops `value` on the background thread.
+fn free_async(value: impl Send + 'static) {
+    // Should the thread be gone, dropping here is the best there is.
+    let _ = LAZYFREE.send(Box::new(value));
+}
+
+// Shared by DEL and UNLINK; `lazy` hands large values to the background
+// thread instead of freeing them while holding the keyspace lock.
+fn del_generic(client: &mut Client, args: Vec<Frame>, lazy: bool) -> Frame {
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let mut deleted = 0;
+    for i in 0..args.len() {
+        let key = arg(&args, i);
+        // An expired key is not there to delete.
+        if keyspace.expire_if_needed(&key) {
+            continue;
+        }
+        let Some(entry) = keyspace.remove(&key) else {
+            continue;
+        };
+        deleted += 1;
+        if lazy && free_effort(&entry) > LAZYFREE_THRESHOLD {
+            free_async(entry);
+        }
+    }
+    if deleted == 0 {
+        client.propagate_nothing();
+    }
+    Frame::integer(deleted)
+}
+
+/// `DEL key [key ...]`
+fn del_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    del_generic(client, args, false)
+}
+
+/// `UNLINK key [key ...]`
+fn unlink_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    del_generic(client, args, true)
+}
+
+/// `EXISTS key [key ...]`
+///
+/// A key named more than once is counted as many times.
+fn exists_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    let found = (0..args.len())
+        .filter(|&i| keyspace.contains_key(&arg(&args, i)))
+        .count();
+    Frame::integer(found as i64)
+}
+
+/// `TOUCH key [key ...]`
+fn touch_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    // Looking a key up is what records the access.
+    let found = (0..args.len())
+        .filter(|&i| keyspace.get(&arg(&args, i)).is_some())
+        .count();
+    Frame::integer(found as i64)
+}
+
+/// `KEYS pattern`
+fn keys_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let pattern = arg(&args, 0);
+    let keyspace = KEYSPACE.read().unwrap();
+    Frame::array(
+        keyspace
+            .iter()
+            .filter(|(key, _)| glob_match(&pattern, key))
+            .map(|(key, _)| Frame::bulk(key.clone()))
+            .collect(),
+    )
+}
+
+/// `SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]`
+///
+/// A key present for the whole iteration is returned at least once; one
+/// added or removed meanwhile may or may not be.
+fn scan_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let scan = match ScanArgs::parse(&args, 0, "scan") {
+        Ok(scan) => scan,
+        Err(e) => return e,
+    };
+    let keyspace = KEYSPACE.read().unwrap();
+    let mut found = Vec::new();
+    let next = keyspace.scan(scan.cursor, scan.count, |key, entry| {
+        let type_ok = scan
+            .type_name
+            .as_ref()
+            .map_or(true, |t| t == entry.type_name());
+        if type_ok && scan.matches(key) {
+            found.push(Frame::bulk(key.clone()));
+        }
+    });
+    Frame::array(vec![Frame::bulk(next.to_string()), Frame::array(found)])
+}
+
+/// `RANDOMKEY`
+fn randomkey_handler(_client: &mut Client, _args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.random_key() {
+        Some(key) => Frame::bulk(key.clone()),
+        None => Frame::Null,
+    }
+}
+
+// Shared by RENAME and RENAMENX: moves the value and expiry of `source` to
+// `destination`, unless `nx` and the destination exists. Returns whether
+// it moved, or an error if there is no source.
+fn rename_generic(client: &mut Client, args: &[Frame], nx: bool) -> Result<bool, Frame> {
+    let (source, destination) = (arg(args, 0), arg(args, 1));
+    let mut keyspace = KEYSPACE.write().unwrap();
+    if !keyspace.contains_key(&source) {
+        return Err(Frame::error("ERR no such key"));
+    }
+    if source == destination || (nx && keyspace.contains_key(&destination)) {
+        client.propagate_nothing();
+        return Ok(!nx);
+    }
+    let expire_at = keyspace.expire_at(&source);
+    let entry = keyspace.remove(&source).expect("the source exists");
+    keyspace.insert(destination.clone(), entry);
+    if let Some(at) = expire_at {
+        keyspace.set_expire_at(&destination, at);
+    }
+    Ok(true)
+}
+
+/// `RENAME key newkey`
+fn rename_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    match rename_generic(client, &args, false) {
+        Ok(_) => Frame::ok(),
+        Err(e) => e,
+    }
+}
+
+/// `RENAMENX key newkey`
+fn renamenx_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    match rename_generic(client, &args, true) {
+        Ok(renamed) => Frame::integer(renamed as i64),
+        Err(e) => e,
+    }
+}
+
+/// `COPY source destination [DB destination-db] [REPLACE]`
+///
+/// There is only database 0 to copy to.
+fn copy_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let (source, destination) = (arg(&args, 0), arg(&args, 1));
+    let mut replace = false;
+    let mut i = 2;
+    while i < args.len() {
+        match option(&args, i).as_str() {
+            "REPLACE" => replace = true,
+            "DB" if i + 1 < args.len() => {
+                match parse_i64(&args[i + 1]) {
+                    Ok(0) => {}
+                    Ok(_) => return Frame::error("ERR DB index is out of range"),
+                    Err(e) => return e,
+                }
+                i += 1;
+            }
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+    if source == destination {
+        return Frame::error("ERR source and destination objects are the same");
+    }
+
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let Some(entry) = keyspace.get(&source).cloned() else {
+        client.propagate_nothing();
+        return Frame::integer(0);
+    };
+    if !replace && keyspace.contains_key(&destination) {
+        client.propagate_nothing();
+        return Frame::integer(0);
+    }
+    let expire_at = keyspace.expire_at(&source);
+    keyspace.insert(destination.clone(), entry);
+    if let Some(at) = expire_at {
+        keyspace.set_expire_at(&destination, at);
+    }
+    Frame::integer(1)
+}
+
+/// `DBSIZE`
+///
+/// Like Redis, counts expired keys that have not been reclaimed yet.
+fn dbsize_handler(_client: &mut Client, _args: Vec<Frame>) -> Frame {
+    Frame::integer(KEYSPACE.read().unwrap().len() as i64)
+}
+
+/// `FLUSHALL [ASYNC | SYNC]` and `FLUSHDB [ASYNC | SYNC]`, the same thing
+/// with a single database.
+fn flushall_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let lazy = match args.len() {
+        0 => false,
+        1 if option(&args, 0) == "ASYNC" => true,
+        1 if option(&args, 0) == "SYNC" => false,
+        _ => return syntax_error(),
+    };
+    let old: Keyspace = KEYSPACE.write().unwrap().flush();
+    // Freed after the lock is released either way.
+    if lazy {
+        free_async(old);
+    } else {
+        drop(old);
+    }
+    Frame::ok()
+}
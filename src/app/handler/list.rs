@@ -0,0 +1,598 @@
+//! List commands.
+
+use super::{arg, option, parse_i64, syntax_error, Client, Command, CommandTable, Flags, KEYSPACE};
+use crate::app::keyspace::{Keyspace, List, WrongType};
+use crate::app::resp::Frame;
+
+pub(super) fn register(table: &mut CommandTable) {
+    use Flags as F;
+    table.register(
+        Command::new("lpush", -3, F::WRITE | F::DENYOOM | F::FAST, lpush_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "list",
+                "1.0.0",
+                "Prepends one or more elements to a list. Creates the key if it doesn't exist.",
+            ),
+    );
+    table.register(
+        Command::new("rpush", -3, F::WRITE | F::DENYOOM | F::FAST, rpush_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "list",
+                "1.0.0",
+                "Appends one or more elements to a list. Creates the key if it doesn't exist.",
+            ),
+    );
+    table.register(
+        Command::new(
+            "lpushx",
+            -3,
+            F::WRITE | F::DENYOOM | F::FAST,
+            lpushx_handler,
+        )
+        .keys(1, 1, 1)
+        .docs(
+            "list",
+            "2.2.0",
+            "Prepends one or more elements to a list only when the list exists.",
+        ),
+    );
+    table.register(
+        Command::new(
+            "rpushx",
+            -3,
+            F::WRITE | F::DENYOOM | F::FAST,
+            rpushx_handler,
+        )
+        .keys(1, 1, 1)
+        .docs(
+            "list",
+            "2.2.0",
+            "Appends an element to a list only when the list exists.",
+        ),
+    );
+    table.register(
+        Command::new("lpop", -2, F::WRITE | F::FAST, lpop_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "list",
+                "1.0.0",
+                "Returns the first elements in a list after removing it. Deletes the list if the last element was popped.",
+            ),
+    );
+    table.register(
+        Command::new("rpop", -2, F::WRITE | F::FAST, rpop_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "list",
+                "1.0.0",
+                "Returns and removes the last elements of a list. Deletes the list if the last element was popped.",
+            ),
+    );
+    table.register(
+        Command::new("llen", 2, F::READONLY | F::FAST, llen_handler)
+            .keys(1, 1, 1)
+            .docs("list", "1.0.0", "Returns the length of a list."),
+    );
+    table.register(
+        Command::new("lrange", 4, F::READONLY, lrange_handler)
+            .keys(1, 1, 1)
+            .docs("list", "1.0.0", "Returns a range of elements from a list."),
+    );
+    table.register(
+        Command::new("lindex", 3, F::READONLY, lindex_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "list",
+                "1.0.0",
+                "Returns an element from a list by its index.",
+            ),
+    );
+    table.register(
+        Command::new("lset", 4, F::WRITE | F::DENYOOM, lset_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "list",
+                "1.0.0",
+                "Sets the value of an element in a list by its index.",
+            ),
+    );
+    table.register(
+        Command::new("lrem", 4, F::WRITE, lrem_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "list",
+                "1.0.0",
+                "Removes elements from a list. Deletes the list if the last element was removed.",
+            ),
+    );
+    table.register(
+        Command::new("ltrim", 4, F::WRITE, ltrim_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "list",
+                "1.0.0",
+                "Removes elements from both ends a list. Deletes the list if all elements were trimmed.",
+            ),
+    );
+    table.register(
+        Command::new("lpos", -3, F::READONLY, lpos_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "list",
+                "6.0.6",
+                "Returns the index of matching elements in a list.",
+            ),
+    );
+    table.register(
+        Command::new("linsert", 5, F::WRITE | F::DENYOOM, linsert_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "list",
+                "2.2.0",
+                "Inserts an element before or after another element in a list.",
+            ),
+    );
+    table.register(
+        Command::new("lmove", 5, F::WRITE | F::DENYOOM, lmove_handler)
+            .keys(1, 2, 1)
+            .docs(
+                "list",
+                "6.2.0",
+                "Returns an element after popping it from one list and pushing it to another. Deletes the list if the last element was moved.",
+            ),
+    );
+    table.register(
+        Command::new("rpoplpush", 3, F::WRITE | F::DENYOOM, rpoplpush_handler)
+            .keys(1, 2, 1)
+            .docs(
+                "list",
+                "1.2.0",
+                "Returns the last element of a list after removing and pushing it to another list. Deletes the list if the last element was popped.",
+            ),
+    );
+}
+
+/// Which end of a list an operation works on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum End {
+    Left,
+    Right,
+}
+
+impl End {
+    pub(super) fn parse(args: &[Frame], i: usize) -> Result<End, Frame> {
+        match option(args, i).as_str() {
+            "LEFT" => Ok(End::Left),
+            "RIGHT" => Ok(End::Right),
+            _ => Err(syntax_error()),
+        }
+    }
+
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            End::Left => "LEFT",
+            End::Right => "RIGHT",
+        }
+    }
+}
+
+fn pop(list: &mut List, end: End) -> Option<Vec<u8>> {
+    match end {
+        End::Left => list.pop_front(),
+        End::Right => list.pop_back(),
+    }
+}
+
+fn push(list: &mut List, end: End, value: Vec<u8>) {
+    match end {
+        End::Left => list.push_front(value),
+        End::Right => list.push_back(value),
+    }
+}
+
+// Turns an inclusive `start..=stop` pair, where negative indexes count from
+// the end, into a range of positions; `None` if it selects nothing.
+pub(super) fn list_range(len: usize, start: i64, stop: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+// Moves one element from `source` to `destination`. Both types are checked
+// before anything changes, so a WRONGTYPE error leaves the source untouched.
+pub(super) fn list_move(
+    keyspace: &mut Keyspace,
+    source: &[u8],
+    destination: &[u8],
+    from: End,
+    to: End,
+) -> Result<Option<Vec<u8>>, WrongType> {
+    if keyspace.get_list(source)?.is_none() {
+        return Ok(None);
+    }
+    keyspace.get_list(destination)?;
+    let list = keyspace.get_list_mut(source)?.expect("checked above");
+    let value = pop(list, from).expect("lists are never empty");
+    keyspace.remove_if_empty(source);
+    push(keyspace.list_or_insert(destination)?, to, value.clone());
+    Ok(Some(value))
+}
+
+// Pops one element from the list at `key`, deleting the key if that
+// emptied it.
+pub(super) fn list_pop(
+    keyspace: &mut Keyspace,
+    key: &[u8],
+    end: End,
+) -> Result<Option<Vec<u8>>, WrongType> {
+    let Some(list) = keyspace.get_list_mut(key)? else {
+        return Ok(None);
+    };
+    let value = pop(list, end);
+    keyspace.remove_if_empty(key);
+    Ok(value)
+}
+
+// Shared by LPUSH, RPUSH, LPUSHX and RPUSHX: `<command> key element [element ...]`.
+fn push_generic(args: Vec<Frame>, end: End, only_existing: bool) -> Frame {
+    let key = arg(&args, 0);
+    let mut keyspace = KEYSPACE.write().unwrap();
+    if only_existing {
+        match keyspace.get_list(&key) {
+            Ok(Some(_)) => {}
+            Ok(None) => return Frame::integer(0),
+            Err(e) => return e.into(),
+        }
+    }
+    let list = match keyspace.list_or_insert(&key) {
+        Ok(list) => list,
+        Err(e) => return e.into(),
+    };
+    for i in 1..args.len() {
+        push(list, end, arg(&args, i));
+    }
+    Frame::integer(list.len() as i64)
+}
+
+/// `LPUSH key element [element ...]`
+fn lpush_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    push_generic(args, End::Left, false)
+}
+
+/// `RPUSH key element [element ...]`
+fn rpush_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    push_generic(args, End::Right, false)
+}
+
+/// `LPUSHX key element [element ...]`
+fn lpushx_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    push_generic(args, End::Left, true)
+}
+
+/// `RPUSHX key element [element ...]`
+fn rpushx_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    push_generic(args, End::Right, true)
+}
+
+// Shared by LPOP and RPOP: `<command> key [count]`. Without a count the
+// reply is a single element, with one it is an array.
+fn pop_generic(client: &mut Client, args: Vec<Frame>, end: End) -> Frame {
+    let key = arg(&args, 0);
+    let count = match args.get(1).map(parse_i64) {
+        None => None,
+        Some(Ok(n)) if n >= 0 => Some(n as usize),
+        Some(Ok(_)) => return Frame::error("ERR value is out of range, must be positive"),
+        Some(Err(e)) => return e,
+    };
+    if args.len() > 2 {
+        return syntax_error();
+    }
+
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let list = match keyspace.get_list_mut(&key) {
+        Ok(Some(list)) => list,
+        Ok(None) => {
+            client.propagate_nothing();
+            return if count.is_some() {
+                Frame::NullArray
+            } else {
+                Frame::Null
+            };
+        }
+        Err(e) => return e.into(),
+    };
+    let reply = match count {
+        None => Frame::bulk(pop(list, end).expect("lists are never empty")),
+        Some(count) => Frame::array(
+            std::iter::from_fn(|| pop(list, end))
+                .take(count)
+                .map(Frame::bulk)
+                .collect(),
+        ),
+    };
+    keyspace.remove_if_empty(&key);
+    reply
+}
+
+/// `LPOP key [count]`
+fn lpop_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    pop_generic(client, args, End::Left)
+}
+
+/// `RPOP key [count]`
+fn rpop_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    pop_generic(client, args, End::Right)
+}
+
+/// `LLEN key`
+fn llen_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_list(&arg(&args, 0)) {
+        Ok(list) => Frame::integer(list.map_or(0, |l| l.len()) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+/// `LRANGE key start stop`
+fn lrange_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let (start, stop) = match (parse_i64(&args[1]), parse_i64(&args[2])) {
+        (Ok(start), Ok(stop)) => (start, stop),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+    let keyspace = KEYSPACE.read().unwrap();
+    let list = match keyspace.get_list(&arg(&args, 0)) {
+        Ok(Some(list)) => list,
+        Ok(None) => return Frame::array(vec![]),
+        Err(e) => return e.into(),
+    };
+    let Some((start, stop)) = list_range(list.len(), start, stop) else {
+        return Frame::array(vec![]);
+    };
+    Frame::array(list.range(start..=stop).cloned().map(Frame::bulk).collect())
+}
+
+// Resolves a possibly negative index into a position in the list.
+fn list_index(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+/// `LINDEX key index`
+fn lindex_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let index = match parse_i64(&args[1]) {
+        Ok(index) => index,
+        Err(e) => return e,
+    };
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_list(&arg(&args, 0)) {
+        Ok(Some(list)) => {
+            list_index(list.len(), index).map_or(Frame::Null, |i| Frame::bulk(list[i].clone()))
+        }
+        Ok(None) => Frame::Null,
+        Err(e) => e.into(),
+    }
+}
+
+/// `LSET key index element`
+fn lset_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let index = match parse_i64(&args[1]) {
+        Ok(index) => index,
+        Err(e) => return e,
+    };
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let list = match keyspace.get_list_mut(&arg(&args, 0)) {
+        Ok(Some(list)) => list,
+        Ok(None) => return Frame::error("ERR no such key"),
+        Err(e) => return e.into(),
+    };
+    match list_index(list.len(), index) {
+        Some(i) => {
+            list[i] = arg(&args, 2);
+            Frame::ok()
+        }
+        None => Frame::error("ERR index out of range"),
+    }
+}
+
+/// `LREM key count element`
+fn lrem_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let element = arg(&args, 2);
+    let count = match parse_i64(&args[1]) {
+        Ok(count) => count,
+        Err(e) => return e,
+    };
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let list = match keyspace.get_list_mut(&key) {
+        Ok(Some(list)) => list,
+        Ok(None) => {
+            client.propagate_nothing();
+            return Frame::integer(0);
+        }
+        Err(e) => return e.into(),
+    };
+
+    // A positive count removes from the head, a negative one from the tail
+    // and zero removes every match.
+    let limit = if count == 0 {
+        usize::MAX
+    } else {
+        count.unsigned_abs() as usize
+    };
+    let mut removed = 0;
+    if count >= 0 {
+        list.retain(|v| {
+            let hit = removed < limit && *v == element;
+            removed += hit as usize;
+            !hit
+        });
+    } else {
+        let mut i = list.len();
+        while i > 0 && removed < limit {
+            i -= 1;
+            if list[i] == element {
+                list.remove(i);
+                removed += 1;
+            }
+        }
+    }
+    keyspace.remove_if_empty(&key);
+    if removed == 0 {
+        client.propagate_nothing();
+    }
+    Frame::integer(removed as i64)
+}
+
+/// `LTRIM key start stop`
+fn ltrim_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let (start, stop) = match (parse_i64(&args[1]), parse_i64(&args[2])) {
+        (Ok(start), Ok(stop)) => (start, stop),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let list = match keyspace.get_list_mut(&key) {
+        Ok(Some(list)) => list,
+        Ok(None) => return Frame::ok(),
+        Err(e) => return e.into(),
+    };
+    match list_range(list.len(), start, stop) {
+        Some((start, stop)) => {
+            list.truncate(stop + 1);
+            list.drain(..start);
+        }
+        None => list.clear(),
+    }
+    keyspace.remove_if_empty(&key);
+    Frame::ok()
+}
+
+/// `LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]`
+fn lpos_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let element = arg(&args, 1);
+    let (mut rank, mut count, mut maxlen) = (1i64, None, 0usize);
+    let mut i = 2;
+    while i < args.len() {
+        // An unknown option is a syntax error whatever follows it.
+        let opt = option(&args, i);
+        if !matches!(opt.as_str(), "RANK" | "COUNT" | "MAXLEN") || i + 1 == args.len() {
+            return syntax_error();
+        }
+        let value = match parse_i64(&args[i + 1]) {
+            Ok(value) => value,
+            Err(e) => return e,
+        };
+        match opt.as_str() {
+            "RANK" if value == 0 => {
+                return Frame::error("ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list");
+            }
+            "RANK" => rank = value,
+            "COUNT" if value < 0 => return Frame::error("ERR COUNT can't be negative"),
+            "COUNT" => count = Some(value as usize),
+            "MAXLEN" if value < 0 => return Frame::error("ERR MAXLEN can't be negative"),
+            "MAXLEN" => maxlen = value as usize,
+            _ => unreachable!("checked above"),
+        }
+        i += 2;
+    }
+
+    let keyspace = KEYSPACE.read().unwrap();
+    let list = match keyspace.get_list(&arg(&args, 0)) {
+        Ok(list) => list,
+        Err(e) => return e.into(),
+    };
+    let len = list.map_or(0, |l| l.len());
+    let scan = if maxlen == 0 { len } else { maxlen.min(len) };
+    let positions: Box<dyn Iterator<Item = usize>> = if rank > 0 {
+        Box::new(0..scan)
+    } else {
+        Box::new((len - scan..len).rev())
+    };
+
+    // Skip the first |rank| - 1 matches, then collect up to `count`
+    // (0 meaning all of them).
+    let wanted = match count {
+        Some(0) => usize::MAX,
+        Some(n) => n,
+        None => 1,
+    };
+    let matches: Vec<Frame> = positions
+        .filter(|&i| list.is_some_and(|l| l[i] == element))
+        .skip(rank.unsigned_abs() as usize - 1)
+        .take(wanted)
+        .map(|i| Frame::integer(i as i64))
+        .collect();
+    match count {
+        Some(_) => Frame::array(matches),
+        None => matches.into_iter().next().unwrap_or(Frame::Null),
+    }
+}
+
+/// `LINSERT key BEFORE | AFTER pivot element`
+fn linsert_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let after = match option(&args, 1).as_str() {
+        "BEFORE" => false,
+        "AFTER" => true,
+        _ => return syntax_error(),
+    };
+    let pivot = arg(&args, 2);
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let list = match keyspace.get_list_mut(&arg(&args, 0)) {
+        Ok(Some(list)) => list,
+        Ok(None) => {
+            client.propagate_nothing();
+            return Frame::integer(0);
+        }
+        Err(e) => return e.into(),
+    };
+    match list.iter().position(|v| *v == pivot) {
+        Some(i) => {
+            list.insert(i + after as usize, arg(&args, 3));
+            Frame::integer(list.len() as i64)
+        }
+        None => {
+            client.propagate_nothing();
+            Frame::integer(-1)
+        }
+    }
+}
+
+// Shared by LMOVE and RPOPLPUSH.
+fn move_generic(client: &mut Client, args: &[Frame], from: End, to: End) -> Frame {
+    let mut keyspace = KEYSPACE.write().unwrap();
+    match list_move(&mut keyspace, &arg(args, 0), &arg(args, 1), from, to) {
+        Ok(Some(value)) => Frame::bulk(value),
+        Ok(None) => {
+            client.propagate_nothing();
+            Frame::Null
+        }
+        Err(e) => e.into(),
+    }
+}
+
+/// `LMOVE source destination LEFT | RIGHT LEFT | RIGHT`
+fn lmove_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    match (End::parse(&args, 2), End::parse(&args, 3)) {
+        (Ok(from), Ok(to)) => move_generic(client, &args, from, to),
+        (Err(e), _) | (_, Err(e)) => e,
+    }
+}
+
+/// `RPOPLPUSH source destination`
+fn rpoplpush_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    move_generic(client, &args, End::Right, End::Left)
+}
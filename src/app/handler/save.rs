@@ -0,0 +1,178 @@
+//! Snapshot commands, the `save` points that take snapshots on their own,
+//! and loading a snapshot at startup.
+
+use super::{dataset, option, syntax_error, Client, Command, CommandTable, Flags, DIRTY, KEYSPACE};
+use crate::app::resp::Frame;
+use crate::app::snapshot;
+use crate::app::util::now_ms;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Seconds to wait after a failed background save before a `save` point
+/// may try again, as in Redis.
+pub const SAVE_RETRY_DELAY: u64 = 5;
+
+// Whether a snapshot is being written, by SAVE or BGSAVE.
+static SAVING: AtomicBool = AtomicBool::new(false);
+// Unix time of the last successful save, in seconds.
+static LASTSAVE: AtomicU64 = AtomicU64::new(0);
+// `DIRTY` as of the last successful save.
+static DIRTY_AT_LASTSAVE: AtomicU64 = AtomicU64::new(0);
+// Unix time of the last failed save, in seconds.
+static LAST_FAILED: AtomicU64 = AtomicU64::new(0);
+
+pub(super) fn register(table: &mut CommandTable) {
+    use Flags as F;
+    table.register(Command::new("save", 1, F::ADMIN, save_handler).docs(
+        "server",
+        "1.0.0",
+        "Synchronously saves the database(s) to disk.",
+    ));
+    table.register(Command::new("bgsave", -1, F::ADMIN, bgsave_handler).docs(
+        "server",
+        "1.0.0",
+        "Asynchronously saves the database(s) to disk.",
+    ));
+    table.register(Command::new("lastsave", 1, F::FAST, lastsave_handler).docs(
+        "server",
+        "1.0.0",
+        "Returns the Unix timestamp of the last successful save to disk.",
+    ));
+}
+
+fn now_secs() -> u64 {
+    now_ms() / 1000
+}
+
+fn snapshot_path() -> PathBuf {
+    PathBuf::from(snapshot::save_config().dbfilename)
+}
+
+// Writes the copy of the dataset taken when `DIRTY` was `dirty`, and
+// records how it went. `SAVING` must be held.
+fn save_dataset(path: &Path, dataset: &snapshot::SharedDataset, dirty: u64) -> io::Result<()> {
+    let result = snapshot::save(path, dataset);
+    match &result {
+        Ok(()) => {
+            LASTSAVE.store(now_secs(), Ordering::Relaxed);
+            DIRTY_AT_LASTSAVE.store(dirty, Ordering::Relaxed);
+        }
+        Err(e) => {
+            eprintln!("Error saving snapshot: {}", e);
+            LAST_FAILED.store(now_secs(), Ordering::Relaxed);
+        }
+    }
+    SAVING.store(false, Ordering::Release);
+    result
+}
+
+fn begin_save() -> Result<(), Frame> {
+    SAVING
+        .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .map(|_| ())
+        .map_err(|_| Frame::error("ERR Background save already in progress"))
+}
+
+/// Saves a snapshot on the calling thread.
+pub(super) fn save_now() -> Result<(), Frame> {
+    begin_save()?;
+    let dirty = DIRTY.load(Ordering::Relaxed);
+    save_dataset(&snapshot_path(), &dataset(), dirty)
+        .map_err(|e| Frame::error(format!("ERR {}", e)))
+}
+
+/// Saves a snapshot on a thread of its own, from a copy of the dataset
+/// taken now.
+pub(super) fn start_bgsave() -> Result<(), Frame> {
+    begin_save()?;
+    let dirty = DIRTY.load(Ordering::Relaxed);
+    let (path, dataset) = (snapshot_path(), dataset());
+    std::thread::spawn(move || save_dataset(&path, &dataset, dirty));
+    Ok(())
+}
+
+/// Whether a snapshot is being written.
+pub fn save_in_progress() -> bool {
+    SAVING.load(Ordering::Acquire)
+}
+
+/// `SAVE`
+fn save_handler(_client: &mut Client, _args: Vec<Frame>) -> Frame {
+    match save_now() {
+        Ok(()) => Frame::ok(),
+        Err(e) => e,
+    }
+}
+
+/// `BGSAVE [SCHEDULE]`
+///
+/// A save never has to wait for anything here, so `SCHEDULE` just starts
+/// one.
+fn bgsave_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    match args.len() {
+        0 => {}
+        1 if option(&args, 0) == "SCHEDULE" => {}
+        _ => return syntax_error(),
+    }
+    match start_bgsave() {
+        Ok(()) => Frame::simple("Background saving started"),
+        Err(e) => e,
+    }
+}
+
+/// `LASTSAVE`
+fn lastsave_handler(_client: &mut Client, _args: Vec<Frame>) -> Frame {
+    Frame::integer(LASTSAVE.load(Ordering::Relaxed) as i64)
+}
+
+/// Loads the snapshot at `path` into the keyspace, returning how many keys
+/// it held. Counts as the last save.
+pub fn load_snapshot(path: &Path) -> io::Result<usize> {
+    let count = load_dataset(snapshot::load(path)?);
+    LASTSAVE.store(now_secs(), Ordering::Relaxed);
+    DIRTY_AT_LASTSAVE.store(DIRTY.load(Ordering::Relaxed), Ordering::Relaxed);
+    Ok(count)
+}
+
+/// Adds the keys of `dataset` to the keyspace with their expiries,
+/// returning how many there were.
+pub fn load_dataset(dataset: snapshot::Dataset) -> usize {
+    let count = dataset.len();
+    let mut keyspace = KEYSPACE.write().unwrap();
+    for (key, entry, expire_at) in dataset {
+        keyspace.insert(key.clone(), entry);
+        if let Some(at) = expire_at {
+            keyspace.set_expire_at(&key, at);
+        }
+    }
+    count
+}
+
+/// Starts the thread that checks the `save` points once a second and
+/// takes a background snapshot when one is reached.
+pub fn spawn_save_points() -> std::thread::JoinHandle<()> {
+    // Without a snapshot to load, the clock starts now, as in Redis.
+    let _ = LASTSAVE.compare_exchange(0, now_secs(), Ordering::Relaxed, Ordering::Relaxed);
+    std::thread::spawn(|| loop {
+        std::thread::sleep(Duration::from_secs(1));
+        let now = now_secs();
+        if save_in_progress()
+            || now.saturating_sub(LAST_FAILED.load(Ordering::Relaxed)) < SAVE_RETRY_DELAY
+        {
+            continue;
+        }
+        let changes = DIRTY
+            .load(Ordering::Relaxed)
+            .saturating_sub(DIRTY_AT_LASTSAVE.load(Ordering::Relaxed));
+        let elapsed = now.saturating_sub(LASTSAVE.load(Ordering::Relaxed));
+        let reached = snapshot::save_config()
+            .save_points
+            .iter()
+            .any(|&(seconds, needed)| changes >= needed && elapsed >= seconds);
+        if reached {
+            let _ = start_bgsave();
+        }
+    })
+}
@@ -0,0 +1,454 @@
+//! Set commands.
+
+use super::{arg, parse_i64, syntax_error, Client, Command, CommandTable, Flags, KEYSPACE};
+use crate::app::keyspace::{Entry, Keyspace, Set, WrongType};
+use crate::app::resp::Frame;
+use crate::app::util::{random_index, random_sample};
+
+pub(super) fn register(table: &mut CommandTable) {
+    use Flags as F;
+    table.register(
+        Command::new("sadd", -3, F::WRITE | F::DENYOOM | F::FAST, sadd_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "set",
+                "1.0.0",
+                "Adds one or more members to a set. Creates the key if it doesn't exist.",
+            ),
+    );
+    table.register(
+        Command::new("srem", -3, F::WRITE | F::FAST, srem_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "set",
+                "1.0.0",
+                "Removes one or more members from a set. Deletes the set if the last member was removed.",
+            ),
+    );
+    table.register(
+        Command::new("sismember", 3, F::READONLY | F::FAST, sismember_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "set",
+                "1.0.0",
+                "Determines whether a member belongs to a set.",
+            ),
+    );
+    table.register(
+        Command::new("smismember", -3, F::READONLY | F::FAST, smismember_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "set",
+                "6.2.0",
+                "Determines whether multiple members belong to a set.",
+            ),
+    );
+    table.register(
+        Command::new("scard", 2, F::READONLY | F::FAST, scard_handler)
+            .keys(1, 1, 1)
+            .docs("set", "1.0.0", "Returns the number of members in a set."),
+    );
+    table.register(
+        Command::new("smembers", 2, F::READONLY, smembers_handler)
+            .keys(1, 1, 1)
+            .docs("set", "1.0.0", "Returns all members of a set."),
+    );
+    table.register(
+        Command::new("spop", -2, F::WRITE | F::FAST, spop_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "set",
+                "1.0.0",
+                "Returns one or more random members from a set after removing them. Deletes the set if the last member was popped.",
+            ),
+    );
+    table.register(
+        Command::new("srandmember", -2, F::READONLY, srandmember_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "set",
+                "1.0.0",
+                "Returns one or more random members from a set.",
+            ),
+    );
+    table.register(
+        Command::new("smove", 4, F::WRITE | F::FAST, smove_handler)
+            .keys(1, 2, 1)
+            .docs("set", "1.0.0", "Moves a member from one set to another."),
+    );
+    table.register(
+        Command::new("sinter", -2, F::READONLY, sinter_handler)
+            .keys(1, -1, 1)
+            .docs("set", "1.0.0", "Returns the intersect of multiple sets."),
+    );
+    table.register(
+        Command::new(
+            "sinterstore",
+            -3,
+            F::WRITE | F::DENYOOM,
+            sinterstore_handler,
+        )
+        .keys(1, -1, 1)
+        .docs(
+            "set",
+            "1.0.0",
+            "Stores the intersect of multiple sets in a key.",
+        ),
+    );
+    table.register(
+        Command::new("sunion", -2, F::READONLY, sunion_handler)
+            .keys(1, -1, 1)
+            .docs("set", "1.0.0", "Returns the union of multiple sets."),
+    );
+    table.register(
+        Command::new(
+            "sunionstore",
+            -3,
+            F::WRITE | F::DENYOOM,
+            sunionstore_handler,
+        )
+        .keys(1, -1, 1)
+        .docs(
+            "set",
+            "1.0.0",
+            "Stores the union of multiple sets in a key.",
+        ),
+    );
+    table.register(
+        Command::new("sdiff", -2, F::READONLY, sdiff_handler)
+            .keys(1, -1, 1)
+            .docs("set", "1.0.0", "Returns the difference of multiple sets."),
+    );
+    table.register(
+        Command::new("sdiffstore", -3, F::WRITE | F::DENYOOM, sdiffstore_handler)
+            .keys(1, -1, 1)
+            .docs(
+                "set",
+                "1.0.0",
+                "Stores the difference of multiple sets in a key.",
+            ),
+    );
+}
+
+fn members_reply<'a>(members: impl Iterator<Item = &'a Vec<u8>>) -> Frame {
+    Frame::Set(members.cloned().map(Frame::bulk).collect())
+}
+
+fn random_member(set: &Set) -> &Vec<u8> {
+    let (member, _) = set
+        .get_index(random_index(set.len()))
+        .expect("index in range");
+    member
+}
+
+/// `SADD key member [member ...]`
+fn sadd_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let set = match keyspace.set_or_insert(&arg(&args, 0)) {
+        Ok(set) => set,
+        Err(e) => return e.into(),
+    };
+    let added = (1..args.len())
+        .filter(|&i| set.insert(arg(&args, i), ()).is_none())
+        .count();
+    if added == 0 {
+        client.propagate_nothing();
+    }
+    Frame::integer(added as i64)
+}
+
+/// `SREM key member [member ...]`
+fn srem_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let set = match keyspace.get_set_mut(&key) {
+        Ok(Some(set)) => set,
+        Ok(None) => {
+            client.propagate_nothing();
+            return Frame::integer(0);
+        }
+        Err(e) => return e.into(),
+    };
+    let removed = (1..args.len())
+        .filter(|&i| set.remove(&arg(&args, i)).is_some())
+        .count();
+    keyspace.remove_if_empty(&key);
+    if removed == 0 {
+        client.propagate_nothing();
+    }
+    Frame::integer(removed as i64)
+}
+
+/// `SISMEMBER key member`
+fn sismember_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_set(&arg(&args, 0)) {
+        Ok(set) => Frame::integer(set.is_some_and(|s| s.contains_key(&arg(&args, 1))) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+/// `SMISMEMBER key member [member ...]`
+fn smismember_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_set(&arg(&args, 0)) {
+        Ok(set) => Frame::array(
+            (1..args.len())
+                .map(|i| Frame::integer(set.is_some_and(|s| s.contains_key(&arg(&args, i))) as i64))
+                .collect(),
+        ),
+        Err(e) => e.into(),
+    }
+}
+
+/// `SCARD key`
+fn scard_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_set(&arg(&args, 0)) {
+        Ok(set) => Frame::integer(set.map_or(0, |s| s.len()) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+/// `SMEMBERS key`
+fn smembers_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_set(&arg(&args, 0)) {
+        Ok(set) => members_reply(set.into_iter().flat_map(|s| s.keys())),
+        Err(e) => e.into(),
+    }
+}
+
+/// `SPOP key [count]`
+///
+/// The members are picked at random, so what gets logged is the `SREM` of
+/// the members actually popped.
+fn spop_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let count = match args.get(1).map(parse_i64) {
+        None => None,
+        Some(Ok(n)) if n >= 0 => Some(n as usize),
+        Some(Ok(_)) => return Frame::error("ERR value is out of range, must be positive"),
+        Some(Err(e)) => return e,
+    };
+    if args.len() > 2 {
+        return syntax_error();
+    }
+
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let set = match keyspace.get_set_mut(&key) {
+        Ok(Some(set)) => set,
+        Ok(None) => {
+            client.propagate_nothing();
+            return if count.is_some() {
+                Frame::Set(vec![])
+            } else {
+                Frame::Null
+            };
+        }
+        Err(e) => return e.into(),
+    };
+    let popped: Vec<Vec<u8>> = (0..count.unwrap_or(1).min(set.len()))
+        .map(|_| {
+            let member = random_member(set).clone();
+            set.remove(&member);
+            member
+        })
+        .collect();
+    keyspace.remove_if_empty(&key);
+
+    if popped.is_empty() {
+        client.propagate_nothing();
+    } else {
+        let mut argv = vec![Frame::bulk("SREM"), Frame::bulk(key)];
+        argv.extend(popped.iter().cloned().map(Frame::bulk));
+        client.propagate(argv);
+    }
+    match count {
+        None => Frame::bulk(popped.into_iter().next().expect("sets are never empty")),
+        Some(_) => members_reply(popped.iter()),
+    }
+}
+
+/// `SRANDMEMBER key [count]`
+///
+/// A positive count returns that many distinct members at most; a negative
+/// one returns exactly that many, possibly repeating members.
+fn srandmember_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let count = match args.get(1).map(parse_i64) {
+        None => None,
+        // Redis's guard against replies too large to build.
+        Some(Ok(n)) if n < -(i64::MAX / 2) => return Frame::error("ERR value is out of range"),
+        Some(Ok(n)) => Some(n),
+        Some(Err(e)) => return e,
+    };
+    if args.len() > 2 {
+        return syntax_error();
+    }
+
+    let keyspace = KEYSPACE.read().unwrap();
+    let set = match keyspace.get_set(&arg(&args, 0)) {
+        Ok(Some(set)) => set,
+        Ok(None) if count.is_some() => return Frame::array(vec![]),
+        Ok(None) => return Frame::Null,
+        Err(e) => return e.into(),
+    };
+    let Some(count) = count else {
+        return Frame::bulk(random_member(set).clone());
+    };
+    if count < 0 {
+        return Frame::array(
+            (0..count.unsigned_abs())
+                .map(|_| Frame::bulk(random_member(set).clone()))
+                .collect(),
+        );
+    }
+
+    Frame::array(
+        random_sample(set.len(), count as usize)
+            .into_iter()
+            .map(|i| Frame::bulk(set.get_index(i).expect("index in range").0.clone()))
+            .collect(),
+    )
+}
+
+/// `SMOVE source destination member`
+fn smove_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let (source, destination, member) = (arg(&args, 0), arg(&args, 1), arg(&args, 2));
+    let mut keyspace = KEYSPACE.write().unwrap();
+    // Both types are checked before anything changes.
+    let present = match keyspace.get_set(&source) {
+        Ok(set) => set.is_some_and(|s| s.contains_key(&member)),
+        Err(e) => return e.into(),
+    };
+    if let Err(e) = keyspace.get_set(&destination) {
+        return e.into();
+    }
+    if !present || source == destination {
+        client.propagate_nothing();
+        return Frame::integer(present as i64);
+    }
+
+    let set = keyspace
+        .get_set_mut(&source)
+        .ok()
+        .flatten()
+        .expect("checked above");
+    set.remove(&member);
+    keyspace.remove_if_empty(&source);
+    match keyspace.set_or_insert(&destination) {
+        Ok(set) => {
+            set.insert(member, ());
+            Frame::integer(1)
+        }
+        Err(e) => e.into(),
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Algebra {
+    Inter,
+    Union,
+    Diff,
+}
+
+// Combines the sets at `keys`, where a missing key is an empty set. Every
+// key is type checked, even when the result is already known to be empty.
+fn set_algebra(keyspace: &Keyspace, keys: &[Vec<u8>], op: Algebra) -> Result<Set, WrongType> {
+    let sets = keys
+        .iter()
+        .map(|key| keyspace.get_set(key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut result = Set::new();
+    match op {
+        Algebra::Inter => {
+            if sets.iter().any(Option::is_none) {
+                return Ok(result);
+            }
+            // Walk the smallest set and probe the others.
+            let mut sets: Vec<&Set> = sets.into_iter().flatten().collect();
+            sets.sort_by_key(|s| s.len());
+            for member in sets[0].keys() {
+                if sets[1..].iter().all(|s| s.contains_key(member)) {
+                    result.insert(member.clone(), ());
+                }
+            }
+        }
+        Algebra::Union => {
+            for member in sets.iter().flatten().flat_map(|s| s.keys()) {
+                result.insert(member.clone(), ());
+            }
+        }
+        Algebra::Diff => {
+            let Some(first) = sets[0] else {
+                return Ok(result);
+            };
+            for member in first.keys() {
+                if !sets[1..].iter().flatten().any(|s| s.contains_key(member)) {
+                    result.insert(member.clone(), ());
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+// Shared by SINTER, SUNION and SDIFF: `<command> key [key ...]`.
+fn algebra_generic(args: &[Frame], op: Algebra) -> Frame {
+    let keys: Vec<Vec<u8>> = (0..args.len()).map(|i| arg(args, i)).collect();
+    let keyspace = KEYSPACE.read().unwrap();
+    match set_algebra(&keyspace, &keys, op) {
+        Ok(result) => members_reply(result.keys()),
+        Err(e) => e.into(),
+    }
+}
+
+// Shared by SINTERSTORE, SUNIONSTORE and SDIFFSTORE:
+// `<command> destination key [key ...]`. The destination is replaced, or
+// deleted if the result is empty.
+fn store_generic(args: &[Frame], op: Algebra) -> Frame {
+    let destination = arg(args, 0);
+    let keys: Vec<Vec<u8>> = (1..args.len()).map(|i| arg(args, i)).collect();
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let result = match set_algebra(&keyspace, &keys, op) {
+        Ok(result) => result,
+        Err(e) => return e.into(),
+    };
+    let len = result.len();
+    if result.is_empty() {
+        keyspace.remove(&destination);
+    } else {
+        keyspace.insert(destination, Entry::Set(result));
+    }
+    Frame::integer(len as i64)
+}
+
+/// `SINTER key [key ...]`
+fn sinter_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    algebra_generic(&args, Algebra::Inter)
+}
+
+/// `SINTERSTORE destination key [key ...]`
+fn sinterstore_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    store_generic(&args, Algebra::Inter)
+}
+
+/// `SUNION key [key ...]`
+fn sunion_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    algebra_generic(&args, Algebra::Union)
+}
+
+/// `SUNIONSTORE destination key [key ...]`
+fn sunionstore_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    store_generic(&args, Algebra::Union)
+}
+
+/// `SDIFF key [key ...]`
+fn sdiff_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    algebra_generic(&args, Algebra::Diff)
+}
+
+/// `SDIFFSTORE destination key [key ...]`
+fn sdiffstore_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    store_generic(&args, Algebra::Diff)
+}
@@ -0,0 +1,573 @@
+//! String commands.
+
+use super::{
+    arg, expire_deadline, invalid_expire, option, parse_i64, syntax_error, Client, Command,
+    CommandTable, Flags, KEYSPACE,
+};
+use crate::app::keyspace::{Entry, WrongType};
+use crate::app::resp::{format_double, Frame, MAX_BULK_LEN};
+use crate::app::util::{parse_float, parse_integer};
+use std::sync::Arc;
+
+pub(super) fn register(table: &mut CommandTable) {
+    use Flags as F;
+    table.register(
+        Command::new("get", 2, F::READONLY | F::FAST, fn_get_handler)
+            .keys(1, 1, 1)
+            .docs("string", "1.0.0", "Returns the string value of a key."),
+    );
+    table.register(
+        Command::new("set", -3, F::WRITE | F::DENYOOM, set_handler)
+            .keys(1, 1, 1)
+            .docs("string", "1.0.0", "Sets the string value of a key."),
+    );
+    table.register(
+        Command::new("setnx", 3, F::WRITE | F::DENYOOM | F::FAST, setnx_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "string",
+                "1.0.0",
+                "Set the string value of a key only when the key doesn't exist.",
+            ),
+    );
+    table.register(
+        Command::new("getset", 3, F::WRITE | F::DENYOOM | F::FAST, getset_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "string",
+                "1.0.0",
+                "Returns the previous string value of a key after setting it to a new value.",
+            ),
+    );
+    table.register(
+        Command::new("getdel", 2, F::WRITE | F::FAST, getdel_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "string",
+                "6.2.0",
+                "Returns the string value of a key after deleting the key.",
+            ),
+    );
+    table.register(
+        Command::new("getex", -2, F::WRITE | F::FAST, getex_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "string",
+                "6.2.0",
+                "Returns the string value of a key after setting its expiration time.",
+            ),
+    );
+    table.register(
+        Command::new("mget", -2, F::READONLY | F::FAST, mget_handler)
+            .keys(1, -1, 1)
+            .docs(
+                "string",
+                "1.0.0",
+                "Atomically returns the string values of one or more keys.",
+            ),
+    );
+    table.register(
+        Command::new("mset", -3, F::WRITE | F::DENYOOM, mset_handler)
+            .keys(1, -1, 2)
+            .docs(
+                "string",
+                "1.0.1",
+                "Atomically creates or modifies the string values of one or more keys.",
+            ),
+    );
+    table.register(
+        Command::new("msetnx", -3, F::WRITE | F::DENYOOM, msetnx_handler)
+            .keys(1, -1, 2)
+            .docs(
+                "string",
+                "1.0.1",
+                "Atomically modifies the string values of one or more keys only when all keys don't exist.",
+            ),
+    );
+    table.register(
+        Command::new("incr", 2, F::WRITE | F::DENYOOM | F::FAST, incr_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "string",
+                "1.0.0",
+                "Increments the integer value of a key by one.",
+            ),
+    );
+    table.register(
+        Command::new("decr", 2, F::WRITE | F::DENYOOM | F::FAST, decr_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "string",
+                "1.0.0",
+                "Decrements the integer value of a key by one.",
+            ),
+    );
+    table.register(
+        Command::new("incrby", 3, F::WRITE | F::DENYOOM | F::FAST, incrby_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "string",
+                "1.0.0",
+                "Increments the integer value of a key by a number.",
+            ),
+    );
+    table.register(
+        Command::new("decrby", 3, F::WRITE | F::DENYOOM | F::FAST, decrby_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "string",
+                "1.0.0",
+                "Decrements a number from the integer value of a key.",
+            ),
+    );
+    table.register(
+        Command::new(
+            "incrbyfloat",
+            3,
+            F::WRITE | F::DENYOOM | F::FAST,
+            incrbyfloat_handler,
+        )
+        .keys(1, 1, 1)
+        .docs(
+            "string",
+            "2.6.0",
+            "Increment the floating point value of a key by a number.",
+        ),
+    );
+    table.register(
+        Command::new("append", 3, F::WRITE | F::DENYOOM | F::FAST, append_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "string",
+                "2.0.0",
+                "Appends a string to the value of a key. Creates the key if it doesn't exist.",
+            ),
+    );
+    table.register(
+        Command::new("strlen", 2, F::READONLY | F::FAST, strlen_handler)
+            .keys(1, 1, 1)
+            .docs("string", "2.2.0", "Returns the length of a string value."),
+    );
+    table.register(
+        Command::new("getrange", 4, F::READONLY, getrange_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "string",
+                "2.4.0",
+                "Returns a substring of the string stored at a key.",
+            ),
+    );
+    table.register(
+        Command::new("setrange", 4, F::WRITE | F::DENYOOM, setrange_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "string",
+                "2.2.0",
+                "Overwrites a part of a string value with another by an offset.",
+            ),
+    );
+}
+
+fn string_too_long() -> Frame {
+    Frame::error("ERR string exceeds maximum allowed size (proto-max-bulk-len)")
+}
+
+// Parses the value after an `EX`, `PX`, `EXAT` or `PXAT` option into an
+// absolute deadline in Unix milliseconds. Like Redis, zero and negative
+// times are rejected rather than treated as already expired.
+fn parse_expire_option(opt: &str, value: &Frame, command: &str) -> Result<i64, Frame> {
+    if parse_i64(value).is_ok_and(|n| n <= 0) {
+        return Err(invalid_expire(command));
+    }
+    let unit_ms = if opt.starts_with('E') { 1000 } else { 1 };
+    expire_deadline(value, unit_ms, opt.ends_with("AT"), command)
+}
+
+fn fn_get_handler(_client: &mut Client, _args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_string(&arg(&_args, 0)) {
+        // If found, return the associated value.
+        Ok(Some(val)) => Frame::bulk(val.clone()),
+        // If not found, return a null value.
+        Ok(None) => Frame::Null,
+        Err(e) => e.into(),
+    }
+}
+
+/// `SET key value [NX | XX] [GET] [EX seconds | PX milliseconds |
+/// EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]`
+fn set_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let value = arg(&args, 1);
+
+    let (mut nx, mut xx, mut get, mut keep_ttl) = (false, false, false, false);
+    let mut deadline = None;
+    let mut i = 2;
+    while i < args.len() {
+        let opt = option(&args, i);
+        match opt.as_str() {
+            "NX" if !xx => nx = true,
+            "XX" if !nx => xx = true,
+            "GET" => get = true,
+            "KEEPTTL" if deadline.is_none() => keep_ttl = true,
+            "EX" | "PX" | "EXAT" | "PXAT"
+                if !keep_ttl && deadline.is_none() && i + 1 < args.len() =>
+            {
+                i += 1;
+                match parse_expire_option(&opt, &args[i], "set") {
+                    Ok(at) => deadline = Some(at),
+                    Err(e) => return e,
+                }
+            }
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let old = match keyspace.get(&key) {
+        Some(Entry::String(s)) => Some(s.clone()),
+        Some(_) if get => return WrongType.into(),
+        _ => None,
+    };
+    let exists = keyspace.contains_key(&key);
+    let old_reply = |old: Option<Vec<u8>>| old.map_or(Frame::Null, Frame::bulk);
+
+    if (nx && exists) || (xx && !exists) {
+        client.propagate_nothing();
+        return if get { old_reply(old) } else { Frame::Null };
+    }
+
+    // SET replaces the key whatever type it held before.
+    let mut logged = vec![
+        Frame::bulk("SET"),
+        Frame::bulk(key.clone()),
+        Frame::bulk(value.clone()),
+    ];
+    if keep_ttl {
+        keyspace.insert_keep_ttl(key.clone(), Entry::String(value));
+        logged.push(Frame::bulk("KEEPTTL"));
+    } else {
+        keyspace.insert(key.clone(), Entry::String(value));
+    }
+    if let Some(at) = deadline {
+        keyspace.set_expire_at(&key, at.max(0) as u64);
+        logged.push(Frame::bulk("PXAT"));
+        logged.push(Frame::bulk(at.max(1).to_string()));
+    }
+    client.propagate(logged);
+
+    if get {
+        old_reply(old)
+    } else {
+        Frame::ok()
+    }
+}
+
+/// `SETNX key value`
+fn setnx_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let mut keyspace = KEYSPACE.write().unwrap();
+    if keyspace.contains_key(&key) {
+        client.propagate_nothing();
+        return Frame::integer(0);
+    }
+    keyspace.insert(key, Entry::String(arg(&args, 1)));
+    Frame::integer(1)
+}
+
+/// `GETSET key value`
+fn getset_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let old = match keyspace.get_string(&key) {
+        Ok(old) => old.cloned(),
+        Err(e) => return e.into(),
+    };
+    keyspace.insert(key, Entry::String(arg(&args, 1)));
+    old.map_or(Frame::Null, Frame::bulk)
+}
+
+/// `GETDEL key`
+fn getdel_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let mut keyspace = KEYSPACE.write().unwrap();
+    match keyspace.get_string(&key) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            client.propagate_nothing();
+            return Frame::Null;
+        }
+        Err(e) => return e.into(),
+    }
+    client.propagate(vec![Frame::bulk("DEL"), Frame::bulk(key.clone())]);
+    match keyspace.remove(&key).map(Arc::unwrap_or_clone) {
+        Some(Entry::String(value)) => Frame::bulk(value),
+        _ => Frame::Null,
+    }
+}
+
+/// `GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | PERSIST]`
+fn getex_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let (mut deadline, mut persist) = (None, false);
+    let mut i = 1;
+    while i < args.len() {
+        let opt = option(&args, i);
+        match opt.as_str() {
+            "PERSIST" if deadline.is_none() && !persist => persist = true,
+            "EX" | "PX" | "EXAT" | "PXAT"
+                if deadline.is_none() && !persist && i + 1 < args.len() =>
+            {
+                i += 1;
+                match parse_expire_option(&opt, &args[i], "getex") {
+                    Ok(at) => deadline = Some(at),
+                    Err(e) => return e,
+                }
+            }
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let value = match keyspace.get_string(&key) {
+        Ok(Some(value)) => value.clone(),
+        Ok(None) => {
+            client.propagate_nothing();
+            return Frame::Null;
+        }
+        Err(e) => return e.into(),
+    };
+
+    if let Some(at) = deadline {
+        keyspace.set_expire_at(&key, at as u64);
+        client.propagate(vec![
+            Frame::bulk("PEXPIREAT"),
+            Frame::bulk(key),
+            Frame::bulk(at.to_string()),
+        ]);
+    } else if persist && keyspace.persist(&key) {
+        client.propagate(vec![Frame::bulk("PERSIST"), Frame::bulk(key)]);
+    } else {
+        client.propagate_nothing();
+    }
+    Frame::bulk(value)
+}
+
+/// `MGET key [key ...]`
+fn mget_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    Frame::array(
+        (0..args.len())
+            .map(|i| match keyspace.get(&arg(&args, i)) {
+                // Keys holding other types read as missing rather than failing.
+                Some(Entry::String(value)) => Frame::bulk(value.clone()),
+                _ => Frame::Null,
+            })
+            .collect(),
+    )
+}
+
+/// `MSET key value [key value ...]`
+fn mset_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    if args.len() % 2 != 0 {
+        return super::wrong_arity("mset");
+    }
+    let mut keyspace = KEYSPACE.write().unwrap();
+    for i in (0..args.len()).step_by(2) {
+        keyspace.insert(arg(&args, i), Entry::String(arg(&args, i + 1)));
+    }
+    Frame::ok()
+}
+
+/// `MSETNX key value [key value ...]`
+fn msetnx_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    if args.len() % 2 != 0 {
+        return super::wrong_arity("msetnx");
+    }
+    let mut keyspace = KEYSPACE.write().unwrap();
+    if (0..args.len())
+        .step_by(2)
+        .any(|i| keyspace.contains_key(&arg(&args, i)))
+    {
+        client.propagate_nothing();
+        return Frame::integer(0);
+    }
+    for i in (0..args.len()).step_by(2) {
+        keyspace.insert(arg(&args, i), Entry::String(arg(&args, i + 1)));
+    }
+    Frame::integer(1)
+}
+
+// Shared by INCR, DECR, INCRBY and DECRBY. The key keeps its expiry.
+fn incr_generic(key: &[u8], by: i64) -> Frame {
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let current = match keyspace.get_string(key) {
+        Ok(None) => 0,
+        Ok(Some(value)) => match parse_integer(value) {
+            Some(current) => current,
+            None => return Frame::error("ERR value is not an integer or out of range"),
+        },
+        Err(e) => return e.into(),
+    };
+    let Some(next) = current.checked_add(by) else {
+        return Frame::error("ERR increment or decrement would overflow");
+    };
+    match keyspace.string_or_insert(key) {
+        Ok(value) => *value = next.to_string().into_bytes(),
+        Err(e) => return e.into(),
+    }
+    Frame::integer(next)
+}
+
+/// `INCR key`
+fn incr_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    incr_generic(&arg(&args, 0), 1)
+}
+
+/// `DECR key`
+fn decr_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    incr_generic(&arg(&args, 0), -1)
+}
+
+/// `INCRBY key increment`
+fn incrby_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    match parse_i64(&args[1]) {
+        Ok(by) => incr_generic(&arg(&args, 0), by),
+        Err(e) => e,
+    }
+}
+
+/// `DECRBY key decrement`
+fn decrby_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    match parse_i64(&args[1]) {
+        Ok(i64::MIN) => Frame::error("ERR decrement would overflow"),
+        Ok(by) => incr_generic(&arg(&args, 0), -by),
+        Err(e) => e,
+    }
+}
+
+/// `INCRBYFLOAT key increment`
+fn incrbyfloat_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let Some(by) = parse_float(&arg(&args, 1)) else {
+        return Frame::error("ERR value is not a valid float");
+    };
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let current = match keyspace.get_string(&key) {
+        Ok(None) => 0.0,
+        Ok(Some(value)) => match parse_float(value) {
+            Some(current) => current,
+            None => return Frame::error("ERR value is not a valid float"),
+        },
+        Err(e) => return e.into(),
+    };
+    let next = current + by;
+    if !next.is_finite() {
+        return Frame::error("ERR increment would produce NaN or Infinity");
+    }
+    let next = format_double(next);
+    keyspace.insert_keep_ttl(key.clone(), Entry::String(next.clone().into_bytes()));
+
+    // Replay must not depend on float rounding, so log the result.
+    client.propagate(vec![
+        Frame::bulk("SET"),
+        Frame::bulk(key),
+        Frame::bulk(next.clone()),
+        Frame::bulk("KEEPTTL"),
+    ]);
+    Frame::bulk(next)
+}
+
+/// `APPEND key value`
+fn append_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let suffix = arg(&args, 1);
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let value = match keyspace.string_or_insert(&arg(&args, 0)) {
+        Ok(value) => value,
+        Err(e) => return e.into(),
+    };
+    if value.len() + suffix.len() > MAX_BULK_LEN {
+        return string_too_long();
+    }
+    value.extend_from_slice(&suffix);
+    Frame::integer(value.len() as i64)
+}
+
+/// `STRLEN key`
+fn strlen_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_string(&arg(&args, 0)) {
+        Ok(value) => Frame::integer(value.map_or(0, |v| v.len()) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+/// `GETRANGE key start end`
+fn getrange_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let (start, end) = match (parse_i64(&args[1]), parse_i64(&args[2])) {
+        (Ok(start), Ok(end)) => (start, end),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+    let keyspace = KEYSPACE.read().unwrap();
+    let value = match keyspace.get_string(&arg(&args, 0)) {
+        Ok(value) => value.map(Vec::as_slice).unwrap_or_default(),
+        Err(e) => return e.into(),
+    };
+
+    // Negative offsets count from the end; both ends are inclusive.
+    let len = value.len() as i64;
+    if len == 0 || (start < 0 && end < 0 && start > end) {
+        return Frame::bulk("");
+    }
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let end = if end < 0 {
+        (len + end).max(0)
+    } else {
+        end.min(len - 1)
+    };
+    if start > end {
+        return Frame::bulk("");
+    }
+    Frame::bulk(&value[start as usize..=end as usize])
+}
+
+/// `SETRANGE key offset value`
+fn setrange_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let patch = arg(&args, 2);
+    let offset = match parse_i64(&args[1]) {
+        Ok(offset) if offset >= 0 => offset as usize,
+        Ok(_) => return Frame::error("ERR offset is out of range"),
+        Err(e) => return e,
+    };
+
+    let mut keyspace = KEYSPACE.write().unwrap();
+    if patch.is_empty() {
+        // Nothing to write, and a missing key is not created.
+        client.propagate_nothing();
+        return match keyspace.get_string(&key) {
+            Ok(value) => Frame::integer(value.map_or(0, |v| v.len()) as i64),
+            Err(e) => e.into(),
+        };
+    }
+    if offset + patch.len() > MAX_BULK_LEN {
+        return string_too_long();
+    }
+    let value = match keyspace.string_or_insert(&key) {
+        Ok(value) => value,
+        Err(e) => return e.into(),
+    };
+    let end = offset + patch.len();
+    if value.len() < end {
+        value.resize(end, 0);
+    }
+    value[offset..end].copy_from_slice(&patch);
+    Frame::integer(value.len() as i64)
+}
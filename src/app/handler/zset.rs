@@ -0,0 +1,757 @@
+//! Sorted set commands.
+
+use super::list::list_range;
+use super::{arg, option, parse_i64, syntax_error, Client, Command, CommandTable, Flags, KEYSPACE};
+use crate::app::keyspace::{Entry, Keyspace, WrongType};
+use crate::app::resp::{Frame, Protocol};
+use crate::app::util::parse_float;
+use crate::app::zset::SortedSet;
+use std::ops::Range;
+
+pub(super) fn register(table: &mut CommandTable) {
+    use Flags as F;
+    table.register(
+        Command::new("zadd", -4, F::WRITE | F::DENYOOM | F::FAST, zadd_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "1.2.0",
+                "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist.",
+            ),
+    );
+    table.register(
+        Command::new(
+            "zincrby",
+            4,
+            F::WRITE | F::DENYOOM | F::FAST,
+            zincrby_handler,
+        )
+        .keys(1, 1, 1)
+        .docs(
+            "sorted-set",
+            "1.2.0",
+            "Increments the score of a member in a sorted set.",
+        ),
+    );
+    table.register(
+        Command::new("zrem", -3, F::WRITE | F::FAST, zrem_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "1.2.0",
+                "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed.",
+            ),
+    );
+    table.register(
+        Command::new("zcard", 2, F::READONLY | F::FAST, zcard_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "1.2.0",
+                "Returns the number of members in a sorted set.",
+            ),
+    );
+    table.register(
+        Command::new("zscore", 3, F::READONLY | F::FAST, zscore_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "1.2.0",
+                "Returns the score of a member in a sorted set.",
+            ),
+    );
+    table.register(
+        Command::new("zrank", 3, F::READONLY | F::FAST, zrank_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "2.0.0",
+                "Returns the index of a member in a sorted set ordered by ascending scores.",
+            ),
+    );
+    table.register(
+        Command::new("zrevrank", 3, F::READONLY | F::FAST, zrevrank_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "2.0.0",
+                "Returns the index of a member in a sorted set ordered by descending scores.",
+            ),
+    );
+    table.register(
+        Command::new("zcount", 4, F::READONLY | F::FAST, zcount_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "2.0.0",
+                "Returns the count of members in a sorted set that have scores within a range.",
+            ),
+    );
+    table.register(
+        Command::new("zrange", -4, F::READONLY, zrange_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "1.2.0",
+                "Returns members in a sorted set within a range of indexes, scores or lexicographical order.",
+            ),
+    );
+    table.register(
+        Command::new(
+            "zrangestore",
+            -5,
+            F::WRITE | F::DENYOOM,
+            zrangestore_handler,
+        )
+        .keys(1, 2, 1)
+        .docs(
+            "sorted-set",
+            "6.2.0",
+            "Stores a range of members from sorted set in a key.",
+        ),
+    );
+    table.register(
+        Command::new("zremrangebyrank", 4, F::WRITE, zremrangebyrank_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "2.0.0",
+                "Removes members in a sorted set within a range of indexes. Deletes the sorted set if all members were removed.",
+            ),
+    );
+    table.register(
+        Command::new("zremrangebyscore", 4, F::WRITE, zremrangebyscore_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "1.2.0",
+                "Removes members in a sorted set within a range of scores. Deletes the sorted set if all members were removed.",
+            ),
+    );
+    table.register(
+        Command::new("zremrangebylex", 4, F::WRITE, zremrangebylex_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "2.8.9",
+                "Removes members in a sorted set within a lexicographical range. Deletes the sorted set if all members were removed.",
+            ),
+    );
+    table.register(
+        Command::new("zpopmin", -2, F::WRITE | F::FAST, zpopmin_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "5.0.0",
+                "Returns the lowest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.",
+            ),
+    );
+    table.register(
+        Command::new("zpopmax", -2, F::WRITE | F::FAST, zpopmax_handler)
+            .keys(1, 1, 1)
+            .docs(
+                "sorted-set",
+                "5.0.0",
+                "Returns the highest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.",
+            ),
+    );
+}
+
+/// Which end of a sorted set a pop takes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Extreme {
+    Min,
+    Max,
+}
+
+impl Extreme {
+    pub(super) fn pop_command(self) -> &'static str {
+        match self {
+            Extreme::Min => "ZPOPMIN",
+            Extreme::Max => "ZPOPMAX",
+        }
+    }
+}
+
+// Removes the lowest or highest scoring member.
+fn pop(zset: &mut SortedSet, extreme: Extreme) -> Option<(Vec<u8>, f64)> {
+    let rank = match extreme {
+        Extreme::Min => 0,
+        Extreme::Max => zset.len().checked_sub(1)?,
+    };
+    let (member, score) = zset.get_index(rank)?;
+    let member = member.to_vec();
+    zset.remove(&member);
+    Some((member, score))
+}
+
+// Pops one member from the sorted set at `key`, deleting the key if that
+// emptied it.
+pub(super) fn zset_pop(
+    keyspace: &mut Keyspace,
+    key: &[u8],
+    extreme: Extreme,
+) -> Result<Option<(Vec<u8>, f64)>, WrongType> {
+    let Some(zset) = keyspace.get_zset_mut(key)? else {
+        return Ok(None);
+    };
+    let popped = pop(zset, extreme);
+    keyspace.remove_if_empty(key);
+    Ok(popped)
+}
+
+// Scores may also be given as `inf`, `+inf` or `-inf`.
+fn parse_score(bytes: &[u8]) -> Option<f64> {
+    if bytes.eq_ignore_ascii_case(b"+inf") {
+        return Some(f64::INFINITY);
+    }
+    parse_float(bytes)
+}
+
+fn not_a_float() -> Frame {
+    Frame::error("ERR value is not a valid float")
+}
+
+fn nan_score() -> Frame {
+    Frame::error("ERR resulting score is not a number (NaN)")
+}
+
+// One end of a score range: `1.5`, or `(1.5` to leave the score out.
+#[derive(Clone, Copy, Debug)]
+struct ScoreBound {
+    score: f64,
+    exclusive: bool,
+}
+
+impl ScoreBound {
+    fn parse(frame: &Frame) -> Result<ScoreBound, Frame> {
+        let bytes = frame.as_bulk().unwrap_or_default();
+        let (bytes, exclusive) = match bytes.strip_prefix(b"(") {
+            Some(rest) => (rest, true),
+            None => (bytes, false),
+        };
+        parse_score(bytes)
+            .map(|score| ScoreBound { score, exclusive })
+            .ok_or_else(|| Frame::error("ERR min or max is not a float"))
+    }
+}
+
+// One end of a lexicographical range: `-` and `+` are the extremes, `[a`
+// includes `a` and `(a` leaves it out.
+#[derive(Clone, Debug)]
+enum LexBound {
+    Min,
+    Max,
+    Inclusive(Vec<u8>),
+    Exclusive(Vec<u8>),
+}
+
+impl LexBound {
+    fn parse(frame: &Frame) -> Result<LexBound, Frame> {
+        let bytes = frame.as_bulk().unwrap_or_default();
+        match bytes.split_first() {
+            Some((b'-', [])) => Ok(LexBound::Min),
+            Some((b'+', [])) => Ok(LexBound::Max),
+            Some((b'[', rest)) => Ok(LexBound::Inclusive(rest.to_vec())),
+            Some((b'(', rest)) => Ok(LexBound::Exclusive(rest.to_vec())),
+            _ => Err(Frame::error("ERR min or max not valid string range item")),
+        }
+    }
+
+    // Whether `member` sorts before this bound used as a minimum.
+    fn below_min(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(v) => member < &v[..],
+            LexBound::Exclusive(v) => member <= &v[..],
+        }
+    }
+
+    // Whether `member` is within this bound used as a maximum.
+    fn within_max(&self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(v) => member <= &v[..],
+            LexBound::Exclusive(v) => member < &v[..],
+        }
+    }
+}
+
+// A range of members by position, score or, for members sharing a score,
+// lexicographically.
+#[derive(Clone, Debug)]
+enum Bounds {
+    Rank(i64, i64),
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+impl Bounds {
+    // The ascending positions the range covers. With `rev`, rank ranges
+    // count from the highest score down.
+    fn positions(&self, zset: &SortedSet, rev: bool) -> Range<usize> {
+        let len = zset.len();
+        match self {
+            Bounds::Rank(start, stop) => match list_range(len, *start, *stop) {
+                Some((start, stop)) if rev => len - 1 - stop..len - start,
+                Some((start, stop)) => start..stop + 1,
+                None => 0..0,
+            },
+            Bounds::Score(min, max) => {
+                let start = zset.partition_point(|score, _| {
+                    score < min.score || (min.exclusive && score == min.score)
+                });
+                let end = zset.partition_point(|score, _| {
+                    score < max.score || (!max.exclusive && score == max.score)
+                });
+                start..end.max(start)
+            }
+            Bounds::Lex(min, max) => {
+                let start = zset.partition_point(|_, member| min.below_min(member));
+                let end = zset.partition_point(|_, member| max.within_max(member));
+                start..end.max(start)
+            }
+        }
+    }
+}
+
+// The arguments of ZRANGE and ZRANGESTORE after the key(s).
+#[derive(Clone, Debug)]
+struct RangeSpec {
+    bounds: Bounds,
+    rev: bool,
+    // Offset and count; a negative count means all.
+    limit: Option<(i64, i64)>,
+    withscores: bool,
+}
+
+impl RangeSpec {
+    // Parses `start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count]
+    // [WITHSCORES]` from `args[first..]`.
+    fn parse(args: &[Frame], first: usize, allow_withscores: bool) -> Result<RangeSpec, Frame> {
+        enum By {
+            Rank,
+            Score,
+            Lex,
+        }
+        let (mut by, mut rev, mut limit, mut withscores) = (By::Rank, false, None, false);
+        let mut i = first + 2;
+        while i < args.len() {
+            match option(args, i).as_str() {
+                "BYSCORE" => by = By::Score,
+                "BYLEX" => by = By::Lex,
+                "REV" => rev = true,
+                "WITHSCORES" if allow_withscores => withscores = true,
+                "LIMIT" if i + 2 < args.len() => {
+                    limit = Some((parse_i64(&args[i + 1])?, parse_i64(&args[i + 2])?));
+                    i += 2;
+                }
+                _ => return Err(syntax_error()),
+            }
+            i += 1;
+        }
+
+        // With REV the range is given from the high end.
+        let (low, high) = if rev {
+            (&args[first + 1], &args[first])
+        } else {
+            (&args[first], &args[first + 1])
+        };
+        let bounds = match by {
+            By::Rank if limit.is_some() => {
+                return Err(Frame::error("ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"));
+            }
+            By::Rank => Bounds::Rank(parse_i64(&args[first])?, parse_i64(&args[first + 1])?),
+            By::Lex if withscores => {
+                return Err(Frame::error(
+                    "ERR syntax error, WITHSCORES not supported in combination with BYLEX",
+                ));
+            }
+            By::Score => Bounds::Score(ScoreBound::parse(low)?, ScoreBound::parse(high)?),
+            By::Lex => Bounds::Lex(LexBound::parse(low)?, LexBound::parse(high)?),
+        };
+        Ok(RangeSpec {
+            bounds,
+            rev,
+            limit,
+            withscores,
+        })
+    }
+
+    // The selected members and scores, in reply order.
+    fn select(&self, zset: &SortedSet) -> Vec<(Vec<u8>, f64)> {
+        let positions = self.bounds.positions(zset, self.rev);
+        let members = zset.range(positions.start, positions.end);
+        let ordered: Box<dyn Iterator<Item = (&[u8], f64)>> = if self.rev {
+            Box::new(members.rev())
+        } else {
+            Box::new(members)
+        };
+        let (offset, count) = self.limit.unwrap_or((0, -1));
+        if offset < 0 {
+            return Vec::new();
+        }
+        let count = usize::try_from(count).unwrap_or(usize::MAX);
+        ordered
+            .skip(offset as usize)
+            .take(count)
+            .map(|(member, score)| (member.to_vec(), score))
+            .collect()
+    }
+}
+
+// Members, optionally with their scores: flat in RESP2, as pairs in RESP3.
+fn scored_reply(client: &Client, members: Vec<(Vec<u8>, f64)>, withscores: bool) -> Frame {
+    Frame::array(if !withscores {
+        members.into_iter().map(|(m, _)| Frame::bulk(m)).collect()
+    } else if client.protocol == Protocol::Resp3 {
+        members
+            .into_iter()
+            .map(|(m, s)| Frame::array(vec![Frame::bulk(m), Frame::double(s)]))
+            .collect()
+    } else {
+        members
+            .into_iter()
+            .flat_map(|(m, s)| [Frame::bulk(m), Frame::double(s)])
+            .collect()
+    })
+}
+
+/// `ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]`
+fn zadd_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let (mut nx, mut xx, mut gt, mut lt, mut ch, mut incr) =
+        (false, false, false, false, false, false);
+    let mut i = 1;
+    while i < args.len() {
+        match option(&args, i).as_str() {
+            "NX" => nx = true,
+            "XX" => xx = true,
+            "GT" => gt = true,
+            "LT" => lt = true,
+            "CH" => ch = true,
+            "INCR" => incr = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    let pairs = &args[i..];
+    if pairs.is_empty() || pairs.len() % 2 != 0 {
+        return syntax_error();
+    }
+    if nx && xx {
+        return Frame::error("ERR XX and NX options at the same time are not compatible");
+    }
+    if (gt && lt) || (nx && (gt || lt)) {
+        return Frame::error("ERR GT, LT, and/or NX options at the same time are not compatible");
+    }
+    if incr && pairs.len() > 2 {
+        return Frame::error("ERR INCR option supports a single increment-element pair");
+    }
+    let mut elements = Vec::with_capacity(pairs.len() / 2);
+    for pair in pairs.chunks(2) {
+        let Some(score) = parse_score(pair[0].as_bulk().unwrap_or_default()) else {
+            return not_a_float();
+        };
+        elements.push((score, pair[1].as_bulk().unwrap_or_default().to_vec()));
+    }
+
+    let mut keyspace = KEYSPACE.write().unwrap();
+    // XX only ever updates, so it never creates the key.
+    let zset = match keyspace.get_zset(&key) {
+        Ok(None) if xx => {
+            client.propagate_nothing();
+            return if incr { Frame::Null } else { Frame::integer(0) };
+        }
+        Ok(_) => keyspace.zset_or_insert(&key).expect("checked the type"),
+        Err(e) => return e.into(),
+    };
+
+    let (mut added, mut updated) = (0, 0);
+    let mut result = None;
+    for (score, member) in elements {
+        let Some(current) = zset.score(&member) else {
+            if !xx {
+                zset.insert(member, score);
+                added += 1;
+                result = Some(score);
+            }
+            continue;
+        };
+        if nx {
+            continue;
+        }
+        let score = if incr { current + score } else { score };
+        if score.is_nan() {
+            keyspace.remove_if_empty(&key);
+            return nan_score();
+        }
+        if (gt && score <= current) || (lt && score >= current) {
+            continue;
+        }
+        if score != current {
+            zset.insert(member, score);
+            updated += 1;
+        }
+        result = Some(score);
+    }
+    keyspace.remove_if_empty(&key);
+
+    if added + updated == 0 {
+        client.propagate_nothing();
+    }
+    if incr {
+        result.map_or(Frame::Null, Frame::double)
+    } else {
+        Frame::integer(added + if ch { updated } else { 0 })
+    }
+}
+
+/// `ZINCRBY key increment member`
+fn zincrby_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let Some(by) = parse_score(&arg(&args, 1)) else {
+        return not_a_float();
+    };
+    let member = arg(&args, 2);
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let zset = match keyspace.zset_or_insert(&arg(&args, 0)) {
+        Ok(zset) => zset,
+        Err(e) => return e.into(),
+    };
+    let score = zset.score(&member).unwrap_or(0.0) + by;
+    if score.is_nan() {
+        keyspace.remove_if_empty(&arg(&args, 0));
+        return nan_score();
+    }
+    zset.insert(member, score);
+    Frame::double(score)
+}
+
+/// `ZREM key member [member ...]`
+fn zrem_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let key = arg(&args, 0);
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let zset = match keyspace.get_zset_mut(&key) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => {
+            client.propagate_nothing();
+            return Frame::integer(0);
+        }
+        Err(e) => return e.into(),
+    };
+    let removed = (1..args.len())
+        .filter(|&i| zset.remove(&arg(&args, i)).is_some())
+        .count();
+    keyspace.remove_if_empty(&key);
+    if removed == 0 {
+        client.propagate_nothing();
+    }
+    Frame::integer(removed as i64)
+}
+
+/// `ZCARD key`
+fn zcard_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_zset(&arg(&args, 0)) {
+        Ok(zset) => Frame::integer(zset.map_or(0, |z| z.len()) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+/// `ZSCORE key member`
+fn zscore_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_zset(&arg(&args, 0)) {
+        Ok(zset) => zset
+            .and_then(|z| z.score(&arg(&args, 1)))
+            .map_or(Frame::Null, Frame::double),
+        Err(e) => e.into(),
+    }
+}
+
+// Shared by ZRANK and ZREVRANK: `<command> key member`.
+fn rank_generic(args: &[Frame], rev: bool) -> Frame {
+    let keyspace = KEYSPACE.read().unwrap();
+    let zset = match keyspace.get_zset(&arg(args, 0)) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => return Frame::Null,
+        Err(e) => return e.into(),
+    };
+    match zset.rank(&arg(args, 1)) {
+        Some(rank) if rev => Frame::integer((zset.len() - 1 - rank) as i64),
+        Some(rank) => Frame::integer(rank as i64),
+        None => Frame::Null,
+    }
+}
+
+/// `ZRANK key member`
+fn zrank_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    rank_generic(&args, false)
+}
+
+/// `ZREVRANK key member`
+fn zrevrank_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    rank_generic(&args, true)
+}
+
+/// `ZCOUNT key min max`
+fn zcount_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let bounds = match (ScoreBound::parse(&args[1]), ScoreBound::parse(&args[2])) {
+        (Ok(min), Ok(max)) => Bounds::Score(min, max),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_zset(&arg(&args, 0)) {
+        Ok(zset) => Frame::integer(zset.map_or(0, |z| bounds.positions(z, false).len()) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+/// `ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count]
+/// [WITHSCORES]`
+fn zrange_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    let spec = match RangeSpec::parse(&args, 1, true) {
+        Ok(spec) => spec,
+        Err(e) => return e,
+    };
+    let keyspace = KEYSPACE.read().unwrap();
+    match keyspace.get_zset(&arg(&args, 0)) {
+        Ok(zset) => {
+            let members = zset.map(|z| spec.select(z)).unwrap_or_default();
+            scored_reply(client, members, spec.withscores)
+        }
+        Err(e) => e.into(),
+    }
+}
+
+/// `ZRANGESTORE dst src min max [BYSCORE | BYLEX] [REV] [LIMIT offset count]`
+///
+/// The destination is replaced, or deleted if the range is empty.
+fn zrangestore_handler(_client: &mut Client, args: Vec<Frame>) -> Frame {
+    let spec = match RangeSpec::parse(&args, 2, false) {
+        Ok(spec) => spec,
+        Err(e) => return e,
+    };
+    let destination = arg(&args, 0);
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let members = match keyspace.get_zset(&arg(&args, 1)) {
+        Ok(zset) => zset.map(|z| spec.select(z)).unwrap_or_default(),
+        Err(e) => return e.into(),
+    };
+    let len = members.len();
+    if members.is_empty() {
+        keyspace.remove(&destination);
+    } else {
+        let mut zset = SortedSet::new();
+        for (member, score) in members {
+            zset.insert(member, score);
+        }
+        keyspace.insert(destination, Entry::ZSet(zset));
+    }
+    Frame::integer(len as i64)
+}
+
+// Shared by the ZREMRANGEBY* commands: `<command> key min max`.
+fn remrange_generic(client: &mut Client, key: &[u8], bounds: Bounds) -> Frame {
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let zset = match keyspace.get_zset_mut(key) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => {
+            client.propagate_nothing();
+            return Frame::integer(0);
+        }
+        Err(e) => return e.into(),
+    };
+    let positions = bounds.positions(zset, false);
+    let doomed: Vec<Vec<u8>> = zset
+        .range(positions.start, positions.end)
+        .map(|(member, _)| member.to_vec())
+        .collect();
+    for member in &doomed {
+        zset.remove(member);
+    }
+    keyspace.remove_if_empty(key);
+    if doomed.is_empty() {
+        client.propagate_nothing();
+    }
+    Frame::integer(doomed.len() as i64)
+}
+
+/// `ZREMRANGEBYRANK key start stop`
+fn zremrangebyrank_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    match (parse_i64(&args[1]), parse_i64(&args[2])) {
+        (Ok(start), Ok(stop)) => {
+            remrange_generic(client, &arg(&args, 0), Bounds::Rank(start, stop))
+        }
+        (Err(e), _) | (_, Err(e)) => e,
+    }
+}
+
+/// `ZREMRANGEBYSCORE key min max`
+fn zremrangebyscore_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    match (ScoreBound::parse(&args[1]), ScoreBound::parse(&args[2])) {
+        (Ok(min), Ok(max)) => remrange_generic(client, &arg(&args, 0), Bounds::Score(min, max)),
+        (Err(e), _) | (_, Err(e)) => e,
+    }
+}
+
+/// `ZREMRANGEBYLEX key min max`
+fn zremrangebylex_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    match (LexBound::parse(&args[1]), LexBound::parse(&args[2])) {
+        (Ok(min), Ok(max)) => remrange_generic(client, &arg(&args, 0), Bounds::Lex(min, max)),
+        (Err(e), _) | (_, Err(e)) => e,
+    }
+}
+
+// Shared by ZPOPMIN and ZPOPMAX: `<command> key [count]`. Without a count
+// the reply is one member and its score, with one it is a list of them.
+fn zpop_generic(client: &mut Client, args: Vec<Frame>, extreme: Extreme) -> Frame {
+    let key = arg(&args, 0);
+    let count = match args.get(1).map(parse_i64) {
+        None => None,
+        Some(Ok(n)) if n >= 0 => Some(n as usize),
+        Some(Ok(_)) => return Frame::error("ERR value is out of range, must be positive"),
+        Some(Err(e)) => return e,
+    };
+    if args.len() > 2 {
+        return syntax_error();
+    }
+
+    let mut keyspace = KEYSPACE.write().unwrap();
+    let zset = match keyspace.get_zset_mut(&key) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => {
+            client.propagate_nothing();
+            return Frame::array(vec![]);
+        }
+        Err(e) => return e.into(),
+    };
+    let popped: Vec<(Vec<u8>, f64)> = std::iter::from_fn(|| pop(zset, extreme))
+        .take(count.unwrap_or(1))
+        .collect();
+    keyspace.remove_if_empty(&key);
+    if popped.is_empty() {
+        client.propagate_nothing();
+    }
+    match count {
+        Some(_) => scored_reply(client, popped, true),
+        None => Frame::array(
+            popped
+                .into_iter()
+                .flat_map(|(m, s)| [Frame::bulk(m), Frame::double(s)])
+                .collect(),
+        ),
+    }
+}
+
+/// `ZPOPMIN key [count]`
+fn zpopmin_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    zpop_generic(client, args, Extreme::Min)
+}
+
+/// `ZPOPMAX key [count]`
+fn zpopmax_handler(client: &mut Client, args: Vec<Frame>) -> Frame {
+    zpop_generic(client, args, Extreme::Max)
+}
@@ -0,0 +1,222 @@
+use crate::app::dict::Dict;
+use crate::app::util::{now_ms, random_index, random_sample};
+use std::collections::BTreeSet;
+
+/// Fields of a hash and their values, each field optionally carrying an
+/// absolute expiry time (Unix milliseconds) as set by `HEXPIRE`.
+///
+/// Fields live in a `Dict` so `HRANDFIELD` and `HSCAN` can address them by
+/// position. An expired field is invisible to every read from the moment
+/// its deadline passes, like an expired key; it is physically removed by
+/// `remove_expired`, which the keyspace runs before handing out the hash
+/// for writing and from its active expiry cycle.
+///
+/// The deadlines are also kept in order, so finding the expired fields
+/// costs nothing while none are due, however many fields have a TTL.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Hash {
+    fields: Dict<Vec<u8>>,
+    expires: Dict<u64>,
+    // `expires` by deadline.
+    deadlines: BTreeSet<(u64, Vec<u8>)>,
+}
+
+impl Hash {
+    pub fn new() -> Self {
+        Hash::default()
+    }
+
+    fn is_expired(&self, field: &[u8], now: u64) -> bool {
+        self.expires.get(field).is_some_and(|&at| at <= now)
+    }
+
+    /// Whether any field's deadline is at or before `now`, so
+    /// `remove_expired` has something to do.
+    pub fn has_expired_fields(&self, now: u64) -> bool {
+        self.deadlines.first().is_some_and(|&(at, _)| at <= now)
+    }
+
+    // Fields whose deadline is at or before `now`, earliest first.
+    fn expired(&self, now: u64) -> impl Iterator<Item = &Vec<u8>> {
+        self.deadlines
+            .iter()
+            .take_while(move |&&(at, _)| at <= now)
+            .map(|(_, f)| f)
+    }
+
+    // Expired fields not removed yet.
+    fn expired_count(&self, now: u64) -> usize {
+        self.expired(now).count()
+    }
+
+    fn live_len(&self, now: u64) -> usize {
+        self.fields.len() - self.expired_count(now)
+    }
+
+    /// Number of live fields.
+    pub fn len(&self) -> usize {
+        self.live_len(now_ms())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        // A field without a TTL keeps the hash alive whatever else expired,
+        // and otherwise the last deadline is the last field to go.
+        self.fields.len() == self.expires.len()
+            && self
+                .deadlines
+                .last()
+                .map_or(true, |&(at, _)| at <= now_ms())
+    }
+
+    // Drops the expiry of `field`, if it has one.
+    fn clear_expire(&mut self, field: &[u8]) -> Option<u64> {
+        let at = self.expires.remove(field)?;
+        self.deadlines.remove(&(at, field.to_vec()));
+        Some(at)
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Vec<u8>> {
+        if self.is_expired(field, now_ms()) {
+            return None;
+        }
+        self.fields.get(field)
+    }
+
+    /// Mutable access to a field's value; its expiry is kept.
+    pub fn get_mut(&mut self, field: &[u8]) -> Option<&mut Vec<u8>> {
+        if self.is_expired(field, now_ms()) {
+            return None;
+        }
+        self.fields.get_mut(field)
+    }
+
+    pub fn contains_key(&self, field: &[u8]) -> bool {
+        self.get(field).is_some()
+    }
+
+    /// Sets a field's value, discarding any expiry it had, and returns the
+    /// previous value.
+    pub fn insert(&mut self, field: Vec<u8>, value: Vec<u8>) -> Option<Vec<u8>> {
+        let expired = self.is_expired(&field, now_ms());
+        self.clear_expire(&field);
+        let old = self.fields.insert(field, value);
+        if expired {
+            None
+        } else {
+            old
+        }
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Vec<u8>> {
+        let expired = self.is_expired(field, now_ms());
+        self.clear_expire(field);
+        let old = self.fields.remove(field);
+        if expired {
+            None
+        } else {
+            old
+        }
+    }
+
+    /// Live fields and their values.
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Vec<u8>)> {
+        let now = now_ms();
+        let any_expired = self.has_expired_fields(now);
+        self.fields
+            .iter()
+            .filter(move |(f, _)| !any_expired || !self.is_expired(f, now))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.iter().map(|(f, _)| f)
+    }
+
+    /// A uniformly random live field and its value.
+    pub fn random_field(&self) -> Option<(&Vec<u8>, &Vec<u8>)> {
+        let now = now_ms();
+        if self.live_len(now) == 0 {
+            return None;
+        }
+        loop {
+            let (field, value) = self
+                .fields
+                .get_index(random_index(self.fields.len()))
+                .expect("index in range");
+            if !self.is_expired(field, now) {
+                return Some((field, value));
+            }
+        }
+    }
+
+    /// `count` distinct random live fields, or all of them if there are
+    /// fewer.
+    pub fn random_fields(&self, count: usize) -> Vec<(&Vec<u8>, &Vec<u8>)> {
+        let now = now_ms();
+        // Enough picks that skipping the expired ones still leaves `count`.
+        let picks = count.saturating_add(self.expired_count(now));
+        random_sample(self.fields.len(), picks)
+            .into_iter()
+            .map(|i| self.fields.get_index(i).expect("index in range"))
+            .filter(|(f, _)| !self.is_expired(f, now))
+            .take(count)
+            .collect()
+    }
+
+    /// One step of a cursor-based scan over the live fields; see
+    /// `Dict::scan`.
+    pub fn scan<'a>(
+        &'a self,
+        cursor: usize,
+        count: usize,
+        mut visit: impl FnMut(&'a Vec<u8>, &'a Vec<u8>),
+    ) -> usize {
+        let now = now_ms();
+        self.fields.scan(cursor, count, |field, value| {
+            if !self.is_expired(field, now) {
+                visit(field, value);
+            }
+        })
+    }
+
+    /// The expiry of a live field: `None` if there is no such field and
+    /// `Some(None)` if it never expires.
+    pub fn expire_at(&self, field: &[u8]) -> Option<Option<u64>> {
+        self.get(field)?;
+        Some(self.expires.get(field).copied())
+    }
+
+    /// Sets the absolute expiry of a live field; returns false if there is
+    /// no such field. Deadlines in the past are the caller's business.
+    pub fn set_expire_at(&mut self, field: &[u8], at_ms: u64) -> bool {
+        if !self.contains_key(field) {
+            return false;
+        }
+        self.clear_expire(field);
+        self.expires.insert(field.to_vec(), at_ms);
+        self.deadlines.insert((at_ms, field.to_vec()));
+        true
+    }
+
+    /// Removes the expiry from a live field; returns whether it had one.
+    pub fn persist(&mut self, field: &[u8]) -> bool {
+        self.contains_key(field) && self.clear_expire(field).is_some()
+    }
+
+    /// Whether any field carries an expiry.
+    pub fn has_volatile_fields(&self) -> bool {
+        !self.expires.is_empty()
+    }
+
+    /// Removes the fields whose deadline is at or before `now`; returns how
+    /// many there were.
+    pub fn remove_expired(&mut self, now: u64) -> usize {
+        let mut count = 0;
+        while self.has_expired_fields(now) {
+            let (_, field) = self.deadlines.pop_first().expect("a deadline is due");
+            self.expires.remove(&field);
+            self.fields.remove(&field);
+            count += 1;
+        }
+        count
+    }
+}
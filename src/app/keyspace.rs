@@ -0,0 +1,769 @@
+use crate::app::dict::Dict;
+use crate::app::evict::{self, EvictionConfig, EvictionPolicy, EvictionPool};
+use crate::app::resp::Frame;
+use crate::app::util::{now_ms, random_index};
+use crate::app::zset::SortedSet;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+
+pub use crate::app::hash::Hash;
+pub type List = VecDeque<Vec<u8>>;
+/// Members of a set. A `Dict` rather than a `HashSet` so `SPOP` and
+/// `SRANDMEMBER` can pick random members in O(1).
+pub type Set = Dict<()>;
+
+pub const WRONGTYPE_ERR: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+/// Keys examined per round of the active expiry cycle, and hashes per
+/// round of the field expiry cycle.
+pub const ACTIVE_EXPIRE_SAMPLE: usize = 20;
+
+/// Estimated bytes a key costs beyond its own bytes and its value: the dict
+/// slot, index entry and bookkeeping.
+pub const KEY_OVERHEAD: usize = 64;
+
+/// Estimated bytes each hash field costs beyond its name and value.
+pub const FIELD_OVERHEAD: usize = 32;
+
+/// Estimated bytes each list element or set member costs beyond its own
+/// bytes.
+pub const ELEMENT_OVERHEAD: usize = 16;
+
+/// Estimated bytes each sorted set member costs beyond two copies of its
+/// bytes: the skiplist node with its levels and the score index entry.
+pub const ZSET_MEMBER_OVERHEAD: usize = 64;
+
+/// Elements looked at to estimate the size of an aggregate value.
+pub const SIZE_SAMPLES: usize = 5;
+
+/// Returned when a command touches a key holding a different type of value.
+#[derive(Debug, PartialEq, Eq)]
+pub struct WrongType;
+
+impl From<WrongType> for Frame {
+    fn from(_: WrongType) -> Frame {
+        Frame::error(WRONGTYPE_ERR)
+    }
+}
+
+/// A value stored under a key.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Entry {
+    String(Vec<u8>),
+    Hash(Hash),
+    List(List),
+    Set(Set),
+    ZSet(SortedSet),
+}
+
+impl Entry {
+    /// The name reported by `TYPE`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Entry::String(_) => "string",
+            Entry::Hash(_) => "hash",
+            Entry::List(_) => "list",
+            Entry::Set(_) => "set",
+            Entry::ZSet(_) => "zset",
+        }
+    }
+
+    /// Whether this is a collection with no elements left. Such keys are
+    /// deleted, as Redis never keeps empty aggregates around.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            Entry::String(_) => false,
+            Entry::Hash(h) => h.is_empty(),
+            Entry::List(l) => l.is_empty(),
+            Entry::Set(s) => s.is_empty(),
+            Entry::ZSet(z) => z.is_empty(),
+        }
+    }
+
+    /// Approximate heap size of the value in bytes. Like Redis's
+    /// `MEMORY USAGE`, aggregates are measured from a few sampled elements
+    /// scaled up to the full length, so this stays cheap on large values.
+    pub fn estimated_size(&self) -> usize {
+        match self {
+            Entry::String(s) => s.len(),
+            Entry::Hash(h) => scaled_size(
+                h.len(),
+                h.iter().map(|(f, v)| f.len() + v.len() + FIELD_OVERHEAD),
+            ),
+            Entry::List(l) => scaled_size(l.len(), l.iter().map(|v| v.len() + ELEMENT_OVERHEAD)),
+            // A dict keeps each member twice, in its slots and its index.
+            Entry::Set(s) => scaled_size(s.len(), s.keys().map(|m| 2 * m.len() + ELEMENT_OVERHEAD)),
+            Entry::ZSet(z) => scaled_size(
+                z.len(),
+                z.iter().map(|(m, _)| 2 * m.len() + ZSET_MEMBER_OVERHEAD),
+            ),
+        }
+    }
+}
+
+// Scales the sizes of the first few of `len` elements up to all of them.
+fn scaled_size(len: usize, sizes: impl Iterator<Item = usize>) -> usize {
+    let sampled: Vec<usize> = sizes.take(SIZE_SAMPLES).collect();
+    if sampled.is_empty() {
+        return 0;
+    }
+    sampled.iter().sum::<usize>() * len / sampled.len()
+}
+
+// A value together with what eviction needs to know about it. The access
+// stamps are atomics so reads can update them under a shared lock. The
+// value may be shared with a snapshot, and is copied before it is changed
+// while it is.
+#[derive(Debug)]
+struct Object {
+    entry: Arc<Entry>,
+    // Estimated bytes charged to `used_memory` for this key.
+    size: usize,
+    // Unix milliseconds of the last access.
+    lru: AtomicU64,
+    // See `evict::lfu_touch`.
+    lfu: AtomicU32,
+}
+
+impl Object {
+    fn new(key: &[u8], entry: Arc<Entry>) -> Self {
+        let now = now_ms();
+        Object {
+            size: key.len() + KEY_OVERHEAD + entry.estimated_size(),
+            entry,
+            lru: AtomicU64::new(now),
+            lfu: AtomicU32::new(evict::lfu_new(now)),
+        }
+    }
+}
+
+/// The single map from keys to values shared by every data type, so a key
+/// can only ever hold one kind of value at a time.
+///
+/// Keys may carry an absolute expiry time (Unix milliseconds). An expired key
+/// is invisible to every read from the moment its deadline passes; it is
+/// physically removed the next time it is written, or by the sampling sweep
+/// in `active_expire_cycle`. Hash fields with their own expiry are reclaimed
+/// the same way, by `active_expire_fields` for hashes that have any, and a
+/// hash whose last field expired is gone as a key too.
+///
+/// Every key is charged an estimated size against `maxmemory`. Values
+/// changed in place through a `&mut` accessor are re-measured by
+/// `refresh_size`, which the dispatcher calls for the keys of every write.
+#[derive(Debug, Default)]
+pub struct Keyspace {
+    entries: Dict<Object>,
+    expires: Dict<u64>,
+    // Keys of hashes that may hold fields with an expiry.
+    volatile_hashes: Dict<()>,
+    used_memory: usize,
+    eviction: EvictionConfig,
+    pool: EvictionPool,
+}
+
+impl Keyspace {
+    pub fn new() -> Self {
+        Keyspace::default()
+    }
+
+    fn is_expired(&self, key: &[u8], now: u64) -> bool {
+        self.expires.get(key).is_some_and(|&at| at <= now)
+    }
+
+    /// Removes `key` if its deadline has passed, or if it is a hash whose
+    /// last field has expired; returns whether it did. Expired fields of a
+    /// hash are removed along the way.
+    pub fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+        let now = now_ms();
+        if self.is_expired(key, now) {
+            self.remove(key);
+            return true;
+        }
+        // The hash is only changed when a field is due, so one shared with
+        // a snapshot is not copied for nothing.
+        let due = self.entries.get(key).is_some_and(
+            |object| matches!(&*object.entry, Entry::Hash(h) if h.has_expired_fields(now)),
+        );
+        if !due {
+            return false;
+        }
+        let entry = &mut self.entries.get_mut(key).expect("checked above").entry;
+        let Entry::Hash(hash) = Arc::make_mut(entry) else {
+            unreachable!("checked above");
+        };
+        hash.remove_expired(now);
+        if hash.is_empty() {
+            self.remove(key);
+            return true;
+        }
+        false
+    }
+
+    // Records an access for the LRU and LFU policies.
+    fn touch(&self, object: &Object) {
+        let now = now_ms();
+        object.lru.store(now, Ordering::Relaxed);
+        let lfu = object.lfu.load(Ordering::Relaxed);
+        object.lfu.store(
+            evict::lfu_touch(lfu, now, &self.eviction),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&Entry> {
+        if self.is_expired(key, now_ms()) {
+            return None;
+        }
+        let object = self.entries.get(key)?;
+        // A hash whose fields have all expired is as good as deleted.
+        if object.entry.is_empty_collection() {
+            return None;
+        }
+        self.touch(object);
+        Some(&object.entry)
+    }
+
+    /// Mutable access to a value. Callers that change its size should call
+    /// `refresh_size` afterwards.
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Entry> {
+        self.expire_if_needed(key);
+        let object = self.entries.get(key)?;
+        self.touch(object);
+        self.entries
+            .get_mut(key)
+            .map(|object| Arc::make_mut(&mut object.entry))
+    }
+
+    /// Stores `entry` under `key`, replacing whatever was there regardless of
+    /// type and discarding any expiry it had. Returns the previous value.
+    pub fn insert(&mut self, key: Vec<u8>, entry: impl Into<Arc<Entry>>) -> Option<Arc<Entry>> {
+        self.expire_if_needed(&key);
+        self.expires.remove(&key);
+        self.insert_keep_ttl(key, entry)
+    }
+
+    /// Like `insert`, but an existing expiry is kept.
+    pub fn insert_keep_ttl(
+        &mut self,
+        key: Vec<u8>,
+        entry: impl Into<Arc<Entry>>,
+    ) -> Option<Arc<Entry>> {
+        let entry = entry.into();
+        self.expire_if_needed(&key);
+        if matches!(&*entry, Entry::Hash(h) if h.has_volatile_fields()) {
+            self.track_field_expiry(&key);
+        }
+        let object = Object::new(&key, entry);
+        self.used_memory += object.size;
+        let old = self.entries.insert(key, object)?;
+        self.used_memory -= old.size;
+        Some(old.entry)
+    }
+
+    /// Removes `key`, returning its value. A snapshot may still share it.
+    pub fn remove(&mut self, key: &[u8]) -> Option<Arc<Entry>> {
+        self.expires.remove(key);
+        self.volatile_hashes.remove(key);
+        let object = self.entries.remove(key)?;
+        self.used_memory -= object.size;
+        Some(object.entry)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Number of keys, including expired ones not reclaimed yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Live keys and their values.
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Entry)> {
+        let now = now_ms();
+        self.entries
+            .iter()
+            .filter(move |(k, object)| {
+                !self.is_expired(k, now) && !object.entry.is_empty_collection()
+            })
+            .map(|(k, object)| (k, &*object.entry))
+    }
+
+    /// Live keys with their values as the keyspace shares them. Cloning
+    /// one only counts a reference; the keyspace copies the value before
+    /// changing it for as long as a clone is around.
+    pub fn iter_shared(&self) -> impl Iterator<Item = (&Vec<u8>, &Arc<Entry>)> {
+        let now = now_ms();
+        self.entries
+            .iter()
+            .filter(move |(k, object)| {
+                !self.is_expired(k, now) && !object.entry.is_empty_collection()
+            })
+            .map(|(k, object)| (k, &object.entry))
+    }
+
+    /// One step of a cursor-based scan over the live keys, as `SCAN` does
+    /// it; see `Dict::scan`. Every key present for the whole scan is
+    /// visited, however many keys come and go meanwhile.
+    pub fn scan<'a>(
+        &'a self,
+        cursor: usize,
+        count: usize,
+        mut visit: impl FnMut(&'a Vec<u8>, &'a Entry),
+    ) -> usize {
+        let now = now_ms();
+        self.entries.scan(cursor, count, |key, object| {
+            if !self.is_expired(key, now) && !object.entry.is_empty_collection() {
+                visit(key, &object.entry);
+            }
+        })
+    }
+
+    /// A uniformly random live key, if there is one.
+    pub fn random_key(&self) -> Option<&Vec<u8>> {
+        let now = now_ms();
+        // Expired keys are skipped; give up on the sampling if they seem to
+        // be all there is.
+        for _ in 0..100 {
+            if self.entries.is_empty() {
+                return None;
+            }
+            let (key, object) = self
+                .entries
+                .get_index(random_index(self.entries.len()))
+                .expect("index in range");
+            if !self.is_expired(key, now) && !object.entry.is_empty_collection() {
+                return Some(key);
+            }
+        }
+        self.iter().next().map(|(key, _)| key)
+    }
+
+    /// Removes every key, keeping the configuration, and returns the old
+    /// contents so the caller decides where the memory is freed.
+    pub fn flush(&mut self) -> Keyspace {
+        Keyspace {
+            entries: std::mem::take(&mut self.entries),
+            expires: std::mem::take(&mut self.expires),
+            volatile_hashes: std::mem::take(&mut self.volatile_hashes),
+            used_memory: std::mem::take(&mut self.used_memory),
+            eviction: self.eviction.clone(),
+            pool: std::mem::take(&mut self.pool),
+        }
+    }
+
+    /// Absolute expiry of a live key in Unix milliseconds.
+    pub fn expire_at(&self, key: &[u8]) -> Option<u64> {
+        if !self.contains_key(key) {
+            return None;
+        }
+        self.expires.get(key).copied()
+    }
+
+    /// Sets the absolute expiry of an existing key. A deadline that has
+    /// already passed deletes the key. Returns false if the key is missing.
+    pub fn set_expire_at(&mut self, key: &[u8], at_ms: u64) -> bool {
+        self.expire_if_needed(key);
+        if !self.entries.contains_key(key) {
+            return false;
+        }
+        if at_ms <= now_ms() {
+            self.remove(key);
+        } else {
+            self.expires.insert(key.to_vec(), at_ms);
+        }
+        true
+    }
+
+    /// Removes the expiry from a key; returns whether it had one.
+    pub fn persist(&mut self, key: &[u8]) -> bool {
+        self.expire_if_needed(key);
+        self.expires.remove(key).is_some()
+    }
+
+    /// Number of keys with an expiry set.
+    pub fn volatile_len(&self) -> usize {
+        self.expires.len()
+    }
+
+    /// One run of the active expiry cycle, modelled on Redis: sample random
+    /// keys that have an expiry and delete the expired ones, repeating while
+    /// more than a quarter of a sample turned out to be expired. The number
+    /// of rounds is capped so a single run cannot hog the keyspace lock.
+    /// Returns how many keys were removed.
+    pub fn active_expire_cycle(&mut self, now: u64, max_rounds: usize) -> usize {
+        let mut removed = 0;
+        for _ in 0..max_rounds {
+            if self.expires.is_empty() {
+                break;
+            }
+            let sample = ACTIVE_EXPIRE_SAMPLE.min(self.expires.len());
+            let mut expired = 0;
+            for _ in 0..sample {
+                if self.expires.is_empty() {
+                    break;
+                }
+                let i = random_index(self.expires.len());
+                let (key, &at) = self.expires.get_index(i).expect("index in range");
+                if at <= now {
+                    let key = key.clone();
+                    self.remove(&key);
+                    expired += 1;
+                }
+            }
+            removed += expired;
+            if expired * 4 <= sample {
+                break;
+            }
+        }
+        removed
+    }
+
+    /// Registers the hash at `key` with `active_expire_fields`, to be called
+    /// whenever one of its fields is given an expiry.
+    pub fn track_field_expiry(&mut self, key: &[u8]) {
+        self.volatile_hashes.insert(key.to_vec(), ());
+    }
+
+    /// One run of the field expiry cycle: like `active_expire_cycle`, but
+    /// sampling hashes with expiring fields and removing their expired
+    /// fields, and the hashes themselves once empty. Returns how many fields
+    /// were removed.
+    pub fn active_expire_fields(&mut self, now: u64, max_rounds: usize) -> usize {
+        let mut removed = 0;
+        for _ in 0..max_rounds {
+            let sample = ACTIVE_EXPIRE_SAMPLE.min(self.volatile_hashes.len());
+            let mut expired = 0;
+            for _ in 0..sample {
+                if self.volatile_hashes.is_empty() {
+                    break;
+                }
+                let i = random_index(self.volatile_hashes.len());
+                let key = self
+                    .volatile_hashes
+                    .get_index(i)
+                    .expect("index in range")
+                    .0
+                    .clone();
+                let Some(object) = self.entries.get_mut(&key) else {
+                    // Deleted since it was registered.
+                    self.volatile_hashes.remove(&key);
+                    continue;
+                };
+                let (due, volatile) = match &*object.entry {
+                    Entry::Hash(hash) => (hash.has_expired_fields(now), hash.has_volatile_fields()),
+                    // Overwritten since it was registered.
+                    _ => (false, false),
+                };
+                if !due {
+                    if !volatile {
+                        self.volatile_hashes.remove(&key);
+                    }
+                    continue;
+                }
+                // Only changed once something is due, as in `expire_if_needed`.
+                let Entry::Hash(hash) = Arc::make_mut(&mut object.entry) else {
+                    unreachable!("checked above");
+                };
+                let fields = hash.remove_expired(now);
+                let (empty, volatile) = (hash.is_empty(), hash.has_volatile_fields());
+                if empty {
+                    self.remove(&key);
+                } else {
+                    if !volatile {
+                        self.volatile_hashes.remove(&key);
+                    }
+                    self.refresh_size(&key);
+                }
+                removed += fields;
+                expired += (fields > 0) as usize;
+            }
+            if expired * 4 <= sample {
+                break;
+            }
+        }
+        removed
+    }
+
+    pub fn get_string(&self, key: &[u8]) -> Result<Option<&Vec<u8>>, WrongType> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Entry::String(s)) => Ok(Some(s)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    pub fn get_string_mut(&mut self, key: &[u8]) -> Result<Option<&mut Vec<u8>>, WrongType> {
+        match self.get_mut(key) {
+            None => Ok(None),
+            Some(Entry::String(s)) => Ok(Some(s)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// The string at `key`, created empty if the key does not exist.
+    pub fn string_or_insert(&mut self, key: &[u8]) -> Result<&mut Vec<u8>, WrongType> {
+        self.expire_if_needed(key);
+        if !self.entries.contains_key(key) {
+            self.insert(key.to_vec(), Entry::String(Vec::new()));
+        }
+        match self.get_mut(key) {
+            Some(Entry::String(s)) => Ok(s),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn get_hash(&self, key: &[u8]) -> Result<Option<&Hash>, WrongType> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Entry::Hash(h)) => Ok(Some(h)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    pub fn get_hash_mut(&mut self, key: &[u8]) -> Result<Option<&mut Hash>, WrongType> {
+        match self.get_mut(key) {
+            None => Ok(None),
+            Some(Entry::Hash(h)) => Ok(Some(h)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// The hash at `key`, created empty if the key does not exist.
+    pub fn hash_or_insert(&mut self, key: &[u8]) -> Result<&mut Hash, WrongType> {
+        self.expire_if_needed(key);
+        if !self.entries.contains_key(key) {
+            self.insert(key.to_vec(), Entry::Hash(Hash::new()));
+        }
+        match self.get_mut(key) {
+            Some(Entry::Hash(h)) => Ok(h),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn get_list(&self, key: &[u8]) -> Result<Option<&List>, WrongType> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Entry::List(l)) => Ok(Some(l)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    pub fn get_list_mut(&mut self, key: &[u8]) -> Result<Option<&mut List>, WrongType> {
+        match self.get_mut(key) {
+            None => Ok(None),
+            Some(Entry::List(l)) => Ok(Some(l)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// The list at `key`, created empty if the key does not exist.
+    pub fn list_or_insert(&mut self, key: &[u8]) -> Result<&mut List, WrongType> {
+        self.expire_if_needed(key);
+        if !self.entries.contains_key(key) {
+            self.insert(key.to_vec(), Entry::List(List::new()));
+        }
+        match self.get_mut(key) {
+            Some(Entry::List(l)) => Ok(l),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn get_set(&self, key: &[u8]) -> Result<Option<&Set>, WrongType> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Entry::Set(s)) => Ok(Some(s)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    pub fn get_set_mut(&mut self, key: &[u8]) -> Result<Option<&mut Set>, WrongType> {
+        match self.get_mut(key) {
+            None => Ok(None),
+            Some(Entry::Set(s)) => Ok(Some(s)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// The set at `key`, created empty if the key does not exist.
+    pub fn set_or_insert(&mut self, key: &[u8]) -> Result<&mut Set, WrongType> {
+        self.expire_if_needed(key);
+        if !self.entries.contains_key(key) {
+            self.insert(key.to_vec(), Entry::Set(Set::new()));
+        }
+        match self.get_mut(key) {
+            Some(Entry::Set(s)) => Ok(s),
+            _ => Err(WrongType),
+        }
+    }
+
+    pub fn get_zset(&self, key: &[u8]) -> Result<Option<&SortedSet>, WrongType> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Entry::ZSet(z)) => Ok(Some(z)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    pub fn get_zset_mut(&mut self, key: &[u8]) -> Result<Option<&mut SortedSet>, WrongType> {
+        match self.get_mut(key) {
+            None => Ok(None),
+            Some(Entry::ZSet(z)) => Ok(Some(z)),
+            Some(_) => Err(WrongType),
+        }
+    }
+
+    /// The sorted set at `key`, created empty if the key does not exist.
+    pub fn zset_or_insert(&mut self, key: &[u8]) -> Result<&mut SortedSet, WrongType> {
+        self.expire_if_needed(key);
+        if !self.entries.contains_key(key) {
+            self.insert(key.to_vec(), Entry::ZSet(SortedSet::new()));
+        }
+        match self.get_mut(key) {
+            Some(Entry::ZSet(z)) => Ok(z),
+            _ => Err(WrongType),
+        }
+    }
+
+    /// Deletes `key` if it holds a collection that has become empty;
+    /// returns whether it did.
+    pub fn remove_if_empty(&mut self, key: &[u8]) -> bool {
+        let empty = self
+            .entries
+            .get(key)
+            .is_some_and(|object| object.entry.is_empty_collection());
+        if empty {
+            self.remove(key);
+        }
+        empty
+    }
+
+    /// Estimated bytes used by all keys and values.
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    /// Re-measures a value after it was changed in place.
+    pub fn refresh_size(&mut self, key: &[u8]) {
+        if let Some(object) = self.entries.get_mut(key) {
+            let size = key.len() + KEY_OVERHEAD + object.entry.estimated_size();
+            self.used_memory = self.used_memory - object.size + size;
+            object.size = size;
+        }
+    }
+
+    pub fn eviction_config(&self) -> &EvictionConfig {
+        &self.eviction
+    }
+
+    pub fn set_eviction_config(&mut self, config: EvictionConfig) {
+        if config.policy != self.eviction.policy {
+            // Scores from another policy are not comparable.
+            self.pool.clear();
+        }
+        self.eviction = config;
+    }
+
+    /// Whether the dataset is larger than `maxmemory` allows.
+    pub fn over_maxmemory(&self) -> bool {
+        self.eviction.maxmemory > 0 && self.used_memory > self.eviction.maxmemory
+    }
+
+    /// Evicts keys according to the configured policy until the dataset
+    /// fits in `maxmemory` or nothing more can be evicted, and returns the
+    /// evicted keys. Callers check `over_maxmemory` afterwards to tell
+    /// whether it worked.
+    pub fn evict(&mut self) -> Vec<Vec<u8>> {
+        let mut evicted = Vec::new();
+        while self.over_maxmemory() {
+            let Some(key) = self.eviction_victim() else {
+                break;
+            };
+            self.remove(&key);
+            evicted.push(key);
+        }
+        evicted
+    }
+
+    // Picks the next key to evict, approximating the policy the way Redis
+    // does: score a few random keys and evict the best candidate seen so
+    // far, remembering the runners-up in the pool for next time.
+    fn eviction_victim(&mut self) -> Option<Vec<u8>> {
+        let policy = self.eviction.policy;
+        if policy == EvictionPolicy::NoEviction {
+            return None;
+        }
+        let candidates = if policy.is_volatile() {
+            self.expires.len()
+        } else {
+            self.entries.len()
+        };
+        if candidates == 0 {
+            return None;
+        }
+        if policy.is_random() {
+            return Some(self.sample_key(policy));
+        }
+
+        // A fresh sample can lose to stale pool entries that turn out to be
+        // gone; the second pass starts from an empty pool.
+        for _ in 0..2 {
+            self.populate_pool(policy);
+            while let Some(key) = self.pool.pop() {
+                // Candidates may have been deleted, or lost their expiry,
+                // since they were pooled.
+                let eligible = if policy.is_volatile() {
+                    self.expires.contains_key(&key)
+                } else {
+                    self.entries.contains_key(&key)
+                };
+                if eligible {
+                    return Some(key);
+                }
+            }
+        }
+        None
+    }
+
+    // Scores `samples` random keys and offers them to the pool; higher
+    // scores are evicted first.
+    fn populate_pool(&mut self, policy: EvictionPolicy) {
+        let now = now_ms();
+        for _ in 0..self.eviction.samples.max(1) {
+            let key = self.sample_key(policy);
+            let Some(object) = self.entries.get(&key) else {
+                continue;
+            };
+            let score = match policy {
+                EvictionPolicy::VolatileTtl => {
+                    u64::MAX - self.expires.get(&key).copied().unwrap_or(u64::MAX)
+                }
+                _ if policy.is_lfu() => {
+                    let lfu = object.lfu.load(Ordering::Relaxed);
+                    255 - evict::lfu_counter(lfu, now, self.eviction.lfu_decay_time) as u64
+                }
+                _ => now.saturating_sub(object.lru.load(Ordering::Relaxed)),
+            };
+            self.pool.offer(score, &key);
+        }
+    }
+
+    // A uniformly random key from the keys the policy may evict.
+    fn sample_key(&self, policy: EvictionPolicy) -> Vec<u8> {
+        let key = if policy.is_volatile() {
+            self.expires
+                .get_index(random_index(self.expires.len()))
+                .map(|(k, _)| k)
+        } else {
+            self.entries
+                .get_index(random_index(self.entries.len()))
+                .map(|(k, _)| k)
+        };
+        key.expect("index in range").clone()
+    }
+}
@@ -0,0 +1,658 @@
+//! Reading and writing Redis RDB files, so data can move from Redis to
+//! Crache and back.
+//!
+//! The reader takes RDB versions up to 12 (Redis 7.4) holding strings,
+//! lists, sets, sorted sets and hashes in any encoding Redis has saved them
+//! with: plain, zipmap, ziplist, intset, quicklist and listpack, with
+//! integer and LZF-compressed strings. Only database 0 is loaded; keys in
+//! other databases are skipped. Streams, modules and hashes with field
+//! expiries are rejected.
+//!
+//! The writer produces version 9 files, which every Redis since 5.0 loads,
+//! using only the plain encodings. RDB 9 has no place for the expiry of a
+//! hash field, so such fields are written without one.
+
+use crate::app::hash::Hash;
+use crate::app::keyspace::{Entry, List, Set};
+use crate::app::snapshot::{self, Checksummed, Dataset};
+use crate::app::util::{crc64, now_ms, parse_float};
+use crate::app::zset::SortedSet;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::path::Path;
+
+pub const MAGIC: &[u8] = b"REDIS";
+/// The version `write` produces.
+pub const VERSION: u32 = 9;
+/// The newest version `read` accepts.
+pub const MAX_VERSION: u32 = 12;
+
+const OP_SLOT_INFO: u8 = 0xf4;
+const OP_FUNCTION2: u8 = 0xf5;
+const OP_FUNCTION_PRE_GA: u8 = 0xf6;
+const OP_MODULE_AUX: u8 = 0xf7;
+const OP_IDLE: u8 = 0xf8;
+const OP_FREQ: u8 = 0xf9;
+const OP_AUX: u8 = 0xfa;
+const OP_RESIZEDB: u8 = 0xfb;
+const OP_EXPIRETIME_MS: u8 = 0xfc;
+const OP_EXPIRETIME: u8 = 0xfd;
+const OP_SELECTDB: u8 = 0xfe;
+const OP_EOF: u8 = 0xff;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_SET_LISTPACK: u8 = 20;
+
+// How a quicklist 2 node holds its elements.
+const QUICKLIST_PLAIN: u64 = 1;
+const QUICKLIST_PACKED: u64 = 2;
+
+// Strings stored in a special encoding, flagged by a length whose top two
+// bits are set.
+const ENC_INT8: u64 = 0;
+const ENC_INT16: u64 = 1;
+const ENC_INT32: u64 = 2;
+const ENC_LZF: u64 = 3;
+
+fn write_len(out: &mut impl Write, n: u64) -> Result<()> {
+    if n < 1 << 6 {
+        out.write_all(&[n as u8])
+    } else if n < 1 << 14 {
+        out.write_all(&[0x40 | (n >> 8) as u8, n as u8])
+    } else if n <= u32::MAX as u64 {
+        out.write_all(&[0x80])?;
+        out.write_all(&(n as u32).to_be_bytes())
+    } else {
+        out.write_all(&[0x81])?;
+        out.write_all(&n.to_be_bytes())
+    }
+}
+
+fn write_string(out: &mut impl Write, s: &[u8]) -> Result<()> {
+    write_len(out, s.len() as u64)?;
+    out.write_all(s)
+}
+
+fn type_of(entry: &Entry) -> u8 {
+    match entry {
+        Entry::String(_) => TYPE_STRING,
+        Entry::List(_) => TYPE_LIST,
+        Entry::Set(_) => TYPE_SET,
+        Entry::ZSet(_) => TYPE_ZSET_2,
+        Entry::Hash(_) => TYPE_HASH,
+    }
+}
+
+fn write_value(out: &mut impl Write, entry: &Entry) -> Result<()> {
+    match entry {
+        Entry::String(value) => write_string(out, value),
+        Entry::List(list) => {
+            write_len(out, list.len() as u64)?;
+            list.iter().try_for_each(|item| write_string(out, item))
+        }
+        Entry::Set(set) => {
+            write_len(out, set.len() as u64)?;
+            set.keys().try_for_each(|member| write_string(out, member))
+        }
+        Entry::ZSet(zset) => {
+            write_len(out, zset.len() as u64)?;
+            zset.iter().try_for_each(|(member, score)| {
+                write_string(out, member)?;
+                out.write_all(&score.to_bits().to_le_bytes())
+            })
+        }
+        Entry::Hash(hash) => {
+            let fields: Vec<_> = hash.iter().collect();
+            write_len(out, fields.len() as u64)?;
+            fields.into_iter().try_for_each(|(field, value)| {
+                write_string(out, field)?;
+                write_string(out, value)
+            })
+        }
+    }
+}
+
+/// Writes `dataset` to `out` as database 0 of an RDB file.
+pub fn write(out: impl Write, dataset: &Dataset) -> Result<()> {
+    let mut out = Checksummed { inner: out, crc: 0 };
+    write!(out, "REDIS{:04}", VERSION)?;
+    out.write_all(&[OP_SELECTDB, 0, OP_RESIZEDB])?;
+    write_len(&mut out, dataset.len() as u64)?;
+    let volatile = dataset.iter().filter(|(_, _, at)| at.is_some()).count();
+    write_len(&mut out, volatile as u64)?;
+    for (key, entry, expire_at) in dataset {
+        if let Some(at) = expire_at {
+            out.write_all(&[OP_EXPIRETIME_MS])?;
+            out.write_all(&at.to_le_bytes())?;
+        }
+        out.write_all(&[type_of(entry)])?;
+        write_string(&mut out, key)?;
+        write_value(&mut out, entry)?;
+    }
+    out.write_all(&[OP_EOF])?;
+    let crc = out.crc;
+    out.inner.write_all(&crc.to_le_bytes())?;
+    out.flush()
+}
+
+/// Writes `dataset` to the RDB file at `path`, replacing any previous one
+/// only once the new one is complete and on disk.
+pub fn save(path: &Path, dataset: &Dataset) -> Result<()> {
+    snapshot::write_file(path, |out| write(out, dataset))
+}
+
+fn corrupt(what: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("corrupt RDB file: {}", what),
+    )
+}
+
+fn unsupported(what: String) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("unsupported {}", what))
+}
+
+fn int_text(n: i64) -> Vec<u8> {
+    n.to_string().into_bytes()
+}
+
+// Reads an RDB file or one of the blobs inside it.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.data.len() {
+            return Err(corrupt("unexpected end"));
+        }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    // A length, or with `true` the special encoding of the string that
+    // follows in its place.
+    fn len_or_encoding(&mut self) -> Result<(u64, bool)> {
+        let first = self.byte()?;
+        let low = (first & 0x3f) as u64;
+        Ok(match first >> 6 {
+            0 => (low, false),
+            1 => (low << 8 | self.byte()? as u64, false),
+            2 => match first {
+                0x80 => (u32::from_be_bytes(self.array()?) as u64, false),
+                0x81 => (u64::from_be_bytes(self.array()?), false),
+                _ => return Err(corrupt("bad length")),
+            },
+            _ => (low, true),
+        })
+    }
+
+    fn len(&mut self) -> Result<u64> {
+        match self.len_or_encoding()? {
+            (n, false) => Ok(n),
+            _ => Err(corrupt("encoded string in place of a length")),
+        }
+    }
+
+    // A count of items, each at least a byte long, so a corrupt count
+    // cannot make us reserve more than the file holds.
+    fn count(&mut self) -> Result<usize> {
+        let n = self.len()?;
+        if n > self.data.len() as u64 {
+            return Err(corrupt("count past the end"));
+        }
+        Ok(n as usize)
+    }
+
+    fn bytes(&mut self, n: u64) -> Result<&'a [u8]> {
+        if n > self.data.len() as u64 {
+            return Err(corrupt("string past the end"));
+        }
+        self.take(n as usize)
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>> {
+        let (n, encoded) = self.len_or_encoding()?;
+        if !encoded {
+            return Ok(self.bytes(n)?.to_vec());
+        }
+        Ok(match n {
+            ENC_INT8 => int_text(self.byte()? as i8 as i64),
+            ENC_INT16 => int_text(i16::from_le_bytes(self.array()?) as i64),
+            ENC_INT32 => int_text(i32::from_le_bytes(self.array()?) as i64),
+            ENC_LZF => {
+                let compressed = self.len()?;
+                let len = self.len()?;
+                lzf_decompress(self.bytes(compressed)?, len)?
+            }
+            _ => return Err(corrupt("unknown string encoding")),
+        })
+    }
+
+    // A score as sorted sets were saved before RDB 8: its length, then the
+    // text, with lengths 253 to 255 standing for NaN and the infinities.
+    fn text_score(&mut self) -> Result<f64> {
+        match self.byte()? {
+            253 => Err(corrupt("NaN score")),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            n => parse_float(self.bytes(n as u64)?).ok_or_else(|| corrupt("bad score")),
+        }
+    }
+
+    fn binary_score(&mut self) -> Result<f64> {
+        let score = f64::from_bits(u64::from_le_bytes(self.array()?));
+        if score.is_nan() {
+            return Err(corrupt("NaN score"));
+        }
+        Ok(score)
+    }
+
+    fn value(&mut self, kind: u8) -> Result<Entry> {
+        Ok(match kind {
+            TYPE_STRING => Entry::String(self.string()?),
+            TYPE_LIST => {
+                let n = self.count()?;
+                let mut list = List::with_capacity(n);
+                for _ in 0..n {
+                    list.push_back(self.string()?);
+                }
+                Entry::List(list)
+            }
+            TYPE_SET => {
+                let mut set = Set::new();
+                for _ in 0..self.count()? {
+                    set.insert(self.string()?, ());
+                }
+                Entry::Set(set)
+            }
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let mut zset = SortedSet::new();
+                for _ in 0..self.count()? {
+                    let member = self.string()?;
+                    let score = if kind == TYPE_ZSET {
+                        self.text_score()?
+                    } else {
+                        self.binary_score()?
+                    };
+                    zset.insert(member, score);
+                }
+                Entry::ZSet(zset)
+            }
+            TYPE_HASH => {
+                let mut hash = Hash::new();
+                for _ in 0..self.count()? {
+                    let field = self.string()?;
+                    hash.insert(field, self.string()?);
+                }
+                Entry::Hash(hash)
+            }
+            TYPE_HASH_ZIPMAP => hash_of(zipmap(&self.string()?)?)?,
+            TYPE_LIST_ZIPLIST => Entry::List(ziplist(&self.string()?)?.into()),
+            TYPE_SET_INTSET => set_of(intset(&self.string()?)?),
+            TYPE_SET_LISTPACK => set_of(listpack(&self.string()?)?),
+            TYPE_ZSET_ZIPLIST => zset_of(ziplist(&self.string()?)?)?,
+            TYPE_ZSET_LISTPACK => zset_of(listpack(&self.string()?)?)?,
+            TYPE_HASH_ZIPLIST => hash_of(ziplist(&self.string()?)?)?,
+            TYPE_HASH_LISTPACK => hash_of(listpack(&self.string()?)?)?,
+            TYPE_LIST_QUICKLIST => {
+                let mut list = List::new();
+                for _ in 0..self.count()? {
+                    list.extend(ziplist(&self.string()?)?);
+                }
+                Entry::List(list)
+            }
+            TYPE_LIST_QUICKLIST_2 => {
+                let mut list = List::new();
+                for _ in 0..self.count()? {
+                    let container = self.len()?;
+                    let node = self.string()?;
+                    match container {
+                        QUICKLIST_PLAIN => list.push_back(node),
+                        QUICKLIST_PACKED => list.extend(listpack(&node)?),
+                        _ => return Err(corrupt("unknown quicklist container")),
+                    }
+                }
+                Entry::List(list)
+            }
+            _ => return Err(unsupported(format!("RDB value type {}", kind))),
+        })
+    }
+}
+
+fn set_of(members: Vec<Vec<u8>>) -> Entry {
+    let mut set = Set::new();
+    for member in members {
+        set.insert(member, ());
+    }
+    Entry::Set(set)
+}
+
+// Hashes are packed as field, value, field, value...
+fn hash_of(items: Vec<Vec<u8>>) -> Result<Entry> {
+    if items.len() % 2 != 0 {
+        return Err(corrupt("hash with a field but no value"));
+    }
+    let mut hash = Hash::new();
+    let mut items = items.into_iter();
+    while let (Some(field), Some(value)) = (items.next(), items.next()) {
+        hash.insert(field, value);
+    }
+    Ok(Entry::Hash(hash))
+}
+
+// Sorted sets are packed as member, score, member, score...
+fn zset_of(items: Vec<Vec<u8>>) -> Result<Entry> {
+    if items.len() % 2 != 0 {
+        return Err(corrupt("sorted set member without a score"));
+    }
+    let mut zset = SortedSet::new();
+    let mut items = items.into_iter();
+    while let (Some(member), Some(score)) = (items.next(), items.next()) {
+        let score = parse_float(&score).ok_or_else(|| corrupt("bad score"))?;
+        zset.insert(member, score);
+    }
+    Ok(Entry::ZSet(zset))
+}
+
+/// Expands LZF-compressed `input` into the `len` bytes it holds.
+fn lzf_decompress(input: &[u8], len: u64) -> Result<Vec<u8>> {
+    let bad = || corrupt("bad LZF data");
+    // Each input byte expands to at most a few hundred, which bounds what
+    // a corrupt length can make us reserve.
+    let mut out = Vec::with_capacity(len.min(input.len() as u64 * 264) as usize);
+    let mut i = 0;
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 1 << 5 {
+            // A run of `ctrl + 1` literal bytes.
+            let run = input.get(i..i + ctrl + 1).ok_or_else(bad)?;
+            out.extend_from_slice(run);
+            i += ctrl + 1;
+        } else {
+            // A copy of earlier output: the top three bits are the length
+            // less two, extended by a byte when all set, and the rest with
+            // the next byte the distance back less one.
+            let mut n = ctrl >> 5;
+            if n == 7 {
+                n += *input.get(i).ok_or_else(bad)? as usize;
+                i += 1;
+            }
+            let back = ((ctrl & 0x1f) << 8 | *input.get(i).ok_or_else(bad)? as usize) + 1;
+            i += 1;
+            if back > out.len() {
+                return Err(bad());
+            }
+            let start = out.len() - back;
+            for k in 0..n + 2 {
+                out.push(out[start + k]);
+            }
+        }
+        if out.len() as u64 > len {
+            return Err(bad());
+        }
+    }
+    if out.len() as u64 != len {
+        return Err(bad());
+    }
+    Ok(out)
+}
+
+/// The members of an intset, as decimal text.
+fn intset(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut reader = Reader { data };
+    let width = u32::from_le_bytes(reader.array()?);
+    let n = u32::from_le_bytes(reader.array()?) as u64;
+    if !matches!(width, 2 | 4 | 8) || n * width as u64 != reader.data.len() as u64 {
+        return Err(corrupt("bad intset"));
+    }
+    (0..n)
+        .map(|_| {
+            Ok(int_text(match width {
+                2 => i16::from_le_bytes(reader.array()?) as i64,
+                4 => i32::from_le_bytes(reader.array()?) as i64,
+                _ => i64::from_le_bytes(reader.array()?),
+            }))
+        })
+        .collect()
+}
+
+/// The entries of a ziplist, integers as decimal text.
+fn ziplist(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut reader = Reader { data };
+    // Total bytes, offset of the last entry and entry count.
+    reader.take(10)?;
+    let mut items = Vec::new();
+    loop {
+        // The length of the previous entry, or the end marker.
+        match reader.byte()? {
+            0xff => break,
+            0xfe => {
+                reader.take(4)?;
+            }
+            _ => {}
+        }
+        let encoding = reader.byte()?;
+        let low = (encoding & 0x3f) as usize;
+        let item = match encoding >> 6 {
+            0..=2 => {
+                let n = match encoding >> 6 {
+                    0 => low,
+                    1 => low << 8 | reader.byte()? as usize,
+                    _ => u32::from_be_bytes(reader.array()?) as usize,
+                };
+                reader.take(n)?.to_vec()
+            }
+            _ => int_text(match encoding {
+                0xc0 => i16::from_le_bytes(reader.array()?) as i64,
+                0xd0 => i32::from_le_bytes(reader.array()?) as i64,
+                0xe0 => i64::from_le_bytes(reader.array()?),
+                0xf0 => {
+                    let [a, b, c] = reader.array()?;
+                    (i32::from_le_bytes([0, a, b, c]) >> 8) as i64
+                }
+                0xfe => reader.byte()? as i8 as i64,
+                0xf1..=0xfd => (encoding & 0x0f) as i64 - 1,
+                _ => return Err(corrupt("bad ziplist entry")),
+            }),
+        };
+        items.push(item);
+    }
+    Ok(items)
+}
+
+// How many bytes a listpack uses to store the length of an entry of `len`
+// bytes after it, seven bits to a byte.
+fn listpack_backlen_size(len: usize) -> usize {
+    match len {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+/// The entries of a listpack, integers as decimal text.
+fn listpack(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut reader = Reader { data };
+    // Total bytes and entry count.
+    reader.take(6)?;
+    let mut items = Vec::new();
+    loop {
+        let encoding = reader.byte()?;
+        if encoding == 0xff {
+            break;
+        }
+        // The entry, and its length counting the encoding byte.
+        let (item, len) = if encoding & 0x80 == 0 {
+            (int_text(encoding as i64), 1)
+        } else if encoding & 0xc0 == 0x80 {
+            let n = (encoding & 0x3f) as usize;
+            (reader.take(n)?.to_vec(), 1 + n)
+        } else if encoding & 0xe0 == 0xc0 {
+            let n = ((encoding & 0x1f) as i64) << 8 | reader.byte()? as i64;
+            // 13 bits, two's complement.
+            (int_text(if n >= 1 << 12 { n - (1 << 13) } else { n }), 2)
+        } else if encoding & 0xf0 == 0xe0 {
+            let n = ((encoding & 0x0f) as usize) << 8 | reader.byte()? as usize;
+            (reader.take(n)?.to_vec(), 2 + n)
+        } else {
+            match encoding {
+                0xf0 => {
+                    let n = u32::from_le_bytes(reader.array()?) as usize;
+                    (reader.take(n)?.to_vec(), 5 + n)
+                }
+                0xf1 => (int_text(i16::from_le_bytes(reader.array()?) as i64), 3),
+                0xf2 => {
+                    let [a, b, c] = reader.array()?;
+                    (int_text((i32::from_le_bytes([0, a, b, c]) >> 8) as i64), 4)
+                }
+                0xf3 => (int_text(i32::from_le_bytes(reader.array()?) as i64), 5),
+                0xf4 => (int_text(i64::from_le_bytes(reader.array()?)), 9),
+                _ => return Err(corrupt("bad listpack entry")),
+            }
+        };
+        reader.take(listpack_backlen_size(len))?;
+        items.push(item);
+    }
+    Ok(items)
+}
+
+/// The keys and values of a zipmap, the hash encoding before ziplists.
+fn zipmap(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    // A length of up to 253, or 254 and then four bytes; 255 ends the map.
+    fn len(reader: &mut Reader) -> Result<Option<usize>> {
+        Ok(match reader.byte()? {
+            0xff => None,
+            0xfe => Some(u32::from_le_bytes(reader.array()?) as usize),
+            n => Some(n as usize),
+        })
+    }
+
+    let mut reader = Reader { data };
+    // The count, which stops being kept past 253.
+    reader.byte()?;
+    let mut items = Vec::new();
+    while let Some(n) = len(&mut reader)? {
+        items.push(reader.take(n)?.to_vec());
+        let n = len(&mut reader)?.ok_or_else(|| corrupt("bad zipmap"))?;
+        let free = reader.byte()? as usize;
+        items.push(reader.take(n)?.to_vec());
+        reader.take(free)?;
+    }
+    Ok(items)
+}
+
+/// Reads an RDB file, checking its checksum when it has one. Keys that have
+/// expired by now are left out, and so are keys outside database 0.
+pub fn read(data: &[u8]) -> Result<Dataset> {
+    let header = MAGIC.len() + 4;
+    if data.len() < header || !data.starts_with(MAGIC) {
+        return Err(corrupt("not an RDB file"));
+    }
+    let version = std::str::from_utf8(&data[MAGIC.len()..header])
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .ok_or_else(|| corrupt("bad version"))?;
+    if version == 0 || version > MAX_VERSION {
+        return Err(unsupported(format!("RDB version {}", version)));
+    }
+    // Since version 5 the file ends in a checksum, which is 0 when Redis
+    // was told not to compute one.
+    let mut body = data;
+    if version >= 5 {
+        if data.len() < header + 9 {
+            return Err(corrupt("unexpected end"));
+        }
+        let (rest, checksum) = data.split_at(data.len() - 8);
+        let expected = u64::from_le_bytes(checksum.try_into().unwrap());
+        if expected != 0 && crc64(0, rest) != expected {
+            return Err(corrupt("checksum mismatch"));
+        }
+        body = rest;
+    }
+
+    let now = now_ms();
+    let mut reader = Reader {
+        data: &body[header..],
+    };
+    let mut dataset = Dataset::new();
+    let mut db = 0;
+    let mut expire_at = None;
+    loop {
+        match reader.byte()? {
+            OP_EOF => break,
+            OP_SELECTDB => db = reader.len()?,
+            OP_RESIZEDB => {
+                reader.len()?;
+                reader.len()?;
+            }
+            OP_AUX => {
+                reader.string()?;
+                reader.string()?;
+            }
+            OP_EXPIRETIME_MS => expire_at = Some(u64::from_le_bytes(reader.array()?)),
+            OP_EXPIRETIME => expire_at = Some(u32::from_le_bytes(reader.array()?) as u64 * 1000),
+            OP_FREQ => {
+                reader.byte()?;
+            }
+            OP_IDLE => {
+                reader.len()?;
+            }
+            OP_SLOT_INFO => {
+                for _ in 0..3 {
+                    reader.len()?;
+                }
+            }
+            // A library of functions, which Crache has no use for.
+            OP_FUNCTION2 => {
+                reader.string()?;
+            }
+            OP_FUNCTION_PRE_GA | OP_MODULE_AUX => {
+                return Err(unsupported("RDB module or function data".to_string()))
+            }
+            kind => {
+                let key = reader.string()?;
+                let entry = reader.value(kind)?;
+                let expire_at = expire_at.take();
+                if db != 0 || expire_at.is_some_and(|at| at <= now) || entry.is_empty_collection() {
+                    continue;
+                }
+                dataset.push((key, entry, expire_at));
+            }
+        }
+    }
+    if !reader.data.is_empty() {
+        return Err(corrupt("data after the end"));
+    }
+    Ok(dataset)
+}
+
+/// Reads the RDB file at `path`.
+pub fn load(path: &Path) -> Result<Dataset> {
+    read(&std::fs::read(path)?)
+}
@@ -1,111 +1,250 @@
-use std::{
-    io::{Cursor, Read},
-    vec,
-};
-#[derive(Clone, Debug)]
-pub struct Value {
-    pub typ: String,
-    pub str: String,       // equivalent to Go's `string`
-    pub num: i32,          // equivalent to Go's `int` (change to i64 if needed)
-    pub bulk: String,      // equivalent to Go's `string` (changed from Vec<u8> to String)
-    pub array: Vec<Value>, // equivalent to Go's `[]Value` (changed from Vec<u8> to Vec<Value>)
+use std::io::{Cursor, IoSlice, Read};
+
+/// Wire protocol spoken on a connection, negotiated with `HELLO`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
 }
 
-impl Value {
-    pub fn marshal(&self) -> Vec<u8> {
-        match self.typ.as_str() {
-            "array" => self.array_marshal(),
-            "bulk" => self.bulk_marshal(),
-            "string" => self.string_marshal(),
-            "integer" => self.integer_marshal(),
-            "null" => self.null_marshal(),
-            "error" => self.error_marshal(),
-            _ => return vec![],
+impl Protocol {
+    pub fn version(self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
         }
     }
+}
 
-    pub fn null_marshal(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        result.push(b'$'); // Use '$' for null type
-        result.extend_from_slice(b"-1"); // Use '-' to indicate null value
-        result.push(b'\r');
-        result.push(b'\n');
-        result
+/// A single RESP value, either parsed off the wire or produced as a reply.
+///
+/// The RESP3-only variants still have a RESP2 encoding (maps flatten into
+/// arrays, doubles become bulk strings and so on), so handlers can always
+/// reply with the richest type and let the connection's protocol decide.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Null,
+    /// The RESP2 null array `*-1`; RESP3 has a single null type.
+    NullArray,
+    Array(Vec<Frame>),
+    Map(Vec<(Frame, Frame)>),
+    Set(Vec<Frame>),
+    Double(f64),
+    Boolean(bool),
+    BigNumber(String),
+    BlobError(Vec<u8>),
+    /// A verbatim string; `format` is a three byte hint such as `txt` or `mkd`.
+    Verbatim {
+        format: String,
+        text: Vec<u8>,
+    },
+    Push(Vec<Frame>),
+    /// Out-of-band attributes that decorate the reply that follows them.
+    Attribute {
+        attrs: Vec<(Frame, Frame)>,
+        data: Box<Frame>,
+    },
+}
+
+impl Frame {
+    pub fn simple(s: impl Into<String>) -> Frame {
+        Frame::Simple(s.into())
     }
 
-    pub fn error_marshal(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        result.push(b'-'); // Use '-' for error type
-        result.extend_from_slice(self.str.as_bytes());
-        result.push(b'\r');
-        result.push(b'\n');
-        result
+    pub fn ok() -> Frame {
+        Frame::Simple("OK".to_string())
     }
 
-    pub fn string_marshal(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        result.push(b'+'); // Use '+' for string type
-        result.extend_from_slice(self.str.as_bytes());
-        result.push(b'\r');
-        result.push(b'\n');
-        result
+    pub fn error(msg: impl Into<String>) -> Frame {
+        Frame::Error(msg.into())
     }
 
-    pub fn bulk_marshal(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        result.push(b'$'); // Use '$' for bulk type
-        result.extend_from_slice(self.bulk.len().to_string().as_bytes());
-        result.push(b'\r');
-        result.push(b'\n');
-        result.extend_from_slice(self.bulk.as_bytes());
-        result.push(b'\r');
-        result.push(b'\n');
-        result
+    pub fn integer(n: i64) -> Frame {
+        Frame::Integer(n)
     }
 
-    pub fn integer_marshal(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        result.push(b':'); // Use ':' for integer type
-        result.extend_from_slice(self.num.to_string().as_bytes());
-        result.push(b'\r');
-        result.push(b'\n');
-        result
+    pub fn bulk(b: impl Into<Vec<u8>>) -> Frame {
+        Frame::Bulk(b.into())
+    }
+
+    pub fn array(items: Vec<Frame>) -> Frame {
+        Frame::Array(items)
+    }
+
+    pub fn map(pairs: Vec<(Frame, Frame)>) -> Frame {
+        Frame::Map(pairs)
     }
 
-    pub fn array_marshal(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        result.push(b'*'); // Use '*' for array type
-        result.extend_from_slice(self.array.len().to_string().as_bytes());
-        result.push(b'\r');
-        result.push(b'\n');
+    pub fn double(d: f64) -> Frame {
+        Frame::Double(d)
+    }
 
-        // Marshal each value in the array
-        for value in &self.array {
-            result.extend_from_slice(&value.marshal());
+    /// Returns the raw payload of a bulk string, or `None` for any other frame.
+    pub fn as_bulk(&self) -> Option<&[u8]> {
+        match self {
+            Frame::Bulk(b) => Some(b),
+            _ => None,
         }
+    }
 
+    pub fn is_error(&self) -> bool {
+        matches!(self, Frame::Error(_) | Frame::BlobError(_))
+    }
+
+    /// Encodes the frame as RESP2, the protocol every connection starts with.
+    pub fn marshal(&self) -> Vec<u8> {
+        self.marshal_with(Protocol::Resp2)
+    }
+
+    pub fn marshal_with(&self, protocol: Protocol) -> Vec<u8> {
+        let mut result = Vec::new();
+        self.marshal_into(&mut result, protocol);
         result
     }
 
-    pub fn print(&self) -> String {
-        match self.typ.as_str() {
-            "array" => {
-                let mut items = Vec::new();
-                for val in &self.array {
-                    items.push(val.print());
+    fn marshal_into(&self, out: &mut Vec<u8>, protocol: Protocol) {
+        let resp3 = protocol == Protocol::Resp3;
+        match self {
+            Frame::Simple(s) => line(out, b'+', s.as_bytes()),
+            Frame::Error(s) => line(out, b'-', s.as_bytes()),
+            Frame::Integer(n) => line(out, b':', n.to_string().as_bytes()),
+            Frame::Bulk(b) => blob(out, b'$', b),
+            Frame::Null | Frame::NullArray if resp3 => out.extend_from_slice(b"_\r\n"),
+            Frame::Null => out.extend_from_slice(b"$-1\r\n"),
+            Frame::NullArray => out.extend_from_slice(b"*-1\r\n"),
+            Frame::Array(items) => {
+                line(out, b'*', items.len().to_string().as_bytes());
+                for item in items {
+                    item.marshal_into(out, protocol);
                 }
-                format!("Array: [{}]", items.join(", "))
             }
-            "bulk" => format!("Bulk: \"{}\"", self.bulk),
-            "string" => format!("String: \"{}\"", self.str),
-            "integer" => format!("Integer: {}", self.num),
-            _ => "Unknown".to_string(),
+            Frame::Map(pairs) => {
+                if resp3 {
+                    line(out, b'%', pairs.len().to_string().as_bytes());
+                } else {
+                    line(out, b'*', (pairs.len() * 2).to_string().as_bytes());
+                }
+                for (k, v) in pairs {
+                    k.marshal_into(out, protocol);
+                    v.marshal_into(out, protocol);
+                }
+            }
+            Frame::Set(items) | Frame::Push(items) => {
+                let prefix = match self {
+                    Frame::Set(_) if resp3 => b'~',
+                    Frame::Push(_) if resp3 => b'>',
+                    _ => b'*',
+                };
+                line(out, prefix, items.len().to_string().as_bytes());
+                for item in items {
+                    item.marshal_into(out, protocol);
+                }
+            }
+            Frame::Double(d) if resp3 => line(out, b',', format_double(*d).as_bytes()),
+            Frame::Double(d) => blob(out, b'$', format_double(*d).as_bytes()),
+            Frame::Boolean(b) if resp3 => line(out, b'#', if *b { b"t" } else { b"f" }),
+            Frame::Boolean(b) => line(out, b':', if *b { b"1" } else { b"0" }),
+            Frame::BigNumber(n) if resp3 => line(out, b'(', n.as_bytes()),
+            Frame::BigNumber(n) => blob(out, b'$', n.as_bytes()),
+            Frame::BlobError(e) if resp3 => blob(out, b'!', e),
+            Frame::BlobError(e) => {
+                // A simple error cannot carry line breaks.
+                let flat: Vec<u8> = e
+                    .iter()
+                    .map(|&c| if c == b'\r' || c == b'\n' { b' ' } else { c })
+                    .collect();
+                line(out, b'-', &flat);
+            }
+            Frame::Verbatim { format, text } if resp3 => {
+                let mut payload = Vec::with_capacity(format.len() + 1 + text.len());
+                payload.extend_from_slice(format.as_bytes());
+                payload.push(b':');
+                payload.extend_from_slice(text);
+                blob(out, b'=', &payload);
+            }
+            Frame::Verbatim { text, .. } => blob(out, b'$', text),
+            Frame::Attribute { attrs, data } => {
+                // RESP2 has no way to express attributes, so they are dropped.
+                if resp3 {
+                    line(out, b'|', attrs.len().to_string().as_bytes());
+                    for (k, v) in attrs {
+                        k.marshal_into(out, protocol);
+                        v.marshal_into(out, protocol);
+                    }
+                }
+                data.marshal_into(out, protocol);
+            }
         }
     }
+
+    pub fn print(&self) -> String {
+        fn pairs(items: &[(Frame, Frame)]) -> String {
+            let items: Vec<String> = items
+                .iter()
+                .map(|(k, v)| format!("{} => {}", k.print(), v.print()))
+                .collect();
+            items.join(", ")
+        }
+        fn list(items: &[Frame]) -> String {
+            let items: Vec<String> = items.iter().map(|v| v.print()).collect();
+            items.join(", ")
+        }
+        match self {
+            Frame::Array(items) => format!("Array: [{}]", list(items)),
+            Frame::Bulk(b) => format!("Bulk: \"{}\"", String::from_utf8_lossy(b)),
+            Frame::Simple(s) => format!("String: \"{}\"", s),
+            Frame::Error(s) => format!("Error: \"{}\"", s),
+            Frame::Integer(n) => format!("Integer: {}", n),
+            Frame::Null | Frame::NullArray => "Null".to_string(),
+            Frame::Map(items) => format!("Map: {{{}}}", pairs(items)),
+            Frame::Set(items) => format!("Set: [{}]", list(items)),
+            Frame::Push(items) => format!("Push: [{}]", list(items)),
+            Frame::Double(d) => format!("Double: {}", format_double(*d)),
+            Frame::Boolean(b) => format!("Boolean: {}", b),
+            Frame::BigNumber(n) => format!("BigNumber: {}", n),
+            Frame::BlobError(e) => format!("Error: \"{}\"", String::from_utf8_lossy(e)),
+            Frame::Verbatim { format, text } => {
+                format!("Verbatim: {}:\"{}\"", format, String::from_utf8_lossy(text))
+            }
+            Frame::Attribute { attrs, data } => {
+                format!("Attribute: {{{}}} {}", pairs(attrs), data.print())
+            }
+        }
+    }
+}
+
+// Writes `<prefix><body>\r\n`.
+fn line(out: &mut Vec<u8>, prefix: u8, body: &[u8]) {
+    out.push(prefix);
+    out.extend_from_slice(body);
+    out.extend_from_slice(b"\r\n");
 }
 
-pub struct Resp {
-    pub reader: Result<Cursor<Vec<u8>>, std::io::Error>,
+// Writes a length-prefixed payload: `<prefix><len>\r\n<payload>\r\n`.
+fn blob(out: &mut Vec<u8>, prefix: u8, payload: &[u8]) {
+    line(out, prefix, payload.len().to_string().as_bytes());
+    out.extend_from_slice(payload);
+    out.extend_from_slice(b"\r\n");
+}
+
+/// Formats a double the way Redis does on the wire, including `inf`/`-inf`/`nan`.
+pub fn format_double(d: f64) -> String {
+    if d.is_nan() {
+        "nan".to_string()
+    } else if d.is_infinite() {
+        if d > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        d.to_string()
+    }
+}
+
+pub struct Resp<T = Vec<u8>> {
+    pub reader: Result<Cursor<T>, std::io::Error>,
 }
 
 impl Resp {
@@ -115,130 +254,244 @@ impl Resp {
             reader: Ok(Cursor::new(vec![])),
         }
     }
+}
 
+impl<T: AsRef<[u8]>> Resp<T> {
+    /// Number of bytes consumed from the underlying buffer so far.
+    pub fn position(&self) -> usize {
+        match self.reader.as_ref() {
+            Ok(c) => c.position() as usize,
+            Err(_) => 0,
+        }
+    }
+
+    fn cursor(&mut self) -> Result<&mut Cursor<T>, std::io::Error> {
+        match self.reader.as_mut() {
+            Ok(c) => Ok(c),
+            Err(e) => Err(std::io::Error::new(e.kind(), "previous error state")),
+        }
+    }
+
+    /// Reads up to and including the next `\r\n`, returning the line without
+    /// it and the number of bytes consumed.
     pub fn read_line(&mut self) -> Result<(Vec<u8>, usize), std::io::Error> {
-        let cursor = match self.reader.as_mut() {
-            Ok(c) => c,
-            Err(e) => return Err(std::io::Error::new(e.kind(), "previous error state")),
-        };
+        let cursor = self.cursor()?;
         let mut line = Vec::new();
-        let mut count = 0;
-        loop {
+        while !line.ends_with(b"\r\n") {
+            if line.len() > MAX_INLINE_LEN {
+                return Err(invalid_data("too big line"));
+            }
             let mut buf = [0u8; 1];
             // Attempt to read one byte; return an error if reading fails.
             cursor.read_exact(&mut buf)?;
-            count += 1;
             line.push(buf[0]);
-            // When we have at least two bytes and the second to last is '\r', assume the line ended.
-            if line.len() >= 2 && line[line.len() - 2] == b'\r' {
-                break;
-            }
-        }
-        // Ensure that there is a trailing "\r\n" to remove.
-        if line.len() < 2 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "line too short",
-            ));
         }
+        let count = line.len();
         // Remove the trailing "\r\n"
         line.truncate(line.len() - 2);
         Ok((line, count))
     }
 
-    pub fn read(&mut self) -> Result<Value, std::io::Error> {
-        // Get mutable access to the cursor.
-        let cursor = match self.reader.as_mut() {
-            Ok(c) => c,
-            Err(e) => return Err(std::io::Error::new(e.kind(), "previous error state")),
-        };
+    pub fn read(&mut self) -> Result<Frame, std::io::Error> {
+        self.read_at(0)
+    }
+
+    // Reads one frame nested `depth` aggregates deep.
+    fn read_at(&mut self, depth: usize) -> Result<Frame, std::io::Error> {
+        if depth > MAX_DEPTH {
+            return Err(invalid_data("too deeply nested aggregate"));
+        }
+
         // Read a single byte for the type
         let mut buf = [0u8; 1];
-        cursor.read_exact(&mut buf)?;
+        self.cursor()?.read_exact(&mut buf)?;
         let type_byte = buf[0];
 
         // Match on the type byte
         match type_byte {
-            b'*' => self.read_array(),
+            b'*' => self.read_array_at(depth),
             b'$' => self.read_bulk(),
-            b'+' => {
-                let (line, _) = self.read_line()?;
-                let str_val = String::from_utf8(line).map_err(|_| {
-                    std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid UTF-8")
-                })?;
-                Ok(Value {
-                    typ: "string".to_string(),
-                    str: str_val,
-                    num: 0,
-                    bulk: String::new(),
-                    array: vec![],
-                })
+            b'+' => Ok(Frame::Simple(self.read_text()?)),
+            b'-' => Ok(Frame::Error(self.read_text()?)),
+            b':' => Ok(Frame::Integer(self.read_integer()?)),
+            b'_' => {
+                self.read_line()?;
+                Ok(Frame::Null)
+            }
+            b'#' => match self.read_line()?.0.as_slice() {
+                b"t" => Ok(Frame::Boolean(true)),
+                b"f" => Ok(Frame::Boolean(false)),
+                _ => Err(invalid_data("Invalid boolean")),
+            },
+            b',' => {
+                let text = self.read_text()?;
+                let d = text
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| invalid_data("Cannot parse double"))?;
+                Ok(Frame::Double(d))
             }
-            b':' => {
-                let num_val = self.read_integer()?;
-                Ok(Value {
-                    typ: "integer".to_string(),
-                    str: String::new(),
-                    num: num_val,
-                    bulk: String::new(),
-                    array: vec![],
-                })
+            b'(' => {
+                let text = self.read_text()?;
+                let digits = text.strip_prefix(['-', '+']).unwrap_or(&text);
+                if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+                    return Err(invalid_data("Invalid big number"));
+                }
+                Ok(Frame::BigNumber(text))
+            }
+            b'!' => match self.read_bulk()? {
+                Frame::Bulk(b) => Ok(Frame::BlobError(b)),
+                other => Ok(other),
+            },
+            b'=' => match self.read_bulk()? {
+                Frame::Bulk(b) => {
+                    if b.len() < 4 || b[3] != b':' {
+                        return Err(invalid_data("Invalid verbatim string"));
+                    }
+                    Ok(Frame::Verbatim {
+                        format: String::from_utf8_lossy(&b[..3]).into_owned(),
+                        text: b[4..].to_vec(),
+                    })
+                }
+                other => Ok(other),
+            },
+            b'%' => Ok(Frame::Map(self.read_pairs(depth)?)),
+            b'|' => {
+                let attrs = self.read_pairs(depth)?;
+                // The value sits one level inside its attributes, so a chain
+                // of them counts toward `MAX_DEPTH` like any nesting.
+                let data = Box::new(self.read_at(depth + 1)?);
+                Ok(Frame::Attribute { attrs, data })
             }
-            _ => Ok(Value {
-                typ: "error".to_string(),
-                str: format!("Unexpected type byte: {}", type_byte as char),
-                num: 0,
-                bulk: String::new(),
-                array: vec![],
-            }),
+            b'~' | b'>' => {
+                let items = match self.read_array_at(depth)? {
+                    Frame::Array(items) => items,
+                    other => return Ok(other),
+                };
+                if type_byte == b'~' {
+                    Ok(Frame::Set(items))
+                } else {
+                    Ok(Frame::Push(items))
+                }
+            }
+            // Anything else is an inline command typed by a human, e.g. over
+            // telnet or netcat: `SET key "hello world"`. Those are only ever
+            // whole commands, never elements of one.
+            _ if depth == 0 => self.read_inline(type_byte),
+            _ => Err(invalid_data(&format!(
+                "expected '$', got '{}'",
+                type_byte.escape_ascii()
+            ))),
         }
     }
 
-    pub fn read_integer(&mut self) -> Result<i32, std::io::Error> {
+    /// Reads the rest of an inline command whose first byte was `first`. The
+    /// line ends at `\n` with an optional `\r`, and is split into bulk string
+    /// arguments with `split_args`.
+    fn read_inline(&mut self, first: u8) -> Result<Frame, std::io::Error> {
+        let cursor = self.cursor()?;
+        let mut line = vec![first];
+        while line.last() != Some(&b'\n') {
+            if line.len() > MAX_INLINE_LEN {
+                return Err(invalid_data("too big inline request"));
+            }
+            let mut buf = [0u8; 1];
+            cursor.read_exact(&mut buf)?;
+            line.push(buf[0]);
+        }
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+
+        let args = split_args(&line).ok_or_else(|| invalid_data("unbalanced quotes in request"))?;
+        Ok(Frame::Array(args.into_iter().map(Frame::Bulk).collect()))
+    }
+
+    pub fn read_integer(&mut self) -> Result<i64, std::io::Error> {
         // Call our existing read_line method.
         let (line, _) = self.read_line()?;
         // Convert the line (Vec<u8>) into a &str.
-        let s = std::str::from_utf8(&line)
-            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid UTF-8"))?;
+        let s = std::str::from_utf8(&line).map_err(|_| invalid_data("Invalid UTF-8"))?;
         // Trim any whitespace and attempt to parse the integer.
-        let int_val = s.trim().parse::<i64>().map_err(|_| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, "Cannot parse int")
-        })?;
-        Ok(int_val as i32)
+        s.trim()
+            .parse::<i64>()
+            .map_err(|_| invalid_data("Cannot parse int"))
     }
 
-    pub fn read_array(&mut self) -> Result<Value, std::io::Error> {
-        // Read the length of the array
-        let length = self.read_integer()? as usize;
+    // Reads an aggregate length: `Ok(None)` for the `-1` null marker, and an
+    // error for any other negative or oversized count.
+    fn read_length(&mut self, max: usize, what: &str) -> Result<Option<usize>, std::io::Error> {
+        match self.read_integer()? {
+            -1 => Ok(None),
+            n if n < 0 || n as u64 > max as u64 => {
+                Err(invalid_data(&format!("invalid {} length", what)))
+            }
+            n => Ok(Some(n as usize)),
+        }
+    }
 
-        // Create an array to store values
-        let mut array = Vec::with_capacity(length);
+    // Reads a line that must be valid UTF-8.
+    fn read_text(&mut self) -> Result<String, std::io::Error> {
+        let (line, _) = self.read_line()?;
+        String::from_utf8(line).map_err(|_| invalid_data("Invalid UTF-8"))
+    }
+
+    // Reads the body of a map or attribute: a count followed by key/value pairs.
+    fn read_pairs(&mut self, depth: usize) -> Result<Vec<(Frame, Frame)>, std::io::Error> {
+        let length = self.read_length(MAX_ARRAY_LEN, "map")?.unwrap_or(0);
+        let mut pairs = Vec::with_capacity(length.min(PREALLOC_LIMIT));
+        for _ in 0..length {
+            let key = self.read_at(depth + 1)?;
+            let value = self.read_at(depth + 1)?;
+            pairs.push((key, value));
+        }
+        Ok(pairs)
+    }
+
+    pub fn read_array(&mut self) -> Result<Frame, std::io::Error> {
+        self.read_array_at(0)
+    }
+
+    fn read_array_at(&mut self, depth: usize) -> Result<Frame, std::io::Error> {
+        // Read the length of the array; `*-1` is the RESP2 null array.
+        let Some(length) = self.read_length(MAX_ARRAY_LEN, "multibulk")? else {
+            return Ok(Frame::NullArray);
+        };
+
+        // The declared length is untrusted, so only a bounded amount is
+        // reserved up front and the rest grows as elements actually arrive.
+        let mut array = Vec::with_capacity(length.min(PREALLOC_LIMIT));
 
         // Read each value recursively
         for _ in 0..length {
-            let val = self.read()?;
+            let val = self.read_at(depth + 1)?;
             array.push(val);
         }
 
-        Ok(Value {
-            typ: "array".to_string(),
-            str: String::new(),
-            num: 0,
-            bulk: String::new(),
-            array:array,
-        })
+        Ok(Frame::Array(array))
     }
 
-    pub fn read_bulk(&mut self) -> Result<Value, std::io::Error> {
-        // Read the length of the bulk string
-        let length = self.read_integer()? as usize;
+    pub fn read_bulk(&mut self) -> Result<Frame, std::io::Error> {
+        // Read the length of the bulk string; `$-1` is the null bulk string.
+        let Some(length) = self.read_length(MAX_BULK_LEN, "bulk")? else {
+            return Ok(Frame::Null);
+        };
+
+        let cursor = self.cursor()?;
+
+        // Bail out before allocating when the payload has not fully arrived yet;
+        // a streaming caller will retry once more bytes are buffered.
+        let remaining = cursor.get_ref().as_ref().len() as u64 - cursor.position();
+        if remaining < length as u64 + 2 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "incomplete bulk string",
+            ));
+        }
 
         // Read exactly length bytes for the content
         let mut buffer = vec![0u8; length];
-        let cursor = match self.reader.as_mut() {
-            Ok(c) => c,
-            Err(e) => return Err(std::io::Error::new(e.kind(), "previous error state")),
-        };
 
         cursor.read_exact(&mut buffer)?;
 
@@ -246,42 +499,332 @@ impl Resp {
         let mut crlf = [0u8; 2];
         cursor.read_exact(&mut crlf)?;
         if crlf != *b"\r\n" {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Missing CRLF after bulk string",
-            ));
+            return Err(invalid_data("Missing CRLF after bulk string"));
+        }
+
+        // Bulk strings are binary safe, so the payload is kept as raw bytes.
+        Ok(Frame::Bulk(buffer))
+    }
+}
+
+/// Largest bulk string payload accepted from the wire (Redis' `proto-max-bulk-len`).
+pub const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// Largest element count accepted for an array, set, push or map.
+pub const MAX_ARRAY_LEN: usize = i32::MAX as usize;
+
+/// Deepest aggregate nesting accepted, so hostile input cannot blow the stack.
+pub const MAX_DEPTH: usize = 128;
+
+// Upper bound on elements reserved ahead of parsing an aggregate.
+const PREALLOC_LIMIT: usize = 1024;
+
+/// Longest inline command accepted before the connection is considered broken.
+pub const MAX_INLINE_LEN: usize = 64 * 1024;
+
+/// Splits an inline command line into arguments the way `redis-cli` does.
+///
+/// Arguments are separated by whitespace. Double quoted arguments understand
+/// the escapes `\n \r \t \b \a \\ \"` and `\xHH`; single quoted arguments
+/// only `\'`. A closing quote must be followed by whitespace or the end of
+/// the line. Returns `None` for unbalanced quotes.
+pub fn split_args(line: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Some(args);
+        }
+
+        let mut current = Vec::new();
+        let mut in_double = false;
+        let mut in_single = false;
+        loop {
+            if in_double {
+                let c = *line.get(i)?;
+                if c == b'\\' && i + 3 < line.len() && line[i + 1] == b'x' {
+                    let hex = std::str::from_utf8(&line[i + 2..i + 4]).ok();
+                    if let Some(byte) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                        current.push(byte);
+                        i += 4;
+                        continue;
+                    }
+                }
+                if c == b'\\' && i + 1 < line.len() {
+                    current.push(match line[i + 1] {
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'b' => 0x08,
+                        b'a' => 0x07,
+                        other => other,
+                    });
+                    i += 2;
+                } else if c == b'"' {
+                    // The closing quote must be followed by a space or nothing.
+                    if i + 1 < line.len() && !line[i + 1].is_ascii_whitespace() {
+                        return None;
+                    }
+                    i += 1;
+                    break;
+                } else {
+                    current.push(c);
+                    i += 1;
+                }
+            } else if in_single {
+                let c = *line.get(i)?;
+                if c == b'\\' && line.get(i + 1) == Some(&b'\'') {
+                    current.push(b'\'');
+                    i += 2;
+                } else if c == b'\'' {
+                    if i + 1 < line.len() && !line[i + 1].is_ascii_whitespace() {
+                        return None;
+                    }
+                    i += 1;
+                    break;
+                } else {
+                    current.push(c);
+                    i += 1;
+                }
+            } else {
+                match line.get(i) {
+                    None => break,
+                    Some(c) if c.is_ascii_whitespace() => break,
+                    Some(b'"') => in_double = true,
+                    Some(b'\'') => in_single = true,
+                    Some(&c) => current.push(c),
+                }
+                i += 1;
+            }
+        }
+        args.push(current);
+    }
+}
+
+fn invalid_data(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Incremental decoder for a byte stream such as a TCP connection.
+///
+/// Bytes are appended as they arrive and complete frames are handed out one at
+/// a time. An incomplete frame is not an error: `decode` returns `Ok(None)` and
+/// the partial bytes stay buffered until the rest shows up.
+#[derive(Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+    // Start of the unconsumed region of `buffer`.
+    start: usize,
+    // How much of the frame at `start` is known to have arrived.
+    scan: Scan,
+}
+
+// Progress through a partly received frame, kept between reads so the bytes
+// already buffered are looked at once rather than parsed again on every read.
+struct Scan {
+    // Offset from `Decoder::start` of the next element header.
+    pos: usize,
+    // Elements each open aggregate still expects, outermost first; the
+    // frame is complete once it is empty.
+    pending: Vec<u64>,
+}
+
+impl Default for Scan {
+    fn default() -> Self {
+        Scan {
+            pos: 0,
+            pending: vec![1],
+        }
+    }
+}
+
+impl Scan {
+    // Walks the element headers in `data` from where the last call stopped,
+    // skipping bulk payloads by their length. Returns `true` once the frame
+    // is complete, or once it holds something only the parser can judge
+    // (and most likely reject); `false` while more bytes are needed.
+    fn advance(&mut self, data: &[u8]) -> bool {
+        while let Some(&remaining) = self.pending.last() {
+            if remaining == 0 {
+                self.pending.pop();
+                continue;
+            }
+            if self.pending.len() > MAX_DEPTH + 1 {
+                return true;
+            }
+            let rest = &data[self.pos..];
+            let Some(&kind) = rest.first() else {
+                return false;
+            };
+            let inline = !b"*$+-:_#,(!=%|~>".contains(&kind);
+            if inline && self.pending.len() > 1 {
+                // Inline commands are never nested; the parser rejects it.
+                return true;
+            }
+            // The header line, terminator included.
+            let window = &rest[..rest.len().min(MAX_INLINE_LEN + 3)];
+            let line = if inline {
+                window.iter().position(|&b| b == b'\n').map(|i| i + 1)
+            } else {
+                window.windows(2).position(|w| w == b"\r\n").map(|i| i + 2)
+            };
+            let Some(line) = line else {
+                // Too long a line is the parser's to reject.
+                return window.len() == MAX_INLINE_LEN + 3;
+            };
+            let length = || {
+                std::str::from_utf8(&rest[1..line - 2])
+                    .ok()
+                    .and_then(|n| n.trim().parse::<i64>().ok())
+            };
+            let end = match kind {
+                b'*' | b'~' | b'>' | b'%' | b'|' => {
+                    let children = match length() {
+                        Some(-1) if kind == b'|' => 1,
+                        Some(-1) => 0,
+                        Some(n) if n >= 0 && n as u64 <= MAX_ARRAY_LEN as u64 => match kind {
+                            b'%' => n as u64 * 2,
+                            // The attributes, then the value they describe.
+                            b'|' => n as u64 * 2 + 1,
+                            _ => n as u64,
+                        },
+                        _ => return true,
+                    };
+                    self.pos += line;
+                    *self.pending.last_mut().unwrap() -= 1;
+                    self.pending.push(children);
+                    continue;
+                }
+                b'$' | b'!' | b'=' => match length() {
+                    Some(-1) => line,
+                    Some(n) if n >= 0 && n as u64 <= MAX_BULK_LEN as u64 => line + n as usize + 2,
+                    _ => return true,
+                },
+                _ => line,
+            };
+            if rest.len() < end {
+                return false;
+            }
+            self.pos += end;
+            *self.pending.last_mut().unwrap() -= 1;
+        }
+        true
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder::default()
+    }
+
+    pub fn extend(&mut self, data: &[u8]) {
+        // Reclaim the consumed prefix once it dominates the buffer, so a
+        // long-lived connection does not grow without bound.
+        if self.start > 0 && self.start * 2 >= self.buffer.len() {
+            self.buffer.drain(..self.start);
+            self.start = 0;
         }
+        self.buffer.extend_from_slice(data);
+    }
 
-        // Convert binary data to UTF-8 string
-        let bulk_str = String::from_utf8(buffer).map_err(|_| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "Invalid UTF-8 in bulk string",
-            )
-        })?;
-
-        Ok(Value {
-            typ: "bulk".to_string(),
-            str: String::new(),
-            num: 0,
-            bulk: bulk_str,
-            array: vec![],
-        })
+    /// Number of buffered bytes that have not been decoded yet.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() - self.start
+    }
+
+    pub fn decode(&mut self) -> Result<Option<Frame>, std::io::Error> {
+        if self.buffered() == 0 || !self.scan.advance(&self.buffer[self.start..]) {
+            return Ok(None);
+        }
+        let mut resp = Resp {
+            reader: Ok(Cursor::new(&self.buffer[self.start..])),
+        };
+        match resp.read() {
+            Ok(frame) => {
+                self.start += resp.position();
+                self.scan = Scan::default();
+                if self.start == self.buffer.len() {
+                    self.buffer.clear();
+                    self.start = 0;
+                }
+                Ok(Some(frame))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 }
 
 pub struct Writer<W: std::io::Write> {
     pub writer: W,
+    pub protocol: Protocol,
 }
 
 impl<W: std::io::Write> Writer<W> {
     pub fn new(writer: W) -> Self {
-        Writer { writer }
+        Writer::with_protocol(writer, Protocol::Resp2)
+    }
+
+    pub fn with_protocol(writer: W, protocol: Protocol) -> Self {
+        Writer { writer, protocol }
     }
 
-    pub fn write(&mut self, v: &Value) -> Result<Vec<u8>, std::io::Error> {
-        let bytes = v.marshal();
+    pub fn write(&mut self, v: &Frame) -> Result<Vec<u8>, std::io::Error> {
+        let bytes = v.marshal_with(self.protocol);
         self.writer.write_all(&bytes)?;
         Ok(bytes)
     }
+
+    /// Writes every frame in order, handing all of them to the underlying
+    /// writer as one vectored write where possible. Returns the bytes written.
+    pub fn write_batch(&mut self, frames: &[Frame]) -> Result<usize, std::io::Error> {
+        let bufs: Vec<Vec<u8>> = frames
+            .iter()
+            .map(|f| f.marshal_with(self.protocol))
+            .collect();
+        self.write_encoded(&bufs)
+    }
+
+    /// Like `write_batch`, for replies that were already encoded (for example
+    /// because the protocol changed part way through a pipeline).
+    pub fn write_encoded(&mut self, bufs: &[Vec<u8>]) -> Result<usize, std::io::Error> {
+        let total = bufs.iter().map(|b| b.len()).sum();
+
+        // Position of the first byte not yet written: buffer index and offset.
+        let (mut idx, mut off) = (0, 0);
+        while idx < bufs.len() {
+            if off == bufs[idx].len() {
+                idx += 1;
+                off = 0;
+                continue;
+            }
+            let slices: Vec<IoSlice> = std::iter::once(IoSlice::new(&bufs[idx][off..]))
+                .chain(bufs[idx + 1..].iter().map(|b| IoSlice::new(b)))
+                .collect();
+            let mut n = self.writer.write_vectored(&slices)?;
+            if n == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::WriteZero,
+                    "failed to write whole batch",
+                ));
+            }
+            // Short writes are allowed, so advance past exactly what went out.
+            while n > 0 {
+                let left = bufs[idx].len() - off;
+                if n >= left {
+                    n -= left;
+                    idx += 1;
+                    off = 0;
+                } else {
+                    off += n;
+                    n = 0;
+                }
+            }
+        }
+        self.writer.flush()?;
+        Ok(total)
+    }
 }
@@ -0,0 +1,182 @@
+use crate::app::aof::{self, Aof};
+use crate::app::handler::{dispatch, get_handler, rewrite_aof, Client};
+use crate::app::resp::{Decoder, Frame, Writer};
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+/// Accepts connections forever, serving each one on its own thread. Every
+/// connection logs to the same `aof`.
+pub fn serve(listener: TcpListener, aof: Option<Arc<Aof>>) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                // A peer that reset the connection straight away has no
+                // address left to report; just drop it.
+                match stream.peer_addr() {
+                    Ok(addr) => println!("New connection: {}", addr),
+                    Err(e) => {
+                        println!("Connection failed: {}", e);
+                        continue;
+                    }
+                }
+                let aof = aof.clone();
+                thread::spawn(move || {
+                    handle_client(stream, aof);
+                });
+            }
+            Err(e) => {
+                println!("Connection failed: {}", e);
+            }
+        }
+    }
+}
+
+pub fn handle_client(mut stream: TcpStream, aof: Option<Arc<Aof>>) {
+    let mut buffer = [0u8; 4096];
+    let mut decoder = Decoder::new();
+    let mut client = Client::new();
+    client.connection = stream.try_clone().ok();
+    client.aof = aof.clone();
+    let aof = aof.as_deref();
+
+    loop {
+        // Process client request
+        let n = match stream.read(&mut buffer) {
+            Ok(0) => {
+                println!("Client disconnected");
+                break;
+            }
+            Ok(n) => n,
+            Err(e) => {
+                println!("Error reading stream: {}", e);
+                break;
+            }
+        };
+        decoder.extend(&buffer[..n]);
+
+        // Drain every complete frame that arrived (clients may pipeline many
+        // commands in one write), then flush all replies together. A trailing
+        // partial frame stays buffered until the rest of it arrives. Replies
+        // are encoded as they are produced since HELLO can switch protocols
+        // part way through a pipeline.
+        let mut replies = Vec::new();
+        let mut closing = false;
+        loop {
+            match decoder.decode() {
+                Ok(Some(value)) => {
+                    // Like a frame that fails to decode, one that is not a
+                    // command ends the connection after its error.
+                    let malformed = !is_command(&value);
+                    if let Some(reply) = handle_command(&mut client, value) {
+                        replies.push(reply.marshal_with(client.protocol));
+                    }
+                    if client.shutdown {
+                        shutdown(aof);
+                    }
+                    if malformed {
+                        closing = true;
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    println!("Error parsing RESP: {}", e);
+                    let reply = Frame::error(format!("ERR Protocol error: {}", e));
+                    replies.push(reply.marshal_with(client.protocol));
+                    closing = true;
+                    break;
+                }
+            }
+        }
+
+        // What these commands logged reaches the file (and under
+        // `appendfsync always`, the disk) before any of them is answered.
+        if let Some(aof) = aof {
+            if let Err(e) = aof.flush() {
+                eprintln!("Error writing AOF file: {}", e);
+                break;
+            }
+        }
+        if let Err(e) = Writer::new(&mut stream).write_encoded(&replies) {
+            eprintln!("Error writing response: {}", e);
+            break;
+        }
+        if closing {
+            break;
+        }
+    }
+}
+
+// Syncs the AOF and exits, as asked by `SHUTDOWN`.
+fn shutdown(aof: Option<&Aof>) -> ! {
+    if let Some(aof) = aof {
+        if let Err(e) = aof.sync() {
+            eprintln!("Error syncing AOF file: {}", e);
+        }
+    }
+    println!("Shutting down");
+    std::process::exit(0)
+}
+
+// Whether `value` has the shape of a command: an array of bulk strings.
+fn is_command(value: &Frame) -> bool {
+    matches!(value, Frame::Array(items) if items.iter().all(|i| matches!(i, Frame::Bulk(_))))
+}
+
+/// Runs one command frame and returns the reply to send, if any: an empty
+/// command is ignored, as in Redis, and anything but an array of bulk
+/// strings is a protocol error. What the command logs is appended to the
+/// client's AOF but left for the caller to flush.
+pub fn handle_command(client: &mut Client, value: Frame) -> Option<Frame> {
+    let aof = client.aof.clone();
+    if !is_command(&value) {
+        return Some(Frame::error(
+            "ERR Protocol error: expected an array of bulk strings",
+        ));
+    }
+    let argv = match value {
+        Frame::Array(items) if !items.is_empty() => items,
+        _ => return None,
+    };
+
+    // Write commands are logged exactly as received once they succeed,
+    // unless the handler asked for something else to be logged instead.
+    let command = get_handler(&String::from_utf8_lossy(
+        argv[0].as_bulk().unwrap_or_default(),
+    ));
+    let logged = match (command, &aof) {
+        (Some(command), Some(_)) if command.is_write() => Some(argv.clone()),
+        _ => None,
+    };
+    // Writes are logged in the order they run; anything else that turns
+    // out to log something takes its turn then.
+    if logged.is_some() {
+        client.order_log();
+    }
+
+    let reply = dispatch(client, argv);
+
+    if let Some(aof) = aof {
+        let mut commands = client.take_also_propagated();
+        if let Some(argv) = logged {
+            if !reply.is_error() {
+                commands.extend(client.take_propagation().unwrap_or_else(|| vec![argv]));
+            }
+        }
+        for argv in commands {
+            aof.append(&Frame::array(argv).marshal());
+        }
+        // Only while holding the log order, so the snapshot lines up with
+        // what is appended after it.
+        if client.log_order.is_some() && aof.rewrite_due(aof::auto_rewrite()) {
+            if let Err(e) = rewrite_aof(&aof) {
+                eprintln!("Error starting AOF rewrite: {}", e);
+            }
+        }
+    }
+    client.log_order = None;
+
+    Some(reply)
+}
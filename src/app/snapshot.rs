@@ -0,0 +1,412 @@
+//! Point-in-time snapshots of the whole keyspace, written by `SAVE` and
+//! `BGSAVE` and loaded at startup.
+//!
+//! The format is Crache's own, laid out like a simplified RDB file:
+//!
+//! ```text
+//! "CRACHE" version:u8
+//! ( [0xFC expire_ms:u64] type:u8 key value )*
+//! 0xFF crc64:u64
+//! ```
+//!
+//! Integers are little-endian, lengths are LEB128 varints and strings are a
+//! length followed by the bytes. Values by type:
+//!
+//! - string (0): the string.
+//! - list (1) and set (2): a count, then that many strings.
+//! - sorted set (3): a count, then member strings each followed by the
+//!   score as the bits of an `f64`.
+//! - hash (4): a count, then field, value and the field's expiry in
+//!   milliseconds, 0 for none.
+//!
+//! The checksum is `util::crc64` over everything before it.
+
+use crate::app::hash::Hash;
+use crate::app::keyspace::{Entry, List, Set};
+use crate::app::util::{crc64, now_ms, sync_parent_dir};
+use crate::app::zset::SortedSet;
+use std::borrow::Borrow;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use lazy_static::lazy_static;
+
+pub const MAGIC: &[u8] = b"CRACHE";
+pub const VERSION: u8 = 1;
+
+const OP_EXPIRE_MS: u8 = 0xfc;
+const OP_EOF: u8 = 0xff;
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+
+/// The keys of a dataset with their values and absolute expiry times, as
+/// taken for a snapshot or an AOF rewrite.
+pub type Dataset = Vec<(Vec<u8>, Entry, Option<u64>)>;
+
+/// A `Dataset` whose values are still shared with the keyspace, so taking
+/// one copies the keys but none of the values; see `Keyspace::iter_shared`.
+pub type SharedDataset = Vec<(Vec<u8>, Arc<Entry>, Option<u64>)>;
+
+/// Where snapshots go and when they are taken on their own, as Redis's
+/// `dbfilename` and `save`, set through `CONFIG SET`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SaveConfig {
+    pub dbfilename: String,
+    /// `(seconds, changes)`: save once at least `changes` writes have been
+    /// made and `seconds` have passed since the last save. Empty turns
+    /// automatic saves off.
+    pub save_points: Vec<(u64, u64)>,
+}
+
+impl Default for SaveConfig {
+    fn default() -> Self {
+        SaveConfig {
+            dbfilename: "dump.crs".to_string(),
+            save_points: vec![(3600, 1), (300, 100), (60, 10000)],
+        }
+    }
+}
+
+impl SaveConfig {
+    /// The save points as `CONFIG GET save` shows them.
+    pub fn save_points_string(&self) -> String {
+        self.save_points
+            .iter()
+            .map(|(seconds, changes)| format!("{} {}", seconds, changes))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Parses `"<seconds> <changes> ..."`; an empty string means none.
+    pub fn parse_save_points(s: &str) -> Option<Vec<(u64, u64)>> {
+        let numbers: Vec<u64> = s
+            .split_whitespace()
+            .map(|n| n.parse().ok())
+            .collect::<Option<_>>()?;
+        if numbers.len() % 2 != 0 {
+            return None;
+        }
+        Some(numbers.chunks(2).map(|p| (p[0], p[1])).collect())
+    }
+}
+
+lazy_static! {
+    static ref SAVE_CONFIG: RwLock<SaveConfig> = RwLock::new(SaveConfig::default());
+}
+
+pub fn save_config() -> SaveConfig {
+    SAVE_CONFIG.read().unwrap().clone()
+}
+
+/// Changes where and when snapshots are saved, as `CONFIG SET` does.
+pub fn set_save_config(config: SaveConfig) {
+    *SAVE_CONFIG.write().unwrap() = config;
+}
+
+// Passes writes through, keeping the checksum of everything written.
+pub(crate) struct Checksummed<W> {
+    pub(crate) inner: W,
+    pub(crate) crc: u64,
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc = crc64(self.crc, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn write_len(out: &mut impl Write, mut n: u64) -> Result<()> {
+    let mut bytes = Vec::with_capacity(10);
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            bytes.push(byte);
+            break;
+        }
+        bytes.push(byte | 0x80);
+    }
+    out.write_all(&bytes)
+}
+
+fn write_string(out: &mut impl Write, s: &[u8]) -> Result<()> {
+    write_len(out, s.len() as u64)?;
+    out.write_all(s)
+}
+
+fn type_of(entry: &Entry) -> u8 {
+    match entry {
+        Entry::String(_) => TYPE_STRING,
+        Entry::List(_) => TYPE_LIST,
+        Entry::Set(_) => TYPE_SET,
+        Entry::ZSet(_) => TYPE_ZSET,
+        Entry::Hash(_) => TYPE_HASH,
+    }
+}
+
+fn write_value(out: &mut impl Write, entry: &Entry) -> Result<()> {
+    match entry {
+        Entry::String(value) => write_string(out, value),
+        Entry::List(list) => {
+            write_len(out, list.len() as u64)?;
+            list.iter().try_for_each(|item| write_string(out, item))
+        }
+        Entry::Set(set) => {
+            write_len(out, set.len() as u64)?;
+            set.keys().try_for_each(|member| write_string(out, member))
+        }
+        Entry::ZSet(zset) => {
+            write_len(out, zset.len() as u64)?;
+            zset.iter().try_for_each(|(member, score)| {
+                write_string(out, member)?;
+                out.write_all(&score.to_bits().to_le_bytes())
+            })
+        }
+        Entry::Hash(hash) => {
+            // Collected first so the count matches even if a field expires
+            // while they are written.
+            let fields: Vec<_> = hash.iter().collect();
+            write_len(out, fields.len() as u64)?;
+            fields.into_iter().try_for_each(|(field, value)| {
+                write_string(out, field)?;
+                write_string(out, value)?;
+                write_len(out, hash.expire_at(field).flatten().unwrap_or(0))
+            })
+        }
+    }
+}
+
+/// Writes a snapshot of `dataset`, a `Dataset` or a `SharedDataset`, to
+/// `out`.
+pub fn write<E: Borrow<Entry>>(
+    out: impl Write,
+    dataset: &[(Vec<u8>, E, Option<u64>)],
+) -> Result<()> {
+    let mut out = Checksummed { inner: out, crc: 0 };
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION])?;
+    for (key, entry, expire_at) in dataset {
+        let entry = entry.borrow();
+        if let Some(at) = expire_at {
+            out.write_all(&[OP_EXPIRE_MS])?;
+            out.write_all(&at.to_le_bytes())?;
+        }
+        out.write_all(&[type_of(entry)])?;
+        write_string(&mut out, key)?;
+        write_value(&mut out, entry)?;
+    }
+    out.write_all(&[OP_EOF])?;
+    let crc = out.crc;
+    out.inner.write_all(&crc.to_le_bytes())?;
+    out.flush()
+}
+
+// Where `write_file` writes before it replaces `path`.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+/// Writes `path` through `write`, replacing any previous file only once
+/// the new one is complete and on disk.
+pub fn write_file(path: &Path, write: impl FnOnce(&mut dyn Write) -> Result<()>) -> Result<()> {
+    let temp = temp_path(path);
+    let result = (|| {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp)?;
+        let mut out = BufWriter::new(&file);
+        write(&mut out)?;
+        out.flush()?;
+        drop(out);
+        file.sync_all()?;
+        std::fs::rename(&temp, path)?;
+        sync_parent_dir(path)
+    })();
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Writes a snapshot of `dataset` to `path`, as `write_file` does.
+pub fn save<E: Borrow<Entry>>(path: &Path, dataset: &[(Vec<u8>, E, Option<u64>)]) -> Result<()> {
+    write_file(path, |out| write(out, dataset))
+}
+
+fn corrupt(what: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("corrupt snapshot: {}", what),
+    )
+}
+
+// Reads the body of a snapshot, its checksum already verified.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.data.len() {
+            return Err(corrupt("unexpected end"));
+        }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<u64> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(corrupt("length too long"))
+    }
+
+    // A count of items, each at least a byte long, so a corrupt count
+    // cannot make us reserve more than the file holds.
+    fn count(&mut self) -> Result<usize> {
+        let n = self.len()?;
+        if n > self.data.len() as u64 {
+            return Err(corrupt("count past the end"));
+        }
+        Ok(n as usize)
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>> {
+        let n = self.len()?;
+        if n > self.data.len() as u64 {
+            return Err(corrupt("string past the end"));
+        }
+        Ok(self.take(n as usize)?.to_vec())
+    }
+
+    // A value of type `kind`, leaving out hash fields expired by `now`.
+    fn value(&mut self, kind: u8, now: u64) -> Result<Entry> {
+        Ok(match kind {
+            TYPE_STRING => Entry::String(self.string()?),
+            TYPE_LIST => {
+                let n = self.count()?;
+                let mut list = List::with_capacity(n);
+                for _ in 0..n {
+                    list.push_back(self.string()?);
+                }
+                Entry::List(list)
+            }
+            TYPE_SET => {
+                let mut set = Set::new();
+                for _ in 0..self.count()? {
+                    set.insert(self.string()?, ());
+                }
+                Entry::Set(set)
+            }
+            TYPE_ZSET => {
+                let mut zset = SortedSet::new();
+                for _ in 0..self.count()? {
+                    let member = self.string()?;
+                    let score = f64::from_bits(self.u64()?);
+                    if score.is_nan() {
+                        return Err(corrupt("NaN score"));
+                    }
+                    zset.insert(member, score);
+                }
+                Entry::ZSet(zset)
+            }
+            TYPE_HASH => {
+                let mut hash = Hash::new();
+                for _ in 0..self.count()? {
+                    let (field, value) = (self.string()?, self.string()?);
+                    let expire_at = self.len()?;
+                    if expire_at != 0 && expire_at <= now {
+                        continue;
+                    }
+                    hash.insert(field.clone(), value);
+                    if expire_at != 0 {
+                        hash.set_expire_at(&field, expire_at);
+                    }
+                }
+                Entry::Hash(hash)
+            }
+            _ => return Err(corrupt("unknown value type")),
+        })
+    }
+}
+
+/// Reads a snapshot, checking its checksum. Keys that have expired by now
+/// are left out, and so are hashes left without fields.
+pub fn read(data: &[u8]) -> Result<Dataset> {
+    let header = MAGIC.len() + 1;
+    if data.len() < header + 9 || !data.starts_with(MAGIC) {
+        return Err(corrupt("not a snapshot"));
+    }
+    if data[MAGIC.len()] != VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("unsupported snapshot version {}", data[MAGIC.len()]),
+        ));
+    }
+    let (body, checksum) = data.split_at(data.len() - 8);
+    if crc64(0, body) != u64::from_le_bytes(checksum.try_into().unwrap()) {
+        return Err(corrupt("checksum mismatch"));
+    }
+
+    let now = now_ms();
+    let mut reader = Reader {
+        data: &body[header..],
+    };
+    let mut dataset = Dataset::new();
+    loop {
+        let mut op = reader.byte()?;
+        if op == OP_EOF {
+            break;
+        }
+        let mut expire_at = None;
+        if op == OP_EXPIRE_MS {
+            expire_at = Some(reader.u64()?);
+            op = reader.byte()?;
+        }
+        let key = reader.string()?;
+        let entry = reader.value(op, now)?;
+        if expire_at.is_some_and(|at| at <= now) || entry.is_empty_collection() {
+            continue;
+        }
+        dataset.push((key, entry, expire_at));
+    }
+    if !reader.data.is_empty() {
+        return Err(corrupt("data after the end"));
+    }
+    Ok(dataset)
+}
+
+/// Reads the snapshot at `path`.
+pub fn load(path: &Path) -> Result<Dataset> {
+    read(&std::fs::read(path)?)
+}
@@ -0,0 +1,206 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current Unix time in milliseconds.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+thread_local! {
+    static RNG_STATE: Cell<u64> = Cell::new(seed());
+}
+
+fn seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    // Mix in a stack address so threads started in the same tick diverge.
+    let local = 0u8;
+    (nanos ^ (&local as *const u8 as u64).rotate_left(32)) | 1
+}
+
+/// Syncs the directory holding `path`. A file renamed over another is
+/// only sure to keep its new name once this is done; a crash before it
+/// may bring the old file back.
+pub fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    File::open(dir)?.sync_all()
+}
+
+/// A fast, non-cryptographic random number (xorshift64*), used for sampling.
+pub fn random_u64() -> u64 {
+    RNG_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    })
+}
+
+/// A random index in `0..n`; `n` must be non-zero.
+pub fn random_index(n: usize) -> usize {
+    (random_u64() % n as u64) as usize
+}
+
+/// `k` distinct random indexes in `0..n`, in random order; all of them if
+/// `k >= n`. Costs O(k), not O(n), when `k` is small.
+pub fn random_sample(n: usize, k: usize) -> Vec<usize> {
+    let k = k.min(n);
+    // The head of a partial Fisher-Yates shuffle. For a few picks out of
+    // many, only the slots it has swapped are kept, in a map.
+    if k < n / 4 {
+        let mut swapped: HashMap<usize, usize> = HashMap::with_capacity(k * 2);
+        return (0..k)
+            .map(|i| {
+                let j = i + random_index(n - i);
+                let picked = swapped.get(&j).copied().unwrap_or(j);
+                let displaced = swapped.get(&i).copied().unwrap_or(i);
+                swapped.insert(j, displaced);
+                picked
+            })
+            .collect();
+    }
+    let mut picks: Vec<usize> = (0..n).collect();
+    for i in 0..k {
+        let j = i + random_index(n - i);
+        picks.swap(i, j);
+    }
+    picks.truncate(k);
+    picks
+}
+
+// Lookup table for `crc64`, one entry per byte value.
+const CRC64_TABLE: [u64; 256] = {
+    const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Extends a CRC-64 checksum (the Jones polynomial, reflected, as Redis
+/// uses for RDB files) with `bytes`; start from 0.
+pub fn crc64(crc: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(crc, |crc, &b| {
+        CRC64_TABLE[((crc ^ b as u64) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Parses a decimal integer as strictly as Redis does: no sign other than a
+/// leading `-`, no leading zeros and no surrounding whitespace.
+pub fn parse_integer(bytes: &[u8]) -> Option<i64> {
+    let n = std::str::from_utf8(bytes).ok()?.parse::<i64>().ok()?;
+    // Anything that does not print back the same way was not canonical.
+    (n.to_string().as_bytes() == bytes).then_some(n)
+}
+
+/// Parses a float the way Redis reads numeric arguments: no surrounding
+/// whitespace, no leading `+` and never NaN.
+pub fn parse_float(bytes: &[u8]) -> Option<f64> {
+    let s = std::str::from_utf8(bytes).ok()?;
+    if s.is_empty() || s.trim() != s || s.starts_with('+') {
+        return None;
+    }
+    s.parse::<f64>().ok().filter(|f| !f.is_nan())
+}
+
+/// Redis-style glob matching: `*`, `?`, `[abc]`, `[^a-z]` and `\` escapes.
+///
+/// Only the last `*` is ever backtracked to, so a match costs at most
+/// `pattern.len() * string.len()` steps however many stars the pattern has.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // The pattern just past the last `*`, and where in the string the text
+    // it swallows currently ends.
+    let mut star: Option<(usize, usize)> = None;
+    loop {
+        if pattern.get(p) == Some(&b'*') {
+            while pattern.get(p) == Some(&b'*') {
+                p += 1;
+            }
+            star = Some((p, s));
+            continue;
+        }
+        if p == pattern.len() && s == string.len() {
+            return true;
+        }
+        if p < pattern.len() && s < string.len() {
+            if let Some(len) = match_one(&pattern[p..], string[s]) {
+                p += len;
+                s += 1;
+                continue;
+            }
+        }
+        // Let the last `*` swallow one more byte and try again after it.
+        match star {
+            Some((after, end)) if end < string.len() => {
+                star = Some((after, end + 1));
+                p = after;
+                s = end + 1;
+            }
+            _ => return false,
+        }
+    }
+}
+
+// Matches `c` against the element that starts `pattern`, anything but a
+// `*`, returning the element's length if it matches.
+fn match_one(pattern: &[u8], c: u8) -> Option<usize> {
+    match pattern[0] {
+        b'?' => Some(1),
+        b'[' => {
+            let mut p = &pattern[1..];
+            let negate = p.first() == Some(&b'^');
+            if negate {
+                p = &p[1..];
+            }
+            let mut matched = false;
+            while let Some(&b) = p.first() {
+                if b == b']' {
+                    break;
+                }
+                if b == b'\\' && p.len() > 1 {
+                    matched |= p[1] == c;
+                    p = &p[2..];
+                } else if p.len() > 2 && p[1] == b'-' && p[2] != b']' {
+                    let (lo, hi) = (b.min(p[2]), b.max(p[2]));
+                    matched |= lo <= c && c <= hi;
+                    p = &p[3..];
+                } else {
+                    matched |= b == c;
+                    p = &p[1..];
+                }
+            }
+            // An unterminated class runs to the end of the pattern.
+            let len = pattern.len() - p.len() + usize::from(!p.is_empty());
+            (matched != negate).then_some(len)
+        }
+        b'\\' if pattern.len() > 1 => (pattern[1] == c).then_some(2),
+        b => (b == c).then_some(1),
+    }
+}
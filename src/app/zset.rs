@@ -0,0 +1,394 @@
+use crate::app::util::random_u64;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Most levels a skiplist node can have; plenty for 2^64 members at p = 1/4.
+pub const MAX_LEVEL: usize = 32;
+
+// Index meaning "no node".
+const NIL: usize = usize::MAX;
+// The header node, which holds no member, always lives in slot 0.
+const HEAD: usize = 0;
+
+#[derive(Clone, Copy, Debug)]
+struct Level {
+    forward: usize,
+    // Nodes the forward link skips over, counting the one it lands on.
+    span: usize,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    member: Vec<u8>,
+    score: f64,
+    backward: usize,
+    levels: Vec<Level>,
+}
+
+// Total order of members: by score, then bytewise.
+fn compare(score: f64, member: &[u8], other_score: f64, other_member: &[u8]) -> Ordering {
+    score
+        .partial_cmp(&other_score)
+        .expect("scores are never NaN")
+        .then_with(|| member.cmp(other_member))
+}
+
+/// A set of members ordered by score, ties broken bytewise by member.
+///
+/// As in Redis, a skiplist keeps the order and a hash index maps members to
+/// their scores. Every forward link records how many nodes it spans, so
+/// finding a member's rank or the member at a rank is O(log n) as well.
+/// Nodes live in an arena addressed by index and freed slots are reused.
+#[derive(Clone, Debug)]
+pub struct SortedSet {
+    scores: HashMap<Vec<u8>, f64>,
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: usize,
+    level: usize,
+    length: usize,
+}
+
+impl Default for SortedSet {
+    fn default() -> Self {
+        SortedSet {
+            scores: HashMap::new(),
+            nodes: vec![Node {
+                member: Vec::new(),
+                score: 0.0,
+                backward: NIL,
+                levels: vec![
+                    Level {
+                        forward: NIL,
+                        span: 0,
+                    };
+                    MAX_LEVEL
+                ],
+            }],
+            free: Vec::new(),
+            tail: NIL,
+            level: 1,
+            length: 0,
+        }
+    }
+}
+
+// Equal when they hold the same members with the same scores.
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        SortedSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        self.scores.contains_key(member)
+    }
+
+    /// Adds `member` or moves it to a new score, returning its previous
+    /// score. `score` must not be NaN.
+    pub fn insert(&mut self, member: Vec<u8>, score: f64) -> Option<f64> {
+        assert!(!score.is_nan(), "sorted set scores cannot be NaN");
+        let old = self.score(&member);
+        match old {
+            Some(old) if old == score => return Some(old),
+            Some(old) => self.unlink(&member, old),
+            None => {}
+        }
+        self.scores.insert(member.clone(), score);
+        self.link(member, score);
+        old
+    }
+
+    /// Removes `member`, returning its score.
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+        self.unlink(member, score);
+        Some(score)
+    }
+
+    /// Zero-based position of `member` in ascending order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.partition_point(|s, m| compare(s, m, score, member) == Ordering::Less))
+    }
+
+    /// The number of leading members, in ascending order, for which `pred`
+    /// holds. Like `slice::partition_point`, `pred` must hold for a prefix
+    /// of the set and fail for the rest; range bounds are found this way.
+    pub fn partition_point(&self, mut pred: impl FnMut(f64, &[u8]) -> bool) -> usize {
+        let mut x = HEAD;
+        let mut rank = 0;
+        for i in (0..self.level).rev() {
+            loop {
+                let Level { forward, span } = self.nodes[x].levels[i];
+                if forward == NIL || !pred(self.nodes[forward].score, &self.nodes[forward].member) {
+                    break;
+                }
+                rank += span;
+                x = forward;
+            }
+        }
+        rank
+    }
+
+    /// The member at zero-based position `rank` and its score.
+    pub fn get_index(&self, rank: usize) -> Option<(&[u8], f64)> {
+        let node = &self.nodes[self.node_at(rank)?];
+        Some((&node.member, node.score))
+    }
+
+    /// Members at positions `start..end` in ascending order; reverse the
+    /// iterator for descending order.
+    pub fn range(&self, start: usize, end: usize) -> Iter<'_> {
+        let end = end.min(self.length);
+        if start >= end {
+            return Iter {
+                set: self,
+                front: NIL,
+                back: NIL,
+                remaining: 0,
+            };
+        }
+        Iter {
+            set: self,
+            front: self.node_at(start).expect("rank in range"),
+            back: if end == self.length {
+                self.tail
+            } else {
+                self.node_at(end - 1).expect("rank in range")
+            },
+            remaining: end - start,
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        self.range(0, self.length)
+    }
+
+    // The node at a zero-based rank, found by following spans.
+    fn node_at(&self, rank: usize) -> Option<usize> {
+        if rank >= self.length {
+            return None;
+        }
+        let target = rank + 1;
+        let mut x = HEAD;
+        let mut traversed = 0;
+        for i in (0..self.level).rev() {
+            loop {
+                let Level { forward, span } = self.nodes[x].levels[i];
+                if forward == NIL || traversed + span > target {
+                    break;
+                }
+                traversed += span;
+                x = forward;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    // Each extra level is kept with probability 1/4, as in Redis.
+    fn random_level() -> usize {
+        let mut level = 1;
+        while level < MAX_LEVEL && random_u64() % 4 == 0 {
+            level += 1;
+        }
+        level
+    }
+
+    fn alloc(&mut self, node: Node) -> usize {
+        match self.free.pop() {
+            Some(i) => {
+                self.nodes[i] = node;
+                i
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+
+    // Adds a node to the skiplist; the member must not be in it already.
+    fn link(&mut self, member: Vec<u8>, score: f64) {
+        // The last node before the new one on every level, and its rank.
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0usize; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i + 1 == self.level { 0 } else { rank[i + 1] };
+            loop {
+                let Level { forward, span } = self.nodes[x].levels[i];
+                if forward == NIL
+                    || compare(
+                        self.nodes[forward].score,
+                        &self.nodes[forward].member,
+                        score,
+                        &member,
+                    ) != Ordering::Less
+                {
+                    break;
+                }
+                rank[i] += span;
+                x = forward;
+            }
+            update[i] = x;
+        }
+
+        let level = Self::random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.length;
+            }
+            self.level = level;
+        }
+
+        let node = self.alloc(Node {
+            member,
+            score,
+            backward: NIL,
+            levels: vec![
+                Level {
+                    forward: NIL,
+                    span: 0,
+                };
+                level
+            ],
+        });
+        for i in 0..level {
+            let prev = update[i];
+            let skipped = rank[0] - rank[i];
+            self.nodes[node].levels[i] = Level {
+                forward: self.nodes[prev].levels[i].forward,
+                span: self.nodes[prev].levels[i].span - skipped,
+            };
+            self.nodes[prev].levels[i] = Level {
+                forward: node,
+                span: skipped + 1,
+            };
+        }
+        // Links above the new node now span one more node.
+        for (i, &prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[prev].levels[i].span += 1;
+        }
+
+        self.nodes[node].backward = if update[0] == HEAD { NIL } else { update[0] };
+        match self.nodes[node].levels[0].forward {
+            NIL => self.tail = node,
+            next => self.nodes[next].backward = node,
+        }
+        self.length += 1;
+    }
+
+    // Removes the node holding `member` at `score` from the skiplist.
+    fn unlink(&mut self, member: &[u8], score: f64) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            loop {
+                let forward = self.nodes[x].levels[i].forward;
+                if forward == NIL
+                    || compare(
+                        self.nodes[forward].score,
+                        &self.nodes[forward].member,
+                        score,
+                        member,
+                    ) != Ordering::Less
+                {
+                    break;
+                }
+                x = forward;
+            }
+            update[i] = x;
+        }
+        let node = self.nodes[x].levels[0].forward;
+        debug_assert!(node != NIL && self.nodes[node].member == member);
+
+        for (i, &prev) in update.iter().enumerate().take(self.level) {
+            if self.nodes[prev].levels[i].forward == node {
+                let removed = self.nodes[node].levels[i];
+                self.nodes[prev].levels[i] = Level {
+                    forward: removed.forward,
+                    span: self.nodes[prev].levels[i].span + removed.span - 1,
+                };
+            } else {
+                self.nodes[prev].levels[i].span -= 1;
+            }
+        }
+        let backward = self.nodes[node].backward;
+        match self.nodes[node].levels[0].forward {
+            NIL => self.tail = backward,
+            next => self.nodes[next].backward = backward,
+        }
+        while self.level > 1 && self.nodes[HEAD].levels[self.level - 1].forward == NIL {
+            self.level -= 1;
+        }
+        self.length -= 1;
+
+        let freed = &mut self.nodes[node];
+        freed.member = Vec::new();
+        freed.levels = Vec::new();
+        self.free.push(node);
+    }
+}
+
+/// Iterator over a range of a `SortedSet`, yielding members and scores.
+pub struct Iter<'a> {
+    set: &'a SortedSet,
+    front: usize,
+    back: usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a [u8], f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = &self.set.nodes[self.front];
+        self.front = node.levels[0].forward;
+        self.remaining -= 1;
+        Some((&node.member, node.score))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = &self.set.nodes[self.back];
+        self.back = node.backward;
+        self.remaining -= 1;
+        Some((&node.member, node.score))
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
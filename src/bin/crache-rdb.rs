@@ -0,0 +1,97 @@
+//! Converts between Redis RDB files and Crache's snapshots and AOF.
+//!
+//! ```text
+//! crache-rdb <from> <to>
+//! ```
+//!
+//! The format of each file comes from its extension: `.rdb` for Redis,
+//! `.crs` for a Crache snapshot and `.aof` for an append-only file, which
+//! can only be written.
+
+use crache::app::aof;
+use crache::app::keyspace::Entry;
+use crache::app::rdb;
+use crache::app::snapshot::{self, Dataset};
+use std::path::Path;
+use std::process::exit;
+
+const USAGE: &str = "Usage: crache-rdb <from.rdb|from.crs> <to.rdb|to.crs|to.aof>";
+
+fn extension(path: &Path) -> &str {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+}
+
+fn load(path: &Path) -> Result<Dataset, String> {
+    let result = match extension(path) {
+        "rdb" => rdb::load(path),
+        "crs" => snapshot::load(path),
+        _ => {
+            return Err(format!(
+                "Cannot read {}: expected .rdb or .crs",
+                path.display()
+            ))
+        }
+    };
+    result.map_err(|e| format!("Error reading {}: {}", path.display(), e))
+}
+
+fn save(path: &Path, dataset: &Dataset) -> Result<(), String> {
+    let result = match extension(path) {
+        "rdb" => {
+            let volatile = dataset
+                .iter()
+                .filter(|(_, entry, _)| matches!(entry, Entry::Hash(h) if h.has_volatile_fields()))
+                .count();
+            if volatile > 0 {
+                eprintln!(
+                    "Warning: {} hashes have fields with expiries, which RDB {} cannot hold; \
+                     those fields are written without them",
+                    volatile,
+                    rdb::VERSION
+                );
+            }
+            rdb::save(path, dataset)
+        }
+        "crs" => snapshot::save(path, dataset),
+        "aof" => snapshot::write_file(path, |out| {
+            for (key, entry, expire_at) in dataset {
+                aof::write_entry(out, key, entry, *expire_at)?;
+            }
+            Ok(())
+        }),
+        _ => {
+            return Err(format!(
+                "Cannot write {}: expected .rdb, .crs or .aof",
+                path.display()
+            ))
+        }
+    };
+    result.map_err(|e| format!("Error writing {}: {}", path.display(), e))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [from, to] = args.as_slice() else {
+        eprintln!("{}", USAGE);
+        exit(1);
+    };
+    let (from, to) = (Path::new(from), Path::new(to));
+    let result = load(from).and_then(|dataset| {
+        save(to, &dataset)?;
+        Ok(dataset.len())
+    });
+    match result {
+        Ok(keys) => println!(
+            "Converted {} keys from {} to {}",
+            keys,
+            from.display(),
+            to.display()
+        ),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
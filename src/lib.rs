@@ -1,5 +1,14 @@
 pub mod app {
-	pub mod resp;
-	pub mod handler;
-	pub mod aof;
+    pub mod aof;
+    pub mod dict;
+    pub mod evict;
+    pub mod handler;
+    pub mod hash;
+    pub mod keyspace;
+    pub mod rdb;
+    pub mod resp;
+    pub mod server;
+    pub mod snapshot;
+    pub mod util;
+    pub mod zset;
 }
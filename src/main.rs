@@ -1,145 +1,166 @@
-use crache::app::handler::get_handler;
-use crache::app::resp::{Resp, Writer, Value};
-use std::io::Read;
-use std::net::{TcpListener, TcpStream};
-use std::thread;
+use crache::app::aof::{Aof, FsyncPolicy};
+use crache::app::handler::{
+    dispatch, load_dataset, load_snapshot, rewrite_aof, spawn_active_expire, spawn_save_points,
+    Client,
+};
+use crache::app::rdb;
+use crache::app::resp::Frame;
+use crache::app::server;
+use crache::app::snapshot;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-fn handle_client(mut stream: TcpStream) {
-    let mut buffer = [0u8; 1024];
-    
-    loop {
-        // Process client request
-        match stream.read(&mut buffer) {
-            Ok(n) => {
-                if n == 0 {
-                    println!("Client disconnected");
-                    break;
-                }
-                
-                let mut resp = Resp {
-                    reader: Ok(std::io::Cursor::new(buffer[..n].to_vec())),
-                };
-                let mut writer = Writer {
-                    writer: &mut stream,
-                };
-
-                match resp.read() {
-                    Ok(val) => {
-                        if val.array.is_empty() {
-                            eprintln!("Error: Empty command array received");
-                            // Consider sending a RESP error back to the client here
-                            continue;
-                        }
+const AOF_PATH: &str = "aof_file.aof";
 
-                        let command = val.array[0].bulk.clone().to_ascii_uppercase();
-                        println!("Received command: {:?}", command);
-                        
-                        let handler = get_handler(&command.to_string().to_ascii_uppercase());
-
-                        if let Some(func) = handler {
-                            let args = val.array[1..].to_vec(); // Extract args only if handler exists
-                            let value = func(args.clone()); // Clone args for potential AOF use
-                            
-                            // AOF Check
-                            if command == "SET" || command == "HSET" {
-                                let aof = crache::app::aof::Aof::new("aof_file.aof");
-                                let mut command_args_values = vec![Value {
-                                    typ: "bulk".to_string(),
-                                    str: String::new(),
-                                    num: 0,
-                                    bulk: command.clone(), // Use the command captured earlier
-                                    array: vec![],
-                                }];
-                                command_args_values.extend(args.iter().map(|arg| Value {
-                                    typ: "bulk".to_string(),
-                                    str: String::new(),
-                                    num: 0,
-                                    bulk: arg.bulk.clone(),
-                                    array: vec![],
-                                }));
-                                let resp_value = Value {
-                                    typ: "array".to_string(),
-                                    str: String::new(),
-                                    num: 0,
-                                    bulk: String::new(),
-                                    array: command_args_values,
-                                };
-                                aof.write(&resp_value.marshal())
-                                    .expect("Failed to write to AOF file");
-                            }
+struct Options {
+    appendonly: bool,
+    fsync: FsyncPolicy,
+    rdb: Option<PathBuf>,
+    /// Whether `--rdb` may replace what a non-empty AOF holds.
+    rdb_replace_aof: bool,
+}
 
-                            // Write response to client
-                            if let Err(e) = writer.write(&value) {
-                                eprintln!("Error writing response: {}", e);
-                                break;
-                            }
-                        } else {
-                            // No handler found for the command
-                            eprintln!("No matching handler found for command: {}", command);
-                            // Send an error response back to the client
-                            let error_response = Value {
-                                typ: "error".to_string(),
-                                str: format!("ERR unknown command '{}'", command),
-                                num: 0,
-                                bulk: String::new(),
-                                array: vec![],
-                            };
-                            if let Err(e) = writer.write(&error_response) {
-                                eprintln!("Error writing error response: {}", e);
-                                break; // Exit loop on write error
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!("Error parsing RESP: {}", e);
-                        // Optionally send a RESP error back to the client
-                        continue;
+// Reads `--appendonly yes|no`, `--appendfsync always|everysec|no`, `--rdb
+// <file>` and `--rdb-replace-aof` from the command line.
+fn parse_args() -> Result<Options, String> {
+    let mut appendonly = true;
+    let mut fsync = FsyncPolicy::default();
+    let mut rdb = None;
+    let mut rdb_replace_aof = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--appendonly" => {
+                appendonly = match args.next().as_deref() {
+                    Some("yes") => true,
+                    Some("no") => false,
+                    other => {
+                        return Err(format!(
+                            "Invalid --appendonly '{}': expected yes or no",
+                            other.unwrap_or_default()
+                        ))
                     }
                 }
             }
-            Err(e) => {
-                println!("Error reading stream: {}", e);
-                break;
+            "--appendfsync" => {
+                let value = args.next().unwrap_or_default();
+                fsync = value.parse().map_err(|_| {
+                    format!(
+                        "Invalid --appendfsync '{}': expected always, everysec or no",
+                        value
+                    )
+                })?;
             }
+            "--rdb" => match args.next() {
+                Some(path) => rdb = Some(PathBuf::from(path)),
+                None => return Err("--rdb needs a file to load".to_string()),
+            },
+            "--rdb-replace-aof" => rdb_replace_aof = true,
+            _ => return Err(format!("Unknown option '{}'", arg)),
         }
     }
+    Ok(Options {
+        appendonly,
+        fsync,
+        rdb,
+        rdb_replace_aof,
+    })
 }
 
-fn main() {
-    let listener = TcpListener::bind("127.0.0.1:6379").expect("Failed to bind to address");
-    println!("Server listening on port 6379");
-
-    let aof = crache::app::aof::Aof::new("aof_file.aof");
-
-    // Load and process all commands from AOF file when a client connects
+// Replays every command in the AOF.
+fn replay(aof: &Aof) {
+    let mut client = Client::new();
+    client.deny_blocking = true;
     if let Err(e) = aof.read(|value| {
-        if value.typ == "array" && !value.array.is_empty() {
-            let command = value.array[0].bulk.to_ascii_uppercase();
-            let args = value.array[1..].to_vec();
-
-            if let Some(handler) = get_handler(&command) {
-                println!("Replaying command from AOF: {}", command);
-                handler(args);
-            } else {
-                println!("Invalid command in AOF: {}", command);
+        if let Frame::Array(argv) = value {
+            if argv.is_empty() {
+                return;
+            }
+            let command =
+                String::from_utf8_lossy(argv[0].as_bulk().unwrap_or_default()).to_ascii_uppercase();
+            println!("Replaying command from AOF: {}", command);
+            if let Frame::Error(e) = dispatch(&mut client, argv) {
+                println!("Invalid command in AOF: {}: {}", command, e);
             }
         }
     }) {
         eprintln!("Error loading AOF file: {}", e);
     }
+}
 
+fn main() {
+    let Options {
+        appendonly,
+        fsync,
+        rdb,
+        rdb_replace_aof,
+    } = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let listener = TcpListener::bind("127.0.0.1:6379").expect("Failed to bind to address");
+    println!(
+        "Server listening on port 6379 (appendfsync {})",
+        fsync.name()
+    );
 
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-                println!("New connection: {}", stream.peer_addr().unwrap());
-                thread::spawn(move || {
-                    handle_client(stream);
-                });
-            }
+    // An import takes the place of whatever the AOF held, so an AOF with
+    // data in it is only thrown away when asked to.
+    let aof_len = std::fs::metadata(AOF_PATH).map_or(0, |m| m.len());
+    if rdb.is_some() && appendonly && aof_len > 0 && !rdb_replace_aof {
+        eprintln!(
+            "Refusing to load an RDB file over {}, which is not empty ({} bytes): \
+             pass --rdb-replace-aof to replace its contents, or move it aside",
+            AOF_PATH, aof_len
+        );
+        std::process::exit(1);
+    }
+
+    let aof = appendonly.then(|| Arc::new(Aof::with_fsync(AOF_PATH, fsync)));
+    if let Some(path) = rdb {
+        let keys = match rdb::load(&path) {
+            Ok(dataset) => load_dataset(dataset),
             Err(e) => {
-                println!("Connection failed: {}", e);
+                eprintln!("Error loading RDB file {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        };
+        println!("Loaded {} keys from {}", keys, path.display());
+        // The AOF is rewritten to hold the imported keys instead.
+        if let Some(aof) = &aof {
+            if aof_len > 0 {
+                println!(
+                    "Replacing the {} bytes in {} with the keys from {}",
+                    aof_len,
+                    AOF_PATH,
+                    path.display()
+                );
+            }
+            if let Err(e) = rewrite_aof(aof) {
+                eprintln!("Error rewriting AOF: {}", e);
+            }
+        }
+    } else if let Some(aof) = &aof {
+        replay(aof);
+    } else {
+        // As in Redis, the AOF holds everything when enabled, so the
+        // snapshot is only loaded without one.
+        let path = snapshot::save_config().dbfilename;
+        if Path::new(&path).exists() {
+            match load_snapshot(Path::new(&path)) {
+                Ok(keys) => println!("Loaded {} keys from {}", keys, path),
+                Err(e) => {
+                    eprintln!("Error loading snapshot {}: {}", path, e);
+                    std::process::exit(1);
+                }
             }
         }
     }
+
+    // Keys replayed with a deadline that has since passed are reclaimed here.
+    spawn_active_expire();
+    spawn_save_points();
+
+    server::serve(listener, aof);
 }
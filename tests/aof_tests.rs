@@ -0,0 +1,419 @@
+use crache::app::aof::{self, Aof, FsyncPolicy, REWRITE_ITEMS_PER_CMD};
+use crache::app::handler::Client;
+use crache::app::keyspace::{Entry, List};
+use crache::app::resp::Frame;
+use crache::app::server::handle_command;
+use crache::app::zset::SortedSet;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// The rewrite tests change the automatic rewrite thresholds, which are
+// global, so they take turns.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+// Each test gets its own file so they can run in parallel.
+fn temp_aof(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("crache_{}_{}.aof", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn test_write_and_replay_commands() {
+    let path = temp_aof("replay");
+    let aof = Aof::new(&path);
+
+    let set = Frame::array(vec![
+        Frame::bulk("SET"),
+        Frame::bulk("key"),
+        Frame::bulk("value"),
+    ]);
+    let hset = Frame::array(vec![
+        Frame::bulk("HSET"),
+        Frame::bulk("hash"),
+        Frame::bulk("field"),
+        Frame::bulk("value"),
+    ]);
+    aof.write(&set.marshal()).unwrap();
+    aof.write(&hset.marshal()).unwrap();
+
+    let mut replayed = Vec::new();
+    aof.read(|value| replayed.push(value)).unwrap();
+    assert_eq!(replayed, vec![set, hset]);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_replay_binary_payload_losslessly() {
+    let path = temp_aof("binary");
+    let aof = Aof::new(&path);
+
+    let payload: Vec<u8> = (0..=255u8).collect();
+    let set = Frame::array(vec![
+        Frame::bulk("SET"),
+        Frame::bulk(vec![0xff, 0x00, b'\r', b'\n']),
+        Frame::bulk(payload),
+    ]);
+    aof.write(&set.marshal()).unwrap();
+
+    let mut replayed = Vec::new();
+    aof.read(|value| replayed.push(value)).unwrap();
+    assert_eq!(replayed, vec![set]);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_replay_empty_file() {
+    let path = temp_aof("empty");
+    let aof = Aof::new(&path);
+
+    let mut count = 0;
+    aof.read(|_| count += 1).unwrap();
+    assert_eq!(count, 0);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+fn set_command(i: usize) -> Frame {
+    Frame::array(vec![
+        Frame::bulk("SET"),
+        Frame::bulk(format!("key:{}", i)),
+        Frame::bulk(format!("value:{}", i)),
+    ])
+}
+
+// Stands in for the disk: every sync the AOF asks for is made for real,
+// counted, and remembered for how much of the file it covered.
+#[derive(Default)]
+struct Disk {
+    durable: AtomicU64,
+    syncs: AtomicUsize,
+}
+
+fn aof_on_disk(path: &str, fsync: FsyncPolicy) -> (Aof, Arc<Disk>) {
+    let disk = Arc::new(Disk::default());
+    let hook_disk = Arc::clone(&disk);
+    let aof = Aof::with_sync_hook(
+        path,
+        fsync,
+        Box::new(move |file| {
+            file.sync_data()?;
+            hook_disk
+                .durable
+                .store(file.metadata()?.len(), Ordering::SeqCst);
+            hook_disk.syncs.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }),
+    );
+    (aof, disk)
+}
+
+// What a crash right now could leave behind: only the bytes some sync
+// covered, copied to a file of their own and replayed from there.
+fn replay_after_crash(path: &str, disk: &Disk) -> Vec<Frame> {
+    let mut data = std::fs::read(path).unwrap();
+    data.truncate(disk.durable.load(Ordering::SeqCst) as usize);
+    let crashed = format!("{}.crashed", path);
+    std::fs::write(&crashed, data).unwrap();
+    let mut replayed = Vec::new();
+    Aof::with_fsync(&crashed, FsyncPolicy::No)
+        .read(|value| replayed.push(value))
+        .unwrap();
+    let _ = std::fs::remove_file(&crashed);
+    replayed
+}
+
+#[test]
+fn test_fsync_policy_names() {
+    for name in ["always", "everysec", "no"] {
+        let policy: FsyncPolicy = name.parse().unwrap();
+        assert_eq!(policy.name(), name);
+    }
+    assert_eq!("EVERYSEC".parse(), Ok(FsyncPolicy::EverySec));
+    assert_eq!("sometimes".parse::<FsyncPolicy>(), Err(()));
+    assert_eq!(FsyncPolicy::default(), FsyncPolicy::EverySec);
+}
+
+#[test]
+fn test_always_survives_crash_after_every_write() {
+    let path = temp_aof("always");
+    let (aof, disk) = aof_on_disk(&path, FsyncPolicy::Always);
+
+    let mut acknowledged = Vec::new();
+    for i in 0..20 {
+        aof.write(&set_command(i).marshal()).unwrap();
+        acknowledged.push(set_command(i));
+        // Every write that returned was synced, whenever the crash comes.
+        assert_eq!(disk.syncs.load(Ordering::SeqCst), i + 1);
+        assert_eq!(replay_after_crash(&path, &disk), acknowledged);
+    }
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_everysec_syncs_in_the_background() {
+    let path = temp_aof("everysec");
+    let (aof, disk) = aof_on_disk(&path, FsyncPolicy::EverySec);
+
+    let commands: Vec<Frame> = (0..5).map(set_command).collect();
+    for command in &commands {
+        aof.write(&command.marshal()).unwrap();
+    }
+    // The next group commit covers all of them.
+    let written = std::fs::metadata(&path).unwrap().len();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while disk.durable.load(Ordering::SeqCst) < written {
+        assert!(std::time::Instant::now() < deadline, "never synced");
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    assert!(disk.syncs.load(Ordering::SeqCst) < commands.len());
+    assert_eq!(aof.synced_len(), written);
+    assert_eq!(replay_after_crash(&path, &disk), commands);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_no_fsync_leaves_it_to_the_os() {
+    let path = temp_aof("no");
+    let (aof, disk) = aof_on_disk(&path, FsyncPolicy::No);
+
+    aof.write(&set_command(0).marshal()).unwrap();
+    assert_eq!(disk.syncs.load(Ordering::SeqCst), 0);
+    assert_eq!(aof.synced_len(), 0);
+    // Still readable, just not promised to survive a crash...
+    let mut replayed = Vec::new();
+    aof.read(|value| replayed.push(value)).unwrap();
+    assert_eq!(replayed, vec![set_command(0)]);
+    assert!(replay_after_crash(&path, &disk).is_empty());
+    // ...unless synced by hand.
+    aof.sync().unwrap();
+    assert_eq!(disk.syncs.load(Ordering::SeqCst), 1);
+    assert_eq!(replay_after_crash(&path, &disk), vec![set_command(0)]);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_appends_are_buffered_until_flushed() {
+    let path = temp_aof("buffered");
+    let aof = Aof::with_fsync(&path, FsyncPolicy::No);
+
+    aof.append(&set_command(0).marshal());
+    aof.append(&set_command(1).marshal());
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+    aof.flush().unwrap();
+    assert_eq!(
+        std::fs::read(&path).unwrap(),
+        [set_command(0).marshal(), set_command(1).marshal()].concat()
+    );
+
+    // Dropping it is a clean shutdown: nothing is left behind.
+    aof.append(&set_command(2).marshal());
+    drop(aof);
+    let mut replayed = Vec::new();
+    Aof::new(&path).read(|value| replayed.push(value)).unwrap();
+    assert_eq!(replayed, (0..3).map(set_command).collect::<Vec<_>>());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+fn list(args: &[&str]) -> Frame {
+    Frame::array(args.iter().map(|a| Frame::bulk(*a)).collect())
+}
+
+fn logged(path: &str) -> Vec<Frame> {
+    let mut logged = Vec::new();
+    Aof::with_fsync(path, FsyncPolicy::No)
+        .read(|value| logged.push(value))
+        .unwrap();
+    logged
+}
+
+fn wait_for_rewrite(aof: &Aof) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while aof.rewrite_in_progress() {
+        assert!(Instant::now() < deadline, "rewrite never finished");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_write_entry_batches_and_keeps_expiries() {
+    let mut out = Vec::new();
+    let items: List = (0..REWRITE_ITEMS_PER_CMD + 1)
+        .map(|i| i.to_string().into_bytes())
+        .collect();
+    aof::write_entry(&mut out, b"l", &Entry::List(items), Some(1234)).unwrap();
+    let mut zset = SortedSet::new();
+    zset.insert(b"m".to_vec(), 1.5);
+    aof::write_entry(&mut out, b"z", &Entry::ZSet(zset), None).unwrap();
+
+    let path = temp_aof("write_entry");
+    std::fs::write(&path, out).unwrap();
+    let commands = logged(&path);
+    assert_eq!(commands.len(), 4);
+    let Frame::Array(first) = &commands[0] else {
+        panic!("expected a command");
+    };
+    assert_eq!(first.len(), 2 + REWRITE_ITEMS_PER_CMD);
+    assert_eq!(commands[1], list(&["RPUSH", "l", "64"]));
+    assert_eq!(commands[2], list(&["PEXPIREAT", "l", "1234"]));
+    assert_eq!(commands[3], list(&["ZADD", "z", "1.5", "m"]));
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_bgrewriteaof_compacts_and_keeps_later_writes() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let path = temp_aof("rewrite");
+    let aof = Arc::new(Aof::with_fsync(&path, FsyncPolicy::No));
+    let mut client = Client::new();
+    client.aof = Some(Arc::clone(&aof));
+    let mut run = |args: &[&str]| handle_command(&mut client, list(args)).unwrap();
+
+    for i in 0..100 {
+        run(&["SET", "rw:counter", &i.to_string()]);
+    }
+    run(&["RPUSH", "rw:list", "a", "b"]);
+    run(&["LPOP", "rw:list"]);
+    run(&["HSET", "rw:hash", "f", "v"]);
+    run(&[
+        "HPEXPIREAT",
+        "rw:hash",
+        "99999999999999",
+        "FIELDS",
+        "1",
+        "f",
+    ]);
+    run(&["SET", "rw:gone", "v"]);
+    run(&["DEL", "rw:gone"]);
+    aof.flush().unwrap();
+    let before = std::fs::metadata(&path).unwrap().len();
+
+    assert_eq!(
+        run(&["BGREWRITEAOF"]),
+        Frame::simple("Background append only file rewriting started")
+    );
+    // Written while the rewrite may still be running.
+    run(&["SET", "rw:after", "v"]);
+    wait_for_rewrite(&aof);
+    run(&["SET", "rw:last", "v"]);
+    aof.flush().unwrap();
+
+    // The rewrite holds the whole dataset; the other tests' keys are
+    // there too.
+    let mut commands: Vec<Frame> = logged(&path)
+        .into_iter()
+        .filter(
+            |c| matches!(c, Frame::Array(argv) if argv[1].as_bulk().unwrap().starts_with(b"rw:")),
+        )
+        .collect();
+    commands.sort_by_key(|c| format!("{:?}", c));
+    let mut expected = vec![
+        list(&["SET", "rw:counter", "99"]),
+        list(&["RPUSH", "rw:list", "b"]),
+        list(&["HSET", "rw:hash", "f", "v"]),
+        list(&[
+            "HPEXPIREAT",
+            "rw:hash",
+            "99999999999999",
+            "FIELDS",
+            "1",
+            "f",
+        ]),
+        list(&["SET", "rw:after", "v"]),
+        list(&["SET", "rw:last", "v"]),
+    ];
+    expected.sort_by_key(|c| format!("{:?}", c));
+    assert_eq!(commands, expected);
+    assert!(std::fs::metadata(&path).unwrap().len() < before);
+    assert!(!std::path::Path::new(&format!("{}.rewrite", path)).exists());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_aof_is_rewritten_once_it_doubles() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let path = temp_aof("auto_rewrite");
+    let aof = Arc::new(Aof::with_fsync(&path, FsyncPolicy::No));
+    let mut client = Client::new();
+    client.aof = Some(Arc::clone(&aof));
+    let mut run = |args: &[&str]| handle_command(&mut client, list(args)).unwrap();
+
+    assert_eq!(
+        run(&["CONFIG", "SET", "auto-aof-rewrite-min-size", "2kb"]),
+        Frame::simple("OK")
+    );
+    assert_eq!(
+        run(&["CONFIG", "GET", "auto-aof-rewrite-*"]),
+        Frame::map(vec![
+            (
+                Frame::bulk("auto-aof-rewrite-percentage"),
+                Frame::bulk("100")
+            ),
+            (
+                Frame::bulk("auto-aof-rewrite-min-size"),
+                Frame::bulk("2048")
+            ),
+        ])
+    );
+    // About 40 bytes a command: the file passes 2kb after some 50.
+    for i in 0..60 {
+        run(&["SET", "auto:key", &i.to_string()]);
+        aof.flush().unwrap();
+    }
+    wait_for_rewrite(&aof);
+    assert!(std::fs::metadata(&path).unwrap().len() < 2048);
+    assert_eq!(
+        logged(&path).last(),
+        Some(&list(&["SET", "auto:key", "59"]))
+    );
+
+    run(&["CONFIG", "SET", "auto-aof-rewrite-percentage", "0"]);
+    run(&["CONFIG", "SET", "auto-aof-rewrite-min-size", "64mb"]);
+    run(&["CONFIG", "SET", "auto-aof-rewrite-percentage", "100"]);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_only_writes_wait_for_the_log_order() {
+    let path = temp_aof("log_order");
+    let aof = Arc::new(Aof::with_fsync(&path, FsyncPolicy::No));
+    let mut holder = Client::new();
+    holder.aof = Some(Arc::clone(&aof));
+    holder.order_log();
+
+    let (tx, rx) = mpsc::channel();
+    let client_aof = Arc::clone(&aof);
+    let handle = std::thread::spawn(move || {
+        let mut client = Client::new();
+        client.aof = Some(client_aof);
+        for args in [
+            &["GET", "order:key"][..],
+            &["TTL", "order:key"],
+            &["SET", "order:key", "v"],
+        ] {
+            handle_command(&mut client, list(args)).unwrap();
+            tx.send(args[0]).unwrap();
+        }
+    });
+    // Reads go ahead while another command holds the log order...
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok("GET"));
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok("TTL"));
+    // ...but a write waits for its turn.
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    holder.log_order = None;
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok("SET"));
+    handle.join().unwrap();
+
+    aof.flush().unwrap();
+    assert_eq!(logged(&path), vec![list(&["SET", "order:key", "v"])]);
+    let _ = std::fs::remove_file(&path);
+}
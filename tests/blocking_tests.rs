@@ -0,0 +1,238 @@
+use crache::app::handler::{self, Client};
+use crache::app::resp::Frame;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn bulks(args: &[&str]) -> Vec<Frame> {
+    args.iter().map(|s| Frame::bulk(*s)).collect()
+}
+
+fn call(args: &[&str]) -> Frame {
+    handler::dispatch(&mut Client::new(), bulks(args))
+}
+
+// Runs a command on its own client in the background, as a blocked
+// connection would.
+fn spawn_call(args: &[&str]) -> thread::JoinHandle<Frame> {
+    let argv = bulks(args);
+    thread::spawn(move || handler::dispatch(&mut Client::new(), argv))
+}
+
+// Gives a background command time to find its keys empty and block.
+fn settle() {
+    thread::sleep(Duration::from_millis(50));
+}
+
+#[test]
+fn test_blpop_returns_at_once_when_data_is_there() {
+    call(&["RPUSH", "block:ready2", "a", "b"]);
+    assert_eq!(
+        call(&["BLPOP", "block:empty", "block:ready2", "0"]),
+        Frame::array(bulks(&["block:ready2", "a"]))
+    );
+    assert_eq!(
+        call(&["BRPOP", "block:ready2", "0"]),
+        Frame::array(bulks(&["block:ready2", "b"]))
+    );
+    assert_eq!(call(&["TYPE", "block:ready2"]), Frame::simple("none"));
+}
+
+#[test]
+fn test_blpop_times_out() {
+    let start = Instant::now();
+    assert_eq!(call(&["BLPOP", "block:never", "0.05"]), Frame::NullArray);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(
+        call(&["BLMOVE", "block:never", "block:dst", "LEFT", "LEFT", "0.01"]),
+        Frame::Null
+    );
+}
+
+#[test]
+fn test_deny_blocking_never_waits() {
+    let mut client = Client::new();
+    client.deny_blocking = true;
+    let start = Instant::now();
+    let reply = handler::dispatch(&mut client, bulks(&["BLPOP", "block:replay", "0"]));
+    assert_eq!(reply, Frame::NullArray);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(client.take_propagation(), Some(vec![]));
+}
+
+#[test]
+fn test_push_wakes_blocked_client() {
+    let blocked = spawn_call(&["BRPOP", "block:wake", "5"]);
+    settle();
+
+    let mut pusher = Client::new();
+    let reply = handler::dispatch(&mut pusher, bulks(&["LPUSH", "block:wake", "job"]));
+    assert_eq!(reply, Frame::integer(1));
+    assert_eq!(
+        blocked.join().unwrap(),
+        Frame::array(bulks(&["block:wake", "job"]))
+    );
+    assert_eq!(call(&["LLEN", "block:wake"]), Frame::integer(0));
+
+    // The pusher logs the pop it served after its own push.
+    assert_eq!(
+        pusher.take_propagation(),
+        Some(vec![
+            bulks(&["LPUSH", "block:wake", "job"]),
+            bulks(&["RPOP", "block:wake"]),
+        ])
+    );
+}
+
+#[test]
+fn test_blocked_clients_are_served_in_order() {
+    let first = spawn_call(&["BLPOP", "block:fifo", "5"]);
+    settle();
+    let second = spawn_call(&["BLPOP", "block:other", "block:fifo", "5"]);
+    settle();
+
+    call(&["RPUSH", "block:fifo", "a", "b", "c"]);
+    assert_eq!(
+        first.join().unwrap(),
+        Frame::array(bulks(&["block:fifo", "a"]))
+    );
+    assert_eq!(
+        second.join().unwrap(),
+        Frame::array(bulks(&["block:fifo", "b"]))
+    );
+    assert_eq!(
+        call(&["LRANGE", "block:fifo", "0", "-1"]),
+        Frame::array(bulks(&["c"]))
+    );
+}
+
+#[test]
+fn test_blmove_wakes_and_chains() {
+    let mover = spawn_call(&["BLMOVE", "block:src", "block:mid", "LEFT", "RIGHT", "5"]);
+    settle();
+    let popper = spawn_call(&["BRPOPLPUSH", "block:mid", "block:end", "5"]);
+    settle();
+
+    let mut pusher = Client::new();
+    handler::dispatch(&mut pusher, bulks(&["RPUSH", "block:src", "x"]));
+    assert_eq!(mover.join().unwrap(), Frame::bulk("x"));
+    assert_eq!(popper.join().unwrap(), Frame::bulk("x"));
+    assert_eq!(call(&["TYPE", "block:src"]), Frame::simple("none"));
+    assert_eq!(call(&["TYPE", "block:mid"]), Frame::simple("none"));
+    assert_eq!(
+        call(&["LRANGE", "block:end", "0", "-1"]),
+        Frame::array(bulks(&["x"]))
+    );
+    assert_eq!(
+        pusher.take_propagation(),
+        Some(vec![
+            bulks(&["RPUSH", "block:src", "x"]),
+            bulks(&["LMOVE", "block:src", "block:mid", "LEFT", "RIGHT"]),
+            bulks(&["LMOVE", "block:mid", "block:end", "RIGHT", "LEFT"]),
+        ])
+    );
+}
+
+#[test]
+fn test_immediate_pop_is_logged_as_non_blocking() {
+    call(&["RPUSH", "block:log", "a"]);
+    let mut client = Client::new();
+    handler::dispatch(&mut client, bulks(&["BLPOP", "block:log", "1"]));
+    assert_eq!(
+        client.take_propagation(),
+        Some(vec![bulks(&["LPOP", "block:log"])])
+    );
+}
+
+#[test]
+fn test_blocking_errors() {
+    assert_eq!(
+        call(&["BLPOP", "block:err", "-1"]),
+        Frame::error("ERR timeout is negative")
+    );
+    assert_eq!(
+        call(&["BLPOP", "block:err", "soon"]),
+        Frame::error("ERR timeout is not a float or out of range")
+    );
+    assert_eq!(
+        call(&["BLMOVE", "block:err", "block:dst", "UP", "LEFT", "0"]),
+        Frame::error("ERR syntax error")
+    );
+    call(&["SET", "block:string", "v"]);
+    assert_eq!(
+        call(&["BLPOP", "block:string", "0"]),
+        Frame::error("WRONGTYPE Operation against a key holding the wrong kind of value")
+    );
+}
+
+#[test]
+fn test_zadd_wakes_bzpopmin() {
+    let blocked = spawn_call(&["BZPOPMIN", "block:zset", "5"]);
+    settle();
+
+    let mut adder = Client::new();
+    let reply = handler::dispatch(
+        &mut adder,
+        bulks(&["ZADD", "block:zset", "2", "b", "1", "a"]),
+    );
+    assert_eq!(reply, Frame::integer(2));
+    assert_eq!(
+        blocked.join().unwrap(),
+        Frame::array(vec![
+            Frame::bulk("block:zset"),
+            Frame::bulk("a"),
+            Frame::double(1.0)
+        ])
+    );
+    assert_eq!(call(&["ZCARD", "block:zset"]), Frame::integer(1));
+    assert_eq!(
+        adder.take_propagation(),
+        Some(vec![
+            bulks(&["ZADD", "block:zset", "2", "b", "1", "a"]),
+            bulks(&["ZPOPMIN", "block:zset"]),
+        ])
+    );
+    assert_eq!(
+        call(&["BZPOPMAX", "block:zset", "0"]),
+        Frame::array(vec![
+            Frame::bulk("block:zset"),
+            Frame::bulk("b"),
+            Frame::double(2.0)
+        ])
+    );
+}
+
+#[test]
+fn test_waiters_are_served_by_type() {
+    // A list waiter ahead in line does not hold up a sorted set waiter.
+    let list_waiter = spawn_call(&["BLPOP", "block:mixed", "0.5"]);
+    settle();
+    let zset_waiter = spawn_call(&["BZPOPMAX", "block:mixed", "5"]);
+    settle();
+
+    call(&["ZADD", "block:mixed", "1", "m"]);
+    assert_eq!(
+        zset_waiter.join().unwrap(),
+        Frame::array(vec![
+            Frame::bulk("block:mixed"),
+            Frame::bulk("m"),
+            Frame::double(1.0)
+        ])
+    );
+    assert_eq!(list_waiter.join().unwrap(), Frame::NullArray);
+}
+
+#[test]
+fn test_client_blocking_during_a_push_is_served() {
+    // A client that blocks while a push on its key is under way must still
+    // be served by it, not left waiting with the element in the list. A big
+    // push keeps the command busy long enough for the client to block.
+    for i in 0..5 {
+        let key = format!("block:race:{}", i);
+        let mut push = bulks(&["LPUSH", &key]);
+        push.extend((0..300_000).map(|n| Frame::bulk(n.to_string())));
+        let pusher = thread::spawn(move || handler::dispatch(&mut Client::new(), push));
+        let blocked = spawn_call(&["BLPOP", &key, "2"]);
+        assert_eq!(pusher.join().unwrap(), Frame::integer(300_000));
+        assert_ne!(blocked.join().unwrap(), Frame::NullArray, "round {}", i);
+    }
+}
@@ -0,0 +1,90 @@
+use crache::app::dict::Dict;
+use std::collections::HashSet;
+
+#[test]
+fn test_insert_get_replace() {
+    let mut dict = Dict::new();
+    assert!(dict.is_empty());
+    assert_eq!(dict.insert(b"a".to_vec(), 1), None);
+    assert_eq!(dict.insert(b"b".to_vec(), 2), None);
+    assert_eq!(dict.insert(b"a".to_vec(), 3), Some(1));
+    assert_eq!(dict.len(), 2);
+    assert_eq!(dict.get(b"a"), Some(&3));
+    assert!(dict.contains_key(b"b"));
+    assert!(!dict.contains_key(b"c"));
+
+    *dict.get_mut(b"b").unwrap() += 10;
+    assert_eq!(dict.get(b"b"), Some(&12));
+    *dict.get_or_insert_with(b"c", || 0) += 1;
+    *dict.get_or_insert_with(b"c", || 0) += 1;
+    assert_eq!(dict.get(b"c"), Some(&2));
+}
+
+#[test]
+fn test_remove_keeps_index_consistent() {
+    let mut dict = Dict::new();
+    for i in 0..10u32 {
+        dict.insert(i.to_string().into_bytes(), i);
+    }
+    // Removing from the middle moves the last entry into the hole.
+    assert_eq!(dict.remove(b"3"), Some(3));
+    assert_eq!(dict.remove(b"3"), None);
+    assert_eq!(dict.len(), 9);
+    for i in (0..10u32).filter(|&i| i != 3) {
+        assert_eq!(dict.get(i.to_string().as_bytes()), Some(&i));
+    }
+
+    let keys: HashSet<_> = (0..dict.len())
+        .map(|i| dict.get_index(i).unwrap().0.clone())
+        .collect();
+    assert_eq!(keys.len(), 9);
+    assert!(dict.get_index(9).is_none());
+    assert_eq!(dict.keys().count(), 9);
+
+    dict.clear();
+    assert!(dict.is_empty());
+    assert!(dict.get(b"0").is_none());
+}
+
+#[test]
+fn test_equality_ignores_order() {
+    let mut a = Dict::new();
+    let mut b = Dict::new();
+    for i in 0..5u32 {
+        a.insert(i.to_string().into_bytes(), i);
+        b.insert((4 - i).to_string().into_bytes(), 4 - i);
+    }
+    assert_eq!(a, b);
+    b.insert(b"0".to_vec(), 7);
+    assert_ne!(a, b);
+    b.remove(b"0");
+    assert_ne!(a, b);
+}
+
+#[test]
+fn test_scan_survives_removals() {
+    let mut dict = Dict::new();
+    for i in 0..100u32 {
+        dict.insert(i.to_string().into_bytes(), i);
+    }
+    // Entries removed mid-scan may or may not be seen; every other one must be.
+    let mut seen = HashSet::new();
+    let mut cursor = 0;
+    loop {
+        let mut batch = Vec::new();
+        cursor = dict.scan(cursor, 7, |_, &v| batch.push(v));
+        seen.extend(batch);
+        for v in (0..100u32).filter(|v| v % 10 == 0) {
+            dict.remove(v.to_string().as_bytes());
+        }
+        if cursor == 0 {
+            break;
+        }
+    }
+    assert!((0..100u32)
+        .filter(|v| v % 10 != 0)
+        .all(|v| seen.contains(&v)));
+
+    let empty: Dict<u32> = Dict::new();
+    assert_eq!(empty.scan(0, 10, |_, _| panic!("nothing to visit")), 0);
+}
@@ -0,0 +1,75 @@
+use crache::app::evict::{self, EvictionConfig, EvictionPolicy, EvictionPool, LFU_INIT_VAL};
+
+#[test]
+fn test_policy_names_round_trip() {
+    for name in [
+        "noeviction",
+        "allkeys-lru",
+        "allkeys-lfu",
+        "allkeys-random",
+        "volatile-lru",
+        "volatile-lfu",
+        "volatile-random",
+        "volatile-ttl",
+    ] {
+        let policy: EvictionPolicy = name.parse().unwrap();
+        assert_eq!(policy.name(), name);
+    }
+    assert_eq!("ALLKEYS-LRU".parse(), Ok(EvictionPolicy::AllKeysLru));
+    assert!("allkeys-fifo".parse::<EvictionPolicy>().is_err());
+    assert!(EvictionPolicy::VolatileTtl.is_volatile());
+    assert!(!EvictionPolicy::AllKeysLfu.is_volatile());
+    assert_eq!(EvictionConfig::default().policy, EvictionPolicy::NoEviction);
+}
+
+#[test]
+fn test_parse_memory() {
+    assert_eq!(evict::parse_memory("4096"), Some(4096));
+    assert_eq!(evict::parse_memory("10b"), Some(10));
+    assert_eq!(evict::parse_memory("1k"), Some(1000));
+    assert_eq!(evict::parse_memory("1kb"), Some(1024));
+    assert_eq!(evict::parse_memory("100MB"), Some(100 * 1024 * 1024));
+    assert_eq!(evict::parse_memory("2g"), Some(2_000_000_000));
+    assert_eq!(evict::parse_memory("1tb"), None);
+    assert_eq!(evict::parse_memory("mb"), None);
+    assert_eq!(evict::parse_memory("-1"), None);
+}
+
+#[test]
+fn test_lfu_counter_grows_logarithmically_and_decays() {
+    let config = EvictionConfig::default();
+    let now = 1_000_000_000;
+    let mut state = evict::lfu_new(now);
+    assert_eq!(evict::lfu_counter(state, now, 1), LFU_INIT_VAL);
+
+    for _ in 0..1000 {
+        state = evict::lfu_touch(state, now, &config);
+    }
+    let hot = evict::lfu_counter(state, now, 1);
+    assert!(hot > LFU_INIT_VAL + 5, "counter was {}", hot);
+    // With the default log factor, a thousand hits is far from saturation.
+    assert!(hot < 100, "counter was {}", hot);
+
+    // Three idle minutes take three steps off.
+    assert_eq!(evict::lfu_counter(state, now + 3 * 60_000, 1), hot - 3);
+    assert_eq!(evict::lfu_counter(state, now + 3 * 60_000, 0), hot);
+}
+
+#[test]
+fn test_pool_keeps_best_candidates() {
+    let mut pool = EvictionPool::default();
+    for score in 0..40u64 {
+        pool.offer(score, format!("key:{}", score).as_bytes());
+    }
+    // Re-offering a key replaces its old score.
+    pool.offer(100, b"key:30");
+    assert_eq!(pool.pop(), Some(b"key:30".to_vec()));
+    assert_eq!(pool.pop(), Some(b"key:39".to_vec()));
+
+    let mut rest = 0;
+    while let Some(key) = pool.pop() {
+        assert_ne!(key, b"key:0".to_vec());
+        rest += 1;
+    }
+    assert_eq!(rest, 14);
+}
@@ -1,24 +1,24 @@
-use crache::app::handler;
-use crache::app::resp::Value;
+use crache::app::handler::{self, Client, Command, Flags};
+use crache::app::resp::{Frame, Protocol};
+
+// Runs a looked-up command through the dispatcher, as the server would.
+fn run(command: &Command, args: Vec<Frame>) -> Frame {
+    let mut argv = vec![Frame::bulk(command.name)];
+    argv.extend(args);
+    handler::dispatch(&mut Client::new(), argv)
+}
 
 // Helper function to create bulk string values for testing
-fn bulk_string(s: &str) -> Value {
-    Value {
-        typ: "bulk".to_string(),
-        str: String::new(),
-        num: 0,
-        bulk: s.to_string(),
-        array: vec![],
-    }
+fn bulk_string(s: &str) -> Frame {
+    Frame::bulk(s)
 }
 
 #[test]
 fn test_ping_handler_no_args() {
     let args = vec![];
     if let Some(handler_fn) = handler::get_handler("PING") {
-        let result = handler_fn(args);
-        assert_eq!(result.typ, "string");
-        assert_eq!(result.str, "PONG");
+        let result = run(handler_fn, args);
+        assert_eq!(result, Frame::simple("PONG"));
     } else {
         panic!("PING handler not found");
     }
@@ -28,9 +28,8 @@ fn test_ping_handler_no_args() {
 fn test_ping_handler_with_arg() {
     let args = vec![bulk_string("hello")];
     if let Some(handler_fn) = handler::get_handler("PING") {
-        let result = handler_fn(args);
-        assert_eq!(result.typ, "string");
-        assert_eq!(result.str, "hello");
+        let result = run(handler_fn, args);
+        assert_eq!(result, Frame::bulk("hello"));
     } else {
         panic!("PING handler not found");
     }
@@ -44,16 +43,14 @@ fn test_set_and_get_handler() {
     let set_args = vec![bulk_string(key), bulk_string(value)];
 
     if let Some(set_fn) = handler::get_handler("SET") {
-        let set_result = set_fn(set_args);
-        assert_eq!(set_result.typ, "string");
-        assert_eq!(set_result.str, "OK");
+        let set_result = run(set_fn, set_args);
+        assert_eq!(set_result, Frame::simple("OK"));
 
         // Test GET
         let get_args = vec![bulk_string(key)];
         if let Some(get_fn) = handler::get_handler("GET") {
-            let get_result = get_fn(get_args);
-            assert_eq!(get_result.typ, "bulk");
-            assert_eq!(get_result.bulk, value);
+            let get_result = run(get_fn, get_args);
+            assert_eq!(get_result, Frame::bulk(value));
         } else {
             panic!("GET handler not found");
         }
@@ -66,8 +63,8 @@ fn test_set_and_get_handler() {
 fn test_get_nonexistent_key() {
     let args = vec![bulk_string("nonexistent_key")];
     if let Some(handler_fn) = handler::get_handler("GET") {
-        let result = handler_fn(args);
-        assert_eq!(result.typ, "null");
+        let result = run(handler_fn, args);
+        assert_eq!(result, Frame::Null);
     } else {
         panic!("GET handler not found");
     }
@@ -79,9 +76,8 @@ fn test_hset_handler_invalid_args() {
     let invalid_args = vec![bulk_string("hash"), bulk_string("field")]; // Missing value
 
     if let Some(handler_fn) = handler::get_handler("HSET") {
-        let result = handler_fn(invalid_args);
-        assert_eq!(result.typ, "error");
-        assert!(result.str.contains("wrong number of arguments"));
+        let result = run(handler_fn, invalid_args);
+        assert!(matches!(&result, Frame::Error(msg) if msg.contains("wrong number of arguments")));
     } else {
         panic!("HSET handler not found");
     }
@@ -93,9 +89,8 @@ fn test_hget_handler_invalid_args() {
     let invalid_args = vec![bulk_string("hash")]; // Missing field
 
     if let Some(handler_fn) = handler::get_handler("HGET") {
-        let result = handler_fn(invalid_args);
-        assert_eq!(result.typ, "error");
-        assert!(result.str.contains("wrong number of arguments"));
+        let result = run(handler_fn, invalid_args);
+        assert!(matches!(&result, Frame::Error(msg) if msg.contains("wrong number of arguments")));
     } else {
         panic!("HGET handler not found");
     }
@@ -110,16 +105,14 @@ fn test_hset_and_hget_handler() {
     let hset_args = vec![bulk_string(hash), bulk_string(field), bulk_string(value)];
 
     if let Some(hset_fn) = handler::get_handler("HSET") {
-        let hset_result = hset_fn(hset_args);
-        assert_eq!(hset_result.typ, "string");
-        assert_eq!(hset_result.str, "OK");
+        let hset_result = run(hset_fn, hset_args);
+        assert_eq!(hset_result, Frame::integer(1));
 
         // Test HGET
         let hget_args = vec![bulk_string(hash), bulk_string(field)];
         if let Some(hget_fn) = handler::get_handler("HGET") {
-            let hget_result = hget_fn(hget_args);
-            assert_eq!(hget_result.typ, "bulk");
-            assert_eq!(hget_result.bulk, value);
+            let hget_result = run(hget_fn, hget_args);
+            assert_eq!(hget_result, Frame::bulk(value));
         } else {
             panic!("HGET handler not found");
         }
@@ -136,8 +129,8 @@ fn test_hget_nonexistent_key() {
     let hget_args = vec![bulk_string(hash), bulk_string(field)];
 
     if let Some(hget_fn) = handler::get_handler("HGET") {
-        let result = hget_fn(hget_args);
-        assert_eq!(result.typ, "null");
+        let result = run(hget_fn, hget_args);
+        assert_eq!(result, Frame::Null);
     } else {
         panic!("HGET handler not found");
     }
@@ -152,16 +145,16 @@ fn test_hget_nonexistent_field() {
     let hset_args = vec![bulk_string(hash), bulk_string(field), bulk_string(value)];
 
     if let Some(hset_fn) = handler::get_handler("HSET") {
-        let hset_result = hset_fn(hset_args);
-        assert_eq!(hset_result.typ, "string");
+        let hset_result = run(hset_fn, hset_args);
+        assert_eq!(hset_result, Frame::integer(1));
 
         // Now try to get a field that doesn't exist
         let nonexistent_field = "nonexistent_field";
         let hget_args = vec![bulk_string(hash), bulk_string(nonexistent_field)];
 
         if let Some(hget_fn) = handler::get_handler("HGET") {
-            let result = hget_fn(hget_args);
-            assert_eq!(result.typ, "null");
+            let result = run(hget_fn, hget_args);
+            assert_eq!(result, Frame::Null);
         } else {
             panic!("HGET handler not found");
         }
@@ -176,9 +169,8 @@ fn test_hgetall_handler_invalid_args() {
     let invalid_args = vec![bulk_string("hash"), bulk_string("extra")];
 
     if let Some(handler_fn) = handler::get_handler("HGETALL") {
-        let result = handler_fn(invalid_args);
-        assert_eq!(result.typ, "error");
-        assert!(result.str.contains("wrong number of arguments"));
+        let result = run(handler_fn, invalid_args);
+        assert!(matches!(&result, Frame::Error(msg) if msg.contains("wrong number of arguments")));
     } else {
         panic!("HGETALL handler not found");
     }
@@ -191,9 +183,8 @@ fn test_hgetall_empty_hash() {
     let hgetall_args = vec![bulk_string(hash)];
 
     if let Some(hgetall_fn) = handler::get_handler("HGETALL") {
-        let result = hgetall_fn(hgetall_args);
-        assert_eq!(result.typ, "array");
-        assert!(result.array.is_empty());
+        let result = run(hgetall_fn, hgetall_args);
+        assert_eq!(result, Frame::map(vec![]));
     } else {
         panic!("HGETALL handler not found");
     }
@@ -209,36 +200,39 @@ fn test_hgetall_handler() {
         // First set all the fields
         for (field, value) in &fields {
             let hset_args = vec![bulk_string(hash), bulk_string(field), bulk_string(value)];
-            hset_fn(hset_args);
+            run(hset_fn, hset_args);
         }
 
         // Now test HGETALL
         let hgetall_args = vec![bulk_string(hash)];
 
         if let Some(hgetall_fn) = handler::get_handler("HGETALL") {
-            let result = hgetall_fn(hgetall_args);
+            let result = run(hgetall_fn, hgetall_args);
 
             // Verify the result
-            assert_eq!(result.typ, "array");
-            assert_eq!(result.array.len(), 6); // 3 fields * 2 (field + value)
+            let pairs = match result {
+                Frame::Map(pairs) => pairs,
+                other => panic!("expected map, got {:?}", other),
+            };
+            assert_eq!(pairs.len(), 3); // 3 field/value pairs
 
             // We need to check all field-value pairs are present
             // Since hash iteration order is not guaranteed, we need to collect all fields and values
             let mut found_fields = Vec::new();
             let mut found_values = Vec::new();
 
-            for i in (0..result.array.len()).step_by(2) {
-                found_fields.push(result.array[i].bulk.clone());
-                found_values.push(result.array[i + 1].bulk.clone());
+            for (field, value) in &pairs {
+                found_fields.push(field.as_bulk().unwrap().to_vec());
+                found_values.push(value.as_bulk().unwrap().to_vec());
             }
 
             // Check each expected field-value pair exists
             for (field, value) in &fields {
-                let field_pos = found_fields.iter().position(|f| f == field);
+                let field_pos = found_fields.iter().position(|f| f == field.as_bytes());
                 assert!(field_pos.is_some());
 
                 let index = field_pos.unwrap();
-                assert_eq!(found_values[index], *value);
+                assert_eq!(found_values[index], value.as_bytes());
             }
         } else {
             panic!("HGETALL handler not found");
@@ -253,3 +247,655 @@ fn test_get_handler_unknown_command() {
     let handler = handler::get_handler("UNKNOWN_COMMAND");
     assert!(handler.is_none());
 }
+
+#[test]
+fn test_set_and_get_binary_value() {
+    let key = vec![0xde, 0xad, 0x00, 0xbe, 0xef];
+    let value = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, 0x00, 0xff];
+
+    let set_fn = handler::get_handler("SET").expect("SET handler not found");
+    let set_result = run(
+        set_fn,
+        vec![Frame::bulk(key.clone()), Frame::bulk(value.clone())],
+    );
+    assert_eq!(set_result, Frame::ok());
+
+    let get_fn = handler::get_handler("GET").expect("GET handler not found");
+    let get_result = run(get_fn, vec![Frame::bulk(key)]);
+    assert_eq!(get_result, Frame::bulk(value));
+}
+
+#[test]
+fn test_hset_and_hget_binary_field() {
+    let hash = b"binary_hash\xff".to_vec();
+    let field = vec![0x00, 0x01, 0x02];
+    let value = vec![0xc3, 0x28];
+
+    let hset_fn = handler::get_handler("HSET").expect("HSET handler not found");
+    run(
+        hset_fn,
+        vec![
+            Frame::bulk(hash.clone()),
+            Frame::bulk(field.clone()),
+            Frame::bulk(value.clone()),
+        ],
+    );
+
+    let hget_fn = handler::get_handler("HGET").expect("HGET handler not found");
+    let result = run(hget_fn, vec![Frame::bulk(hash), Frame::bulk(field)]);
+    assert_eq!(result, Frame::bulk(value));
+}
+
+// Command table tests
+
+fn bulks(items: &[&str]) -> Vec<Frame> {
+    items.iter().map(|s| Frame::bulk(*s)).collect()
+}
+
+#[test]
+fn test_dispatch_checks_arity() {
+    let mut client = Client::new();
+    let reply = handler::dispatch(&mut client, bulks(&["GET"]));
+    assert_eq!(
+        reply,
+        Frame::error("ERR wrong number of arguments for 'get' command")
+    );
+
+    let reply = handler::dispatch(&mut client, bulks(&["set", "k"]));
+    assert_eq!(
+        reply,
+        Frame::error("ERR wrong number of arguments for 'set' command")
+    );
+
+    let reply = handler::dispatch(&mut client, bulks(&["GET", "a", "b"]));
+    assert!(reply.is_error());
+}
+
+#[test]
+fn test_dispatch_unknown_command() {
+    let reply = handler::dispatch(&mut Client::new(), bulks(&["NOPE", "x"]));
+    assert_eq!(reply, Frame::error("ERR unknown command 'NOPE'"));
+}
+
+#[test]
+fn test_lookup_is_case_insensitive() {
+    let command = handler::get_handler("hGeTaLl").expect("HGETALL not found");
+    assert_eq!(command.name, "hgetall");
+}
+
+#[test]
+fn test_command_metadata() {
+    let set = handler::get_handler("SET").unwrap();
+    assert!(set.is_write());
+    assert_eq!(set.flags.names(), vec!["write", "denyoom"]);
+    assert_eq!((set.first_key, set.last_key, set.key_step), (1, 1, 1));
+
+    let get = handler::get_handler("GET").unwrap();
+    assert!(!get.is_write());
+    assert!(get.flags.contains(Flags::READONLY | Flags::FAST));
+
+    let ping = handler::get_handler("PING").unwrap();
+    assert!(ping.arity_ok(1) && ping.arity_ok(2));
+    assert_eq!(ping.key_positions(&bulks(&["PING"])), Vec::<usize>::new());
+}
+
+#[test]
+fn test_key_positions() {
+    let hset = handler::get_handler("HSET").unwrap();
+    assert_eq!(
+        hset.key_positions(&bulks(&["HSET", "h", "f", "v"])),
+        vec![1]
+    );
+
+    fn noop(_: &mut Client, _: Vec<Frame>) -> Frame {
+        Frame::ok()
+    }
+    // MSET-style: every other argument from the first is a key.
+    let mset = Command::new("mset", -3, Flags::WRITE, noop).keys(1, -1, 2);
+    assert_eq!(
+        mset.key_positions(&bulks(&["MSET", "a", "1", "b", "2", "c", "3"])),
+        vec![1, 3, 5]
+    );
+    // BLPOP-style: all arguments but the trailing timeout.
+    let blpop = Command::new("blpop", -3, Flags::WRITE, noop).keys(1, -2, 1);
+    assert_eq!(
+        blpop.key_positions(&bulks(&["BLPOP", "a", "b", "0"])),
+        vec![1, 2]
+    );
+}
+
+#[test]
+fn test_command_table_registry() {
+    fn echo(_: &mut Client, args: Vec<Frame>) -> Frame {
+        args.into_iter().next().unwrap_or(Frame::Null)
+    }
+    let mut table = handler::CommandTable::new();
+    assert!(table.is_empty());
+    table.register(Command::new("echo", 2, Flags::FAST, echo));
+
+    assert_eq!(table.len(), 1);
+    assert!(table.get("ECHO").is_some());
+    assert!(handler::commands().len() >= 8);
+}
+
+#[test]
+fn test_hello_switches_protocol_and_sets_name() {
+    let mut client = Client::new();
+
+    let reply = handler::dispatch(
+        &mut client,
+        bulks(&[
+            "HELLO", "3", "AUTH", "default", "secret", "SETNAME", "worker-1",
+        ]),
+    );
+    assert!(matches!(reply, Frame::Map(_)));
+    assert_eq!(client.protocol, Protocol::Resp3);
+    assert_eq!(client.name.as_deref(), Some(&b"worker-1"[..]));
+
+    handler::dispatch(&mut client, bulks(&["HELLO", "2"]));
+    assert_eq!(client.protocol, Protocol::Resp2);
+
+    let reply = handler::dispatch(&mut client, bulks(&["HELLO", "3", "SETNAME"]));
+    assert!(reply.is_error());
+    assert_eq!(client.protocol, Protocol::Resp2);
+}
+
+#[test]
+fn test_hello_rejects_unknown_protocol() {
+    let mut client = Client::new();
+
+    let reply = handler::dispatch(&mut client, bulks(&["HELLO", "4"]));
+    assert!(matches!(reply, Frame::Error(msg) if msg.starts_with("NOPROTO")));
+    assert_eq!(client.protocol, Protocol::Resp2);
+
+    let reply = handler::dispatch(&mut client, bulks(&["HELLO", "three"]));
+    assert!(reply.is_error());
+}
+
+// COMMAND tests
+
+fn command(args: &[&str]) -> Frame {
+    let mut argv = vec!["COMMAND"];
+    argv.extend_from_slice(args);
+    handler::dispatch(&mut Client::new(), bulks(&argv))
+}
+
+#[test]
+fn test_command_count_and_list() {
+    let count = handler::commands().len() as i64;
+    assert_eq!(command(&["COUNT"]), Frame::integer(count));
+
+    match command(&["LIST"]) {
+        Frame::Array(names) => {
+            assert_eq!(names.len() as i64, count);
+            assert!(names.contains(&Frame::bulk("hgetall")));
+        }
+        other => panic!("expected array, got {:?}", other),
+    }
+
+    match command(&[]) {
+        Frame::Array(entries) => assert_eq!(entries.len() as i64, count),
+        other => panic!("expected array, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_command_info_shape() {
+    let entries = match command(&["INFO", "get", "no-such-command"]) {
+        Frame::Array(entries) => entries,
+        other => panic!("expected array, got {:?}", other),
+    };
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1], Frame::Null);
+
+    let info = match &entries[0] {
+        Frame::Array(info) => info,
+        other => panic!("expected array, got {:?}", other),
+    };
+    assert_eq!(info.len(), 10);
+    assert_eq!(info[0], Frame::bulk("get"));
+    assert_eq!(info[1], Frame::integer(2));
+    assert_eq!(
+        info[2],
+        Frame::Set(vec![Frame::simple("readonly"), Frame::simple("fast")])
+    );
+    assert_eq!(
+        &info[3..6],
+        &[Frame::integer(1), Frame::integer(1), Frame::integer(1)]
+    );
+    match &info[6] {
+        Frame::Set(categories) => {
+            assert!(categories.contains(&Frame::simple("@read")));
+            assert!(categories.contains(&Frame::simple("@string")));
+        }
+        other => panic!("expected set, got {:?}", other),
+    }
+    match &info[8] {
+        Frame::Array(specs) => assert_eq!(specs.len(), 1),
+        other => panic!("expected array, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_command_info_resp2_encoding() {
+    // Flags are a set in RESP3 but a plain array for RESP2 clients.
+    let info = command(&["INFO", "ping"]).marshal();
+    let text = String::from_utf8(info).unwrap();
+    assert!(text.starts_with("*1\r\n*10\r\n$4\r\nping\r\n:-1\r\n*1\r\n+fast\r\n"));
+}
+
+#[test]
+fn test_command_docs() {
+    let docs = match command(&["DOCS", "set"]) {
+        Frame::Map(docs) => docs,
+        other => panic!("expected map, got {:?}", other),
+    };
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].0, Frame::bulk("set"));
+    match &docs[0].1 {
+        Frame::Map(fields) => {
+            assert!(fields.contains(&(Frame::bulk("group"), Frame::bulk("string"))));
+            assert!(fields.contains(&(Frame::bulk("since"), Frame::bulk("1.0.0"))));
+        }
+        other => panic!("expected map, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_command_getkeys() {
+    assert_eq!(
+        command(&["GETKEYS", "SET", "user:1", "value"]),
+        Frame::array(vec![Frame::bulk("user:1")])
+    );
+    assert_eq!(
+        command(&["GETKEYS", "hset", "h", "f", "v"]),
+        Frame::array(vec![Frame::bulk("h")])
+    );
+    assert_eq!(
+        command(&["GETKEYS", "NOPE", "x"]),
+        Frame::error("ERR Invalid command specified")
+    );
+    assert_eq!(
+        command(&["GETKEYS", "GET"]),
+        Frame::error("ERR Invalid number of arguments specified for command")
+    );
+    assert_eq!(
+        command(&["GETKEYS", "PING"]),
+        Frame::error("ERR The command has no key arguments")
+    );
+}
+
+#[test]
+fn test_command_unknown_subcommand() {
+    assert!(command(&["BOGUS"]).is_error());
+    assert!(command(&["COUNT", "extra"]).is_error());
+}
+
+// Keyspace type tests
+
+fn call(args: &[&str]) -> Frame {
+    handler::dispatch(&mut Client::new(), bulks(args))
+}
+
+const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+#[test]
+fn test_get_on_hash_is_wrongtype() {
+    call(&["HSET", "wt:hash", "field", "value"]);
+    assert_eq!(call(&["GET", "wt:hash"]), Frame::error(WRONGTYPE));
+}
+
+#[test]
+fn test_hash_commands_on_string_are_wrongtype() {
+    call(&["SET", "wt:string", "value"]);
+    assert_eq!(
+        call(&["HSET", "wt:string", "f", "v"]),
+        Frame::error(WRONGTYPE)
+    );
+    assert_eq!(call(&["HGET", "wt:string", "f"]), Frame::error(WRONGTYPE));
+    assert_eq!(call(&["HGETALL", "wt:string"]), Frame::error(WRONGTYPE));
+    // The failed HSET must not have clobbered the string.
+    assert_eq!(call(&["GET", "wt:string"]), Frame::bulk("value"));
+}
+
+#[test]
+fn test_set_replaces_hash() {
+    call(&["HSET", "wt:replaced", "field", "value"]);
+    assert_eq!(call(&["SET", "wt:replaced", "now a string"]), Frame::ok());
+    assert_eq!(call(&["GET", "wt:replaced"]), Frame::bulk("now a string"));
+    assert_eq!(call(&["TYPE", "wt:replaced"]), Frame::simple("string"));
+}
+
+#[test]
+fn test_type_command() {
+    call(&["SET", "type:string", "v"]);
+    call(&["HSET", "type:hash", "f", "v"]);
+
+    assert_eq!(call(&["TYPE", "type:string"]), Frame::simple("string"));
+    assert_eq!(call(&["TYPE", "type:hash"]), Frame::simple("hash"));
+    assert_eq!(call(&["TYPE", "type:missing"]), Frame::simple("none"));
+}
+
+// Expiry tests
+
+fn integer(frame: Frame) -> i64 {
+    match frame {
+        Frame::Integer(n) => n,
+        other => panic!("expected integer, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_set_with_ex_and_px() {
+    assert_eq!(call(&["SET", "exp:ex", "v", "EX", "100"]), Frame::ok());
+    let ttl = integer(call(&["TTL", "exp:ex"]));
+    assert!((99..=100).contains(&ttl), "ttl was {}", ttl);
+
+    assert_eq!(call(&["SET", "exp:px", "v", "px", "100000"]), Frame::ok());
+    let pttl = integer(call(&["PTTL", "exp:px"]));
+    assert!((99_000..=100_000).contains(&pttl), "pttl was {}", pttl);
+
+    // A plain SET clears the TTL.
+    call(&["SET", "exp:px", "v"]);
+    assert_eq!(call(&["TTL", "exp:px"]), Frame::integer(-1));
+}
+
+#[test]
+fn test_set_invalid_expire_and_syntax() {
+    let invalid = Frame::error("ERR invalid expire time in 'set' command");
+    assert_eq!(call(&["SET", "exp:bad", "v", "EX", "0"]), invalid);
+    assert_eq!(call(&["SET", "exp:bad", "v", "PX", "-5"]), invalid);
+    assert_eq!(
+        call(&["SET", "exp:bad", "v", "EX", "9223372036854775807"]),
+        invalid
+    );
+    assert_eq!(
+        call(&["SET", "exp:bad", "v", "EX", "ten"]),
+        Frame::error("ERR value is not an integer or out of range")
+    );
+
+    let syntax = Frame::error("ERR syntax error");
+    assert_eq!(call(&["SET", "exp:bad", "v", "NX", "XX"]), syntax);
+    assert_eq!(
+        call(&["SET", "exp:bad", "v", "EX", "10", "PX", "10"]),
+        syntax
+    );
+    assert_eq!(
+        call(&["SET", "exp:bad", "v", "EX", "10", "KEEPTTL"]),
+        syntax
+    );
+    assert_eq!(call(&["SET", "exp:bad", "v", "EX"]), syntax);
+    assert_eq!(call(&["SET", "exp:bad", "v", "BOGUS"]), syntax);
+    assert_eq!(call(&["GET", "exp:bad"]), Frame::Null);
+}
+
+#[test]
+fn test_set_nx_xx_get() {
+    assert_eq!(call(&["SET", "exp:nx", "a", "XX"]), Frame::Null);
+    assert_eq!(call(&["GET", "exp:nx"]), Frame::Null);
+    assert_eq!(call(&["SET", "exp:nx", "a", "NX"]), Frame::ok());
+    assert_eq!(call(&["SET", "exp:nx", "b", "NX"]), Frame::Null);
+    assert_eq!(call(&["SET", "exp:nx", "c", "XX", "GET"]), Frame::bulk("a"));
+    assert_eq!(call(&["SET", "exp:nx", "d", "NX", "GET"]), Frame::bulk("c"));
+    assert_eq!(call(&["GET", "exp:nx"]), Frame::bulk("c"));
+    assert_eq!(call(&["SET", "exp:new", "v", "GET"]), Frame::Null);
+
+    call(&["HSET", "exp:hash", "f", "v"]);
+    assert_eq!(
+        call(&["SET", "exp:hash", "v", "GET"]),
+        Frame::error(WRONGTYPE)
+    );
+    assert_eq!(call(&["TYPE", "exp:hash"]), Frame::simple("hash"));
+}
+
+#[test]
+fn test_set_keepttl() {
+    call(&["SET", "exp:keep", "a", "EX", "100"]);
+    call(&["SET", "exp:keep", "b", "KEEPTTL"]);
+    assert_eq!(call(&["GET", "exp:keep"]), Frame::bulk("b"));
+    assert!(integer(call(&["TTL", "exp:keep"])) > 0);
+}
+
+#[test]
+fn test_ttl_missing_and_persistent() {
+    assert_eq!(call(&["TTL", "exp:missing"]), Frame::integer(-2));
+    assert_eq!(call(&["PTTL", "exp:missing"]), Frame::integer(-2));
+    assert_eq!(call(&["EXPIRETIME", "exp:missing"]), Frame::integer(-2));
+    call(&["SET", "exp:forever", "v"]);
+    assert_eq!(call(&["TTL", "exp:forever"]), Frame::integer(-1));
+    assert_eq!(call(&["PEXPIRETIME", "exp:forever"]), Frame::integer(-1));
+}
+
+#[test]
+fn test_expire_and_persist() {
+    assert_eq!(call(&["EXPIRE", "exp:none", "10"]), Frame::integer(0));
+
+    call(&["SET", "exp:key", "v"]);
+    assert_eq!(call(&["EXPIRE", "exp:key", "100"]), Frame::integer(1));
+    assert!(integer(call(&["TTL", "exp:key"])) > 0);
+    assert_eq!(call(&["PERSIST", "exp:key"]), Frame::integer(1));
+    assert_eq!(call(&["PERSIST", "exp:key"]), Frame::integer(0));
+    assert_eq!(call(&["TTL", "exp:key"]), Frame::integer(-1));
+}
+
+#[test]
+fn test_expireat_and_expiretime() {
+    call(&["SET", "exp:at", "v"]);
+    assert_eq!(
+        call(&["EXPIREAT", "exp:at", "4000000000"]),
+        Frame::integer(1)
+    );
+    assert_eq!(
+        call(&["EXPIRETIME", "exp:at"]),
+        Frame::integer(4_000_000_000)
+    );
+    assert_eq!(
+        call(&["PEXPIRETIME", "exp:at"]),
+        Frame::integer(4_000_000_000_000)
+    );
+    assert_eq!(
+        call(&["PEXPIREAT", "exp:at", "4000000000123"]),
+        Frame::integer(1)
+    );
+    assert_eq!(
+        call(&["PEXPIRETIME", "exp:at"]),
+        Frame::integer(4_000_000_000_123)
+    );
+}
+
+#[test]
+fn test_expire_in_the_past_deletes() {
+    call(&["SET", "exp:past", "v"]);
+    assert_eq!(call(&["EXPIRE", "exp:past", "-1"]), Frame::integer(1));
+    assert_eq!(call(&["GET", "exp:past"]), Frame::Null);
+    assert_eq!(call(&["TYPE", "exp:past"]), Frame::simple("none"));
+}
+
+#[test]
+fn test_expire_options() {
+    call(&["SET", "exp:opt", "v"]);
+    assert_eq!(call(&["EXPIRE", "exp:opt", "100", "XX"]), Frame::integer(0));
+    assert_eq!(call(&["EXPIRE", "exp:opt", "100", "GT"]), Frame::integer(0));
+    assert_eq!(call(&["EXPIRE", "exp:opt", "100", "NX"]), Frame::integer(1));
+    assert_eq!(call(&["EXPIRE", "exp:opt", "200", "NX"]), Frame::integer(0));
+    assert_eq!(call(&["EXPIRE", "exp:opt", "50", "GT"]), Frame::integer(0));
+    assert_eq!(call(&["EXPIRE", "exp:opt", "200", "GT"]), Frame::integer(1));
+    assert_eq!(call(&["EXPIRE", "exp:opt", "300", "LT"]), Frame::integer(0));
+    assert_eq!(call(&["EXPIRE", "exp:opt", "150", "lt"]), Frame::integer(1));
+    let ttl = integer(call(&["TTL", "exp:opt"]));
+    assert!((149..=150).contains(&ttl), "ttl was {}", ttl);
+
+    // LT applies to keys without a TTL, which count as never expiring.
+    call(&["SET", "exp:opt2", "v"]);
+    assert_eq!(
+        call(&["EXPIRE", "exp:opt2", "100", "LT"]),
+        Frame::integer(1)
+    );
+}
+
+#[test]
+fn test_expire_option_errors() {
+    call(&["SET", "exp:opterr", "v"]);
+    assert_eq!(
+        call(&["EXPIRE", "exp:opterr", "10", "NX", "XX"]),
+        Frame::error("ERR NX and XX, GT or LT options at the same time are not compatible")
+    );
+    assert_eq!(
+        call(&["EXPIRE", "exp:opterr", "10", "GT", "LT"]),
+        Frame::error("ERR GT and LT options at the same time are not compatible")
+    );
+    assert_eq!(
+        call(&["EXPIRE", "exp:opterr", "10", "SOON"]),
+        Frame::error("ERR Unsupported option SOON")
+    );
+    assert_eq!(
+        call(&["EXPIRE", "exp:opterr", "9223372036854775807"]),
+        Frame::error("ERR invalid expire time in 'expire' command")
+    );
+    assert_eq!(call(&["TTL", "exp:opterr"]), Frame::integer(-1));
+}
+
+#[test]
+fn test_key_expires_after_deadline() {
+    call(&["SET", "exp:short", "v", "PX", "30"]);
+    call(&["HSET", "exp:shorthash", "f", "v"]);
+    call(&["PEXPIRE", "exp:shorthash", "30"]);
+    assert_eq!(call(&["GET", "exp:short"]), Frame::bulk("v"));
+
+    std::thread::sleep(std::time::Duration::from_millis(60));
+    assert_eq!(call(&["GET", "exp:short"]), Frame::Null);
+    assert_eq!(call(&["TTL", "exp:short"]), Frame::integer(-2));
+    assert_eq!(call(&["HGET", "exp:shorthash", "f"]), Frame::Null);
+    assert_eq!(call(&["EXPIRE", "exp:short", "10"]), Frame::integer(0));
+}
+
+#[test]
+fn test_set_propagates_absolute_deadline() {
+    let mut client = Client::new();
+    handler::dispatch(&mut client, bulks(&["SET", "exp:prop", "v", "EX", "100"]));
+    let logged = client.take_propagation().expect("SET propagates");
+    assert_eq!(logged.len(), 1);
+    assert_eq!(
+        &logged[0][..4],
+        &bulks(&["SET", "exp:prop", "v", "PXAT"])[..]
+    );
+    let at: i64 = String::from_utf8_lossy(logged[0][4].as_bulk().unwrap())
+        .parse()
+        .unwrap();
+    assert_eq!(call(&["PEXPIRETIME", "exp:prop"]), Frame::integer(at));
+
+    // A SET that did nothing logs nothing.
+    handler::dispatch(&mut client, bulks(&["SET", "exp:prop", "w", "NX"]));
+    assert_eq!(client.take_propagation(), Some(vec![]));
+}
+
+#[test]
+fn test_expire_propagates_pexpireat() {
+    let mut client = Client::new();
+    call(&["SET", "exp:prop2", "v"]);
+    handler::dispatch(&mut client, bulks(&["EXPIREAT", "exp:prop2", "4000000000"]));
+    assert_eq!(
+        client.take_propagation(),
+        Some(vec![bulks(&["PEXPIREAT", "exp:prop2", "4000000000000"])])
+    );
+
+    handler::dispatch(&mut client, bulks(&["EXPIRE", "exp:nothing", "10"]));
+    assert_eq!(client.take_propagation(), Some(vec![]));
+}
+
+// CONFIG tests. These leave maxmemory unset, since the keyspace is shared
+// with every other test.
+
+fn config_map(args: &[&str]) -> Vec<(String, String)> {
+    let mut argv = vec!["CONFIG", "GET"];
+    argv.extend_from_slice(args);
+    match call(&argv) {
+        Frame::Map(pairs) => pairs
+            .into_iter()
+            .map(|(k, v)| {
+                (
+                    String::from_utf8(k.as_bulk().unwrap().to_vec()).unwrap(),
+                    String::from_utf8(v.as_bulk().unwrap().to_vec()).unwrap(),
+                )
+            })
+            .collect(),
+        other => panic!("expected map, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_config_get() {
+    assert_eq!(
+        config_map(&["maxmemory"]),
+        vec![("maxmemory".to_string(), "0".to_string())]
+    );
+    let names: Vec<String> = config_map(&["maxmemory*"])
+        .into_iter()
+        .map(|(k, _)| k)
+        .collect();
+    assert_eq!(
+        names,
+        vec!["maxmemory", "maxmemory-policy", "maxmemory-samples"]
+    );
+    assert!(config_map(&["nope"]).is_empty());
+}
+
+#[test]
+fn test_config_set() {
+    assert_eq!(
+        call(&["CONFIG", "SET", "lfu-log-factor", "20"]),
+        Frame::ok()
+    );
+    assert_eq!(
+        config_map(&["lfu-log-factor"]),
+        vec![("lfu-log-factor".to_string(), "20".to_string())]
+    );
+    assert_eq!(
+        call(&["CONFIG", "SET", "lfu-log-factor", "10"]),
+        Frame::ok()
+    );
+
+    assert_eq!(
+        call(&["CONFIG", "SET", "bogus", "1"]),
+        Frame::error("ERR Unknown option or number of arguments for CONFIG SET - 'bogus'")
+    );
+    assert_eq!(
+        call(&["CONFIG", "SET", "maxmemory", "lots"]),
+        Frame::error(
+            "ERR CONFIG SET failed (possibly related to argument 'maxmemory') - argument must be a memory value"
+        )
+    );
+    // A rejected parameter leaves the others in the same call unapplied.
+    assert!(call(&[
+        "CONFIG",
+        "SET",
+        "maxmemory-samples",
+        "7",
+        "maxmemory-samples",
+        "0"
+    ])
+    .is_error());
+    assert_eq!(
+        config_map(&["maxmemory-samples"]),
+        vec![("maxmemory-samples".to_string(), "5".to_string())]
+    );
+    assert!(call(&["CONFIG", "SET", "maxmemory"]).is_error());
+    assert!(call(&["CONFIG", "REWRITE"]).is_error());
+}
+
+#[test]
+fn test_shutdown_only_asks_the_server_to_exit() {
+    let mut client = Client::new();
+    let shutdown = |client: &mut Client, args: &[&str]| {
+        let mut argv = vec![Frame::bulk("SHUTDOWN")];
+        argv.extend(args.iter().map(|a| Frame::bulk(*a)));
+        handler::dispatch(client, argv)
+    };
+    assert_eq!(
+        shutdown(&mut client, &["NOW", "LATER"]),
+        Frame::error("ERR syntax error")
+    );
+    assert!(!client.shutdown);
+    assert_eq!(shutdown(&mut client, &["nosave"]), Frame::simple("OK"));
+    assert!(client.shutdown);
+}
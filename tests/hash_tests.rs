@@ -0,0 +1,476 @@
+use crache::app::aof::Aof;
+use crache::app::handler::{self, Client};
+use crache::app::resp::{Frame, Protocol};
+use crache::app::server::handle_command;
+use std::sync::Arc;
+
+fn bulks(args: &[&str]) -> Vec<Frame> {
+    args.iter().map(|s| Frame::bulk(*s)).collect()
+}
+
+fn call(args: &[&str]) -> Frame {
+    handler::dispatch(&mut Client::new(), bulks(args))
+}
+
+fn list(args: &[&str]) -> Frame {
+    Frame::array(bulks(args))
+}
+
+// The strings of a reply array, sorted, since hashes have no order.
+fn sorted(frame: Frame) -> Vec<String> {
+    let Frame::Array(items) = frame else {
+        panic!("not an array: {:?}", frame);
+    };
+    let mut items: Vec<String> = items
+        .iter()
+        .map(|f| String::from_utf8(f.as_bulk().unwrap().to_vec()).unwrap())
+        .collect();
+    items.sort();
+    items
+}
+
+const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+#[test]
+fn test_set_get_and_delete_fields() {
+    assert_eq!(
+        call(&["HSET", "hash:a", "f1", "v1", "f2", "v2"]),
+        Frame::integer(2)
+    );
+    assert_eq!(
+        call(&["HSET", "hash:a", "f2", "new", "f3", "v3"]),
+        Frame::integer(1)
+    );
+    assert_eq!(
+        call(&["HSET", "hash:a", "f1"]),
+        Frame::error("ERR wrong number of arguments for 'hset' command")
+    );
+    assert_eq!(call(&["HMSET", "hash:a", "f4", "v4"]), Frame::simple("OK"));
+    assert_eq!(call(&["HLEN", "hash:a"]), Frame::integer(4));
+    assert_eq!(
+        call(&["HMGET", "hash:a", "f1", "nope", "f2"]),
+        Frame::array(vec![Frame::bulk("v1"), Frame::Null, Frame::bulk("new")])
+    );
+    assert_eq!(call(&["HEXISTS", "hash:a", "f3"]), Frame::integer(1));
+    assert_eq!(call(&["HEXISTS", "hash:a", "f5"]), Frame::integer(0));
+    assert_eq!(call(&["HSTRLEN", "hash:a", "f2"]), Frame::integer(3));
+    assert_eq!(call(&["HSTRLEN", "hash:a", "f5"]), Frame::integer(0));
+    assert_eq!(sorted(call(&["HKEYS", "hash:a"])), ["f1", "f2", "f3", "f4"]);
+    assert_eq!(
+        sorted(call(&["HVALS", "hash:a"])),
+        ["new", "v1", "v3", "v4"]
+    );
+
+    assert_eq!(call(&["HSETNX", "hash:a", "f1", "x"]), Frame::integer(0));
+    assert_eq!(call(&["HSETNX", "hash:a", "f5", "x"]), Frame::integer(1));
+    assert_eq!(call(&["HGET", "hash:a", "f1"]), Frame::bulk("v1"));
+
+    assert_eq!(
+        call(&["HDEL", "hash:a", "f1", "f2", "nope"]),
+        Frame::integer(2)
+    );
+    assert_eq!(
+        call(&["HDEL", "hash:a", "f3", "f4", "f5"]),
+        Frame::integer(3)
+    );
+    // Removing the last field deletes the key.
+    assert_eq!(call(&["TYPE", "hash:a"]), Frame::simple("none"));
+    assert_eq!(call(&["HLEN", "hash:a"]), Frame::integer(0));
+    assert_eq!(call(&["HKEYS", "hash:a"]), list(&[]));
+    assert_eq!(call(&["HDEL", "hash:a", "f1"]), Frame::integer(0));
+}
+
+#[test]
+fn test_hincrby() {
+    assert_eq!(call(&["HINCRBY", "hash:n", "c", "5"]), Frame::integer(5));
+    assert_eq!(call(&["HINCRBY", "hash:n", "c", "-7"]), Frame::integer(-2));
+    call(&["HSET", "hash:n", "s", "abc", "max", &i64::MAX.to_string()]);
+    assert_eq!(
+        call(&["HINCRBY", "hash:n", "s", "1"]),
+        Frame::error("ERR hash value is not an integer")
+    );
+    assert_eq!(
+        call(&["HINCRBY", "hash:n", "max", "1"]),
+        Frame::error("ERR increment or decrement would overflow")
+    );
+    assert_eq!(
+        call(&["HINCRBY", "hash:n", "c", "x"]),
+        Frame::error("ERR value is not an integer or out of range")
+    );
+
+    assert_eq!(
+        call(&["HINCRBYFLOAT", "hash:n", "f", "1.5"]),
+        Frame::bulk("1.5")
+    );
+    assert_eq!(
+        call(&["HINCRBYFLOAT", "hash:n", "c", "0.25"]),
+        Frame::bulk("-1.75")
+    );
+    assert_eq!(
+        call(&["HINCRBYFLOAT", "hash:n", "s", "1"]),
+        Frame::error("ERR hash value is not a float")
+    );
+    assert_eq!(
+        call(&["HINCRBYFLOAT", "hash:n", "f", "inf"]),
+        Frame::error("ERR increment would produce NaN or Infinity")
+    );
+    assert_eq!(call(&["HGET", "hash:n", "f"]), Frame::bulk("1.5"));
+}
+
+#[test]
+fn test_hrandfield() {
+    call(&["HSET", "hash:rand", "a", "1", "b", "2", "c", "3"]);
+    let Frame::Bulk(one) = call(&["HRANDFIELD", "hash:rand"]) else {
+        panic!("expected a field");
+    };
+    assert!(["a", "b", "c"].contains(&std::str::from_utf8(&one).unwrap()));
+
+    // A positive count never repeats and is capped at the hash's size.
+    assert_eq!(
+        sorted(call(&["HRANDFIELD", "hash:rand", "5"])),
+        ["a", "b", "c"]
+    );
+    // A negative count returns exactly that many, repeats allowed.
+    assert_eq!(sorted(call(&["HRANDFIELD", "hash:rand", "-5"])).len(), 5);
+    assert_eq!(
+        sorted(call(&["HRANDFIELD", "hash:rand", "3", "WITHVALUES"])),
+        ["1", "2", "3", "a", "b", "c"]
+    );
+    let mut client = Client::new();
+    client.protocol = Protocol::Resp3;
+    let Frame::Array(pairs) = handler::dispatch(
+        &mut client,
+        bulks(&["HRANDFIELD", "hash:rand", "-2", "WITHVALUES"]),
+    ) else {
+        panic!("expected an array");
+    };
+    assert_eq!(pairs.len(), 2);
+    assert!(pairs
+        .iter()
+        .all(|p| matches!(p, Frame::Array(kv) if kv.len() == 2)));
+
+    assert_eq!(call(&["HRANDFIELD", "hash:none"]), Frame::Null);
+    assert_eq!(call(&["HRANDFIELD", "hash:none", "2"]), list(&[]));
+    assert_eq!(
+        call(&["HRANDFIELD", "hash:rand", "2", "VALUES"]),
+        Frame::error("ERR syntax error")
+    );
+}
+
+#[test]
+fn test_hscan() {
+    for i in 0..50 {
+        call(&["HSET", "hash:scan", &format!("f{}", i), &i.to_string()]);
+    }
+    let (mut cursor, mut fields) = ("0".to_string(), Vec::new());
+    loop {
+        let Frame::Array(reply) = call(&["HSCAN", "hash:scan", &cursor, "COUNT", "7"]) else {
+            panic!("expected an array");
+        };
+        cursor = String::from_utf8(reply[0].as_bulk().unwrap().to_vec()).unwrap();
+        let Frame::Array(items) = &reply[1] else {
+            panic!("expected items");
+        };
+        assert!(items.len() <= 14);
+        fields.extend(items.chunks(2).map(|kv| kv[0].clone()));
+        if cursor == "0" {
+            break;
+        }
+    }
+    assert_eq!(fields.len(), 50);
+
+    let Frame::Array(reply) = call(&[
+        "HSCAN",
+        "hash:scan",
+        "0",
+        "MATCH",
+        "f1*",
+        "COUNT",
+        "100",
+        "NOVALUES",
+    ]) else {
+        panic!("expected an array");
+    };
+    assert_eq!(reply[0], Frame::bulk("0"));
+    assert_eq!(
+        sorted(reply[1].clone()),
+        ["f1", "f10", "f11", "f12", "f13", "f14", "f15", "f16", "f17", "f18", "f19"]
+    );
+
+    assert_eq!(
+        call(&["HSCAN", "hash:none", "0"]),
+        Frame::array(vec![Frame::bulk("0"), list(&[])])
+    );
+    assert_eq!(
+        call(&["HSCAN", "hash:scan", "-1"]),
+        Frame::error("ERR invalid cursor")
+    );
+    assert_eq!(
+        call(&["HSCAN", "hash:scan", "0", "COUNT", "0"]),
+        Frame::error("ERR syntax error")
+    );
+}
+
+#[test]
+fn test_hash_wrongtype() {
+    call(&["SET", "hash:string", "v"]);
+    assert_eq!(
+        call(&["HSET", "hash:string", "f", "v"]),
+        Frame::error(WRONGTYPE)
+    );
+    assert_eq!(
+        call(&["HMGET", "hash:string", "f"]),
+        Frame::error(WRONGTYPE)
+    );
+    assert_eq!(call(&["HLEN", "hash:string"]), Frame::error(WRONGTYPE));
+    assert_eq!(
+        call(&["HINCRBY", "hash:string", "f", "1"]),
+        Frame::error(WRONGTYPE)
+    );
+    assert_eq!(
+        call(&["HSCAN", "hash:string", "0"]),
+        Frame::error(WRONGTYPE)
+    );
+}
+
+#[test]
+fn test_hincrbyfloat_is_logged_as_hset() {
+    let path = std::env::temp_dir().join(format!("crache_hashes_{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let path = path.to_string_lossy().into_owned();
+    let aof = Arc::new(Aof::new(&path));
+
+    let mut client = Client::new();
+    client.aof = Some(Arc::clone(&aof));
+    for args in [
+        &["HINCRBYFLOAT", "hash:aof", "f", "0.5"][..],
+        &["HINCRBYFLOAT", "hash:aof", "f", "0.25"],
+        &["HSETNX", "hash:aof", "f", "x"],
+        &["HDEL", "hash:aof", "nope"],
+    ] {
+        handle_command(&mut client, Frame::array(bulks(args)));
+    }
+
+    let mut logged = Vec::new();
+    aof.read(|frame| logged.push(frame)).unwrap();
+    assert_eq!(
+        logged,
+        vec![
+            list(&["HSET", "hash:aof", "f", "0.5"]),
+            list(&["HSET", "hash:aof", "f", "0.75"])
+        ]
+    );
+    let _ = std::fs::remove_file(&path);
+}
+
+fn integers(values: &[i64]) -> Frame {
+    Frame::array(values.iter().map(|&n| Frame::integer(n)).collect())
+}
+
+#[test]
+fn test_field_expiry() {
+    call(&["HSET", "hash:ttl", "a", "1", "b", "2", "c", "3"]);
+    assert_eq!(
+        call(&["HEXPIRE", "hash:ttl", "100", "FIELDS", "2", "a", "nope"]),
+        integers(&[1, -2])
+    );
+    assert_eq!(
+        call(&["HTTL", "hash:ttl", "FIELDS", "3", "a", "b", "nope"]),
+        integers(&[100, -1, -2])
+    );
+    let Frame::Array(pttl) = call(&["HPTTL", "hash:ttl", "FIELDS", "1", "a"]) else {
+        panic!("expected an array");
+    };
+    assert!(matches!(pttl[0], Frame::Integer(ms) if ms > 99_000 && ms <= 100_000));
+
+    // Conditions, with no TTL counting as an infinite one.
+    assert_eq!(
+        call(&["HEXPIRE", "hash:ttl", "200", "NX", "FIELDS", "2", "a", "b"]),
+        integers(&[0, 1])
+    );
+    assert_eq!(
+        call(&["HEXPIRE", "hash:ttl", "150", "GT", "FIELDS", "2", "a", "b"]),
+        integers(&[1, 0])
+    );
+    assert_eq!(
+        call(&["HEXPIRE", "hash:ttl", "50", "XX", "FIELDS", "2", "a", "c"]),
+        integers(&[1, 0])
+    );
+    assert_eq!(
+        call(&["HPEXPIRE", "hash:ttl", "5000", "LT", "FIELDS", "2", "b", "c"]),
+        integers(&[1, 1])
+    );
+
+    assert_eq!(
+        call(&["HPERSIST", "hash:ttl", "FIELDS", "3", "a", "nope", "a"]),
+        integers(&[1, -2, -1])
+    );
+    assert_eq!(
+        call(&["HTTL", "hash:ttl", "FIELDS", "1", "a"]),
+        integers(&[-1])
+    );
+    // HSET replaces the value and drops the TTL.
+    call(&["HSET", "hash:ttl", "b", "new"]);
+    assert_eq!(
+        call(&["HTTL", "hash:ttl", "FIELDS", "1", "b"]),
+        integers(&[-1])
+    );
+
+    // A deadline in the past deletes the field at once.
+    assert_eq!(
+        call(&["HEXPIREAT", "hash:ttl", "1", "FIELDS", "1", "a"]),
+        integers(&[2])
+    );
+    assert_eq!(
+        call(&["HPEXPIRE", "hash:ttl", "0", "FIELDS", "2", "b", "c"]),
+        integers(&[2, 2])
+    );
+    assert_eq!(call(&["TYPE", "hash:ttl"]), Frame::simple("none"));
+    assert_eq!(
+        call(&["HEXPIRE", "hash:ttl", "100", "FIELDS", "1", "a"]),
+        integers(&[-2])
+    );
+    assert_eq!(
+        call(&["HPERSIST", "hash:ttl", "FIELDS", "1", "a"]),
+        integers(&[-2])
+    );
+}
+
+#[test]
+fn test_field_expiry_errors() {
+    call(&["HSET", "hash:ttl_err", "a", "1"]);
+    assert_eq!(
+        call(&["HEXPIRE", "hash:ttl_err", "100", "a", "FIELDS", "1", "a"]),
+        Frame::error("ERR Mandatory argument FIELDS is missing or not at the right position")
+    );
+    assert_eq!(
+        call(&["HEXPIRE", "hash:ttl_err", "100", "FIELDS", "0", "a"]),
+        Frame::error("ERR Parameter `numFields` should be greater than 0")
+    );
+    assert_eq!(
+        call(&["HTTL", "hash:ttl_err", "FIELDS", "2", "a"]),
+        Frame::error("ERR The `numfields` parameter must match the number of arguments")
+    );
+    assert_eq!(
+        call(&["HEXPIRE", "hash:ttl_err", "-1", "FIELDS", "1", "a"]),
+        Frame::error("ERR invalid expire time, must be >= 0")
+    );
+    assert_eq!(
+        call(&[
+            "HEXPIRE",
+            "hash:ttl_err",
+            &i64::MAX.to_string(),
+            "FIELDS",
+            "1",
+            "a"
+        ]),
+        Frame::error("ERR invalid expire time in 'hexpire' command")
+    );
+    call(&["SET", "hash:ttl_string", "v"]);
+    assert_eq!(
+        call(&["HTTL", "hash:ttl_string", "FIELDS", "1", "a"]),
+        Frame::error(WRONGTYPE)
+    );
+}
+
+#[test]
+fn test_fields_expire_lazily() {
+    call(&["HSET", "hash:lazy", "a", "1", "b", "2"]);
+    call(&["HPEXPIRE", "hash:lazy", "30", "FIELDS", "1", "a"]);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+
+    assert_eq!(call(&["HGET", "hash:lazy", "a"]), Frame::Null);
+    assert_eq!(call(&["HEXISTS", "hash:lazy", "a"]), Frame::integer(0));
+    assert_eq!(call(&["HLEN", "hash:lazy"]), Frame::integer(1));
+    assert_eq!(sorted(call(&["HKEYS", "hash:lazy"])), ["b"]);
+    assert_eq!(
+        call(&["HTTL", "hash:lazy", "FIELDS", "1", "a"]),
+        integers(&[-2])
+    );
+    // An expired field counts as new when set again.
+    assert_eq!(call(&["HSETNX", "hash:lazy", "a", "x"]), Frame::integer(1));
+
+    // The hash goes away with its last field.
+    call(&["HPEXPIRE", "hash:lazy", "30", "FIELDS", "2", "a", "b"]);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert_eq!(call(&["TYPE", "hash:lazy"]), Frame::simple("none"));
+    assert_eq!(call(&["HRANDFIELD", "hash:lazy"]), Frame::Null);
+    assert_eq!(call(&["HSET", "hash:lazy", "c", "3"]), Frame::integer(1));
+    assert_eq!(call(&["HLEN", "hash:lazy"]), Frame::integer(1));
+}
+
+#[test]
+fn test_many_volatile_fields_do_not_slow_every_access() {
+    let fields: Vec<String> = (0..100_000).map(|i| format!("f{}", i)).collect();
+    let mut hset = vec!["HSET", "hash:volatile"];
+    let mut hexpire = vec!["HEXPIRE", "hash:volatile", "1000", "FIELDS", "100000"];
+    for field in &fields {
+        hset.extend([field.as_str(), "v"]);
+        hexpire.push(field);
+    }
+    call(&hset);
+    call(&hexpire);
+
+    // Nothing is due, so no access looks through the deadlines.
+    let start = std::time::Instant::now();
+    for i in 0..10_000 {
+        let field = format!("f{}", i);
+        assert_eq!(call(&["HGET", "hash:volatile", &field]), Frame::bulk("v"));
+        assert_eq!(call(&["TYPE", "hash:volatile"]), Frame::simple("hash"));
+        assert_eq!(
+            call(&["HSET", "hash:volatile", &field, "w"]),
+            Frame::integer(0)
+        );
+    }
+    assert!(
+        start.elapsed() < std::time::Duration::from_secs(5),
+        "took {:?}",
+        start.elapsed()
+    );
+    assert_eq!(call(&["HLEN", "hash:volatile"]), Frame::integer(100_000));
+}
+
+#[test]
+fn test_field_expiry_is_logged_with_absolute_times() {
+    let path = std::env::temp_dir().join(format!("crache_hash_ttl_{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let path = path.to_string_lossy().into_owned();
+    let aof = Arc::new(Aof::new(&path));
+
+    let mut client = Client::new();
+    client.aof = Some(Arc::clone(&aof));
+    for args in [
+        &["HSET", "hash:ttl_aof", "f", "1.5", "g", "v"][..],
+        &[
+            "HEXPIREAT",
+            "hash:ttl_aof",
+            "4000000000",
+            "FIELDS",
+            "2",
+            "f",
+            "nope",
+        ],
+        &["HEXPIRE", "hash:ttl_aof", "100", "NX", "FIELDS", "1", "f"],
+        &["HINCRBYFLOAT", "hash:ttl_aof", "f", "1"],
+        &["HEXPIRE", "hash:ttl_aof", "0", "FIELDS", "1", "g"],
+        &["HPERSIST", "hash:ttl_aof", "FIELDS", "1", "g"],
+    ] {
+        handle_command(&mut client, Frame::array(bulks(args)));
+    }
+
+    let mut logged = Vec::new();
+    aof.read(|frame| logged.push(frame)).unwrap();
+    let at = "4000000000000";
+    assert_eq!(
+        logged,
+        vec![
+            list(&["HSET", "hash:ttl_aof", "f", "1.5", "g", "v"]),
+            list(&["HPEXPIREAT", "hash:ttl_aof", at, "FIELDS", "1", "f"]),
+            // HSET would drop the TTL on replay, so it is logged again.
+            list(&["HSET", "hash:ttl_aof", "f", "2.5"]),
+            list(&["HPEXPIREAT", "hash:ttl_aof", at, "FIELDS", "1", "f"]),
+            list(&["HDEL", "hash:ttl_aof", "g"]),
+        ]
+    );
+    let _ = std::fs::remove_file(&path);
+}
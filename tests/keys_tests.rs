@@ -0,0 +1,260 @@
+use crache::app::aof::Aof;
+use crache::app::handler::{self, Client};
+use crache::app::resp::Frame;
+use crache::app::server::handle_command;
+use std::sync::{Arc, Mutex};
+
+// FLUSHALL and whole-keyspace reads would see the other tests' keys come
+// and go, so the tests in this file take turns.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn serial() -> std::sync::MutexGuard<'static, ()> {
+    SERIAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn bulks(args: &[&str]) -> Vec<Frame> {
+    args.iter().map(|s| Frame::bulk(*s)).collect()
+}
+
+fn call(args: &[&str]) -> Frame {
+    handler::dispatch(&mut Client::new(), bulks(args))
+}
+
+fn list(args: &[&str]) -> Frame {
+    Frame::array(bulks(args))
+}
+
+// The strings of a reply array, sorted, since keys come in no order.
+fn sorted(frame: &Frame) -> Vec<String> {
+    let Frame::Array(items) = frame else {
+        panic!("not an array: {:?}", frame);
+    };
+    let mut items: Vec<String> = items
+        .iter()
+        .map(|f| String::from_utf8(f.as_bulk().unwrap().to_vec()).unwrap())
+        .collect();
+    items.sort();
+    items
+}
+
+// Runs a whole SCAN with the given options and returns every key it saw,
+// calling `between` after each step.
+fn full_scan(options: &[&str], mut between: impl FnMut()) -> Vec<String> {
+    let (mut cursor, mut keys) = ("0".to_string(), Vec::new());
+    loop {
+        let mut args = vec!["SCAN", &cursor];
+        args.extend(options);
+        let Frame::Array(reply) = call(&args) else {
+            panic!("expected an array");
+        };
+        keys.extend(sorted(&reply[1]));
+        cursor = String::from_utf8(reply[0].as_bulk().unwrap().to_vec()).unwrap();
+        between();
+        if cursor == "0" {
+            break;
+        }
+    }
+    keys.sort();
+    keys
+}
+
+#[test]
+fn test_del_exists_touch() {
+    let _serial = serial();
+    call(&["SET", "keys:s", "v"]);
+    call(&["HSET", "keys:h", "f", "v"]);
+    call(&["RPUSH", "keys:l", "a"]);
+
+    assert_eq!(
+        call(&["EXISTS", "keys:s", "keys:h", "keys:s", "keys:none"]),
+        Frame::integer(3)
+    );
+    assert_eq!(
+        call(&["TOUCH", "keys:s", "keys:l", "keys:none"]),
+        Frame::integer(2)
+    );
+    assert_eq!(
+        call(&["DEL", "keys:s", "keys:h", "keys:none"]),
+        Frame::integer(2)
+    );
+    assert_eq!(call(&["EXISTS", "keys:s", "keys:h"]), Frame::integer(0));
+    assert_eq!(call(&["UNLINK", "keys:l", "keys:l"]), Frame::integer(1));
+    assert_eq!(call(&["DEL", "keys:none"]), Frame::integer(0));
+
+    // Large values are freed in the background, but gone all the same.
+    let members: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+    let mut sadd = vec!["SADD", "keys:big"];
+    sadd.extend(members.iter().map(String::as_str));
+    call(&sadd);
+    assert_eq!(call(&["UNLINK", "keys:big"]), Frame::integer(1));
+    assert_eq!(call(&["SCARD", "keys:big"]), Frame::integer(0));
+
+    // Expired keys are not there to delete.
+    call(&["SET", "keys:exp", "v", "PX", "10"]);
+    std::thread::sleep(std::time::Duration::from_millis(30));
+    assert_eq!(call(&["DEL", "keys:exp"]), Frame::integer(0));
+}
+
+#[test]
+fn test_keys_scan_and_randomkey() {
+    let _serial = serial();
+    call(&["FLUSHALL"]);
+    assert_eq!(call(&["RANDOMKEY"]), Frame::Null);
+    for i in 0..30 {
+        call(&["SET", &format!("user:{}", i), "v"]);
+    }
+    call(&["HSET", "user:h", "f", "v"]);
+    call(&["SET", "other", "v"]);
+    assert_eq!(call(&["DBSIZE"]), Frame::integer(32));
+
+    // user:0 to user:9, and user:h.
+    assert_eq!(sorted(&call(&["KEYS", "user:?"])).len(), 11);
+    assert_eq!(sorted(&call(&["KEYS", "*"])).len(), 32);
+    assert_eq!(sorted(&call(&["KEYS", "oth[a-f]r"])), ["other"]);
+
+    assert_eq!(full_scan(&[], || {}).len(), 32);
+    assert_eq!(
+        full_scan(&["COUNT", "3", "MATCH", "user:2*"], || {}).len(),
+        11
+    );
+    assert_eq!(full_scan(&["TYPE", "HASH"], || {}), ["user:h"]);
+    assert_eq!(full_scan(&["TYPE", "zset"], || {}), Vec::<String>::new());
+
+    // Keys deleted or added mid-scan may or may not be seen; every other
+    // key is seen.
+    let mut added = 0;
+    let seen = full_scan(&["COUNT", "4"], || {
+        call(&["DEL", &format!("user:{}", added)]);
+        call(&["SET", &format!("new:{}", added), "v"]);
+        added += 1;
+    });
+    for i in added..30 {
+        assert!(seen.contains(&format!("user:{}", i)));
+    }
+    assert!(seen.contains(&"other".to_string()));
+
+    let Frame::Bulk(key) = call(&["RANDOMKEY"]) else {
+        panic!("expected a key");
+    };
+    assert_eq!(
+        call(&["EXISTS", &String::from_utf8(key).unwrap()]),
+        Frame::integer(1)
+    );
+
+    assert_eq!(call(&["SCAN", "x"]), Frame::error("ERR invalid cursor"));
+    assert_eq!(
+        call(&["SCAN", "0", "NOVALUES"]),
+        Frame::error("ERR syntax error")
+    );
+
+    assert_eq!(call(&["FLUSHALL", "ASYNC"]), Frame::simple("OK"));
+    assert_eq!(call(&["DBSIZE"]), Frame::integer(0));
+    call(&["SET", "a", "v"]);
+    assert_eq!(call(&["FLUSHDB", "SYNC"]), Frame::simple("OK"));
+    assert_eq!(call(&["KEYS", "*"]), list(&[]));
+    assert_eq!(
+        call(&["FLUSHALL", "LATER"]),
+        Frame::error("ERR syntax error")
+    );
+}
+
+#[test]
+fn test_rename_and_copy() {
+    let _serial = serial();
+    call(&["SET", "keys:src", "v", "EX", "100"]);
+    assert_eq!(
+        call(&["RENAME", "keys:src", "keys:dst"]),
+        Frame::simple("OK")
+    );
+    assert_eq!(call(&["EXISTS", "keys:src"]), Frame::integer(0));
+    assert_eq!(call(&["GET", "keys:dst"]), Frame::bulk("v"));
+    // The expiry moves along.
+    assert_eq!(call(&["TTL", "keys:dst"]), Frame::integer(100));
+    assert_eq!(
+        call(&["RENAME", "keys:src", "keys:dst"]),
+        Frame::error("ERR no such key")
+    );
+    assert_eq!(
+        call(&["RENAME", "keys:dst", "keys:dst"]),
+        Frame::simple("OK")
+    );
+
+    call(&["RPUSH", "keys:list", "a"]);
+    assert_eq!(
+        call(&["RENAMENX", "keys:dst", "keys:list"]),
+        Frame::integer(0)
+    );
+    assert_eq!(
+        call(&["RENAMENX", "keys:dst", "keys:moved"]),
+        Frame::integer(1)
+    );
+    // RENAME overwrites whatever type was there.
+    assert_eq!(
+        call(&["RENAME", "keys:moved", "keys:list"]),
+        Frame::simple("OK")
+    );
+    assert_eq!(call(&["TYPE", "keys:list"]), Frame::simple("string"));
+
+    call(&["SADD", "keys:set", "a", "b"]);
+    assert_eq!(call(&["COPY", "keys:set", "keys:copy"]), Frame::integer(1));
+    call(&["SREM", "keys:set", "a"]);
+    assert_eq!(call(&["SCARD", "keys:copy"]), Frame::integer(2));
+    assert_eq!(call(&["COPY", "keys:set", "keys:copy"]), Frame::integer(0));
+    assert_eq!(
+        call(&["COPY", "keys:set", "keys:copy", "DB", "0", "REPLACE"]),
+        Frame::integer(1)
+    );
+    assert_eq!(call(&["SCARD", "keys:copy"]), Frame::integer(1));
+    assert_eq!(call(&["COPY", "keys:none", "keys:copy"]), Frame::integer(0));
+    assert_eq!(
+        call(&["COPY", "keys:set", "keys:copy", "DB", "1"]),
+        Frame::error("ERR DB index is out of range")
+    );
+    assert_eq!(
+        call(&["COPY", "keys:set", "keys:set"]),
+        Frame::error("ERR source and destination objects are the same")
+    );
+
+    // Field expiries are part of the value.
+    call(&["HSET", "keys:hash", "f", "v", "g", "v"]);
+    call(&["HEXPIRE", "keys:hash", "100", "FIELDS", "1", "f"]);
+    call(&["COPY", "keys:hash", "keys:hash2"]);
+    assert_eq!(
+        call(&["HTTL", "keys:hash2", "FIELDS", "2", "f", "g"]),
+        Frame::array(vec![Frame::integer(100), Frame::integer(-1)])
+    );
+}
+
+#[test]
+fn test_deletions_are_logged_as_del() {
+    let _serial = serial();
+    let path = std::env::temp_dir().join(format!("crache_keys_{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let path = path.to_string_lossy().into_owned();
+    let aof = Arc::new(Aof::new(&path));
+
+    let mut client = Client::new();
+    client.aof = Some(Arc::clone(&aof));
+    for args in [
+        &["SET", "keys:aof", "v"][..],
+        &["GETDEL", "keys:aof"],
+        &["DEL", "keys:aof"],
+        &["SET", "keys:aof", "v"],
+        &["UNLINK", "keys:aof", "keys:none"],
+    ] {
+        handle_command(&mut client, Frame::array(bulks(args)));
+    }
+
+    let mut logged = Vec::new();
+    aof.read(|frame| logged.push(frame)).unwrap();
+    assert_eq!(
+        logged,
+        vec![
+            list(&["SET", "keys:aof", "v"]),
+            list(&["DEL", "keys:aof"]),
+            list(&["SET", "keys:aof", "v"]),
+            list(&["UNLINK", "keys:aof", "keys:none"]),
+        ]
+    );
+    let _ = std::fs::remove_file(&path);
+}
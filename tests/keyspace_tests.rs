@@ -0,0 +1,371 @@
+use crache::app::evict::EvictionConfig;
+use crache::app::keyspace::{
+    Entry, Hash, Keyspace, List, Set, WrongType, FIELD_OVERHEAD, KEY_OVERHEAD, WRONGTYPE_ERR,
+};
+use crache::app::resp::Frame;
+use crache::app::zset::SortedSet;
+use std::sync::Arc;
+
+#[test]
+fn test_insert_get_remove() {
+    let mut keyspace = Keyspace::new();
+    assert!(keyspace.is_empty());
+
+    keyspace.insert(b"k".to_vec(), Entry::String(b"v".to_vec()));
+    assert_eq!(keyspace.len(), 1);
+    assert!(keyspace.contains_key(b"k"));
+    assert_eq!(keyspace.get(b"k"), Some(&Entry::String(b"v".to_vec())));
+
+    assert_eq!(
+        keyspace.remove(b"k").as_deref(),
+        Some(&Entry::String(b"v".to_vec()))
+    );
+    assert!(keyspace.get(b"k").is_none());
+}
+
+#[test]
+fn test_shared_values_are_copied_on_write() {
+    let mut keyspace = Keyspace::new();
+    keyspace.insert(b"l".to_vec(), Entry::List(List::from([b"a".to_vec()])));
+    keyspace.insert(b"s".to_vec(), Entry::String(b"v".to_vec()));
+
+    // Taking the values copies none of them.
+    let shared: Vec<(Vec<u8>, Arc<Entry>)> = keyspace
+        .iter_shared()
+        .map(|(k, e)| (k.clone(), Arc::clone(e)))
+        .collect();
+    assert_eq!(shared.len(), 2);
+    let list = &shared.iter().find(|(k, _)| k == b"l").unwrap().1;
+    assert!(std::ptr::eq(&**list, keyspace.get(b"l").unwrap()));
+
+    // A change copies the value first, and the copy taken keeps the old one.
+    let Some(Entry::List(l)) = keyspace.get_mut(b"l") else {
+        panic!("expected a list");
+    };
+    l.push_back(b"b".to_vec());
+    keyspace.remove(b"s");
+    assert_eq!(**list, Entry::List(List::from([b"a".to_vec()])));
+    assert_eq!(
+        keyspace.get(b"l"),
+        Some(&Entry::List(List::from([b"a".to_vec(), b"b".to_vec()])))
+    );
+    let string = &shared.iter().find(|(k, _)| k == b"s").unwrap().1;
+    assert_eq!(**string, Entry::String(b"v".to_vec()));
+}
+
+#[test]
+fn test_typed_accessors() {
+    let mut keyspace = Keyspace::new();
+    keyspace.insert(b"s".to_vec(), Entry::String(b"v".to_vec()));
+    keyspace
+        .hash_or_insert(b"h")
+        .unwrap()
+        .insert(b"f".to_vec(), b"v".to_vec());
+
+    assert_eq!(keyspace.get_string(b"s"), Ok(Some(&b"v".to_vec())));
+    assert_eq!(keyspace.get_string(b"h"), Err(WrongType));
+    assert_eq!(keyspace.get_string(b"missing"), Ok(None));
+
+    let mut expected = Hash::new();
+    expected.insert(b"f".to_vec(), b"v".to_vec());
+    assert_eq!(keyspace.get_hash(b"h"), Ok(Some(&expected)));
+    assert_eq!(keyspace.get_hash(b"s"), Err(WrongType));
+    assert!(keyspace.get_hash_mut(b"s").is_err());
+    assert!(keyspace.hash_or_insert(b"s").is_err());
+}
+
+#[test]
+fn test_entry_type_names() {
+    assert_eq!(Entry::String(vec![]).type_name(), "string");
+    assert_eq!(Entry::Hash(Hash::new()).type_name(), "hash");
+    assert_eq!(Entry::List(List::new()).type_name(), "list");
+    assert_eq!(Entry::Set(Set::new()).type_name(), "set");
+    assert_eq!(Entry::ZSet(SortedSet::new()).type_name(), "zset");
+}
+
+#[test]
+fn test_wrongtype_reply() {
+    assert_eq!(Frame::from(WrongType), Frame::error(WRONGTYPE_ERR));
+}
+
+fn now_ms() -> u64 {
+    crache::app::util::now_ms()
+}
+
+#[test]
+fn test_set_expire_at_and_persist() {
+    let mut keyspace = Keyspace::new();
+    assert!(!keyspace.set_expire_at(b"missing", now_ms() + 1000));
+
+    keyspace.insert(b"k".to_vec(), Entry::String(b"v".to_vec()));
+    let at = now_ms() + 10_000;
+    assert!(keyspace.set_expire_at(b"k", at));
+    assert_eq!(keyspace.expire_at(b"k"), Some(at));
+    assert_eq!(keyspace.volatile_len(), 1);
+
+    // Overwriting the key clears the TTL, unless asked to keep it.
+    keyspace.insert_keep_ttl(b"k".to_vec(), Entry::String(b"w".to_vec()));
+    assert_eq!(keyspace.expire_at(b"k"), Some(at));
+    assert!(keyspace.persist(b"k"));
+    assert!(!keyspace.persist(b"k"));
+    assert_eq!(keyspace.expire_at(b"k"), None);
+    assert_eq!(keyspace.volatile_len(), 0);
+}
+
+#[test]
+fn test_past_deadline_deletes() {
+    let mut keyspace = Keyspace::new();
+    keyspace.insert(b"k".to_vec(), Entry::String(b"v".to_vec()));
+    assert!(keyspace.set_expire_at(b"k", 1));
+    assert!(!keyspace.contains_key(b"k"));
+    assert!(keyspace.is_empty());
+    assert_eq!(keyspace.volatile_len(), 0);
+}
+
+#[test]
+fn test_expired_keys_are_hidden_from_reads() {
+    let mut keyspace = Keyspace::new();
+    keyspace.insert(b"k".to_vec(), Entry::String(b"v".to_vec()));
+    keyspace.set_expire_at(b"k", now_ms() + 20);
+    std::thread::sleep(std::time::Duration::from_millis(40));
+
+    assert!(keyspace.get(b"k").is_none());
+    assert!(!keyspace.contains_key(b"k"));
+    assert_eq!(keyspace.iter().count(), 0);
+    // Reads never delete; the next write does.
+    assert_eq!(keyspace.volatile_len(), 1);
+    assert!(keyspace.expire_if_needed(b"k"));
+    assert_eq!(keyspace.volatile_len(), 0);
+}
+
+#[test]
+fn test_active_expire_cycle() {
+    let mut keyspace = Keyspace::new();
+    let now = now_ms();
+    for i in 0..100 {
+        let key = format!("volatile:{}", i).into_bytes();
+        keyspace.insert(key.clone(), Entry::String(b"v".to_vec()));
+        keyspace.set_expire_at(&key, now + 50);
+    }
+    for i in 0..10 {
+        let key = format!("lasting:{}", i).into_bytes();
+        keyspace.insert(key.clone(), Entry::String(b"v".to_vec()));
+        keyspace.set_expire_at(&key, now + 60_000);
+    }
+    keyspace.insert(b"forever".to_vec(), Entry::String(b"v".to_vec()));
+
+    // Nothing is due yet.
+    assert_eq!(keyspace.active_expire_cycle(now, 100), 0);
+
+    // Pretend the volatile keys are due; repeated cycles reclaim all of them
+    // and leave everything else alone.
+    let later = now + 100;
+    let mut removed = 0;
+    for _ in 0..100 {
+        removed += keyspace.active_expire_cycle(later, 100);
+    }
+    assert_eq!(removed, 100);
+    assert_eq!(keyspace.volatile_len(), 10);
+    assert_eq!(keyspace.len(), 11);
+}
+
+#[test]
+fn test_hash_fields_expire() {
+    let mut keyspace = Keyspace::new();
+    let now = now_ms();
+    for i in 0..50 {
+        let key = format!("hash:{}", i).into_bytes();
+        let hash = keyspace.hash_or_insert(&key).unwrap();
+        hash.insert(b"gone".to_vec(), b"v".to_vec());
+        hash.set_expire_at(b"gone", now + 30);
+        if i % 2 == 0 {
+            hash.insert(b"kept".to_vec(), b"v".to_vec());
+        }
+        keyspace.track_field_expiry(&key);
+    }
+
+    assert_eq!(keyspace.active_expire_fields(now, 100), 0);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    // Expired fields are invisible at once, and so is a hash left with none.
+    assert!(keyspace.get(b"hash:1").is_none());
+    assert_eq!(keyspace.get_hash(b"hash:0").unwrap().unwrap().len(), 1);
+    assert_eq!(keyspace.iter().count(), 25);
+    assert_eq!(keyspace.len(), 50);
+
+    let mut removed = 0;
+    for _ in 0..100 {
+        removed += keyspace.active_expire_fields(now_ms(), 100);
+    }
+    assert_eq!(removed, 50);
+    assert_eq!(keyspace.len(), 25);
+    let mut expected = Hash::new();
+    expected.insert(b"kept".to_vec(), b"v".to_vec());
+    assert_eq!(keyspace.get_hash(b"hash:0"), Ok(Some(&expected)));
+}
+
+// Memory accounting and eviction
+
+fn string(value: &str) -> Entry {
+    Entry::String(value.as_bytes().to_vec())
+}
+
+fn with_policy(keyspace: &mut Keyspace, policy: &str, maxmemory: usize) {
+    keyspace.set_eviction_config(EvictionConfig {
+        maxmemory,
+        policy: policy.parse().unwrap(),
+        samples: 64,
+        ..EvictionConfig::default()
+    });
+}
+
+#[test]
+fn test_used_memory_tracks_writes() {
+    let mut keyspace = Keyspace::new();
+    assert_eq!(keyspace.used_memory(), 0);
+
+    keyspace.insert(b"k".to_vec(), string("value"));
+    let one = keyspace.used_memory();
+    assert_eq!(one, 1 + KEY_OVERHEAD + 5);
+
+    keyspace.insert(b"k".to_vec(), string("a much longer value"));
+    assert_eq!(keyspace.used_memory(), one + 14);
+
+    // In-place changes are charged once the key is refreshed.
+    keyspace
+        .hash_or_insert(b"h")
+        .unwrap()
+        .insert(b"field".to_vec(), b"value".to_vec());
+    let before = keyspace.used_memory();
+    keyspace.refresh_size(b"h");
+    assert_eq!(keyspace.used_memory(), before + 10 + FIELD_OVERHEAD);
+
+    keyspace.remove(b"k");
+    keyspace.remove(b"h");
+    assert_eq!(keyspace.used_memory(), 0);
+}
+
+#[test]
+fn test_hash_size_is_estimated_from_samples() {
+    let mut hash = Hash::new();
+    for i in 0..1000 {
+        hash.insert(format!("f{:04}", i).into_bytes(), b"123456".to_vec());
+    }
+    assert_eq!(
+        Entry::Hash(hash).estimated_size(),
+        1000 * (5 + 6 + FIELD_OVERHEAD)
+    );
+    assert_eq!(Entry::Hash(Hash::new()).estimated_size(), 0);
+}
+
+#[test]
+fn test_noeviction_reports_over_limit() {
+    let mut keyspace = Keyspace::new();
+    for i in 0..10 {
+        keyspace.insert(format!("k{}", i).into_bytes(), string("v"));
+    }
+    assert!(!keyspace.over_maxmemory());
+    with_policy(&mut keyspace, "noeviction", 100);
+    assert!(keyspace.over_maxmemory());
+    assert!(keyspace.evict().is_empty());
+    assert_eq!(keyspace.len(), 10);
+}
+
+#[test]
+fn test_allkeys_random_evicts_down_to_limit() {
+    let mut keyspace = Keyspace::new();
+    for i in 0..100 {
+        keyspace.insert(format!("k{:02}", i).into_bytes(), string("v"));
+    }
+    let per_key = keyspace.used_memory() / 100;
+    with_policy(&mut keyspace, "allkeys-random", per_key * 40);
+
+    let evicted = keyspace.evict();
+    assert_eq!(evicted.len(), 60);
+    assert_eq!(keyspace.len(), 40);
+    assert!(!keyspace.over_maxmemory());
+    assert!(evicted.iter().all(|k| !keyspace.contains_key(k)));
+}
+
+#[test]
+fn test_allkeys_lru_keeps_recently_used() {
+    let mut keyspace = Keyspace::new();
+    for i in 0..10 {
+        keyspace.insert(format!("cold{}", i).into_bytes(), string("v"));
+    }
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    for i in 0..10 {
+        keyspace.insert(format!("warm{}", i).into_bytes(), string("v"));
+    }
+    let per_key = keyspace.used_memory() / 20;
+    with_policy(&mut keyspace, "allkeys-lru", per_key * 10);
+
+    keyspace.evict();
+    assert_eq!(keyspace.len(), 10);
+    assert!(keyspace.iter().all(|(k, _)| k.starts_with(b"warm")));
+}
+
+#[test]
+fn test_allkeys_lfu_keeps_frequently_used() {
+    let mut keyspace = Keyspace::new();
+    for i in 0..20 {
+        keyspace.insert(format!("k{:02}", i).into_bytes(), string("v"));
+    }
+    for i in 0..10 {
+        for _ in 0..200 {
+            keyspace.get(format!("k{:02}", i).as_bytes());
+        }
+    }
+    let per_key = keyspace.used_memory() / 20;
+    with_policy(&mut keyspace, "allkeys-lfu", per_key * 10);
+
+    keyspace.evict();
+    assert_eq!(keyspace.len(), 10);
+    for i in 0..10 {
+        assert!(keyspace.contains_key(format!("k{:02}", i).as_bytes()));
+    }
+}
+
+#[test]
+fn test_volatile_ttl_evicts_soonest_expiring_only() {
+    let mut keyspace = Keyspace::new();
+    let now = now_ms();
+    for i in 0..10u64 {
+        let key = format!("v{}", i).into_bytes();
+        keyspace.insert(key.clone(), string("v"));
+        keyspace.set_expire_at(&key, now + 10_000 * (i + 1));
+    }
+    for i in 0..10 {
+        keyspace.insert(format!("p{}", i).into_bytes(), string("v"));
+    }
+    let per_key = keyspace.used_memory() / 20;
+
+    with_policy(&mut keyspace, "volatile-ttl", per_key * 15);
+    let mut evicted = keyspace.evict();
+    evicted.sort();
+    let expected: Vec<Vec<u8>> = (0..5).map(|i| format!("v{}", i).into_bytes()).collect();
+    assert_eq!(evicted, expected);
+
+    // Once the volatile keys run out, nothing else can go.
+    with_policy(&mut keyspace, "volatile-lru", per_key * 5);
+    assert_eq!(keyspace.evict().len(), 5);
+    assert!(keyspace.over_maxmemory());
+    assert_eq!(keyspace.len(), 10);
+    assert_eq!(keyspace.volatile_len(), 0);
+}
+
+#[test]
+fn test_empty_collections_are_removed() {
+    let mut keyspace = Keyspace::new();
+    keyspace
+        .list_or_insert(b"l")
+        .unwrap()
+        .push_back(b"a".to_vec());
+    keyspace.insert(b"s".to_vec(), Entry::String(vec![]));
+    assert!(!keyspace.remove_if_empty(b"l"));
+    assert!(!keyspace.remove_if_empty(b"s"));
+
+    keyspace.get_list_mut(b"l").unwrap().unwrap().pop_front();
+    assert!(keyspace.remove_if_empty(b"l"));
+    assert!(!keyspace.contains_key(b"l"));
+    assert!(keyspace.get_list(b"s").is_err());
+}
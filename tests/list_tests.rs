@@ -0,0 +1,297 @@
+use crache::app::aof::Aof;
+use crache::app::handler::{self, Client};
+use crache::app::resp::{Frame, Protocol};
+use crache::app::server::handle_command;
+use std::sync::Arc;
+
+fn bulks(args: &[&str]) -> Vec<Frame> {
+    args.iter().map(|s| Frame::bulk(*s)).collect()
+}
+
+fn call(args: &[&str]) -> Frame {
+    handler::dispatch(&mut Client::new(), bulks(args))
+}
+
+fn list(args: &[&str]) -> Frame {
+    Frame::array(bulks(args))
+}
+
+const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+#[test]
+fn test_push_and_range() {
+    assert_eq!(call(&["RPUSH", "list:a", "b", "c"]), Frame::integer(2));
+    assert_eq!(call(&["LPUSH", "list:a", "a", "z"]), Frame::integer(4));
+    assert_eq!(
+        call(&["LRANGE", "list:a", "0", "-1"]),
+        list(&["z", "a", "b", "c"])
+    );
+    assert_eq!(call(&["LRANGE", "list:a", "1", "2"]), list(&["a", "b"]));
+    assert_eq!(call(&["LRANGE", "list:a", "-2", "100"]), list(&["b", "c"]));
+    assert_eq!(call(&["LRANGE", "list:a", "3", "1"]), list(&[]));
+    assert_eq!(call(&["LRANGE", "list:missing", "0", "-1"]), list(&[]));
+    assert_eq!(call(&["LLEN", "list:a"]), Frame::integer(4));
+    assert_eq!(call(&["LLEN", "list:missing"]), Frame::integer(0));
+    assert_eq!(call(&["TYPE", "list:a"]), Frame::simple("list"));
+}
+
+#[test]
+fn test_pushx_needs_existing_list() {
+    assert_eq!(call(&["LPUSHX", "list:x", "a"]), Frame::integer(0));
+    assert_eq!(call(&["TYPE", "list:x"]), Frame::simple("none"));
+    call(&["RPUSH", "list:x", "a"]);
+    assert_eq!(call(&["RPUSHX", "list:x", "b", "c"]), Frame::integer(3));
+    assert_eq!(call(&["LPUSHX", "list:x", "z"]), Frame::integer(4));
+}
+
+#[test]
+fn test_pop() {
+    call(&["RPUSH", "list:pop", "a", "b", "c", "d", "e"]);
+    assert_eq!(call(&["LPOP", "list:pop"]), Frame::bulk("a"));
+    assert_eq!(call(&["RPOP", "list:pop"]), Frame::bulk("e"));
+    assert_eq!(call(&["LPOP", "list:pop", "2"]), list(&["b", "c"]));
+    assert_eq!(call(&["RPOP", "list:pop", "0"]), list(&[]));
+    assert_eq!(call(&["RPOP", "list:pop", "10"]), list(&["d"]));
+
+    // Popping the last element deletes the key.
+    assert_eq!(call(&["TYPE", "list:pop"]), Frame::simple("none"));
+    assert_eq!(call(&["LPOP", "list:pop"]), Frame::Null);
+    assert_eq!(call(&["LPOP", "list:pop", "1"]), Frame::NullArray);
+    assert_eq!(
+        call(&["LPOP", "list:pop", "-1"]),
+        Frame::error("ERR value is out of range, must be positive")
+    );
+}
+
+#[test]
+fn test_lindex_lset() {
+    call(&["RPUSH", "list:idx", "a", "b", "c"]);
+    assert_eq!(call(&["LINDEX", "list:idx", "0"]), Frame::bulk("a"));
+    assert_eq!(call(&["LINDEX", "list:idx", "-1"]), Frame::bulk("c"));
+    assert_eq!(call(&["LINDEX", "list:idx", "3"]), Frame::Null);
+    assert_eq!(call(&["LINDEX", "list:idx", "-4"]), Frame::Null);
+
+    assert_eq!(call(&["LSET", "list:idx", "-2", "B"]), Frame::ok());
+    assert_eq!(
+        call(&["LRANGE", "list:idx", "0", "-1"]),
+        list(&["a", "B", "c"])
+    );
+    assert_eq!(
+        call(&["LSET", "list:idx", "5", "x"]),
+        Frame::error("ERR index out of range")
+    );
+    assert_eq!(
+        call(&["LSET", "list:nokey", "0", "x"]),
+        Frame::error("ERR no such key")
+    );
+}
+
+#[test]
+fn test_lrem() {
+    call(&["RPUSH", "list:rem", "a", "x", "b", "x", "c", "x"]);
+    assert_eq!(call(&["LREM", "list:rem", "1", "x"]), Frame::integer(1));
+    assert_eq!(
+        call(&["LRANGE", "list:rem", "0", "-1"]),
+        list(&["a", "b", "x", "c", "x"])
+    );
+    assert_eq!(call(&["LREM", "list:rem", "-1", "x"]), Frame::integer(1));
+    assert_eq!(
+        call(&["LRANGE", "list:rem", "0", "-1"]),
+        list(&["a", "b", "x", "c"])
+    );
+    call(&["RPUSH", "list:rem", "x"]);
+    assert_eq!(call(&["LREM", "list:rem", "0", "x"]), Frame::integer(2));
+    assert_eq!(call(&["LREM", "list:rem", "0", "nope"]), Frame::integer(0));
+
+    call(&["RPUSH", "list:rem_all", "x", "x"]);
+    assert_eq!(call(&["LREM", "list:rem_all", "0", "x"]), Frame::integer(2));
+    assert_eq!(call(&["TYPE", "list:rem_all"]), Frame::simple("none"));
+}
+
+#[test]
+fn test_ltrim() {
+    call(&["RPUSH", "list:trim", "a", "b", "c", "d", "e"]);
+    assert_eq!(call(&["LTRIM", "list:trim", "1", "-2"]), Frame::ok());
+    assert_eq!(
+        call(&["LRANGE", "list:trim", "0", "-1"]),
+        list(&["b", "c", "d"])
+    );
+    assert_eq!(call(&["LTRIM", "list:trim", "5", "10"]), Frame::ok());
+    assert_eq!(call(&["TYPE", "list:trim"]), Frame::simple("none"));
+}
+
+#[test]
+fn test_lpos() {
+    call(&["RPUSH", "list:pos", "a", "b", "c", "1", "2", "3", "c", "c"]);
+    assert_eq!(call(&["LPOS", "list:pos", "c"]), Frame::integer(2));
+    assert_eq!(
+        call(&["LPOS", "list:pos", "c", "RANK", "2"]),
+        Frame::integer(6)
+    );
+    assert_eq!(
+        call(&["LPOS", "list:pos", "c", "RANK", "-1"]),
+        Frame::integer(7)
+    );
+    assert_eq!(
+        call(&["LPOS", "list:pos", "c", "COUNT", "2"]),
+        Frame::array(vec![Frame::integer(2), Frame::integer(6)])
+    );
+    assert_eq!(
+        call(&["LPOS", "list:pos", "c", "COUNT", "0", "RANK", "-1"]),
+        Frame::array(vec![
+            Frame::integer(7),
+            Frame::integer(6),
+            Frame::integer(2)
+        ])
+    );
+    assert_eq!(
+        call(&["LPOS", "list:pos", "c", "COUNT", "0", "MAXLEN", "3"]),
+        Frame::array(vec![Frame::integer(2)])
+    );
+    assert_eq!(call(&["LPOS", "list:pos", "z"]), Frame::Null);
+    assert_eq!(call(&["LPOS", "list:pos", "z", "COUNT", "0"]), list(&[]));
+    assert_eq!(call(&["LPOS", "list:nokey", "a"]), Frame::Null);
+
+    assert!(call(&["LPOS", "list:pos", "c", "RANK", "0"]).is_error());
+    // An unknown option is a syntax error, not a bad number.
+    assert_eq!(
+        call(&["LPOS", "list:pos", "c", "BOGUS", "x"]),
+        Frame::error("ERR syntax error")
+    );
+    assert_eq!(
+        call(&["LPOS", "list:pos", "c", "RANK", "x"]),
+        Frame::error("ERR value is not an integer or out of range")
+    );
+    assert_eq!(
+        call(&["LPOS", "list:pos", "c", "COUNT", "-1"]),
+        Frame::error("ERR COUNT can't be negative")
+    );
+    assert_eq!(
+        call(&["LPOS", "list:pos", "c", "RANK"]),
+        Frame::error("ERR syntax error")
+    );
+}
+
+#[test]
+fn test_linsert() {
+    call(&["RPUSH", "list:ins", "a", "c"]);
+    assert_eq!(
+        call(&["LINSERT", "list:ins", "BEFORE", "c", "b"]),
+        Frame::integer(3)
+    );
+    assert_eq!(
+        call(&["LINSERT", "list:ins", "after", "c", "d"]),
+        Frame::integer(4)
+    );
+    assert_eq!(
+        call(&["LRANGE", "list:ins", "0", "-1"]),
+        list(&["a", "b", "c", "d"])
+    );
+    assert_eq!(
+        call(&["LINSERT", "list:ins", "BEFORE", "zz", "x"]),
+        Frame::integer(-1)
+    );
+    assert_eq!(
+        call(&["LINSERT", "list:nokey", "BEFORE", "a", "x"]),
+        Frame::integer(0)
+    );
+    assert_eq!(
+        call(&["LINSERT", "list:ins", "NEAR", "a", "x"]),
+        Frame::error("ERR syntax error")
+    );
+}
+
+#[test]
+fn test_lmove_and_rpoplpush() {
+    call(&["RPUSH", "list:src", "a", "b", "c"]);
+    assert_eq!(
+        call(&["LMOVE", "list:src", "list:dst", "LEFT", "RIGHT"]),
+        Frame::bulk("a")
+    );
+    assert_eq!(
+        call(&["RPOPLPUSH", "list:src", "list:dst"]),
+        Frame::bulk("c")
+    );
+    assert_eq!(call(&["LRANGE", "list:dst", "0", "-1"]), list(&["c", "a"]));
+
+    // Moving within one list rotates it.
+    assert_eq!(
+        call(&["LMOVE", "list:dst", "list:dst", "LEFT", "RIGHT"]),
+        Frame::bulk("c")
+    );
+    assert_eq!(call(&["LRANGE", "list:dst", "0", "-1"]), list(&["a", "c"]));
+
+    assert_eq!(
+        call(&["LMOVE", "list:src", "list:dst", "RIGHT", "LEFT"]),
+        Frame::bulk("b")
+    );
+    assert_eq!(call(&["TYPE", "list:src"]), Frame::simple("none"));
+    assert_eq!(
+        call(&["LMOVE", "list:src", "list:dst", "RIGHT", "LEFT"]),
+        Frame::Null
+    );
+    assert_eq!(
+        call(&["LMOVE", "list:dst", "list:src", "UP", "LEFT"]),
+        Frame::error("ERR syntax error")
+    );
+}
+
+#[test]
+fn test_list_wrongtype() {
+    call(&["SET", "list:string", "v"]);
+    call(&["RPUSH", "list:real", "a"]);
+    assert_eq!(
+        call(&["LPUSH", "list:string", "a"]),
+        Frame::error(WRONGTYPE)
+    );
+    assert_eq!(
+        call(&["LRANGE", "list:string", "0", "-1"]),
+        Frame::error(WRONGTYPE)
+    );
+    assert_eq!(call(&["LLEN", "list:string"]), Frame::error(WRONGTYPE));
+    assert_eq!(call(&["GET", "list:real"]), Frame::error(WRONGTYPE));
+    // A wrong destination type leaves the source untouched.
+    assert_eq!(
+        call(&["LMOVE", "list:real", "list:string", "LEFT", "LEFT"]),
+        Frame::error(WRONGTYPE)
+    );
+    assert_eq!(call(&["LLEN", "list:real"]), Frame::integer(1));
+}
+
+#[test]
+fn test_null_array_encoding() {
+    assert_eq!(Frame::NullArray.marshal_with(Protocol::Resp2), b"*-1\r\n");
+    assert_eq!(Frame::NullArray.marshal_with(Protocol::Resp3), b"_\r\n");
+}
+
+#[test]
+fn test_list_commands_are_logged_to_aof() {
+    let path = std::env::temp_dir().join(format!("crache_lists_{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let path = path.to_string_lossy().into_owned();
+    let aof = Arc::new(Aof::new(&path));
+
+    let mut client = Client::new();
+    client.aof = Some(Arc::clone(&aof));
+    for args in [
+        &["RPUSH", "list:aof", "a", "b", "c"][..],
+        &["LRANGE", "list:aof", "0", "-1"],
+        &["LPOP", "list:aof"],
+        &["LPOP", "list:aof_missing"],
+        &["LSET", "list:aof", "0", "B"],
+    ] {
+        handle_command(&mut client, Frame::array(bulks(args)));
+    }
+
+    let mut logged = Vec::new();
+    aof.read(|frame| logged.push(frame)).unwrap();
+    assert_eq!(
+        logged,
+        vec![
+            list(&["RPUSH", "list:aof", "a", "b", "c"]),
+            list(&["LPOP", "list:aof"]),
+            list(&["LSET", "list:aof", "0", "B"]),
+        ]
+    );
+    let _ = std::fs::remove_file(&path);
+}
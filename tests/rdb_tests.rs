@@ -0,0 +1,338 @@
+use crache::app::handler::{self, load_dataset, Client};
+use crache::app::hash::Hash;
+use crache::app::keyspace::{Entry, List, Set};
+use crache::app::rdb;
+use crache::app::resp::Frame;
+use crache::app::snapshot::Dataset;
+use crache::app::util::{crc64, now_ms};
+use crache::app::zset::SortedSet;
+use std::io::ErrorKind;
+
+fn encode(dataset: &Dataset) -> Vec<u8> {
+    let mut out = Vec::new();
+    rdb::write(&mut out, dataset).unwrap();
+    out
+}
+
+// An RDB file of the given version holding `body`, with its checksum.
+fn rdb_file(version: &str, body: &[u8]) -> Vec<u8> {
+    let mut data = [b"REDIS", version.as_bytes(), body, &[0xff]].concat();
+    let crc = crc64(0, &data);
+    data.extend(crc.to_le_bytes());
+    data
+}
+
+fn len(n: usize) -> Vec<u8> {
+    if n < 1 << 6 {
+        vec![n as u8]
+    } else {
+        vec![0x40 | (n >> 8) as u8, n as u8]
+    }
+}
+
+fn string(s: &[u8]) -> Vec<u8> {
+    [len(s.len()), s.to_vec()].concat()
+}
+
+// A listpack of already encoded entries, each under 128 bytes.
+fn listpack(entries: &[Vec<u8>]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for entry in entries {
+        body.extend(entry);
+        body.push(entry.len() as u8);
+    }
+    body.push(0xff);
+    let total = (body.len() + 6) as u32;
+    [
+        total.to_le_bytes().to_vec(),
+        (entries.len() as u16).to_le_bytes().to_vec(),
+        body,
+    ]
+    .concat()
+}
+
+fn lp_str(s: &[u8]) -> Vec<u8> {
+    [vec![0x80 | s.len() as u8], s.to_vec()].concat()
+}
+
+// A ziplist of already encoded entries, each under 254 bytes.
+fn ziplist(entries: &[Vec<u8>]) -> Vec<u8> {
+    let (mut body, mut prev, mut tail) = (Vec::new(), 0, 10);
+    for entry in entries {
+        tail = 10 + body.len();
+        body.push(prev as u8);
+        body.extend(entry);
+        prev = entry.len() + 1;
+    }
+    body.push(0xff);
+    [
+        ((body.len() + 10) as u32).to_le_bytes().to_vec(),
+        (tail as u32).to_le_bytes().to_vec(),
+        (entries.len() as u16).to_le_bytes().to_vec(),
+        body,
+    ]
+    .concat()
+}
+
+fn zl_str(s: &[u8]) -> Vec<u8> {
+    [vec![s.len() as u8], s.to_vec()].concat()
+}
+
+fn set_of(members: &[&[u8]]) -> Entry {
+    let mut set = Set::new();
+    for member in members {
+        set.insert(member.to_vec(), ());
+    }
+    Entry::Set(set)
+}
+
+fn hash_of(pairs: &[(&[u8], &[u8])]) -> Entry {
+    let mut hash = Hash::new();
+    for (field, value) in pairs {
+        hash.insert(field.to_vec(), value.to_vec());
+    }
+    Entry::Hash(hash)
+}
+
+fn list_of(items: &[&[u8]]) -> Entry {
+    Entry::List(items.iter().map(|i| i.to_vec()).collect())
+}
+
+#[test]
+fn test_rdb_roundtrip() {
+    let later = now_ms() + 100_000;
+    let list: List = [b"a".to_vec(), vec![b'x'; 20_000]].into_iter().collect();
+    let mut zset = SortedSet::new();
+    zset.insert(b"low".to_vec(), -1.5);
+    zset.insert(b"high".to_vec(), f64::INFINITY);
+    let dataset = vec![
+        (
+            b"s".to_vec(),
+            Entry::String(vec![0, 255, b'\n']),
+            Some(later),
+        ),
+        (b"long".to_vec(), Entry::String(vec![b'y'; 100]), None),
+        (b"l".to_vec(), Entry::List(list), None),
+        (b"set".to_vec(), set_of(&[b"m", b"n"]), None),
+        (b"z".to_vec(), Entry::ZSet(zset), None),
+        (b"h".to_vec(), hash_of(&[(b"f", b"v")]), None),
+    ];
+    assert_eq!(rdb::read(&encode(&dataset)).unwrap(), dataset);
+    assert_eq!(rdb::read(&encode(&Vec::new())).unwrap(), Vec::new());
+}
+
+#[test]
+fn test_rdb_writer_output_matches_redis() {
+    let dataset = vec![(b"k".to_vec(), Entry::String(b"v".to_vec()), Some(1 << 40))];
+    let body = [
+        &[0xfe, 0, 0xfb, 1, 1, 0xfc][..],
+        &(1u64 << 40).to_le_bytes(),
+        &[0, 1, b'k', 1, b'v'],
+    ]
+    .concat();
+    assert_eq!(encode(&dataset), rdb_file("0009", &body));
+}
+
+#[test]
+fn test_rdb_writer_drops_field_expiries() {
+    let mut hash = Hash::new();
+    hash.insert(b"f".to_vec(), b"v".to_vec());
+    hash.set_expire_at(b"f", now_ms() + 100_000);
+    let dataset = vec![(b"h".to_vec(), Entry::Hash(hash), None)];
+    let loaded = rdb::read(&encode(&dataset)).unwrap();
+    assert_eq!(
+        loaded,
+        vec![(b"h".to_vec(), hash_of(&[(b"f", b"v")]), None)]
+    );
+}
+
+#[test]
+fn test_rdb_reads_redis_encodings() {
+    let later = now_ms() + 100_000;
+    let intset = [
+        &[2, 0, 0, 0, 3, 0, 0, 0][..],
+        &(-1i16).to_le_bytes(),
+        &5i16.to_le_bytes(),
+        &300i16.to_le_bytes(),
+    ]
+    .concat();
+    let minus_100 = (-100i16 as u16) & 0x1fff;
+    let body = [
+        // Header fields, one of them an integer.
+        [vec![0xfa], string(b"redis-ver"), string(b"7.2.4")].concat(),
+        [vec![0xfa], string(b"redis-bits"), vec![0xc0, 64]].concat(),
+        vec![0xfe, 0, 0xfb, 11, 1],
+        // Integer and LZF-compressed strings.
+        [
+            vec![0],
+            string(b"int"),
+            vec![0xc1],
+            (-300i16).to_le_bytes().to_vec(),
+        ]
+        .concat(),
+        [
+            vec![0],
+            string(b"lzf"),
+            vec![0xc3, 7, 12, 2, b'a', b'b', b'c', 0xe0, 0, 2],
+        ]
+        .concat(),
+        [vec![11], string(b"ints"), string(&intset)].concat(),
+        [
+            vec![20],
+            string(b"members"),
+            string(&listpack(&[lp_str(b"x"), vec![5]])),
+        ]
+        .concat(),
+        [
+            vec![16],
+            string(b"hlp"),
+            string(&listpack(&[
+                lp_str(b"f1"),
+                lp_str(b"v1"),
+                lp_str(b"f2"),
+                vec![0xc0 | (minus_100 >> 8) as u8, minus_100 as u8],
+            ])),
+        ]
+        .concat(),
+        [
+            vec![17],
+            string(b"zlp"),
+            string(&listpack(&[
+                lp_str(b"a"),
+                vec![1],
+                lp_str(b"b"),
+                lp_str(b"1.5"),
+            ])),
+        ]
+        .concat(),
+        [
+            vec![18],
+            string(b"q2"),
+            vec![2, 2],
+            string(&listpack(&[
+                lp_str(b"a"),
+                [vec![0xf1], 1000i16.to_le_bytes().to_vec()].concat(),
+            ])),
+            vec![1],
+            string(b"big"),
+        ]
+        .concat(),
+        [
+            vec![13],
+            string(b"hzl"),
+            string(&ziplist(&[zl_str(b"f"), vec![0xfe, -5i8 as u8]])),
+        ]
+        .concat(),
+        [
+            vec![14],
+            string(b"q1"),
+            vec![1],
+            string(&ziplist(&[
+                zl_str(b"x"),
+                vec![0xf3],
+                [vec![0xc0], 1234i16.to_le_bytes().to_vec()].concat(),
+            ])),
+        ]
+        .concat(),
+        [
+            vec![12],
+            string(b"zzl"),
+            string(&ziplist(&[zl_str(b"m"), vec![0xf4]])),
+        ]
+        .concat(),
+        // Expiries, and the eviction hints Redis stores before a key.
+        [
+            vec![0xfd],
+            1000u32.to_le_bytes().to_vec(),
+            vec![0],
+            string(b"old"),
+            string(b"v"),
+        ]
+        .concat(),
+        [
+            vec![0xfc],
+            later.to_le_bytes().to_vec(),
+            vec![0xf9, 3, 0],
+            string(b"ttl"),
+            string(b"v"),
+        ]
+        .concat(),
+        [vec![0xf8, 5, 0], string(b"idle"), string(b"v")].concat(),
+        // Other databases are skipped.
+        [vec![0xfe, 1, 0], string(b"other"), string(b"v")].concat(),
+    ]
+    .concat();
+
+    let mut zlp = SortedSet::new();
+    zlp.insert(b"a".to_vec(), 1.0);
+    zlp.insert(b"b".to_vec(), 1.5);
+    let mut zzl = SortedSet::new();
+    zzl.insert(b"m".to_vec(), 3.0);
+    let expected = vec![
+        (b"int".to_vec(), Entry::String(b"-300".to_vec()), None),
+        (
+            b"lzf".to_vec(),
+            Entry::String(b"abcabcabcabc".to_vec()),
+            None,
+        ),
+        (b"ints".to_vec(), set_of(&[b"-1", b"5", b"300"]), None),
+        (b"members".to_vec(), set_of(&[b"x", b"5"]), None),
+        (
+            b"hlp".to_vec(),
+            hash_of(&[(b"f1", b"v1"), (b"f2", b"-100")]),
+            None,
+        ),
+        (b"zlp".to_vec(), Entry::ZSet(zlp), None),
+        (b"q2".to_vec(), list_of(&[b"a", b"1000", b"big"]), None),
+        (b"hzl".to_vec(), hash_of(&[(b"f", b"-5")]), None),
+        (b"q1".to_vec(), list_of(&[b"x", b"2", b"1234"]), None),
+        (b"zzl".to_vec(), Entry::ZSet(zzl), None),
+        (b"ttl".to_vec(), Entry::String(b"v".to_vec()), Some(later)),
+        (b"idle".to_vec(), Entry::String(b"v".to_vec()), None),
+    ];
+    assert_eq!(rdb::read(&rdb_file("0011", &body)).unwrap(), expected);
+}
+
+#[test]
+fn test_rdb_checksums_and_versions() {
+    let data = encode(&vec![(b"k".to_vec(), Entry::String(b"v".to_vec()), None)]);
+    for i in [0, 10, data.len() - 1] {
+        let mut damaged = data.clone();
+        damaged[i] ^= 0x20;
+        let err = rdb::read(&damaged).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData, "byte {}", i);
+    }
+
+    // A zero checksum means Redis did not compute one.
+    let mut unchecked = data.clone();
+    let n = unchecked.len();
+    unchecked[n - 8..].fill(0);
+    assert_eq!(rdb::read(&unchecked).unwrap().len(), 1);
+
+    // Before version 5 there is no checksum at all.
+    let old = [&b"REDIS0004"[..], &[0, 1, b'k', 1, b'v', 0xff]].concat();
+    assert_eq!(rdb::read(&old).unwrap().len(), 1);
+
+    let err = rdb::read(&rdb_file("0013", &[])).unwrap_err();
+    assert!(err.to_string().contains("version 13"), "{}", err);
+    // Streams have nowhere to go.
+    let stream = [vec![15], string(b"s"), vec![0]].concat();
+    let err = rdb::read(&rdb_file("0011", &stream)).unwrap_err();
+    assert!(err.to_string().contains("type 15"), "{}", err);
+}
+
+#[test]
+fn test_load_dataset_fills_the_keyspace() {
+    let dataset = rdb::read(&encode(&vec![(
+        b"rdb:k".to_vec(),
+        Entry::String(b"v".to_vec()),
+        None,
+    )]))
+    .unwrap();
+    assert_eq!(load_dataset(dataset), 1);
+    let argv = vec![Frame::bulk("GET"), Frame::bulk("rdb:k")];
+    assert_eq!(
+        handler::dispatch(&mut Client::new(), argv),
+        Frame::bulk("v")
+    );
+}
@@ -1,4 +1,6 @@
-use crache::app::resp::{Resp, Value, Writer};
+use crache::app::resp::{
+    split_args, Decoder, Frame, Protocol, Resp, Writer, MAX_BULK_LEN, MAX_DEPTH,
+};
 use std::io::Cursor;
 
 #[test]
@@ -11,10 +13,8 @@ fn test_read_array_empty() {
 
     let result = resp.read();
     assert!(result.is_ok());
-    let value = result.unwrap();
-    assert_eq!(value.typ, "array");
     // An empty RESP array should yield an empty inner array.
-    assert!(value.array.is_empty());
+    assert_eq!(result.unwrap(), Frame::Array(vec![]));
 }
 
 #[test]
@@ -27,24 +27,37 @@ fn test_read_bulk_empty() {
 
     let result = resp.read();
     assert!(result.is_ok());
-    let value = result.unwrap();
-    assert_eq!(value.typ, "bulk");
-    assert!(value.bulk.is_empty());
+    assert_eq!(result.unwrap(), Frame::bulk(""));
 }
 
 #[test]
-fn test_unknown_type() {
-    // Use an unsupported type byte, e.g. '?'
+fn test_unknown_type_reads_as_inline_command() {
+    // A line without a RESP type byte is an inline command.
     let input = b"?0\r\n".to_vec();
     let mut resp = Resp {
         reader: Ok(Cursor::new(input)),
     };
 
     let result = resp.read();
-    assert!(result.is_ok()); // Now we expect an Ok result
-    let value = result.unwrap();
-    assert_eq!(value.typ, "error"); // With an error type
-    assert!(value.str.contains("Unexpected type byte")); // And appropriate message
+    assert!(result.is_ok());
+    assert_eq!(result.unwrap(), Frame::array(vec![Frame::bulk("?0")]));
+}
+
+#[test]
+fn test_inline_element_inside_an_array_is_an_error() {
+    // Inline commands are whole commands, never an element of one.
+    let input = b"*3\r\n$3\r\nSET\r\nfoo\r\nbar\r\n".to_vec();
+    let mut resp = Resp {
+        reader: Ok(Cursor::new(input.clone())),
+    };
+    let err = resp.read().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "expected '$', got 'f'");
+
+    // The decoder does not wait for the rest of the line either.
+    let mut decoder = Decoder::new();
+    decoder.extend(&input[..input.len() - 2]);
+    assert!(decoder.decode().is_err());
 }
 
 #[test]
@@ -57,9 +70,7 @@ fn test_read_simple_string() {
 
     let result = resp.read();
     assert!(result.is_ok());
-    let value = result.unwrap();
-    assert_eq!(value.typ, "string");
-    assert_eq!(value.str, "OK");
+    assert_eq!(result.unwrap(), Frame::simple("OK"));
 }
 
 #[test]
@@ -72,9 +83,7 @@ fn test_read_integer() {
 
     let result = resp.read();
     assert!(result.is_ok());
-    let value = result.unwrap();
-    assert_eq!(value.typ, "integer");
-    assert_eq!(value.num, 1000);
+    assert_eq!(result.unwrap(), Frame::integer(1000));
 }
 
 #[test]
@@ -87,9 +96,7 @@ fn test_read_bulk_string() {
 
     let result = resp.read();
     assert!(result.is_ok());
-    let value = result.unwrap();
-    assert_eq!(value.typ, "bulk");
-    assert_eq!(value.bulk, "Ahmed");
+    assert_eq!(result.unwrap(), Frame::bulk("Ahmed"));
 }
 
 #[test]
@@ -102,9 +109,7 @@ fn test_read_bulk_string_with_spaces() {
 
     let result = resp.read();
     assert!(result.is_ok());
-    let value = result.unwrap();
-    assert_eq!(value.typ, "bulk");
-    assert_eq!(value.bulk, "Hello World");
+    assert_eq!(result.unwrap(), Frame::bulk("Hello World"));
 }
 
 #[test]
@@ -117,9 +122,7 @@ fn test_read_bulk_string_with_special_chars() {
 
     let result = resp.read();
     assert!(result.is_ok());
-    let value = result.unwrap();
-    assert_eq!(value.typ, "bulk");
-    assert_eq!(value.bulk, "Test@123!$%^&*()");
+    assert_eq!(result.unwrap(), Frame::bulk("Test@123!$%^&*()"));
 }
 
 #[test]
@@ -132,15 +135,14 @@ fn test_read_array_with_mixed_types() {
 
     let result = resp.read();
     assert!(result.is_ok());
-    let value = result.unwrap();
-    assert_eq!(value.typ, "array");
-    assert_eq!(value.array.len(), 3);
-    assert_eq!(value.array[0].typ, "string");
-    assert_eq!(value.array[0].str, "OK");
-    assert_eq!(value.array[1].typ, "integer");
-    assert_eq!(value.array[1].num, 1000);
-    assert_eq!(value.array[2].typ, "bulk");
-    assert_eq!(value.array[2].bulk, "Ahmed");
+    assert_eq!(
+        result.unwrap(),
+        Frame::array(vec![
+            Frame::simple("OK"),
+            Frame::integer(1000),
+            Frame::bulk("Ahmed"),
+        ])
+    );
 }
 
 #[test]
@@ -153,21 +155,20 @@ fn test_read_nested_array() {
 
     let result = resp.read();
     assert!(result.is_ok());
-    let value = result.unwrap();
-    assert_eq!(value.typ, "array");
-    assert_eq!(value.array.len(), 2);
+    let items = match result.unwrap() {
+        Frame::Array(items) => items,
+        other => panic!("expected array, got {:?}", other),
+    };
+    assert_eq!(items.len(), 2);
 
     // Check first element (nested array)
-    assert_eq!(value.array[0].typ, "array");
-    assert_eq!(value.array[0].array.len(), 2);
-    assert_eq!(value.array[0].array[0].typ, "string");
-    assert_eq!(value.array[0].array[0].str, "Hello");
-    assert_eq!(value.array[0].array[1].typ, "string");
-    assert_eq!(value.array[0].array[1].str, "World");
+    assert_eq!(
+        items[0],
+        Frame::array(vec![Frame::simple("Hello"), Frame::simple("World")])
+    );
 
     // Check second element (bulk string)
-    assert_eq!(value.array[1].typ, "bulk");
-    assert_eq!(value.array[1].bulk, "Ahmed");
+    assert_eq!(items[1], Frame::bulk("Ahmed"));
 }
 
 #[test]
@@ -180,18 +181,14 @@ fn test_read_command() {
 
     let result = resp.read();
     assert!(result.is_ok());
-    let value = result.unwrap();
-    assert_eq!(value.typ, "array");
-    assert_eq!(value.array.len(), 3);
-
-    assert_eq!(value.array[0].typ, "bulk");
-    assert_eq!(value.array[0].bulk, "SET");
-
-    assert_eq!(value.array[1].typ, "bulk");
-    assert_eq!(value.array[1].bulk, "key");
-
-    assert_eq!(value.array[2].typ, "bulk");
-    assert_eq!(value.array[2].bulk, "value");
+    assert_eq!(
+        result.unwrap(),
+        Frame::array(vec![
+            Frame::bulk("SET"),
+            Frame::bulk("key"),
+            Frame::bulk("value"),
+        ])
+    );
 }
 
 #[test]
@@ -226,119 +223,59 @@ fn test_print_array() {
 
 #[test]
 fn test_marshal_string() {
-    let value = Value {
-        typ: "string".to_string(),
-        str: "OK".to_string(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![],
-    };
-
-    let result = value.marshal();
+    let result = Frame::simple("OK").marshal();
     assert_eq!(result, b"+OK\r\n");
 }
 
 #[test]
-fn test_marshal_error() {
-    let value = Value {
-        typ: "error".to_string(),
-        str: "Error message".to_string(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![],
-    };
+fn test_marshal_ok_helper() {
+    assert_eq!(Frame::ok(), Frame::simple("OK"));
+    assert_eq!(Frame::ok().marshal(), b"+OK\r\n");
+}
 
-    let result = value.marshal();
+#[test]
+fn test_marshal_error() {
+    let result = Frame::error("Error message").marshal();
     assert_eq!(result, b"-Error message\r\n");
 }
 
 #[test]
 fn test_marshal_integer() {
-    let value = Value {
-        typ: "integer".to_string(),
-        str: String::new(),
-        num: 42,
-        bulk: String::new(),
-        array: vec![],
-    };
-
-    let result = value.marshal();
+    let result = Frame::integer(42).marshal();
     assert_eq!(result, b":42\r\n");
 }
 
 #[test]
-fn test_marshal_bulk() {
-    let value = Value {
-        typ: "bulk".to_string(),
-        str: String::new(),
-        num: 0,
-        bulk: "Hello World".to_string(),
-        array: vec![],
-    };
+fn test_marshal_negative_integer() {
+    let result = Frame::integer(-7).marshal();
+    assert_eq!(result, b":-7\r\n");
+}
 
-    let result = value.marshal();
+#[test]
+fn test_marshal_bulk() {
+    let result = Frame::bulk("Hello World").marshal();
     assert_eq!(result, b"$11\r\nHello World\r\n");
 }
 
 #[test]
 fn test_marshal_null() {
-    let value = Value {
-        typ: "null".to_string(),
-        str: String::new(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![],
-    };
-
-    let result = value.marshal();
+    let result = Frame::Null.marshal();
     assert_eq!(result, b"$-1\r\n");
 }
 
 #[test]
 fn test_marshal_empty_array() {
-    let value = Value {
-        typ: "array".to_string(),
-        str: String::new(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![],
-    };
-
-    let result = value.marshal();
+    let result = Frame::array(vec![]).marshal();
     assert_eq!(result, b"*0\r\n");
 }
 
 #[test]
 fn test_marshal_array_with_elements() {
-    let value = Value {
-        typ: "array".to_string(),
-        str: String::new(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![
-            Value {
-                typ: "string".to_string(),
-                str: "OK".to_string(),
-                num: 0,
-                bulk: String::new(),
-                array: vec![],
-            },
-            Value {
-                typ: "integer".to_string(),
-                str: String::new(),
-                num: 1000,
-                bulk: String::new(),
-                array: vec![],
-            },
-            Value {
-                typ: "bulk".to_string(),
-                str: String::new(),
-                num: 0,
-                bulk: "Ahmed".to_string(),
-                array: vec![],
-            },
-        ],
-    };
+    let value = Frame::array(vec![
+        Frame::simple("OK"),
+        Frame::integer(1000),
+        Frame::bulk("Ahmed"),
+    ]);
 
     let result = value.marshal();
     assert_eq!(result, b"*3\r\n+OK\r\n:1000\r\n$5\r\nAhmed\r\n");
@@ -346,43 +283,10 @@ fn test_marshal_array_with_elements() {
 
 #[test]
 fn test_marshal_nested_array() {
-    let value = Value {
-        typ: "array".to_string(),
-        str: String::new(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![
-            Value {
-                typ: "array".to_string(),
-                str: String::new(),
-                num: 0,
-                bulk: String::new(),
-                array: vec![
-                    Value {
-                        typ: "string".to_string(),
-                        str: "Hello".to_string(),
-                        num: 0,
-                        bulk: String::new(),
-                        array: vec![],
-                    },
-                    Value {
-                        typ: "string".to_string(),
-                        str: "World".to_string(),
-                        num: 0,
-                        bulk: String::new(),
-                        array: vec![],
-                    },
-                ],
-            },
-            Value {
-                typ: "bulk".to_string(),
-                str: String::new(),
-                num: 0,
-                bulk: "Ahmed".to_string(),
-                array: vec![],
-            },
-        ],
-    };
+    let value = Frame::array(vec![
+        Frame::array(vec![Frame::simple("Hello"), Frame::simple("World")]),
+        Frame::bulk("Ahmed"),
+    ]);
 
     let result = value.marshal();
     assert_eq!(result, b"*2\r\n*2\r\n+Hello\r\n+World\r\n$5\r\nAhmed\r\n");
@@ -391,101 +295,54 @@ fn test_marshal_nested_array() {
 #[test]
 fn test_marshal_redis_command() {
     // Create a RESP array representation of "SET key value"
-    let value = Value {
-        typ: "array".to_string(),
-        str: String::new(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![
-            Value {
-                typ: "bulk".to_string(),
-                str: String::new(),
-                num: 0,
-                bulk: "SET".to_string(),
-                array: vec![],
-            },
-            Value {
-                typ: "bulk".to_string(),
-                str: String::new(),
-                num: 0,
-                bulk: "key".to_string(),
-                array: vec![],
-            },
-            Value {
-                typ: "bulk".to_string(),
-                str: String::new(),
-                num: 0,
-                bulk: "value".to_string(),
-                array: vec![],
-            },
-        ],
-    };
+    let value = Frame::array(vec![
+        Frame::bulk("SET"),
+        Frame::bulk("key"),
+        Frame::bulk("value"),
+    ]);
 
     let result = value.marshal();
     assert_eq!(result, b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n");
 }
 
 #[test]
-fn test_writer_writes_value() {
-    // Create a test value
-    let value = Value {
-        typ: "string".to_string(),
-        str: "OK".to_string(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![],
+fn test_marshal_read_roundtrip() {
+    let value = Frame::array(vec![
+        Frame::simple("OK"),
+        Frame::integer(-12),
+        Frame::bulk("payload"),
+        Frame::array(vec![Frame::bulk("nested")]),
+    ]);
+
+    let mut resp = Resp {
+        reader: Ok(Cursor::new(value.marshal())),
     };
+    assert_eq!(resp.read().unwrap(), value);
+}
 
+#[test]
+fn test_writer_writes_value() {
     // Create a writer with a Vec<u8> as the underlying buffer
     let buffer = Vec::new();
     let mut writer = Writer::new(buffer);
 
     // Write value to buffer
-    let result = writer.write(&value);
+    let result = writer.write(&Frame::ok());
     assert!(result.is_ok());
+    assert_eq!(writer.writer, b"+OK\r\n");
 }
 
 #[test]
 fn test_writer_writes_complex_value() {
     // Create a complex RESP value (array with different value types)
-    let value = Value {
-        typ: "array".to_string(),
-        str: String::new(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![
-            Value {
-                typ: "string".to_string(),
-                str: "OK".to_string(),
-                num: 0,
-                bulk: String::new(),
-                array: vec![],
-            },
-            Value {
-                typ: "integer".to_string(),
-                str: String::new(),
-                num: 42,
-                bulk: String::new(),
-                array: vec![],
-            },
-            Value {
-                typ: "bulk".to_string(),
-                str: String::new(),
-                num: 0,
-                bulk: "Hello".to_string(),
-                array: vec![],
-            },
-            Value {
-                typ: "null".to_string(),
-                str: String::new(),
-                num: 0,
-                bulk: String::new(),
-                array: vec![],
-            },
-        ],
-    };
-
-    // Create a writer with a cursor as the underlying writer
+    let value = Frame::array(vec![
+        Frame::simple("OK"),
+        Frame::integer(42),
+        Frame::bulk("Hello"),
+        Frame::Null,
+    ]);
+
+    // Create a writer with a Vec<u8> as the underlying writer
     let buffer = Vec::new();
     let mut writer = Writer::new(buffer);
 
@@ -493,21 +350,485 @@ fn test_writer_writes_complex_value() {
     let result = writer.write(&value);
     assert!(result.is_ok());
 
-    // Get the underlying buffer and check the content
-    let buffer = writer.write(&value);
-    assert!(buffer.is_ok());
+    // Check the content of the underlying buffer
+    assert_eq!(
+        writer.writer,
+        b"*4\r\n+OK\r\n:42\r\n$5\r\nHello\r\n$-1\r\n".to_vec()
+    );
 }
 
 #[test]
-fn test_unknown_type_marshal() {
-    let value = Value {
-        typ: "unknown".to_string(), // An unsupported type
-        str: String::new(),
-        num: 0,
-        bulk: String::new(),
-        array: vec![],
+fn test_read_bulk_binary_payload() {
+    // Invalid UTF-8, an embedded CRLF and a NUL byte must all survive parsing.
+    let input = b"$6\r\n\xff\x00\r\n\xfe\x01\r\n".to_vec();
+    let mut resp = Resp {
+        reader: Ok(Cursor::new(input)),
     };
 
-    let result = value.marshal();
-    assert!(result.is_empty()); // Should return an empty vector for unknown types
+    let result = resp.read();
+    assert!(result.is_ok());
+    assert_eq!(
+        result.unwrap(),
+        Frame::bulk(vec![0xff, 0x00, b'\r', b'\n', 0xfe, 0x01])
+    );
+}
+
+#[test]
+fn test_marshal_bulk_binary_payload() {
+    let result = Frame::bulk(vec![0x00, 0xff, b'\n']).marshal();
+    assert_eq!(result, b"$3\r\n\x00\xff\n\r\n");
+}
+
+// Decoder Tests
+
+#[test]
+fn test_decoder_complete_frame() {
+    let mut decoder = Decoder::new();
+    decoder.extend(b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n");
+
+    let frame = decoder.decode().unwrap();
+    assert_eq!(
+        frame,
+        Some(Frame::array(vec![Frame::bulk("GET"), Frame::bulk("key")]))
+    );
+    assert_eq!(decoder.buffered(), 0);
+    assert_eq!(decoder.decode().unwrap(), None);
+}
+
+#[test]
+fn test_decoder_byte_at_a_time() {
+    let input = b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n";
+    let mut decoder = Decoder::new();
+
+    // Every strict prefix is incomplete and must not be treated as an error.
+    for byte in &input[..input.len() - 1] {
+        decoder.extend(std::slice::from_ref(byte));
+        assert_eq!(decoder.decode().unwrap(), None);
+    }
+    decoder.extend(&input[input.len() - 1..]);
+
+    assert_eq!(
+        decoder.decode().unwrap(),
+        Some(Frame::array(vec![
+            Frame::bulk("SET"),
+            Frame::bulk("key"),
+            Frame::bulk("value"),
+        ]))
+    );
+}
+
+#[test]
+fn test_decoder_split_inside_bulk_payload() {
+    let mut decoder = Decoder::new();
+    decoder.extend(b"$11\r\nHello");
+    assert_eq!(decoder.decode().unwrap(), None);
+    assert_eq!(decoder.buffered(), 10);
+
+    decoder.extend(b" World\r\n");
+    assert_eq!(decoder.decode().unwrap(), Some(Frame::bulk("Hello World")));
+}
+
+#[test]
+fn test_decoder_oversized_frame_across_reads() {
+    // Far larger than any single socket read.
+    let payload = vec![b'x'; 64 * 1024];
+    let command = Frame::array(vec![
+        Frame::bulk("SET"),
+        Frame::bulk("big"),
+        Frame::bulk(payload),
+    ]);
+    let bytes = command.marshal();
+
+    let mut decoder = Decoder::new();
+    let mut decoded = Vec::new();
+    for chunk in bytes.chunks(1024) {
+        decoder.extend(chunk);
+        while let Some(frame) = decoder.decode().unwrap() {
+            decoded.push(frame);
+        }
+    }
+    assert_eq!(decoded, vec![command]);
+    assert_eq!(decoder.buffered(), 0);
+}
+
+#[test]
+fn test_decoder_large_multibulk_in_small_reads_is_linear() {
+    // About 2 MB of small arguments arriving 4 KB at a time: bytes already
+    // buffered must not be decoded again on every read.
+    let mut items = vec![Frame::bulk("SADD"), Frame::bulk("s")];
+    items.extend((0..150_000).map(|i| Frame::bulk(format!("member:{}", i))));
+    let command = Frame::array(items);
+    let bytes = command.marshal();
+
+    let started = std::time::Instant::now();
+    let mut decoder = Decoder::new();
+    let mut decoded = Vec::new();
+    for chunk in bytes.chunks(4096) {
+        decoder.extend(chunk);
+        while let Some(frame) = decoder.decode().unwrap() {
+            decoded.push(frame);
+        }
+    }
+    assert_eq!(decoded, vec![command]);
+    assert!(
+        started.elapsed() < std::time::Duration::from_secs(5),
+        "took {:?}",
+        started.elapsed()
+    );
+}
+
+#[test]
+fn test_decoder_multiple_frames_and_trailing_partial() {
+    let mut decoder = Decoder::new();
+    decoder.extend(b"+OK\r\n:42\r\n$5\r\nAh");
+
+    assert_eq!(decoder.decode().unwrap(), Some(Frame::simple("OK")));
+    assert_eq!(decoder.decode().unwrap(), Some(Frame::integer(42)));
+    assert_eq!(decoder.decode().unwrap(), None);
+
+    decoder.extend(b"med\r\n");
+    assert_eq!(decoder.decode().unwrap(), Some(Frame::bulk("Ahmed")));
+    assert_eq!(decoder.decode().unwrap(), None);
+}
+
+#[test]
+fn test_decoder_reports_malformed_input() {
+    let mut decoder = Decoder::new();
+    decoder.extend(b"$3\r\nabcXY");
+
+    assert!(decoder.decode().is_err());
+}
+
+// RESP3 Tests
+
+fn read_one(input: &[u8]) -> Frame {
+    let mut resp = Resp {
+        reader: Ok(Cursor::new(input.to_vec())),
+    };
+    resp.read().unwrap()
+}
+
+#[test]
+fn test_read_resp3_scalars() {
+    assert_eq!(read_one(b"_\r\n"), Frame::Null);
+    assert_eq!(read_one(b"#t\r\n"), Frame::Boolean(true));
+    assert_eq!(read_one(b"#f\r\n"), Frame::Boolean(false));
+    assert_eq!(read_one(b",3.25\r\n"), Frame::double(3.25));
+    assert_eq!(read_one(b",-inf\r\n"), Frame::double(f64::NEG_INFINITY));
+    assert_eq!(
+        read_one(b"(3492890328409238509324850943850943825024385\r\n"),
+        Frame::BigNumber("3492890328409238509324850943850943825024385".to_string())
+    );
+    assert_eq!(
+        read_one(b"!21\r\nSYNTAX invalid syntax\r\n"),
+        Frame::BlobError(b"SYNTAX invalid syntax".to_vec())
+    );
+    assert_eq!(
+        read_one(b"=15\r\ntxt:Some string\r\n"),
+        Frame::Verbatim {
+            format: "txt".to_string(),
+            text: b"Some string".to_vec(),
+        }
+    );
+}
+
+#[test]
+fn test_read_resp3_aggregates() {
+    assert_eq!(
+        read_one(b"%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n"),
+        Frame::map(vec![
+            (Frame::simple("first"), Frame::integer(1)),
+            (Frame::simple("second"), Frame::integer(2)),
+        ])
+    );
+    assert_eq!(
+        read_one(b"~2\r\n+orange\r\n+apple\r\n"),
+        Frame::Set(vec![Frame::simple("orange"), Frame::simple("apple")])
+    );
+    assert_eq!(
+        read_one(b">2\r\n+invalidate\r\n*1\r\n$3\r\nkey\r\n"),
+        Frame::Push(vec![
+            Frame::simple("invalidate"),
+            Frame::array(vec![Frame::bulk("key")]),
+        ])
+    );
+}
+
+#[test]
+fn test_read_resp3_attribute_wraps_following_reply() {
+    let input = b"|1\r\n+key-popularity\r\n%1\r\n$1\r\na\r\n,0.1923\r\n*1\r\n:2039123\r\n";
+    assert_eq!(
+        read_one(input),
+        Frame::Attribute {
+            attrs: vec![(
+                Frame::simple("key-popularity"),
+                Frame::map(vec![(Frame::bulk("a"), Frame::double(0.1923))]),
+            )],
+            data: Box::new(Frame::array(vec![Frame::integer(2039123)])),
+        }
+    );
+}
+
+#[test]
+fn test_marshal_resp3_roundtrip() {
+    let value = Frame::array(vec![
+        Frame::Null,
+        Frame::Boolean(true),
+        Frame::double(1.5),
+        Frame::BigNumber("-12345678901234567890".to_string()),
+        Frame::map(vec![(
+            Frame::bulk("k"),
+            Frame::Set(vec![Frame::integer(1)]),
+        )]),
+        Frame::Push(vec![Frame::bulk("message")]),
+        Frame::Verbatim {
+            format: "mkd".to_string(),
+            text: b"# title".to_vec(),
+        },
+        Frame::BlobError(b"ERR multi\nline".to_vec()),
+    ]);
+
+    let mut resp = Resp {
+        reader: Ok(Cursor::new(value.marshal_with(Protocol::Resp3))),
+    };
+    assert_eq!(resp.read().unwrap(), value);
+}
+
+#[test]
+fn test_marshal_resp3_types_downgrade_to_resp2() {
+    assert_eq!(Frame::Null.marshal(), b"$-1\r\n");
+    assert_eq!(Frame::Null.marshal_with(Protocol::Resp3), b"_\r\n");
+    assert_eq!(Frame::Boolean(true).marshal(), b":1\r\n");
+    assert_eq!(Frame::double(2.5).marshal(), b"$3\r\n2.5\r\n");
+    assert_eq!(Frame::double(f64::INFINITY).marshal(), b"$3\r\ninf\r\n");
+    assert_eq!(
+        Frame::map(vec![(Frame::bulk("f"), Frame::bulk("v"))]).marshal(),
+        b"*2\r\n$1\r\nf\r\n$1\r\nv\r\n"
+    );
+    assert_eq!(
+        Frame::map(vec![(Frame::bulk("f"), Frame::bulk("v"))]).marshal_with(Protocol::Resp3),
+        b"%1\r\n$1\r\nf\r\n$1\r\nv\r\n"
+    );
+    assert_eq!(
+        Frame::BlobError(b"ERR a\r\nb".to_vec()).marshal(),
+        b"-ERR a  b\r\n"
+    );
+}
+
+#[test]
+fn test_writer_uses_connection_protocol() {
+    let mut writer = Writer::with_protocol(Vec::new(), Protocol::Resp3);
+    writer.write(&Frame::Boolean(false)).unwrap();
+    writer.write(&Frame::Null).unwrap();
+    assert_eq!(writer.writer, b"#f\r\n_\r\n");
+}
+
+// Inline Command Tests
+
+fn args(items: &[&str]) -> Vec<Vec<u8>> {
+    items.iter().map(|s| s.as_bytes().to_vec()).collect()
+}
+
+#[test]
+fn test_read_inline_command() {
+    assert_eq!(
+        read_one(b"SET key value\r\n"),
+        Frame::array(vec![
+            Frame::bulk("SET"),
+            Frame::bulk("key"),
+            Frame::bulk("value"),
+        ])
+    );
+    // A bare newline is accepted as the terminator too.
+    assert_eq!(read_one(b"PING\n"), Frame::array(vec![Frame::bulk("PING")]));
+    assert_eq!(read_one(b"  \r\n"), Frame::array(vec![]));
+}
+
+#[test]
+fn test_split_args_whitespace() {
+    assert_eq!(split_args(b"  GET\t  key   "), Some(args(&["GET", "key"])));
+    assert_eq!(split_args(b""), Some(vec![]));
+}
+
+#[test]
+fn test_split_args_quoting() {
+    assert_eq!(
+        split_args(br#"SET "hello world" 'it''s'"#),
+        None,
+        "closing quote must be followed by a space"
+    );
+    assert_eq!(
+        split_args(br#"SET "hello world" 'it\'s' """#),
+        Some(args(&["SET", "hello world", "it's", ""]))
+    );
+    assert_eq!(
+        split_args(br#"SET k "a\tb\n\x41\"\\""#),
+        Some(vec![
+            b"SET".to_vec(),
+            b"k".to_vec(),
+            b"a\tb\nA\"\\".to_vec()
+        ])
+    );
+    assert_eq!(
+        split_args(br#"SET k "\xff\x00""#),
+        Some(vec![b"SET".to_vec(), b"k".to_vec(), vec![0xff, 0x00]])
+    );
+    // Single quotes keep backslashes literally.
+    assert_eq!(split_args(br"'a\nb'"), Some(vec![br"a\nb".to_vec()]));
+}
+
+#[test]
+fn test_split_args_unbalanced_quotes() {
+    assert_eq!(split_args(br#"SET k "unterminated"#), None);
+    assert_eq!(split_args(br"SET k 'unterminated"), None);
+    assert_eq!(split_args(br#"SET k "a"b"#), None);
+}
+
+#[test]
+fn test_decoder_inline_waits_for_newline() {
+    let mut decoder = Decoder::new();
+    decoder.extend(b"SET greeting \"hi the");
+    assert_eq!(decoder.decode().unwrap(), None);
+
+    decoder.extend(b"re\"\r\nPING\n");
+    assert_eq!(
+        decoder.decode().unwrap(),
+        Some(Frame::array(vec![
+            Frame::bulk("SET"),
+            Frame::bulk("greeting"),
+            Frame::bulk("hi there"),
+        ]))
+    );
+    assert_eq!(
+        decoder.decode().unwrap(),
+        Some(Frame::array(vec![Frame::bulk("PING")]))
+    );
+}
+
+#[test]
+fn test_decoder_inline_unbalanced_quotes_is_an_error() {
+    let mut decoder = Decoder::new();
+    decoder.extend(b"SET k \"oops\r\n");
+    assert!(decoder.decode().is_err());
+}
+
+// RESP2 Completeness Tests
+
+fn read_err(input: &[u8]) -> std::io::Error {
+    let mut resp = Resp {
+        reader: Ok(Cursor::new(input.to_vec())),
+    };
+    resp.read().unwrap_err()
+}
+
+#[test]
+fn test_read_error_frame() {
+    assert_eq!(
+        read_one(b"-ERR unknown command 'FOO'\r\n"),
+        Frame::error("ERR unknown command 'FOO'")
+    );
+    assert_eq!(
+        read_one(b"-WRONGTYPE Operation against a key\r\n"),
+        Frame::error("WRONGTYPE Operation against a key")
+    );
+}
+
+#[test]
+fn test_read_null_bulk_and_null_array() {
+    assert_eq!(read_one(b"$-1\r\n"), Frame::Null);
+    assert_eq!(read_one(b"*-1\r\n"), Frame::NullArray);
+    assert_eq!(
+        read_one(b"*3\r\n$-1\r\n*-1\r\n$0\r\n\r\n"),
+        Frame::array(vec![Frame::Null, Frame::NullArray, Frame::bulk("")])
+    );
+}
+
+#[test]
+fn test_marshal_null_array() {
+    assert_eq!(Frame::NullArray.marshal(), b"*-1\r\n");
+    assert_eq!(Frame::NullArray.marshal_with(Protocol::Resp3), b"_\r\n");
+}
+
+#[test]
+fn test_read_64_bit_integers() {
+    assert_eq!(
+        read_one(b":9223372036854775807\r\n"),
+        Frame::integer(i64::MAX)
+    );
+    assert_eq!(
+        read_one(b":-9223372036854775808\r\n"),
+        Frame::integer(i64::MIN)
+    );
+    assert_eq!(read_one(b":4294967296\r\n"), Frame::integer(1 << 32));
+    assert_eq!(
+        read_err(b":9223372036854775808\r\n").kind(),
+        std::io::ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_read_rejects_invalid_lengths() {
+    assert!(read_err(b"$-2\r\n")
+        .to_string()
+        .contains("invalid bulk length"));
+    assert!(read_err(b"*-5\r\n")
+        .to_string()
+        .contains("invalid multibulk length"));
+    // One byte over the limit is refused before any payload is read.
+    let oversized = format!("${}\r\n", MAX_BULK_LEN + 1);
+    assert!(read_err(oversized.as_bytes())
+        .to_string()
+        .contains("invalid bulk length"));
+}
+
+#[test]
+fn test_huge_declared_array_does_not_preallocate() {
+    // Claims two billion elements but only carries one; must fail cleanly
+    // as incomplete rather than reserving memory for the declared count.
+    let mut decoder = Decoder::new();
+    decoder.extend(b"*2000000000\r\n$1\r\na\r\n");
+    assert_eq!(decoder.decode().unwrap(), None);
+}
+
+#[test]
+fn test_huge_declared_bulk_waits_for_payload() {
+    let mut decoder = Decoder::new();
+    decoder.extend(format!("${}\r\nabc", MAX_BULK_LEN).as_bytes());
+    assert_eq!(decoder.decode().unwrap(), None);
+}
+
+#[test]
+fn test_read_rejects_deep_nesting() {
+    let mut input = Vec::new();
+    for _ in 0..=MAX_DEPTH + 1 {
+        input.extend_from_slice(b"*1\r\n");
+    }
+    input.extend_from_slice(b":1\r\n");
+    assert_eq!(read_err(&input).kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_deep_attribute_chain_is_a_protocol_error() {
+    // Each attribute wraps the value after it, so a long run of empty ones
+    // is nesting like any other and must not overflow the stack.
+    let chain = b"|0\r\n".repeat(200_000);
+    let input = [&chain[..], b"+x\r\n"].concat();
+    assert_eq!(read_err(&input).kind(), std::io::ErrorKind::InvalidData);
+
+    // Through the decoder it fails as soon as the limit is passed, without
+    // waiting for the value the chain never gets to.
+    let mut decoder = Decoder::new();
+    decoder.extend(&chain);
+    let err = decoder.decode().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+    // A short chain is still fine.
+    let short = [&b"|0\r\n".repeat(3)[..], b"+x\r\n"].concat();
+    assert!(matches!(read_one(&short), Frame::Attribute { .. }));
+}
+
+#[test]
+fn test_read_line_requires_crlf() {
+    // A lone CR inside a simple string is part of the payload.
+    assert_eq!(read_one(b"+a\rb\r\n"), Frame::simple("a\rb"));
 }
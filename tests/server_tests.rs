@@ -0,0 +1,407 @@
+use crache::app::aof::{Aof, FsyncPolicy};
+use crache::app::resp::{Decoder, Frame, Writer};
+use crache::app::server;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// Spawns a server on an ephemeral port without an AOF.
+fn start_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || server::serve(listener, None));
+    addr
+}
+
+fn command(args: &[&[u8]]) -> Frame {
+    Frame::array(args.iter().map(|a| Frame::bulk(a.to_vec())).collect())
+}
+
+// Reads until `count` complete replies have arrived.
+fn read_replies(stream: &mut TcpStream, count: usize) -> Vec<Frame> {
+    let mut decoder = Decoder::new();
+    let mut replies = Vec::with_capacity(count);
+    let mut buffer = [0u8; 8192];
+    while replies.len() < count {
+        while let Some(frame) = decoder.decode().unwrap() {
+            replies.push(frame);
+        }
+        if replies.len() >= count {
+            break;
+        }
+        let n = stream.read(&mut buffer).unwrap();
+        assert!(n > 0, "server closed the connection early");
+        decoder.extend(&buffer[..n]);
+    }
+    replies
+}
+
+#[test]
+fn test_single_command_roundtrip() {
+    let mut stream = TcpStream::connect(start_server()).unwrap();
+    stream.write_all(&command(&[b"PING"]).marshal()).unwrap();
+
+    assert_eq!(read_replies(&mut stream, 1), vec![Frame::simple("PONG")]);
+}
+
+#[test]
+fn test_pipelined_set_get_in_one_write() {
+    let mut stream = TcpStream::connect(start_server()).unwrap();
+    let n = 5000;
+
+    let mut batch = Vec::new();
+    for i in 0..n {
+        let key = format!("pipeline:{}", i);
+        let value = format!("value:{}", i);
+        batch.extend(command(&[b"SET", key.as_bytes(), value.as_bytes()]).marshal());
+    }
+    for i in 0..n {
+        let key = format!("pipeline:{}", i);
+        batch.extend(command(&[b"GET", key.as_bytes()]).marshal());
+    }
+    stream.write_all(&batch).unwrap();
+
+    let replies = read_replies(&mut stream, 2 * n);
+    for reply in &replies[..n] {
+        assert_eq!(*reply, Frame::ok());
+    }
+    for (i, reply) in replies[n..].iter().enumerate() {
+        assert_eq!(*reply, Frame::bulk(format!("value:{}", i)));
+    }
+}
+
+#[test]
+fn test_pipelined_commands_split_across_writes() {
+    let mut stream = TcpStream::connect(start_server()).unwrap();
+    stream.set_nodelay(true).unwrap();
+
+    let mut batch = Vec::new();
+    for i in 0..200 {
+        let key = format!("split:{}", i);
+        batch.extend(command(&[b"SET", key.as_bytes(), b"x"]).marshal());
+        batch.extend(command(&[b"GET", key.as_bytes()]).marshal());
+    }
+    // Odd-sized chunks so frames straddle write boundaries.
+    for chunk in batch.chunks(37) {
+        stream.write_all(chunk).unwrap();
+    }
+
+    let replies = read_replies(&mut stream, 400);
+    for pair in replies.chunks(2) {
+        assert_eq!(pair[0], Frame::ok());
+        assert_eq!(pair[1], Frame::bulk("x"));
+    }
+}
+
+#[test]
+fn test_pipelined_unknown_command_keeps_order() {
+    let mut stream = TcpStream::connect(start_server()).unwrap();
+
+    let mut batch = command(&[b"PING"]).marshal();
+    batch.extend(command(&[b"NOPE"]).marshal());
+    batch.extend(command(&[b"PING", b"after"]).marshal());
+    stream.write_all(&batch).unwrap();
+
+    let expected = b"+PONG\r\n-ERR unknown command 'NOPE'\r\n$5\r\nafter\r\n";
+    let mut received = Vec::new();
+    let mut buffer = [0u8; 256];
+    while received.len() < expected.len() {
+        let n = stream.read(&mut buffer).unwrap();
+        assert!(n > 0, "server closed the connection early");
+        received.extend_from_slice(&buffer[..n]);
+    }
+    assert_eq!(received, expected.to_vec());
+}
+
+#[test]
+fn test_writer_batch_concatenates_frames() {
+    let mut writer = Writer::new(Vec::new());
+    let frames = vec![
+        Frame::ok(),
+        Frame::integer(7),
+        Frame::bulk("hi"),
+        Frame::Null,
+    ];
+
+    let written = writer.write_batch(&frames).unwrap();
+    let expected = b"+OK\r\n:7\r\n$2\r\nhi\r\n$-1\r\n";
+    assert_eq!(written, expected.len());
+    assert_eq!(writer.writer, expected.to_vec());
+}
+
+#[test]
+fn test_hello_switches_to_resp3() {
+    let mut stream = TcpStream::connect(start_server()).unwrap();
+
+    let mut batch = command(&[b"HSET", b"hello:resp3", b"field", b"value"]).marshal();
+    batch.extend(command(&[b"HGETALL", b"hello:resp3"]).marshal());
+    batch.extend(command(&[b"HELLO", b"3"]).marshal());
+    batch.extend(command(&[b"HGETALL", b"hello:resp3"]).marshal());
+    batch.extend(command(&[b"GET", b"hello:missing"]).marshal());
+    stream.write_all(&batch).unwrap();
+
+    let replies = read_replies(&mut stream, 5);
+    // Before HELLO the hash is a flat array and after it a real map.
+    assert_eq!(
+        replies[1],
+        Frame::array(vec![Frame::bulk("field"), Frame::bulk("value")])
+    );
+    match &replies[2] {
+        Frame::Map(pairs) => {
+            assert!(pairs.contains(&(Frame::bulk("proto"), Frame::integer(3))));
+            assert!(pairs.contains(&(Frame::bulk("server"), Frame::bulk("crache"))));
+        }
+        other => panic!("expected map, got {:?}", other),
+    }
+    assert_eq!(
+        replies[3],
+        Frame::map(vec![(Frame::bulk("field"), Frame::bulk("value"))])
+    );
+    assert_eq!(replies[4], Frame::Null);
+}
+
+#[test]
+fn test_inline_commands_over_raw_socket() {
+    let mut stream = TcpStream::connect(start_server()).unwrap();
+
+    stream
+        .write_all(b"PING\r\nSET inline:key \"hello world\"\nGET inline:key\r\n")
+        .unwrap();
+
+    let replies = read_replies(&mut stream, 3);
+    assert_eq!(
+        replies,
+        vec![
+            Frame::simple("PONG"),
+            Frame::ok(),
+            Frame::bulk("hello world")
+        ]
+    );
+}
+
+#[test]
+fn test_wrong_arity_is_an_error_not_a_crash() {
+    let mut stream = TcpStream::connect(start_server()).unwrap();
+
+    let mut batch = command(&[b"GET"]).marshal();
+    batch.extend(command(&[b"SET", b"only-key"]).marshal());
+    batch.extend(command(&[b"PING"]).marshal());
+    stream.write_all(&batch).unwrap();
+
+    let replies = read_replies(&mut stream, 3);
+    assert_eq!(
+        replies[0],
+        Frame::error("ERR wrong number of arguments for 'get' command")
+    );
+    assert_eq!(
+        replies[1],
+        Frame::error("ERR wrong number of arguments for 'set' command")
+    );
+    assert_eq!(replies[2], Frame::simple("PONG"));
+}
+
+#[test]
+fn test_frame_that_is_not_a_command_gets_a_protocol_error() {
+    let mut stream = TcpStream::connect(start_server()).unwrap();
+
+    // An empty command is skipped without a reply; a bare integer is not a
+    // command at all.
+    stream.write_all(b"*0\r\nPING\r\n:1\r\nPING\r\n").unwrap();
+
+    let replies = read_replies(&mut stream, 2);
+    assert_eq!(replies[0], Frame::simple("PONG"));
+    assert!(
+        matches!(&replies[1], Frame::Error(e) if e.starts_with("ERR Protocol error")),
+        "{:?}",
+        replies[1]
+    );
+    // The connection is closed after the error, as for any protocol error.
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn test_arguments_that_are_not_bulk_strings_get_a_protocol_error() {
+    let addr = start_server();
+    // Arguments missing their `$` lengths are not inline commands.
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"*3\r\n$3\r\nSET\r\nfoo\r\nbar\r\n")
+        .unwrap();
+    let replies = read_replies(&mut stream, 1);
+    assert_eq!(
+        replies[0],
+        Frame::error("ERR Protocol error: expected '$', got 'f'")
+    );
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    // Nor is any other type a command argument.
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"*2\r\n$3\r\nGET\r\n:1\r\n").unwrap();
+    let replies = read_replies(&mut stream, 1);
+    assert!(
+        matches!(&replies[0], Frame::Error(e) if e.starts_with("ERR Protocol error")),
+        "{:?}",
+        replies[0]
+    );
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    // Neither stored anything.
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(&command(&[b"EXISTS", b"", b"foo"]).marshal())
+        .unwrap();
+    assert_eq!(read_replies(&mut stream, 1), vec![Frame::integer(0)]);
+}
+
+#[test]
+fn test_blpop_over_tcp_is_woken_by_another_connection() {
+    let addr = start_server();
+    let mut waiter = TcpStream::connect(addr).unwrap();
+    let mut pusher = TcpStream::connect(addr).unwrap();
+
+    waiter
+        .write_all(&command(&[b"BLPOP", b"tcp:queue", b"5"]).marshal())
+        .unwrap();
+    thread::sleep(std::time::Duration::from_millis(50));
+    pusher
+        .write_all(&command(&[b"RPUSH", b"tcp:queue", b"job"]).marshal())
+        .unwrap();
+
+    assert_eq!(read_replies(&mut pusher, 1), vec![Frame::integer(1)]);
+    assert_eq!(
+        read_replies(&mut waiter, 1),
+        vec![Frame::array(vec![
+            Frame::bulk("tcp:queue"),
+            Frame::bulk("job")
+        ])]
+    );
+}
+
+#[test]
+fn test_closed_blocked_connection_does_not_swallow_pushes() {
+    let addr = start_server();
+    let mut waiter = TcpStream::connect(addr).unwrap();
+    waiter
+        .write_all(&command(&[b"BLPOP", b"tcp:orphan", b"0"]).marshal())
+        .unwrap();
+    thread::sleep(std::time::Duration::from_millis(50));
+    drop(waiter);
+    // Long enough for the blocked thread to notice the hang-up.
+    thread::sleep(std::time::Duration::from_millis(300));
+
+    let mut pusher = TcpStream::connect(addr).unwrap();
+    pusher
+        .write_all(&command(&[b"RPUSH", b"tcp:orphan", b"job"]).marshal())
+        .unwrap();
+    pusher
+        .write_all(&command(&[b"LLEN", b"tcp:orphan"]).marshal())
+        .unwrap();
+    assert_eq!(
+        read_replies(&mut pusher, 2),
+        vec![Frame::integer(1), Frame::integer(1)]
+    );
+}
+
+#[test]
+fn test_connections_share_one_ordered_aof() {
+    let path = std::env::temp_dir().join(format!("crache_server_{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let path = path.to_string_lossy().into_owned();
+    let aof = Arc::new(Aof::with_fsync(&path, FsyncPolicy::Always));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shared = Arc::clone(&aof);
+    thread::spawn(move || server::serve(listener, Some(shared)));
+
+    // Connections race to overwrite the same key.
+    let writers: Vec<_> = (0..8)
+        .map(|c| {
+            thread::spawn(move || {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                for i in 0..100 {
+                    let value = format!("{}:{}", c, i);
+                    stream
+                        .write_all(&command(&[b"SET", b"aof:race", value.as_bytes()]).marshal())
+                        .unwrap();
+                    assert_eq!(read_replies(&mut stream, 1), vec![Frame::simple("OK")]);
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(&command(&[b"GET", b"aof:race"]).marshal())
+        .unwrap();
+    let current = read_replies(&mut stream, 1).remove(0);
+
+    // Every acknowledged write is in the file, and the last one logged is
+    // the one that won.
+    let mut logged = Vec::new();
+    Aof::new(&path).read(|frame| logged.push(frame)).unwrap();
+    assert_eq!(logged.len(), 800);
+    let Frame::Array(last) = logged.pop().unwrap() else {
+        panic!("expected a command");
+    };
+    assert_eq!(last[2], current);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_always_replies_only_after_the_fsync() {
+    let path = std::env::temp_dir().join(format!("crache_server_fsync_{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let path = path.to_string_lossy().into_owned();
+
+    // The disk holds every sync up until the test lets it through.
+    let disk = Arc::new(Mutex::new(()));
+    let synced = Arc::new(AtomicBool::new(false));
+    let held = disk.lock().unwrap();
+    let (hook_disk, hook_synced) = (Arc::clone(&disk), Arc::clone(&synced));
+    let aof = Aof::with_sync_hook(
+        &path,
+        FsyncPolicy::Always,
+        Box::new(move |file| {
+            let _through = hook_disk.lock().unwrap();
+            file.sync_data()?;
+            hook_synced.store(true, Ordering::SeqCst);
+            Ok(())
+        }),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || server::serve(listener, Some(Arc::new(aof))));
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(&command(&[b"SET", b"aof:fsync", b"v"]).marshal())
+        .unwrap();
+    // No reply while the sync is pending...
+    stream
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    assert!(stream.read(&mut [0u8; 64]).is_err());
+    assert!(!synced.load(Ordering::SeqCst));
+
+    // ...and the write is on disk by the time there is one.
+    drop(held);
+    stream.set_read_timeout(None).unwrap();
+    assert_eq!(read_replies(&mut stream, 1), vec![Frame::simple("OK")]);
+    assert!(synced.load(Ordering::SeqCst));
+
+    let _ = std::fs::remove_file(&path);
+}
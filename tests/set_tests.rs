@@ -0,0 +1,245 @@
+use crache::app::aof::Aof;
+use crache::app::handler::{self, Client};
+use crache::app::resp::Frame;
+use crache::app::server::handle_command;
+use std::sync::Arc;
+
+fn bulks(args: &[&str]) -> Vec<Frame> {
+    args.iter().map(|s| Frame::bulk(*s)).collect()
+}
+
+fn call(args: &[&str]) -> Frame {
+    handler::dispatch(&mut Client::new(), bulks(args))
+}
+
+fn list(args: &[&str]) -> Frame {
+    Frame::array(bulks(args))
+}
+
+// The members of a set reply, sorted, since sets have no order.
+fn members(frame: Frame) -> Vec<String> {
+    let (Frame::Set(items) | Frame::Array(items)) = frame else {
+        panic!("not a collection: {:?}", frame);
+    };
+    let mut members: Vec<String> = items
+        .iter()
+        .map(|f| String::from_utf8(f.as_bulk().unwrap().to_vec()).unwrap())
+        .collect();
+    members.sort();
+    members
+}
+
+const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+#[test]
+fn test_add_remove_and_membership() {
+    assert_eq!(call(&["SADD", "set:a", "x", "y", "x"]), Frame::integer(2));
+    assert_eq!(call(&["SADD", "set:a", "y", "z"]), Frame::integer(1));
+    assert_eq!(call(&["SCARD", "set:a"]), Frame::integer(3));
+    assert_eq!(members(call(&["SMEMBERS", "set:a"])), ["x", "y", "z"]);
+    assert_eq!(call(&["SISMEMBER", "set:a", "x"]), Frame::integer(1));
+    assert_eq!(call(&["SISMEMBER", "set:a", "w"]), Frame::integer(0));
+    assert_eq!(
+        call(&["SMISMEMBER", "set:a", "x", "w", "z"]),
+        Frame::array(vec![
+            Frame::integer(1),
+            Frame::integer(0),
+            Frame::integer(1)
+        ])
+    );
+    assert_eq!(call(&["TYPE", "set:a"]), Frame::simple("set"));
+
+    assert_eq!(call(&["SREM", "set:a", "x", "w"]), Frame::integer(1));
+    assert_eq!(call(&["SREM", "set:a", "y", "z"]), Frame::integer(2));
+    // Removing the last member deletes the key.
+    assert_eq!(call(&["TYPE", "set:a"]), Frame::simple("none"));
+    assert_eq!(call(&["SCARD", "set:a"]), Frame::integer(0));
+    assert_eq!(call(&["SMEMBERS", "set:a"]), Frame::Set(vec![]));
+    assert_eq!(call(&["SREM", "set:a", "x"]), Frame::integer(0));
+}
+
+#[test]
+fn test_spop() {
+    call(&["SADD", "set:pop", "a", "b", "c", "d"]);
+    let Frame::Bulk(one) = call(&["SPOP", "set:pop"]) else {
+        panic!("expected a member");
+    };
+    assert_eq!(
+        call(&["SISMEMBER", "set:pop", &String::from_utf8(one).unwrap()]),
+        Frame::integer(0)
+    );
+    assert_eq!(members(call(&["SPOP", "set:pop", "2"])).len(), 2);
+    assert_eq!(members(call(&["SPOP", "set:pop", "0"])).len(), 0);
+    assert_eq!(members(call(&["SPOP", "set:pop", "10"])).len(), 1);
+    assert_eq!(call(&["TYPE", "set:pop"]), Frame::simple("none"));
+
+    assert_eq!(call(&["SPOP", "set:pop"]), Frame::Null);
+    assert_eq!(call(&["SPOP", "set:pop", "3"]), Frame::Set(vec![]));
+    assert_eq!(
+        call(&["SPOP", "set:pop", "-1"]),
+        Frame::error("ERR value is out of range, must be positive")
+    );
+}
+
+#[test]
+fn test_srandmember() {
+    call(&["SADD", "set:rand", "a", "b", "c"]);
+    let Frame::Bulk(one) = call(&["SRANDMEMBER", "set:rand"]) else {
+        panic!("expected a member");
+    };
+    assert!(["a", "b", "c"].contains(&std::str::from_utf8(&one).unwrap()));
+
+    // A positive count never repeats and is capped at the set's size.
+    assert_eq!(
+        members(call(&["SRANDMEMBER", "set:rand", "5"])),
+        ["a", "b", "c"]
+    );
+    let two = members(call(&["SRANDMEMBER", "set:rand", "2"]));
+    assert_eq!(two.len(), 2);
+    assert_ne!(two[0], two[1]);
+    // A negative count returns exactly that many, repeats allowed.
+    assert_eq!(members(call(&["SRANDMEMBER", "set:rand", "-7"])).len(), 7);
+    assert_eq!(call(&["SCARD", "set:rand"]), Frame::integer(3));
+
+    assert_eq!(call(&["SRANDMEMBER", "set:rand_missing"]), Frame::Null);
+    assert_eq!(call(&["SRANDMEMBER", "set:rand_missing", "2"]), list(&[]));
+}
+
+#[test]
+fn test_smove() {
+    call(&["SADD", "set:src", "a", "b"]);
+    assert_eq!(
+        call(&["SMOVE", "set:src", "set:dst", "a"]),
+        Frame::integer(1)
+    );
+    assert_eq!(
+        call(&["SMOVE", "set:src", "set:dst", "a"]),
+        Frame::integer(0)
+    );
+    assert_eq!(
+        call(&["SMOVE", "set:src", "set:src", "b"]),
+        Frame::integer(1)
+    );
+    assert_eq!(
+        call(&["SMOVE", "set:src", "set:dst", "b"]),
+        Frame::integer(1)
+    );
+    assert_eq!(call(&["TYPE", "set:src"]), Frame::simple("none"));
+    assert_eq!(members(call(&["SMEMBERS", "set:dst"])), ["a", "b"]);
+}
+
+#[test]
+fn test_algebra() {
+    call(&["SADD", "set:x", "a", "b", "c", "d"]);
+    call(&["SADD", "set:y", "c"]);
+    call(&["SADD", "set:z", "a", "c", "e"]);
+
+    assert_eq!(members(call(&["SINTER", "set:x", "set:y", "set:z"])), ["c"]);
+    assert_eq!(members(call(&["SINTER", "set:x", "set:z"])), ["a", "c"]);
+    assert_eq!(
+        members(call(&["SINTER", "set:x", "set:none"])),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        members(call(&["SUNION", "set:x", "set:z", "set:none"])),
+        ["a", "b", "c", "d", "e"]
+    );
+    assert_eq!(
+        members(call(&["SDIFF", "set:x", "set:y", "set:z"])),
+        ["b", "d"]
+    );
+    assert_eq!(
+        members(call(&["SDIFF", "set:none", "set:x"])),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn test_store_variants() {
+    call(&["SADD", "set:s1", "a", "b", "c"]);
+    call(&["SADD", "set:s2", "b", "c", "d"]);
+
+    assert_eq!(
+        call(&["SINTERSTORE", "set:out", "set:s1", "set:s2"]),
+        Frame::integer(2)
+    );
+    assert_eq!(members(call(&["SMEMBERS", "set:out"])), ["b", "c"]);
+    assert_eq!(
+        call(&["SUNIONSTORE", "set:out", "set:s1", "set:s2"]),
+        Frame::integer(4)
+    );
+    assert_eq!(
+        call(&["SDIFFSTORE", "set:out", "set:s1", "set:s2"]),
+        Frame::integer(1)
+    );
+    assert_eq!(members(call(&["SMEMBERS", "set:out"])), ["a"]);
+
+    // The destination is overwritten whatever it held, and an empty result
+    // deletes it.
+    call(&["SET", "set:str", "v"]);
+    assert_eq!(
+        call(&["SUNIONSTORE", "set:str", "set:s1"]),
+        Frame::integer(3)
+    );
+    assert_eq!(call(&["TYPE", "set:str"]), Frame::simple("set"));
+    assert_eq!(
+        call(&["SINTERSTORE", "set:out", "set:s1", "set:none"]),
+        Frame::integer(0)
+    );
+    assert_eq!(call(&["TYPE", "set:out"]), Frame::simple("none"));
+    // A source may also be the destination.
+    assert_eq!(
+        call(&["SDIFFSTORE", "set:s1", "set:s1", "set:s2"]),
+        Frame::integer(1)
+    );
+    assert_eq!(members(call(&["SMEMBERS", "set:s1"])), ["a"]);
+}
+
+#[test]
+fn test_set_wrongtype() {
+    call(&["SET", "set:string", "v"]);
+    call(&["SADD", "set:real", "a"]);
+    assert_eq!(call(&["SADD", "set:string", "a"]), Frame::error(WRONGTYPE));
+    assert_eq!(call(&["SCARD", "set:string"]), Frame::error(WRONGTYPE));
+    assert_eq!(call(&["GET", "set:real"]), Frame::error(WRONGTYPE));
+    assert_eq!(
+        call(&["SINTER", "set:none", "set:string"]),
+        Frame::error(WRONGTYPE)
+    );
+    // A wrong destination type leaves the source untouched.
+    assert_eq!(
+        call(&["SMOVE", "set:real", "set:string", "a"]),
+        Frame::error(WRONGTYPE)
+    );
+    assert_eq!(call(&["SCARD", "set:real"]), Frame::integer(1));
+}
+
+#[test]
+fn test_spop_is_logged_as_srem() {
+    let path = std::env::temp_dir().join(format!("crache_sets_{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let path = path.to_string_lossy().into_owned();
+    let aof = Arc::new(Aof::new(&path));
+
+    let mut client = Client::new();
+    client.aof = Some(Arc::clone(&aof));
+    for args in [
+        &["SADD", "set:aof", "a"][..],
+        &["SADD", "set:aof", "a"],
+        &["SPOP", "set:aof"],
+        &["SPOP", "set:aof"],
+    ] {
+        handle_command(&mut client, Frame::array(bulks(args)));
+    }
+
+    let mut logged = Vec::new();
+    aof.read(|frame| logged.push(frame)).unwrap();
+    assert_eq!(
+        logged,
+        vec![
+            list(&["SADD", "set:aof", "a"]),
+            list(&["SREM", "set:aof", "a"])
+        ]
+    );
+    let _ = std::fs::remove_file(&path);
+}
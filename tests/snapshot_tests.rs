@@ -0,0 +1,159 @@
+use crache::app::handler::{self, load_snapshot, Client};
+use crache::app::hash::Hash;
+use crache::app::keyspace::{Entry, List, Set};
+use crache::app::resp::Frame;
+use crache::app::snapshot::{self, Dataset, SaveConfig};
+use crache::app::util::now_ms;
+use crache::app::zset::SortedSet;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+fn call(args: &[&str]) -> Frame {
+    let argv = args.iter().map(|a| Frame::bulk(*a)).collect();
+    handler::dispatch(&mut Client::new(), argv)
+}
+
+fn sample() -> Dataset {
+    let later = now_ms() + 100_000;
+    let list: List = [b"a".to_vec(), b"b".to_vec()].into_iter().collect();
+    let mut set = Set::new();
+    set.insert(b"m".to_vec(), ());
+    let mut zset = SortedSet::new();
+    zset.insert(b"low".to_vec(), -1.5);
+    zset.insert(b"high".to_vec(), f64::INFINITY);
+    let mut hash = Hash::new();
+    hash.insert(b"f".to_vec(), b"v".to_vec());
+    hash.insert(b"ttl".to_vec(), b"v".to_vec());
+    hash.set_expire_at(b"ttl", later);
+    vec![
+        (
+            b"s".to_vec(),
+            Entry::String(vec![0, 255, b'\n']),
+            Some(later),
+        ),
+        (b"l".to_vec(), Entry::List(list), None),
+        (b"set".to_vec(), Entry::Set(set), None),
+        (b"z".to_vec(), Entry::ZSet(zset), None),
+        (b"h".to_vec(), Entry::Hash(hash), None),
+    ]
+}
+
+fn encode(dataset: &Dataset) -> Vec<u8> {
+    let mut out = Vec::new();
+    snapshot::write(&mut out, dataset).unwrap();
+    out
+}
+
+#[test]
+fn test_snapshot_roundtrip() {
+    let dataset = sample();
+    assert_eq!(snapshot::read(&encode(&dataset)).unwrap(), dataset);
+    assert_eq!(snapshot::read(&encode(&Vec::new())).unwrap(), Vec::new());
+}
+
+#[test]
+fn test_expired_keys_are_not_loaded() {
+    let mut hash = Hash::new();
+    hash.insert(b"f".to_vec(), b"v".to_vec());
+    hash.set_expire_at(b"f", now_ms() + 50);
+    let dataset = vec![
+        (b"gone".to_vec(), Entry::String(b"v".to_vec()), Some(1)),
+        (b"empty".to_vec(), Entry::Hash(hash), None),
+        (b"kept".to_vec(), Entry::String(b"v".to_vec()), None),
+    ];
+    let data = encode(&dataset);
+    std::thread::sleep(Duration::from_millis(60));
+    let loaded = snapshot::read(&data).unwrap();
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].0, b"kept");
+}
+
+#[test]
+fn test_corrupt_snapshots_are_rejected() {
+    let data = encode(&sample());
+    for i in [0, 7, data.len() / 2, data.len() - 1] {
+        let mut damaged = data.clone();
+        damaged[i] ^= 0x20;
+        let err = snapshot::read(&damaged).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData, "byte {}", i);
+    }
+    for len in [0, 5, data.len() - 1] {
+        assert!(snapshot::read(&data[..len]).is_err(), "length {}", len);
+    }
+}
+
+#[test]
+fn test_save_points_parse() {
+    assert_eq!(
+        SaveConfig::parse_save_points("3600 1 60 10000"),
+        Some(vec![(3600, 1), (60, 10000)])
+    );
+    assert_eq!(SaveConfig::parse_save_points(""), Some(vec![]));
+    assert_eq!(SaveConfig::parse_save_points("60"), None);
+    assert_eq!(SaveConfig::parse_save_points("60 x"), None);
+    assert_eq!(
+        SaveConfig::default().save_points_string(),
+        "3600 1 300 100 60 10000"
+    );
+}
+
+#[test]
+fn test_save_bgsave_and_reload() {
+    let name = format!("crache_snapshot_{}.crs", std::process::id());
+    let path = Path::new(&name);
+    assert_eq!(
+        call(&["CONFIG", "SET", "dbfilename", &name, "save", ""]),
+        Frame::simple("OK")
+    );
+    assert_eq!(
+        call(&["CONFIG", "GET", "save"]),
+        Frame::map(vec![(Frame::bulk("save"), Frame::bulk(""))])
+    );
+    assert_eq!(
+        call(&["CONFIG", "SET", "dbfilename", "/etc/passwd"]),
+        Frame::error("ERR CONFIG SET failed (possibly related to argument 'dbfilename') - dbfilename can't be a path, just a filename")
+    );
+
+    call(&["SET", "snap:s", "v", "EX", "100"]);
+    call(&["RPUSH", "snap:l", "a", "b"]);
+    call(&["ZADD", "snap:z", "2", "m"]);
+    call(&["HSET", "snap:h", "f", "v", "g", "w"]);
+    call(&["HEXPIRE", "snap:h", "100", "FIELDS", "1", "g"]);
+
+    assert_eq!(call(&["SAVE"]), Frame::simple("OK"));
+    let Frame::Integer(lastsave) = call(&["LASTSAVE"]) else {
+        panic!("expected an integer");
+    };
+    assert!((now_ms() / 1000).abs_diff(lastsave as u64) <= 1);
+
+    // The snapshot taken by BGSAVE has the key written just before it.
+    call(&["SET", "snap:late", "v"]);
+    std::fs::remove_file(path).unwrap();
+    assert_eq!(
+        call(&["BGSAVE"]),
+        Frame::simple("Background saving started")
+    );
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !path.exists() {
+        assert!(Instant::now() < deadline, "BGSAVE never finished");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    call(&["FLUSHALL"]);
+    assert_eq!(load_snapshot(path).unwrap(), 5);
+    assert_eq!(call(&["GET", "snap:late"]), Frame::bulk("v"));
+    assert_eq!(call(&["TTL", "snap:s"]), Frame::integer(100));
+    assert_eq!(
+        call(&["LRANGE", "snap:l", "0", "-1"]),
+        Frame::array(vec![Frame::bulk("a"), Frame::bulk("b")])
+    );
+    assert_eq!(call(&["ZSCORE", "snap:z", "m"]), Frame::Double(2.0));
+    assert_eq!(
+        call(&["HTTL", "snap:h", "FIELDS", "2", "f", "g"]),
+        Frame::array(vec![Frame::integer(-1), Frame::integer(100)])
+    );
+
+    assert_eq!(call(&["BGSAVE", "NOW"]), Frame::error("ERR syntax error"));
+    let _ = std::fs::remove_file(path);
+}
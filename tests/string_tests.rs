@@ -0,0 +1,329 @@
+use crache::app::handler::{self, Client};
+use crache::app::resp::Frame;
+
+fn call(args: &[&str]) -> Frame {
+    let argv = args.iter().map(|s| Frame::bulk(*s)).collect();
+    handler::dispatch(&mut Client::new(), argv)
+}
+
+// Runs a command and returns what it asked to have logged to the AOF.
+fn propagated(args: &[&str]) -> Option<Vec<Vec<Frame>>> {
+    let mut client = Client::new();
+    let argv = args.iter().map(|s| Frame::bulk(*s)).collect();
+    handler::dispatch(&mut client, argv);
+    client.take_propagation()
+}
+
+const NOT_AN_INTEGER: &str = "ERR value is not an integer or out of range";
+const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+#[test]
+fn test_incr_decr() {
+    assert_eq!(call(&["INCR", "str:counter"]), Frame::integer(1));
+    assert_eq!(call(&["INCRBY", "str:counter", "41"]), Frame::integer(42));
+    assert_eq!(call(&["DECR", "str:counter"]), Frame::integer(41));
+    assert_eq!(call(&["DECRBY", "str:counter", "50"]), Frame::integer(-9));
+    assert_eq!(call(&["GET", "str:counter"]), Frame::bulk("-9"));
+    assert_eq!(call(&["DECR", "str:fresh"]), Frame::integer(-1));
+}
+
+#[test]
+fn test_incr_errors() {
+    call(&["SET", "str:text", "abc"]);
+    assert_eq!(call(&["INCR", "str:text"]), Frame::error(NOT_AN_INTEGER));
+    call(&["SET", "str:padded", " 1"]);
+    assert_eq!(call(&["INCR", "str:padded"]), Frame::error(NOT_AN_INTEGER));
+    call(&["SET", "str:empty", ""]);
+    assert_eq!(call(&["INCR", "str:empty"]), Frame::error(NOT_AN_INTEGER));
+    assert_eq!(
+        call(&["INCRBY", "str:x", "1.5"]),
+        Frame::error(NOT_AN_INTEGER)
+    );
+    assert_eq!(
+        call(&["INCRBY", "str:x", "+1"]),
+        Frame::error(NOT_AN_INTEGER)
+    );
+
+    call(&["SET", "str:max", "9223372036854775807"]);
+    assert_eq!(
+        call(&["INCR", "str:max"]),
+        Frame::error("ERR increment or decrement would overflow")
+    );
+    call(&["SET", "str:min", "-9223372036854775808"]);
+    assert_eq!(
+        call(&["DECR", "str:min"]),
+        Frame::error("ERR increment or decrement would overflow")
+    );
+    assert_eq!(
+        call(&["DECRBY", "str:min", "-9223372036854775808"]),
+        Frame::error("ERR decrement would overflow")
+    );
+    // Failed increments leave the value alone.
+    assert_eq!(
+        call(&["GET", "str:max"]),
+        Frame::bulk("9223372036854775807")
+    );
+
+    call(&["HSET", "str:hash", "f", "v"]);
+    assert_eq!(call(&["INCR", "str:hash"]), Frame::error(WRONGTYPE));
+}
+
+#[test]
+fn test_incr_keeps_ttl() {
+    call(&["SET", "str:ttl", "1", "EX", "100"]);
+    call(&["INCR", "str:ttl"]);
+    assert!(matches!(call(&["TTL", "str:ttl"]), Frame::Integer(n) if n > 0));
+}
+
+#[test]
+fn test_incrbyfloat() {
+    assert_eq!(
+        call(&["INCRBYFLOAT", "str:float", "10.5"]),
+        Frame::bulk("10.5")
+    );
+    assert_eq!(
+        call(&["INCRBYFLOAT", "str:float", "0.1"]),
+        Frame::bulk("10.6")
+    );
+    assert_eq!(
+        call(&["INCRBYFLOAT", "str:float", "-5"]),
+        Frame::bulk("5.6")
+    );
+    call(&["SET", "str:float_int", "3"]);
+    assert_eq!(
+        call(&["INCRBYFLOAT", "str:float_int", "2.0"]),
+        Frame::bulk("5")
+    );
+
+    let not_float = Frame::error("ERR value is not a valid float");
+    assert_eq!(call(&["INCRBYFLOAT", "str:float", "abc"]), not_float);
+    assert_eq!(call(&["INCRBYFLOAT", "str:float", "nan"]), not_float);
+    call(&["SET", "str:float_text", "abc"]);
+    assert_eq!(call(&["INCRBYFLOAT", "str:float_text", "1"]), not_float);
+    call(&["SET", "str:float_big", "1.7e308"]);
+    assert_eq!(
+        call(&["INCRBYFLOAT", "str:float_big", "1.7e308"]),
+        Frame::error("ERR increment would produce NaN or Infinity")
+    );
+}
+
+#[test]
+fn test_incrbyfloat_propagates_result() {
+    call(&["SET", "str:float_prop", "1.5"]);
+    assert_eq!(
+        propagated(&["INCRBYFLOAT", "str:float_prop", "1"]),
+        Some(vec![vec![
+            Frame::bulk("SET"),
+            Frame::bulk("str:float_prop"),
+            Frame::bulk("2.5"),
+            Frame::bulk("KEEPTTL"),
+        ]])
+    );
+}
+
+#[test]
+fn test_append_and_strlen() {
+    assert_eq!(call(&["STRLEN", "str:append"]), Frame::integer(0));
+    assert_eq!(call(&["APPEND", "str:append", "Hello"]), Frame::integer(5));
+    assert_eq!(
+        call(&["APPEND", "str:append", " World"]),
+        Frame::integer(11)
+    );
+    assert_eq!(call(&["GET", "str:append"]), Frame::bulk("Hello World"));
+    assert_eq!(call(&["STRLEN", "str:append"]), Frame::integer(11));
+
+    call(&["HSET", "str:append_hash", "f", "v"]);
+    assert_eq!(
+        call(&["APPEND", "str:append_hash", "x"]),
+        Frame::error(WRONGTYPE)
+    );
+    assert_eq!(
+        call(&["STRLEN", "str:append_hash"]),
+        Frame::error(WRONGTYPE)
+    );
+}
+
+#[test]
+fn test_getrange() {
+    call(&["SET", "str:range", "This is a string"]);
+    assert_eq!(
+        call(&["GETRANGE", "str:range", "0", "3"]),
+        Frame::bulk("This")
+    );
+    assert_eq!(
+        call(&["GETRANGE", "str:range", "-3", "-1"]),
+        Frame::bulk("ing")
+    );
+    assert_eq!(
+        call(&["GETRANGE", "str:range", "0", "-1"]),
+        Frame::bulk("This is a string")
+    );
+    assert_eq!(
+        call(&["GETRANGE", "str:range", "10", "100"]),
+        Frame::bulk("string")
+    );
+    assert_eq!(call(&["GETRANGE", "str:range", "5", "3"]), Frame::bulk(""));
+    assert_eq!(
+        call(&["GETRANGE", "str:range", "-1", "-5"]),
+        Frame::bulk("")
+    );
+    assert_eq!(
+        call(&["GETRANGE", "str:range", "100", "200"]),
+        Frame::bulk("")
+    );
+    assert_eq!(
+        call(&["GETRANGE", "str:missing", "0", "-1"]),
+        Frame::bulk("")
+    );
+    assert_eq!(
+        call(&["GETRANGE", "str:range", "a", "1"]),
+        Frame::error(NOT_AN_INTEGER)
+    );
+}
+
+#[test]
+fn test_setrange() {
+    call(&["SET", "str:setrange", "Hello World"]);
+    assert_eq!(
+        call(&["SETRANGE", "str:setrange", "6", "Redis"]),
+        Frame::integer(11)
+    );
+    assert_eq!(call(&["GET", "str:setrange"]), Frame::bulk("Hello Redis"));
+
+    // Writing past the end pads with zero bytes.
+    assert_eq!(
+        call(&["SETRANGE", "str:padded_range", "3", "ab"]),
+        Frame::integer(5)
+    );
+    assert_eq!(
+        call(&["GET", "str:padded_range"]),
+        Frame::bulk(b"\0\0\0ab".to_vec())
+    );
+
+    // An empty patch does not create the key.
+    assert_eq!(
+        call(&["SETRANGE", "str:never", "10", ""]),
+        Frame::integer(0)
+    );
+    assert_eq!(call(&["TYPE", "str:never"]), Frame::simple("none"));
+
+    assert_eq!(
+        call(&["SETRANGE", "str:setrange", "-1", "x"]),
+        Frame::error("ERR offset is out of range")
+    );
+    assert_eq!(
+        call(&["SETRANGE", "str:setrange", "536870912", "x"]),
+        Frame::error("ERR string exceeds maximum allowed size (proto-max-bulk-len)")
+    );
+}
+
+#[test]
+fn test_mget_mset() {
+    assert_eq!(call(&["MSET", "str:m1", "a", "str:m2", "b"]), Frame::ok());
+    call(&["HSET", "str:mhash", "f", "v"]);
+    assert_eq!(
+        call(&["MGET", "str:m1", "str:m2", "str:mmissing", "str:mhash"]),
+        Frame::array(vec![
+            Frame::bulk("a"),
+            Frame::bulk("b"),
+            Frame::Null,
+            Frame::Null
+        ])
+    );
+    assert_eq!(
+        call(&["MSET", "str:m1", "a", "str:m2"]),
+        Frame::error("ERR wrong number of arguments for 'mset' command")
+    );
+}
+
+#[test]
+fn test_msetnx() {
+    assert_eq!(
+        call(&["MSETNX", "str:nx1", "a", "str:nx2", "b"]),
+        Frame::integer(1)
+    );
+    // All or nothing: one existing key blocks every write.
+    assert_eq!(
+        call(&["MSETNX", "str:nx2", "x", "str:nx3", "c"]),
+        Frame::integer(0)
+    );
+    assert_eq!(call(&["GET", "str:nx2"]), Frame::bulk("b"));
+    assert_eq!(call(&["GET", "str:nx3"]), Frame::Null);
+}
+
+#[test]
+fn test_setnx_getset() {
+    assert_eq!(call(&["SETNX", "str:setnx", "a"]), Frame::integer(1));
+    assert_eq!(call(&["SETNX", "str:setnx", "b"]), Frame::integer(0));
+    assert_eq!(call(&["GET", "str:setnx"]), Frame::bulk("a"));
+    assert_eq!(propagated(&["SETNX", "str:setnx", "c"]), Some(vec![]));
+
+    call(&["EXPIRE", "str:setnx", "100"]);
+    assert_eq!(call(&["GETSET", "str:setnx", "b"]), Frame::bulk("a"));
+    assert_eq!(call(&["GET", "str:setnx"]), Frame::bulk("b"));
+    // GETSET discards the TTL, as SET does.
+    assert_eq!(call(&["TTL", "str:setnx"]), Frame::integer(-1));
+    assert_eq!(call(&["GETSET", "str:getset_new", "v"]), Frame::Null);
+}
+
+#[test]
+fn test_getdel() {
+    call(&["SET", "str:getdel", "v"]);
+    assert_eq!(call(&["GETDEL", "str:getdel"]), Frame::bulk("v"));
+    assert_eq!(call(&["GETDEL", "str:getdel"]), Frame::Null);
+    assert_eq!(call(&["TYPE", "str:getdel"]), Frame::simple("none"));
+
+    call(&["HSET", "str:getdel_hash", "f", "v"]);
+    assert_eq!(
+        call(&["GETDEL", "str:getdel_hash"]),
+        Frame::error(WRONGTYPE)
+    );
+}
+
+#[test]
+fn test_getex() {
+    call(&["SET", "str:getex", "v"]);
+    assert_eq!(call(&["GETEX", "str:getex"]), Frame::bulk("v"));
+    assert_eq!(call(&["TTL", "str:getex"]), Frame::integer(-1));
+
+    assert_eq!(call(&["GETEX", "str:getex", "EX", "100"]), Frame::bulk("v"));
+    assert!(matches!(call(&["TTL", "str:getex"]), Frame::Integer(n) if n > 0));
+    assert_eq!(call(&["GETEX", "str:getex", "PERSIST"]), Frame::bulk("v"));
+    assert_eq!(call(&["TTL", "str:getex"]), Frame::integer(-1));
+
+    assert_eq!(
+        call(&["GETEX", "str:getex", "EXAT", "4000000000"]),
+        Frame::bulk("v")
+    );
+    assert_eq!(
+        call(&["EXPIRETIME", "str:getex"]),
+        Frame::integer(4_000_000_000)
+    );
+
+    assert_eq!(
+        call(&["GETEX", "str:getex_missing", "EX", "1"]),
+        Frame::Null
+    );
+    assert_eq!(
+        call(&["GETEX", "str:getex", "EX", "0"]),
+        Frame::error("ERR invalid expire time in 'getex' command")
+    );
+    assert_eq!(
+        call(&["GETEX", "str:getex", "EX", "1", "PERSIST"]),
+        Frame::error("ERR syntax error")
+    );
+}
+
+#[test]
+fn test_getex_propagates_absolute_deadline() {
+    call(&["SET", "str:getex_prop", "v"]);
+    assert_eq!(
+        propagated(&["GETEX", "str:getex_prop", "PXAT", "4000000000123"]),
+        Some(vec![vec![
+            Frame::bulk("PEXPIREAT"),
+            Frame::bulk("str:getex_prop"),
+            Frame::bulk("4000000000123"),
+        ]])
+    );
+    assert_eq!(propagated(&["GETEX", "str:getex_prop"]), Some(vec![]));
+}
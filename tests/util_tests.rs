@@ -0,0 +1,120 @@
+use crache::app::util::{crc64, glob_match, random_index};
+
+#[test]
+fn test_glob_wildcards() {
+    assert!(glob_match(b"*", b""));
+    assert!(glob_match(b"*", b"anything"));
+    assert!(glob_match(b"user:*", b"user:1"));
+    assert!(!glob_match(b"user:*", b"users"));
+    assert!(glob_match(b"h?llo", b"hello"));
+    assert!(!glob_match(b"h?llo", b"hllo"));
+    assert!(glob_match(b"*max*", b"maxmemory-policy"));
+    assert!(glob_match(b"a**b", b"ab"));
+}
+
+#[test]
+fn test_glob_classes_and_escapes() {
+    assert!(glob_match(b"h[ae]llo", b"hallo"));
+    assert!(!glob_match(b"h[ae]llo", b"hillo"));
+    assert!(glob_match(b"h[^e]llo", b"hallo"));
+    assert!(!glob_match(b"h[^e]llo", b"hello"));
+    assert!(glob_match(b"h[a-c]llo", b"hbllo"));
+    assert!(!glob_match(b"h[a-c]llo", b"hdllo"));
+    assert!(glob_match(b"h\\*llo", b"h*llo"));
+    assert!(!glob_match(b"h\\*llo", b"hello"));
+}
+
+#[test]
+fn test_glob_backtracks_to_the_last_star() {
+    assert!(glob_match(b"*a*b", b"xaxxab"));
+    assert!(glob_match(b"a*[0-9]?", b"abc12"));
+    assert!(!glob_match(b"a*[0-9]?", b"abc1"));
+    assert!(glob_match(b"*\\**", b"x*y"));
+    assert!(glob_match(b"*[ab", b"xa"));
+}
+
+#[test]
+fn test_glob_pathological_pattern_is_fast() {
+    // Exponential for a matcher that tries every split at every star.
+    let started = std::time::Instant::now();
+    let key = vec![b'a'; 60];
+    assert!(!glob_match(b"*a*a*a*a*a*a*a*b", &key));
+    let long = vec![b'a'; 10_000];
+    assert!(!glob_match(
+        &b"*a"
+            .repeat(50)
+            .into_iter()
+            .chain(*b"*b")
+            .collect::<Vec<_>>(),
+        &long
+    ));
+    assert!(started.elapsed() < std::time::Duration::from_secs(1));
+}
+
+#[test]
+fn test_random_index_in_range() {
+    for n in 1..50 {
+        assert!(random_index(n) < n);
+    }
+}
+
+#[test]
+fn test_random_sample_is_distinct() {
+    use crache::app::util::random_sample;
+    for n in 0..20 {
+        for k in 0..25 {
+            let mut picks = random_sample(n, k);
+            assert_eq!(picks.len(), k.min(n));
+            picks.sort();
+            picks.dedup();
+            assert_eq!(picks.len(), k.min(n));
+            assert!(picks.iter().all(|&i| i < n));
+        }
+    }
+}
+
+#[test]
+fn test_random_sample_of_a_huge_range() {
+    use crache::app::util::random_sample;
+    // Far too many indexes to list, so only the picks may be stored.
+    let n = usize::MAX / 2;
+    let mut picks = random_sample(n, 100);
+    assert!(picks.iter().all(|&i| i < n));
+    picks.sort();
+    picks.dedup();
+    assert_eq!(picks.len(), 100);
+
+    // Sparse picks still reach every index.
+    let seen: std::collections::HashSet<usize> =
+        (0..2000).flat_map(|_| random_sample(40, 3)).collect();
+    assert_eq!(seen.len(), 40);
+}
+
+#[test]
+fn test_parse_integer_is_strict() {
+    use crache::app::util::parse_integer;
+    assert_eq!(parse_integer(b"42"), Some(42));
+    assert_eq!(parse_integer(b"-7"), Some(-7));
+    assert_eq!(parse_integer(b"0"), Some(0));
+    assert_eq!(parse_integer(b"-9223372036854775808"), Some(i64::MIN));
+    for bad in [
+        &b""[..],
+        b"+1",
+        b"01",
+        b"-0",
+        b" 1",
+        b"1 ",
+        b"1.0",
+        b"9223372036854775808",
+    ] {
+        assert_eq!(parse_integer(bad), None, "{:?}", bad);
+    }
+}
+
+#[test]
+fn test_crc64_matches_redis() {
+    // The check value from Redis's crc64.c.
+    assert_eq!(crc64(0, b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+    // Checksums can be built up piece by piece.
+    assert_eq!(crc64(crc64(0, b"1234"), b"56789"), crc64(0, b"123456789"));
+}
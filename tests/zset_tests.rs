@@ -0,0 +1,461 @@
+use crache::app::util::random_index;
+use crache::app::zset::SortedSet;
+
+fn members(set: &SortedSet) -> Vec<(String, f64)> {
+    set.iter()
+        .map(|(m, s)| (String::from_utf8(m.to_vec()).unwrap(), s))
+        .collect()
+}
+
+#[test]
+fn test_orders_by_score_then_member() {
+    let mut set = SortedSet::new();
+    assert!(set.is_empty());
+    assert_eq!(set.insert(b"b".to_vec(), 2.0), None);
+    assert_eq!(set.insert(b"a".to_vec(), 2.0), None);
+    assert_eq!(set.insert(b"c".to_vec(), 1.0), None);
+    assert_eq!(set.insert(b"d".to_vec(), f64::NEG_INFINITY), None);
+    assert_eq!(
+        members(&set),
+        [
+            ("d".to_string(), f64::NEG_INFINITY),
+            ("c".to_string(), 1.0),
+            ("a".to_string(), 2.0),
+            ("b".to_string(), 2.0),
+        ]
+    );
+
+    // Changing a score moves the member.
+    assert_eq!(set.insert(b"d".to_vec(), 3.0), Some(f64::NEG_INFINITY));
+    assert_eq!(set.len(), 4);
+    assert_eq!(set.rank(b"d"), Some(3));
+    assert_eq!(set.rank(b"c"), Some(0));
+    assert_eq!(set.rank(b"z"), None);
+    assert_eq!(set.get_index(1), Some((&b"a"[..], 2.0)));
+    assert_eq!(set.get_index(4), None);
+    assert_eq!(set.score(b"b"), Some(2.0));
+}
+
+#[test]
+fn test_ranges_and_partition_point() {
+    let mut set = SortedSet::new();
+    for i in 0..10 {
+        set.insert(format!("m{}", i).into_bytes(), i as f64);
+    }
+    let scores: Vec<f64> = set.range(2, 5).map(|(_, s)| s).collect();
+    assert_eq!(scores, [2.0, 3.0, 4.0]);
+    let scores: Vec<f64> = set.range(7, 100).rev().map(|(_, s)| s).collect();
+    assert_eq!(scores, [9.0, 8.0, 7.0]);
+    assert_eq!(set.range(5, 5).count(), 0);
+    assert_eq!(set.range(3, 6).len(), 3);
+
+    assert_eq!(set.partition_point(|s, _| s < 4.5), 5);
+    assert_eq!(set.partition_point(|_, _| true), 10);
+    assert_eq!(set.partition_point(|_, _| false), 0);
+}
+
+#[test]
+fn test_remove() {
+    let mut set = SortedSet::new();
+    set.insert(b"a".to_vec(), 1.0);
+    set.insert(b"b".to_vec(), 2.0);
+    assert_eq!(set.remove(b"a"), Some(1.0));
+    assert_eq!(set.remove(b"a"), None);
+    assert_eq!(members(&set), [("b".to_string(), 2.0)]);
+    assert_eq!(set.remove(b"b"), Some(2.0));
+    assert!(set.is_empty());
+    assert_eq!(set.iter().next_back(), None);
+}
+
+// Random inserts, updates and removals checked against a sorted vector.
+#[test]
+fn test_matches_a_sorted_vector() {
+    let mut set = SortedSet::new();
+    let mut model: Vec<(f64, Vec<u8>)> = Vec::new();
+    for _ in 0..3000 {
+        let member = format!("m{}", random_index(300)).into_bytes();
+        if random_index(3) == 0 {
+            let expected = model
+                .iter()
+                .position(|(_, m)| *m == member)
+                .map(|i| model.remove(i).0);
+            assert_eq!(set.remove(&member), expected);
+        } else {
+            let score = random_index(50) as f64 / 2.0;
+            model.retain(|(_, m)| *m != member);
+            model.push((score, member.clone()));
+            model.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            set.insert(member, score);
+        }
+        assert_eq!(set.len(), model.len());
+    }
+
+    let expected: Vec<(&[u8], f64)> = model.iter().map(|(s, m)| (&m[..], *s)).collect();
+    assert_eq!(set.iter().collect::<Vec<_>>(), expected);
+    let reversed: Vec<(&[u8], f64)> = expected.iter().rev().copied().collect();
+    assert_eq!(set.iter().rev().collect::<Vec<_>>(), reversed);
+    for (rank, (score, member)) in model.iter().enumerate() {
+        assert_eq!(set.rank(member), Some(rank));
+        assert_eq!(set.get_index(rank), Some((&member[..], *score)));
+    }
+    assert_eq!(set.clone(), set);
+}
+
+mod commands {
+    use crache::app::handler::{self, Client};
+    use crache::app::resp::{Frame, Protocol};
+
+    fn bulks(args: &[&str]) -> Vec<Frame> {
+        args.iter().map(|s| Frame::bulk(*s)).collect()
+    }
+
+    fn call(args: &[&str]) -> Frame {
+        handler::dispatch(&mut Client::new(), bulks(args))
+    }
+
+    fn list(args: &[&str]) -> Frame {
+        Frame::array(bulks(args))
+    }
+
+    // A flat member/score reply, as RESP2 clients see it.
+    fn scored(pairs: &[(&str, f64)]) -> Frame {
+        Frame::array(
+            pairs
+                .iter()
+                .flat_map(|&(m, s)| [Frame::bulk(m), Frame::double(s)])
+                .collect(),
+        )
+    }
+
+    const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+    #[test]
+    fn test_zadd_options() {
+        assert_eq!(
+            call(&["ZADD", "z:add", "1", "a", "2", "b"]),
+            Frame::integer(2)
+        );
+        assert_eq!(
+            call(&["ZADD", "z:add", "5", "a", "3", "c"]),
+            Frame::integer(1)
+        );
+        assert_eq!(
+            call(&["ZADD", "z:add", "CH", "6", "a", "3", "c", "4", "d"]),
+            Frame::integer(2)
+        );
+        assert_eq!(
+            call(&["ZADD", "z:add", "NX", "0", "a", "7", "e"]),
+            Frame::integer(1)
+        );
+        assert_eq!(call(&["ZSCORE", "z:add", "a"]), Frame::double(6.0));
+        assert_eq!(
+            call(&["ZADD", "z:add", "XX", "CH", "1", "a", "1", "f"]),
+            Frame::integer(1)
+        );
+        assert_eq!(call(&["ZSCORE", "z:add", "f"]), Frame::Null);
+        // GT and LT only move scores one way, but still add new members.
+        assert_eq!(
+            call(&["ZADD", "z:add", "GT", "CH", "0", "a", "9", "b", "1", "g"]),
+            Frame::integer(2)
+        );
+        assert_eq!(call(&["ZSCORE", "z:add", "a"]), Frame::double(1.0));
+        assert_eq!(call(&["ZSCORE", "z:add", "b"]), Frame::double(9.0));
+        assert_eq!(
+            call(&["ZADD", "z:add", "LT", "CH", "5", "b"]),
+            Frame::integer(1)
+        );
+        assert_eq!(call(&["ZCARD", "z:add"]), Frame::integer(6));
+        assert_eq!(call(&["TYPE", "z:add"]), Frame::simple("zset"));
+
+        assert_eq!(
+            call(&["ZADD", "z:add", "INCR", "2.5", "a"]),
+            Frame::double(3.5)
+        );
+        assert_eq!(
+            call(&["ZADD", "z:add", "INCR", "NX", "1", "a"]),
+            Frame::Null
+        );
+        assert_eq!(call(&["ZADD", "z:add", "XX", "1", "a"]), Frame::integer(0));
+        assert_eq!(call(&["ZADD", "z:none", "XX", "1", "a"]), Frame::integer(0));
+        assert_eq!(call(&["TYPE", "z:none"]), Frame::simple("none"));
+        assert_eq!(
+            call(&["ZADD", "z:add", "+inf", "inf", "-inf", "ninf"]),
+            Frame::integer(2)
+        );
+        assert_eq!(
+            call(&["ZADD", "z:add", "INCR", "-inf", "inf"]),
+            Frame::error("ERR resulting score is not a number (NaN)")
+        );
+
+        for (args, err) in [
+            (&["ZADD", "z:add", "NX", "1"][..], "ERR syntax error"),
+            (&["ZADD", "z:add", "1", "a", "2"], "ERR syntax error"),
+            (
+                &["ZADD", "z:add", "x", "a"],
+                "ERR value is not a valid float",
+            ),
+            (
+                &["ZADD", "z:add", "nan", "a"],
+                "ERR value is not a valid float",
+            ),
+            (
+                &["ZADD", "z:add", "NX", "XX", "1", "a"],
+                "ERR XX and NX options at the same time are not compatible",
+            ),
+            (
+                &["ZADD", "z:add", "GT", "LT", "1", "a"],
+                "ERR GT, LT, and/or NX options at the same time are not compatible",
+            ),
+            (
+                &["ZADD", "z:add", "INCR", "1", "a", "2", "b"],
+                "ERR INCR option supports a single increment-element pair",
+            ),
+        ] {
+            assert_eq!(call(args), Frame::error(err), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_zincrby_zscore_and_rank() {
+        assert_eq!(call(&["ZINCRBY", "z:inc", "2", "a"]), Frame::double(2.0));
+        assert_eq!(call(&["ZINCRBY", "z:inc", "-0.5", "a"]), Frame::double(1.5));
+        call(&["ZADD", "z:inc", "1", "b", "3", "c"]);
+        assert_eq!(call(&["ZRANK", "z:inc", "b"]), Frame::integer(0));
+        assert_eq!(call(&["ZRANK", "z:inc", "c"]), Frame::integer(2));
+        assert_eq!(call(&["ZREVRANK", "z:inc", "c"]), Frame::integer(0));
+        assert_eq!(call(&["ZRANK", "z:inc", "nope"]), Frame::Null);
+        assert_eq!(call(&["ZRANK", "z:missing", "a"]), Frame::Null);
+        assert_eq!(call(&["ZSCORE", "z:missing", "a"]), Frame::Null);
+        assert_eq!(
+            call(&["ZINCRBY", "z:inc", "x", "a"]),
+            Frame::error("ERR value is not a valid float")
+        );
+    }
+
+    #[test]
+    fn test_zrange_by_rank() {
+        call(&["ZADD", "z:rank", "1", "a", "2", "b", "3", "c", "4", "d"]);
+        assert_eq!(
+            call(&["ZRANGE", "z:rank", "0", "-1"]),
+            list(&["a", "b", "c", "d"])
+        );
+        assert_eq!(call(&["ZRANGE", "z:rank", "1", "2"]), list(&["b", "c"]));
+        assert_eq!(
+            call(&["ZRANGE", "z:rank", "0", "1", "REV"]),
+            list(&["d", "c"])
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:rank", "-2", "100", "REV"]),
+            list(&["b", "a"])
+        );
+        assert_eq!(call(&["ZRANGE", "z:rank", "3", "1"]), list(&[]));
+        assert_eq!(
+            call(&["ZRANGE", "z:rank", "0", "1", "WITHSCORES"]),
+            scored(&[("a", 1.0), ("b", 2.0)])
+        );
+        assert_eq!(call(&["ZRANGE", "z:missing", "0", "-1"]), list(&[]));
+        assert_eq!(
+            call(&["ZRANGE", "z:rank", "0", "1", "LIMIT", "0", "1"]),
+            Frame::error(
+                "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+            )
+        );
+
+        // RESP3 clients get member/score pairs.
+        let mut client = Client::new();
+        client.protocol = Protocol::Resp3;
+        assert_eq!(
+            handler::dispatch(
+                &mut client,
+                bulks(&["ZRANGE", "z:rank", "0", "0", "WITHSCORES"])
+            ),
+            Frame::array(vec![Frame::array(vec![
+                Frame::bulk("a"),
+                Frame::double(1.0)
+            ])])
+        );
+    }
+
+    #[test]
+    fn test_zrange_by_score() {
+        call(&[
+            "ZADD", "z:score", "1", "a", "2", "b", "2", "c", "3", "d", "5", "e",
+        ]);
+        assert_eq!(
+            call(&["ZRANGE", "z:score", "2", "3", "BYSCORE"]),
+            list(&["b", "c", "d"])
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:score", "(2", "3", "BYSCORE"]),
+            list(&["d"])
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:score", "-inf", "(2", "BYSCORE"]),
+            list(&["a"])
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:score", "+inf", "2", "BYSCORE", "REV"]),
+            list(&["e", "d", "c", "b"])
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:score", "-inf", "+inf", "BYSCORE", "LIMIT", "1", "2"]),
+            list(&["b", "c"])
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:score", "-inf", "+inf", "BYSCORE", "LIMIT", "3", "-1"]),
+            list(&["d", "e"])
+        );
+        assert_eq!(
+            call(&[
+                "ZRANGE",
+                "z:score",
+                "+inf",
+                "-inf",
+                "BYSCORE",
+                "REV",
+                "LIMIT",
+                "0",
+                "2",
+                "WITHSCORES"
+            ]),
+            scored(&[("e", 5.0), ("d", 3.0)])
+        );
+        assert_eq!(call(&["ZRANGE", "z:score", "4", "2", "BYSCORE"]), list(&[]));
+        assert_eq!(
+            call(&["ZRANGE", "z:score", "x", "2", "BYSCORE"]),
+            Frame::error("ERR min or max is not a float")
+        );
+
+        assert_eq!(call(&["ZCOUNT", "z:score", "2", "3"]), Frame::integer(3));
+        assert_eq!(call(&["ZCOUNT", "z:score", "(1", "(5"]), Frame::integer(3));
+        assert_eq!(
+            call(&["ZCOUNT", "z:score", "-inf", "+inf"]),
+            Frame::integer(5)
+        );
+        assert_eq!(
+            call(&["ZCOUNT", "z:missing", "-inf", "+inf"]),
+            Frame::integer(0)
+        );
+    }
+
+    #[test]
+    fn test_zrange_by_lex() {
+        call(&["ZADD", "z:lex", "0", "a", "0", "b", "0", "c", "0", "d"]);
+        assert_eq!(
+            call(&["ZRANGE", "z:lex", "-", "+", "BYLEX"]),
+            list(&["a", "b", "c", "d"])
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:lex", "[b", "(d", "BYLEX"]),
+            list(&["b", "c"])
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:lex", "(b", "+", "BYLEX"]),
+            list(&["c", "d"])
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:lex", "[c", "-", "BYLEX", "REV", "LIMIT", "0", "2"]),
+            list(&["c", "b"])
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:lex", "b", "+", "BYLEX"]),
+            Frame::error("ERR min or max not valid string range item")
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:lex", "-", "+", "BYLEX", "WITHSCORES"]),
+            Frame::error("ERR syntax error, WITHSCORES not supported in combination with BYLEX")
+        );
+    }
+
+    #[test]
+    fn test_zrangestore() {
+        call(&["ZADD", "z:src", "1", "a", "2", "b", "3", "c"]);
+        assert_eq!(
+            call(&["ZRANGESTORE", "z:dst", "z:src", "(1", "+inf", "BYSCORE"]),
+            Frame::integer(2)
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:dst", "0", "-1", "WITHSCORES"]),
+            scored(&[("b", 2.0), ("c", 3.0)])
+        );
+        assert_eq!(
+            call(&["ZRANGESTORE", "z:dst", "z:src", "5", "6"]),
+            Frame::integer(0)
+        );
+        assert_eq!(call(&["TYPE", "z:dst"]), Frame::simple("none"));
+        assert_eq!(
+            call(&["ZRANGESTORE", "z:dst", "z:src", "0", "-1", "WITHSCORES"]),
+            Frame::error("ERR syntax error")
+        );
+    }
+
+    #[test]
+    fn test_zrem_and_remrange() {
+        call(&[
+            "ZADD", "z:rem", "1", "a", "2", "b", "3", "c", "4", "d", "5", "e", "6", "f",
+        ]);
+        assert_eq!(call(&["ZREM", "z:rem", "a", "nope"]), Frame::integer(1));
+        assert_eq!(
+            call(&["ZREMRANGEBYRANK", "z:rem", "-1", "-1"]),
+            Frame::integer(1)
+        );
+        assert_eq!(
+            call(&["ZREMRANGEBYSCORE", "z:rem", "(2", "3"]),
+            Frame::integer(1)
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:rem", "0", "-1"]),
+            list(&["b", "d", "e"])
+        );
+        assert_eq!(
+            call(&["ZREMRANGEBYSCORE", "z:rem", "10", "20"]),
+            Frame::integer(0)
+        );
+
+        call(&["ZADD", "z:remlex", "0", "a", "0", "b", "0", "c"]);
+        assert_eq!(
+            call(&["ZREMRANGEBYLEX", "z:remlex", "[a", "(c"]),
+            Frame::integer(2)
+        );
+        assert_eq!(
+            call(&["ZREMRANGEBYLEX", "z:remlex", "-", "+"]),
+            Frame::integer(1)
+        );
+        assert_eq!(call(&["TYPE", "z:remlex"]), Frame::simple("none"));
+        assert_eq!(call(&["ZREM", "z:remlex", "a"]), Frame::integer(0));
+    }
+
+    #[test]
+    fn test_zpop() {
+        call(&["ZADD", "z:pop", "1", "a", "2", "b", "3", "c", "4", "d"]);
+        assert_eq!(call(&["ZPOPMIN", "z:pop"]), scored(&[("a", 1.0)]));
+        assert_eq!(call(&["ZPOPMAX", "z:pop"]), scored(&[("d", 4.0)]));
+        assert_eq!(
+            call(&["ZPOPMAX", "z:pop", "5"]),
+            scored(&[("c", 3.0), ("b", 2.0)])
+        );
+        assert_eq!(call(&["TYPE", "z:pop"]), Frame::simple("none"));
+        assert_eq!(call(&["ZPOPMIN", "z:pop"]), list(&[]));
+        assert_eq!(
+            call(&["ZPOPMIN", "z:pop", "-1"]),
+            Frame::error("ERR value is out of range, must be positive")
+        );
+    }
+
+    #[test]
+    fn test_zset_wrongtype() {
+        call(&["SET", "z:string", "v"]);
+        call(&["ZADD", "z:real", "1", "a"]);
+        assert_eq!(
+            call(&["ZADD", "z:string", "1", "a"]),
+            Frame::error(WRONGTYPE)
+        );
+        assert_eq!(
+            call(&["ZRANGE", "z:string", "0", "-1"]),
+            Frame::error(WRONGTYPE)
+        );
+        assert_eq!(call(&["ZPOPMIN", "z:string"]), Frame::error(WRONGTYPE));
+        assert_eq!(call(&["GET", "z:real"]), Frame::error(WRONGTYPE));
+        assert_eq!(call(&["LLEN", "z:real"]), Frame::error(WRONGTYPE));
+    }
+}